@@ -1,7 +1,4 @@
-use sendgrid::v3::{
-    ClickTrackingSetting, Content, Email, Message, OpenTrackingSetting, Personalization, Sender,
-    SubscriptionTrackingSetting, TrackingSettings,
-};
+use sendgrid::v3::{Email, Message, Personalization, Sender, TrackingSettings};
 
 fn main() {
     let args = std::env::args().collect::<Vec<String>>();
@@ -27,20 +24,8 @@ fn main() {
 
     let message = Message::new(Email::new(from_email).set_name(from_name))
         .set_subject(subject)
-        .add_content(Content::new().set_content_type("text/html").set_value(html))
-        .set_tracking_settings(TrackingSettings {
-            click_tracking: Some(ClickTrackingSetting {
-                enable: Some(false),
-                enable_text: None,
-            }),
-            subscription_tracking: Some(SubscriptionTrackingSetting {
-                enable: Some(false),
-            }),
-            open_tracking: Some(OpenTrackingSetting {
-                enable: Some(false),
-                substitution_tag: None,
-            }),
-        })
+        .set_html(html)
+        .set_tracking_settings(TrackingSettings::disable_all())
         .add_personalization(person);
 
     let sender = Sender::new(api_key.to_owned(), None);
@@ -0,0 +1,259 @@
+//! Wraps the account provisioning API used by Twilio SendGrid resellers to manage customer
+//! accounts.
+//!
+//! See the [account provisioning docs](https://www.twilio.com/docs/sendgrid/api-reference/account-provisioning-api-account-operations)
+//! for details on the individual fields.
+
+use reqwest::{Client, Method};
+use serde::{Deserialize, Serialize};
+
+use crate::api::ApiClient;
+use crate::error::SendgridResult;
+
+/// The profile of a new customer account.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct AccountProfile {
+    /// The first name of the account owner.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub first_name: Option<String>,
+
+    /// The last name of the account owner.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_name: Option<String>,
+
+    /// The company name of the customer.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub company_name: Option<String>,
+
+    /// The company website of the customer.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub company_website: Option<String>,
+
+    /// The email address of the account owner.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+
+    /// The phone number of the account owner.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub phone: Option<String>,
+
+    /// The timezone of the account, for example `America/Chicago`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<String>,
+}
+
+/// An offering such as a package or add-on which can be assigned to an account.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct Offering {
+    /// The name of the offering.
+    pub name: String,
+
+    /// The type of the offering, either `package` or `addon`.
+    #[serde(rename = "type")]
+    pub offering_type: String,
+
+    /// The quantity of the offering, only used by some add-ons.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quantity: Option<u64>,
+}
+
+/// A customer account as returned when listing accounts.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct Account {
+    /// The id of the account.
+    pub id: String,
+
+    /// When the account was created.
+    #[serde(default)]
+    pub created_at: Option<String>,
+
+    /// When the account was last updated.
+    #[serde(default)]
+    pub updated_at: Option<String>,
+
+    /// Whether this is a test account.
+    #[serde(default)]
+    pub test_account: bool,
+}
+
+/// The state of a customer account, for example `activated` or `deactivated`.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct AccountState {
+    /// The state of the account.
+    pub state: String,
+}
+
+#[derive(Serialize)]
+struct CreateAccount<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    profile: Option<&'a AccountProfile>,
+    offerings: &'a [Offering],
+}
+
+#[derive(Deserialize)]
+struct CreatedAccount {
+    account_id: String,
+}
+
+#[derive(Deserialize)]
+struct AccountList {
+    accounts: Vec<Account>,
+}
+
+#[derive(Deserialize, Serialize)]
+struct Offerings {
+    offerings: Vec<Offering>,
+}
+
+/// A client for the account provisioning endpoints.
+#[derive(Clone, Debug)]
+pub struct AccountsClient {
+    api: ApiClient,
+}
+
+impl AccountsClient {
+    /// Construct a new accounts client. The `client` parameter is optional and `None` uses the
+    /// default.
+    pub fn new(api_key: String, client: Option<Client>) -> AccountsClient {
+        AccountsClient {
+            api: ApiClient::new(api_key, client),
+        }
+    }
+
+    /// Sets the base URL to use for the API, for example `https://api.sendgrid.com`.
+    pub fn set_host<S: Into<String>>(&mut self, host: S) {
+        self.api.set_base_url(host.into());
+    }
+
+    /// Create a customer account with the given offerings and return its id. Test accounts are
+    /// not billed.
+    pub async fn create_account(
+        &self,
+        profile: Option<&AccountProfile>,
+        offerings: &[Offering],
+        test_account: bool,
+    ) -> SendgridResult<String> {
+        let mut request = self
+            .api
+            .request(Method::POST, "/v3/partners/accounts")?
+            .json(&CreateAccount { profile, offerings });
+        if test_account {
+            request = request.header("T-Test-Account", "true");
+        }
+
+        let resp = self.api.execute(request).await?;
+        let created: CreatedAccount = serde_json::from_slice(&resp.bytes().await?)?;
+        Ok(created.account_id)
+    }
+
+    /// List customer accounts, `limit` at a time starting from the account id `offset`.
+    pub async fn list_accounts(
+        &self,
+        limit: Option<u32>,
+        offset: Option<&str>,
+    ) -> SendgridResult<Vec<Account>> {
+        let mut query = Vec::new();
+        if let Some(limit) = limit {
+            query.push(("limit", limit.to_string()));
+        }
+        if let Some(offset) = offset {
+            query.push(("offset", offset.to_string()));
+        }
+
+        let request = self
+            .api
+            .request(Method::GET, "/v3/partners/accounts")?
+            .query(&query);
+        let resp = self.api.execute(request).await?;
+        let list: AccountList = serde_json::from_slice(&resp.bytes().await?)?;
+        Ok(list.accounts)
+    }
+
+    /// Delete a customer account.
+    pub async fn delete_account(&self, account_id: &str) -> SendgridResult<()> {
+        self.api
+            .delete(&format!("/v3/partners/accounts/{}", account_id))
+            .await
+    }
+
+    /// Get the state of a customer account.
+    pub async fn get_account_state(&self, account_id: &str) -> SendgridResult<AccountState> {
+        self.api
+            .get(&format!("/v3/partners/accounts/{}/state", account_id))
+            .await
+    }
+
+    /// Update the state of a customer account, for example to deactivate it.
+    pub async fn update_account_state(
+        &self,
+        account_id: &str,
+        state: &AccountState,
+    ) -> SendgridResult<()> {
+        let request = self
+            .api
+            .request(
+                Method::PUT,
+                &format!("/v3/partners/accounts/{}/state", account_id),
+            )?
+            .json(state);
+        self.api.execute(request).await?;
+        Ok(())
+    }
+
+    /// List the offerings available to the reseller.
+    pub async fn list_offerings(&self) -> SendgridResult<Vec<Offering>> {
+        let offerings: Offerings = self.api.get("/v3/partners/offerings").await?;
+        Ok(offerings.offerings)
+    }
+
+    /// List the offerings assigned to a customer account.
+    pub async fn get_account_offerings(&self, account_id: &str) -> SendgridResult<Vec<Offering>> {
+        let offerings: Offerings = self
+            .api
+            .get(&format!("/v3/partners/accounts/{}/offerings", account_id))
+            .await?;
+        Ok(offerings.offerings)
+    }
+
+    /// Replace the offerings assigned to a customer account.
+    pub async fn update_account_offerings(
+        &self,
+        account_id: &str,
+        offerings: Vec<Offering>,
+    ) -> SendgridResult<Vec<Offering>> {
+        let offerings: Offerings = self
+            .api
+            .send_json(
+                Method::PUT,
+                &format!("/v3/partners/accounts/{}/offerings", account_id),
+                &Offerings { offerings },
+            )
+            .await?;
+        Ok(offerings.offerings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn create_account_body() {
+        let offerings = [Offering {
+            name: String::from("org.ei.free.v1"),
+            offering_type: String::from("package"),
+            quantity: None,
+        }];
+        let profile = AccountProfile {
+            company_name: Some(String::from("Acme")),
+            ..Default::default()
+        };
+        let body = serde_json::to_string(&CreateAccount {
+            profile: Some(&profile),
+            offerings: &offerings,
+        })
+        .unwrap();
+        let expected = r#"{"profile":{"company_name":"Acme"},"offerings":[{"name":"org.ei.free.v1","type":"package"}]}"#;
+        assert_eq!(body, expected);
+    }
+}
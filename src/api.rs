@@ -0,0 +1,86 @@
+// A small authenticated JSON client shared by the sub-clients wrapping the wider v3 API.
+
+use reqwest::header::{self, HeaderMap, HeaderValue};
+use reqwest::{Client, Method, RequestBuilder, Response};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::error::{RequestNotSuccessful, SendgridResult};
+use crate::response::ResponseExt;
+use crate::tls;
+
+const API_BASE_URL: &str = "https://api.sendgrid.com";
+
+#[derive(Clone, Debug)]
+pub(crate) struct ApiClient {
+    api_key: String,
+    client: Client,
+    base_url: String,
+}
+
+impl ApiClient {
+    pub(crate) fn new(api_key: String, client: Option<Client>) -> ApiClient {
+        ApiClient {
+            api_key,
+            client: client.unwrap_or_else(tls::default_client),
+            base_url: API_BASE_URL.to_string(),
+        }
+    }
+
+    pub(crate) fn set_base_url(&mut self, base_url: String) {
+        self.base_url = base_url.trim_end_matches('/').to_string();
+    }
+
+    fn headers(&self) -> SendgridResult<HeaderMap> {
+        let mut headers = HeaderMap::with_capacity(2);
+        headers.insert(
+            header::AUTHORIZATION,
+            HeaderValue::from_str(&format!("Bearer {}", self.api_key))?,
+        );
+        headers.insert(header::USER_AGENT, HeaderValue::from_static("sendgrid-rs"));
+        Ok(headers)
+    }
+
+    pub(crate) fn request(&self, method: Method, path: &str) -> SendgridResult<RequestBuilder> {
+        Ok(self
+            .client
+            .request(method, format!("{}{}", self.base_url, path))
+            .headers(self.headers()?))
+    }
+
+    // Send the request, turning unsuccessful status codes into errors.
+    pub(crate) async fn execute(&self, request: RequestBuilder) -> SendgridResult<Response> {
+        let resp = request.send().await?;
+        if resp.error_for_status_ref().is_err() {
+            let (rate_limit, retry_after) = (resp.rate_limit(), resp.retry_after());
+            let error = RequestNotSuccessful::new(resp.status(), resp.text().await?)
+                .with_rate_limit(rate_limit)
+                .with_retry_after(retry_after);
+            return Err(error.into());
+        }
+        Ok(resp)
+    }
+
+    pub(crate) async fn get<R: DeserializeOwned>(&self, path: &str) -> SendgridResult<R> {
+        let resp = self.execute(self.request(Method::GET, path)?).await?;
+        Ok(serde_json::from_slice(&resp.bytes().await?)?)
+    }
+
+    pub(crate) async fn send_json<B, R>(
+        &self,
+        method: Method,
+        path: &str,
+        body: &B,
+    ) -> SendgridResult<R>
+    where
+        B: Serialize + ?Sized,
+        R: DeserializeOwned,
+    {
+        let resp = self.execute(self.request(method, path)?.json(body)).await?;
+        Ok(serde_json::from_slice(&resp.bytes().await?)?)
+    }
+
+    pub(crate) async fn delete(&self, path: &str) -> SendgridResult<()> {
+        self.execute(self.request(Method::DELETE, path)?).await?;
+        Ok(())
+    }
+}
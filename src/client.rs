@@ -1,3 +1,5 @@
+use std::time::Instant;
+
 use reqwest::header::{self, HeaderMap, HeaderValue};
 
 #[cfg(feature = "blocking")]
@@ -8,7 +10,11 @@ use url::form_urlencoded::Serializer;
 
 use crate::{
     error::{RequestNotSuccessful, SendgridResult},
+    interceptor::{Interceptors, SendInterceptor},
     mail::Mail,
+    region::Region,
+    response::ResponseExt,
+    tls,
 };
 
 static API_URL: &str = "https://api.sendgrid.com/api/mail.send.json?";
@@ -22,6 +28,7 @@ pub struct SGClient {
     client: reqwest::Client,
     #[cfg(feature = "blocking")]
     blocking_client: reqwest::blocking::Client,
+    interceptors: Interceptors,
 }
 
 // Given a form value and a key, generate the correct key.
@@ -65,7 +72,7 @@ fn make_post_body(mut mail_info: Mail) -> SendgridResult<String> {
     encoder.append_pair("subject", mail_info.subject);
     encoder.append_pair("html", mail_info.html);
     encoder.append_pair("text", mail_info.text);
-    encoder.append_pair("fromname", mail_info.from_name);
+    encoder.append_pair("fromname", mail_info.checked_from_name()?);
     encoder.append_pair("replyto", mail_info.reply_to);
     encoder.append_pair("date", mail_info.date);
     encoder.append_pair("headers", &mail_info.make_header_string()?);
@@ -79,20 +86,14 @@ impl SGClient {
     /// default TLS backend and do not have a default TLS backend available. If you are using the
     /// RustTLS backend, this can never panic because RustTLS is statically linked.
     pub fn new<S: Into<String>>(key: S) -> SGClient {
-        let async_builder = reqwest::ClientBuilder::new();
-        #[cfg(feature = "rustls")]
-        let async_builder = async_builder.use_rustls_tls();
-        let client = async_builder.build().unwrap();
+        let client = tls::client_builder().build().unwrap();
 
         #[cfg(feature = "blocking")]
         let blocking_client: reqwest::blocking::Client;
 
         #[cfg(feature = "blocking")]
         {
-            let blocking_builder = reqwest::blocking::ClientBuilder::new();
-            #[cfg(feature = "rustls")]
-            let blocking_builder = blocking_builder.use_rustls_tls();
-            blocking_client = blocking_builder.build().unwrap();
+            blocking_client = tls::blocking_client_builder().build().unwrap();
         }
 
         SGClient {
@@ -101,6 +102,7 @@ impl SGClient {
             #[cfg(feature = "blocking")]
             blocking_client,
             host: API_URL.to_string(),
+            interceptors: Interceptors::default(),
         }
     }
 
@@ -110,6 +112,17 @@ impl SGClient {
         self.host = host.into();
     }
 
+    /// Sends through the v2 mail send endpoint of `region`.
+    pub fn set_region(&mut self, region: Region) {
+        self.host = region.v2_mail_send_url();
+    }
+
+    /// Register an interceptor which sees every request before it is sent and every response
+    /// received. Interceptors run in the order they were added.
+    pub fn add_interceptor<I: SendInterceptor + 'static>(&mut self, interceptor: I) {
+        self.interceptors.add(interceptor);
+    }
+
     /// Sends a messages through the SendGrid API. It takes a Mail struct as an argument. It returns
     /// the string response from the API as JSON.
     ///
@@ -134,16 +147,54 @@ impl SGClient {
     /// ```
     #[cfg(feature = "blocking")]
     pub fn blocking_send(&self, mail_info: Mail) -> SendgridResult<BlockingResponse> {
+        self.blocking_send_with_key(mail_info, &self.api_key)
+    }
+
+    /// Like [`SGClient::blocking_send`], but authenticates with `api_key` instead of the key of
+    /// this client. This lets one client and its connection pool send for many accounts.
+    #[cfg(feature = "blocking")]
+    pub fn blocking_send_with_key(
+        &self,
+        mail_info: Mail,
+        api_key: &str,
+    ) -> SendgridResult<BlockingResponse> {
         let post_body = make_post_body(mail_info)?;
+
+        #[cfg(feature = "otel")]
+        let telemetry = crate::telemetry::SendTelemetry::start(&self.host);
+        #[cfg(feature = "metrics")]
+        let metrics = crate::send_metrics::SendMetrics::start("v2", post_body.len());
+
+        let result = self.blocking_post(post_body, api_key);
+
+        #[cfg(feature = "otel")]
+        telemetry.finish(result.as_ref().map(BlockingResponse::status));
+        #[cfg(feature = "metrics")]
+        metrics.finish(result.as_ref().map(BlockingResponse::status));
+        result
+    }
+
+    #[cfg(feature = "blocking")]
+    fn blocking_post(&self, post_body: String, api_key: &str) -> SendgridResult<BlockingResponse> {
+        let mut headers = headers(api_key)?;
+        self.interceptors
+            .before_send(&self.host, &mut headers, &post_body)?;
+        let start = Instant::now();
         let resp = self
             .blocking_client
             .post(&self.host)
-            .headers(self.headers()?)
+            .headers(headers)
             .body(post_body)
             .send()?;
+        self.interceptors
+            .after_send(&self.host, resp.status(), resp.headers(), start.elapsed());
 
         if resp.error_for_status_ref().is_err() {
-            return Err(RequestNotSuccessful::new(resp.status(), resp.text()?).into());
+            let (rate_limit, retry_after) = (resp.rate_limit(), resp.retry_after());
+            let error = RequestNotSuccessful::new(resp.status(), resp.text()?)
+                .with_rate_limit(rate_limit)
+                .with_retry_after(retry_after);
+            return Err(error.into());
         }
 
         Ok(resp)
@@ -174,35 +225,71 @@ impl SGClient {
     /// }
     /// ```
     pub async fn send(&self, mail_info: Mail<'_>) -> SendgridResult<Response> {
+        self.send_with_key(mail_info, &self.api_key).await
+    }
+
+    /// Like [`SGClient::send`], but authenticates with `api_key` instead of the key of this
+    /// client. This lets one client and its connection pool send for many accounts.
+    pub async fn send_with_key(
+        &self,
+        mail_info: Mail<'_>,
+        api_key: &str,
+    ) -> SendgridResult<Response> {
         let post_body = make_post_body(mail_info)?;
+
+        #[cfg(feature = "otel")]
+        let telemetry = crate::telemetry::SendTelemetry::start(&self.host);
+        #[cfg(feature = "metrics")]
+        let metrics = crate::send_metrics::SendMetrics::start("v2", post_body.len());
+
+        let result = self.post(post_body, api_key).await;
+
+        #[cfg(feature = "otel")]
+        telemetry.finish(result.as_ref().map(Response::status));
+        #[cfg(feature = "metrics")]
+        metrics.finish(result.as_ref().map(Response::status));
+        result
+    }
+
+    async fn post(&self, post_body: String, api_key: &str) -> SendgridResult<Response> {
+        let mut headers = headers(api_key)?;
+        self.interceptors
+            .before_send(&self.host, &mut headers, &post_body)?;
+        let start = Instant::now();
         let resp = self
             .client
             .post(&self.host)
-            .headers(self.headers()?)
+            .headers(headers)
             .body(post_body)
             .send()
             .await?;
+        self.interceptors
+            .after_send(&self.host, resp.status(), resp.headers(), start.elapsed());
 
         if resp.error_for_status_ref().is_err() {
-            return Err(RequestNotSuccessful::new(resp.status(), resp.text().await?).into());
+            let (rate_limit, retry_after) = (resp.rate_limit(), resp.retry_after());
+            let error = RequestNotSuccessful::new(resp.status(), resp.text().await?)
+                .with_rate_limit(rate_limit)
+                .with_retry_after(retry_after);
+            return Err(error.into());
         }
 
         Ok(resp)
     }
+}
 
-    fn headers(&self) -> SendgridResult<HeaderMap> {
-        let mut headers = HeaderMap::new();
-        headers.insert(
-            header::AUTHORIZATION,
-            HeaderValue::from_str(&format!("Bearer {}", self.api_key.clone()))?,
-        );
-        headers.insert(
-            header::CONTENT_TYPE,
-            HeaderValue::from_static("application/x-www-form-urlencoded"),
-        );
-        headers.insert(header::USER_AGENT, HeaderValue::from_static("sendgrid-rs"));
-        Ok(headers)
-    }
+fn headers(api_key: &str) -> SendgridResult<HeaderMap> {
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::AUTHORIZATION,
+        HeaderValue::from_str(&format!("Bearer {}", api_key))?,
+    );
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/x-www-form-urlencoded"),
+    );
+    headers.insert(header::USER_AGENT, HeaderValue::from_static("sendgrid-rs"));
+    Ok(headers)
 }
 
 #[test]
@@ -230,3 +317,73 @@ fn test_proper_key() {
     let got = make_form_key("files", "test.jpg");
     assert_eq!(want, got);
 }
+
+#[test]
+fn headers_use_the_given_key() {
+    let headers = headers("SG.tenant").unwrap();
+    assert_eq!(headers[header::AUTHORIZATION], "Bearer SG.tenant");
+}
+
+// Contract tests for the V2 form encoding. Each case is compared against a recorded fixture in
+// `tests/fixtures/v2`. Run with `SENDGRID_RECORD=1 cargo test` to re-record the fixtures after an
+// intentional change to the encoding.
+#[cfg(test)]
+fn check_fixture(name: &str, mail: Mail) {
+    let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures/v2")
+        .join(format!("{}.txt", name));
+    let body = make_post_body(mail).unwrap();
+
+    if std::env::var_os("SENDGRID_RECORD").is_some() {
+        std::fs::write(&path, &body).unwrap();
+        return;
+    }
+
+    let want = std::fs::read_to_string(&path)
+        .unwrap_or_else(|_| panic!("missing fixture {}", path.display()));
+    assert_eq!(body, want.trim_end(), "fixture {} does not match", name);
+}
+
+#[test]
+fn contract_multiple_recipients() {
+    let m = Mail::new()
+        .add_to(("first@example.com", "First").into())
+        .add_to(("second@example.com", "Second Person").into())
+        .add_cc("cc@example.com")
+        .add_bcc("bcc@example.com")
+        .add_from("me@example.com")
+        .add_from_name("Me")
+        .add_reply_to("reply@example.com")
+        .add_subject("Multiple")
+        .add_html("<p>Hi</p>")
+        .add_text("Hi");
+
+    check_fixture("multiple_recipients", m);
+}
+
+#[test]
+fn contract_headers_and_content() {
+    let m = Mail::new()
+        .add_to(("test@example.com", "Test").into())
+        .add_from("me@example.com")
+        .add_subject("Inline")
+        .add_html("<img src=\"cid:logo\">")
+        .add_content(String::from("logo"), "logo.png")
+        .add_header(String::from("X-Mailer"), "sendgrid-rs")
+        .add_date("Thu, 21 Dec 2000 16:01:07 +0200")
+        .add_x_smtpapi(r#"{"category":["test"]}"#);
+
+    check_fixture("headers_and_content", m);
+}
+
+#[test]
+fn contract_special_characters() {
+    let m = Mail::new()
+        .add_to(("o'brien+tag@example.com", "Ünïcødé & Co").into())
+        .add_from("me@example.com")
+        .add_from_name("Zoë = 100%")
+        .add_subject("Question? Yes & no / maybe")
+        .add_text("line one\nline two");
+
+    check_fixture("special_characters", m);
+}
@@ -0,0 +1,214 @@
+//! DNS resolution options for the HTTP clients used by this crate.
+//!
+//! Environments with slow or unreliable DNS, such as serverless functions, can pin the API host to
+//! known addresses, cache lookups for longer than the system resolver does, or prefer one IP
+//! family. [`DnsOptions`] applies these settings to a `reqwest` client builder, and
+//! [`Sender::with_dns_options`](crate::v3::Sender::with_dns_options) builds a sender from them.
+
+use std::collections::HashMap;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+
+/// Which IP family to use for the resolved addresses.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum IpPreference {
+    /// Use the addresses in the order returned by the system resolver.
+    #[default]
+    Any,
+
+    /// Try IPv4 addresses before IPv6 addresses.
+    PreferIpv4,
+
+    /// Try IPv6 addresses before IPv4 addresses.
+    PreferIpv6,
+
+    /// Only use IPv4 addresses.
+    Ipv4Only,
+
+    /// Only use IPv6 addresses.
+    Ipv6Only,
+}
+
+impl IpPreference {
+    fn apply(self, addrs: &mut Vec<SocketAddr>) {
+        match self {
+            IpPreference::Any => {}
+            IpPreference::PreferIpv4 => addrs.sort_by_key(|addr| !addr.is_ipv4()),
+            IpPreference::PreferIpv6 => addrs.sort_by_key(|addr| !addr.is_ipv6()),
+            IpPreference::Ipv4Only => addrs.retain(SocketAddr::is_ipv4),
+            IpPreference::Ipv6Only => addrs.retain(SocketAddr::is_ipv6),
+        }
+    }
+}
+
+/// DNS settings for the clients used to talk to SendGrid.
+#[must_use]
+#[derive(Clone, Debug, Default)]
+pub struct DnsOptions {
+    overrides: HashMap<String, Vec<SocketAddr>>,
+    cache_ttl: Option<Duration>,
+    ip_preference: IpPreference,
+}
+
+impl DnsOptions {
+    /// Construct options which use the system resolver without caching.
+    pub fn new() -> DnsOptions {
+        DnsOptions::default()
+    }
+
+    /// Resolve `domain` to the given addresses without querying DNS.
+    pub fn add_override<S: Into<String>>(mut self, domain: S, addrs: &[SocketAddr]) -> DnsOptions {
+        self.overrides.insert(domain.into(), addrs.to_vec());
+        self
+    }
+
+    /// Cache successful lookups for `ttl`.
+    pub fn set_cache_ttl(mut self, ttl: Duration) -> DnsOptions {
+        self.cache_ttl = Some(ttl);
+        self
+    }
+
+    /// Set which IP family to use.
+    pub fn set_ip_preference(mut self, ip_preference: IpPreference) -> DnsOptions {
+        self.ip_preference = ip_preference;
+        self
+    }
+
+    /// Apply the options to an async client builder.
+    pub fn apply(&self, mut builder: reqwest::ClientBuilder) -> reqwest::ClientBuilder {
+        for (domain, addrs) in &self.overrides {
+            builder = builder.resolve_to_addrs(domain, addrs);
+        }
+        match self.resolver() {
+            Some(resolver) => builder.dns_resolver(resolver),
+            None => builder,
+        }
+    }
+
+    /// Apply the options to a blocking client builder.
+    #[cfg(feature = "blocking")]
+    pub fn apply_blocking(
+        &self,
+        mut builder: reqwest::blocking::ClientBuilder,
+    ) -> reqwest::blocking::ClientBuilder {
+        for (domain, addrs) in &self.overrides {
+            builder = builder.resolve_to_addrs(domain, addrs);
+        }
+        match self.resolver() {
+            Some(resolver) => builder.dns_resolver(resolver),
+            None => builder,
+        }
+    }
+
+    // The default resolver is kept unless caching or a preference needs a custom one.
+    fn resolver(&self) -> Option<Arc<CachingResolver>> {
+        if self.cache_ttl.is_none() && self.ip_preference == IpPreference::Any {
+            return None;
+        }
+        Some(Arc::new(CachingResolver {
+            ttl: self.cache_ttl,
+            ip_preference: self.ip_preference,
+            cache: Arc::new(Mutex::new(HashMap::new())),
+        }))
+    }
+}
+
+type Cache = Arc<Mutex<HashMap<String, (Instant, Vec<SocketAddr>)>>>;
+
+struct CachingResolver {
+    ttl: Option<Duration>,
+    ip_preference: IpPreference,
+    cache: Cache,
+}
+
+// A panic while the lock is held cannot leave the map half updated, so the cache stays usable.
+fn lock(cache: &Cache) -> MutexGuard<'_, HashMap<String, (Instant, Vec<SocketAddr>)>> {
+    cache.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+impl CachingResolver {
+    fn cached(&self, name: &str) -> Option<Vec<SocketAddr>> {
+        lock(&self.cache)
+            .get(name)
+            .filter(|(expires, _)| Instant::now() < *expires)
+            .map(|(_, addrs)| addrs.clone())
+    }
+}
+
+impl Resolve for CachingResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let name = name.as_str().to_string();
+        if let Some(addrs) = self.cached(&name) {
+            return Box::pin(async move { Ok(Box::new(addrs.into_iter()) as Addrs) });
+        }
+
+        let ttl = self.ttl;
+        let ip_preference = self.ip_preference;
+        let cache = self.cache.clone();
+        Box::pin(async move {
+            // The system resolver blocks, so it runs on the blocking thread pool.
+            let host = name.clone();
+            let mut addrs = tokio::task::spawn_blocking(move || {
+                (host.as_str(), 0).to_socket_addrs().map(Vec::from_iter)
+            })
+            .await??;
+
+            ip_preference.apply(&mut addrs);
+            if addrs.is_empty() {
+                return Err(format!("no usable addresses for {}", name).into());
+            }
+            if let Some(ttl) = ttl {
+                let expires = Instant::now() + ttl;
+                lock(&cache).insert(name, (expires, addrs.clone()));
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ip_preference_orders_and_filters() {
+        let v4: SocketAddr = "127.0.0.1:443".parse().unwrap();
+        let v6: SocketAddr = "[::1]:443".parse().unwrap();
+
+        let mut addrs = vec![v6, v4];
+        IpPreference::PreferIpv4.apply(&mut addrs);
+        assert_eq!(addrs, vec![v4, v6]);
+
+        IpPreference::Ipv6Only.apply(&mut addrs);
+        assert_eq!(addrs, vec![v6]);
+    }
+
+    #[tokio::test]
+    async fn lookups_are_cached() {
+        let resolver = DnsOptions::new()
+            .set_cache_ttl(Duration::from_secs(60))
+            .set_ip_preference(IpPreference::Ipv4Only)
+            .resolver()
+            .unwrap();
+
+        let addrs: Vec<_> = resolver
+            .resolve("localhost".parse().unwrap())
+            .await
+            .unwrap()
+            .collect();
+        assert!(addrs.iter().all(SocketAddr::is_ipv4));
+        assert_eq!(resolver.cached("localhost"), Some(addrs));
+
+        let cache = resolver.cache.clone();
+        let _ = std::thread::spawn(move || {
+            let _guard = cache.lock().unwrap();
+            panic!("poison the cache");
+        })
+        .join();
+        assert!(resolver.cache.is_poisoned());
+        assert!(resolver.cached("localhost").is_some());
+    }
+}
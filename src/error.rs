@@ -1,11 +1,14 @@
 use std::{
     fmt::{self, Display},
     io,
+    time::Duration,
 };
 
 use reqwest::{self, header::InvalidHeaderValue, StatusCode};
 use thiserror::Error as ThisError;
 
+use crate::response::RateLimitInfo;
+
 /// Wrapper type which contains a failed request's status code and body.
 #[derive(Debug)]
 pub struct RequestNotSuccessful {
@@ -13,12 +16,33 @@ pub struct RequestNotSuccessful {
     pub status: StatusCode,
     /// Body returned by the HTTP call to the SendGrid API.
     pub body: String,
+    /// The rate limit reported in the response headers, if any.
+    pub rate_limit: Option<RateLimitInfo>,
+    /// How long the response asked to wait before trying again, if it did.
+    pub retry_after: Option<Duration>,
 }
 
 impl RequestNotSuccessful {
     /// Create a new unsuccessful request error.
     pub fn new(status: StatusCode, body: String) -> Self {
-        Self { status, body }
+        Self {
+            status,
+            body,
+            rate_limit: None,
+            retry_after: None,
+        }
+    }
+
+    /// Attach the rate limit reported alongside the error.
+    pub fn with_rate_limit(mut self, rate_limit: Option<RateLimitInfo>) -> Self {
+        self.rate_limit = rate_limit;
+        self
+    }
+
+    /// Attach the `Retry-After` delay reported alongside the error.
+    pub fn with_retry_after(mut self, retry_after: Option<Duration>) -> Self {
+        self.retry_after = retry_after;
+        self
     }
 }
 
@@ -53,6 +77,39 @@ pub enum SendgridError {
     #[error("could not UTF-8 decode this filename")]
     InvalidFilename,
 
+    /// A from name contained line breaks or other control characters.
+    #[error("the from name `{0}` contains control characters")]
+    InvalidFromName(String),
+
+    /// A CSV file could not be read.
+    #[cfg(feature = "csv")]
+    #[error("CSV Error: `{0}`")]
+    Csv(#[from] csv::Error),
+
+    /// A mail merge could not map its input to personalizations.
+    #[error("Merge Error: {0}")]
+    Merge(String),
+
+    /// An uploaded multipart field could not be read.
+    #[error("Multipart Error: {0}")]
+    Multipart(String),
+
+    /// A host URL could not be used to send requests to.
+    #[error("invalid host `{host}`: {reason}")]
+    InvalidHost {
+        /// The host as it was given.
+        host: String,
+        /// Why it was rejected.
+        reason: String,
+    },
+
+    /// A response body was longer than the limit it was read with.
+    #[error("the response body exceeds the limit of {limit} bytes")]
+    ResponseTooLarge {
+        /// The largest body allowed, in bytes.
+        limit: usize,
+    },
+
     /// A failure that indicates that the type was not a valid object.
     #[error("dynamic template data must be a serializable object")]
     InvalidTemplateValue,
@@ -61,10 +118,245 @@ pub enum SendgridError {
     #[error("the number of items exceeded the max capacity")]
     TooManyItems,
 
+    /// More unsubscribe groups were added to display than SendGrid allows.
+    #[error("{count} unsubscribe groups to display exceeds the limit of {limit}")]
+    TooManyGroupsToDisplay {
+        /// The number of groups.
+        count: usize,
+        /// The largest number of groups allowed.
+        limit: usize,
+    },
+
+    /// A message could not be queued because the receiving half of a message sink was dropped.
+    #[error("the message sink has been closed")]
+    SinkClosed,
+
+    /// An operation was stopped by its cancellation token before it completed.
+    #[error("the operation was cancelled")]
+    Cancelled,
+
+    /// The deadline of a send passed before a request could be made.
+    #[error("the deadline of the send has passed")]
+    DeadlineExceeded,
+
+    /// A payload posted by the Inbound Parse webhook could not be decoded.
+    #[error("Inbound Parse Error: `{0}`")]
+    InboundParse(String),
+
+    /// A reply address did not contain a validly signed conversation token.
+    #[error("the reply address does not contain a valid token")]
+    InvalidReplyToken,
+
+    /// Every API key of a rotating sender has been removed from the rotation.
+    #[error("no API key is available to send with")]
+    NoHealthyKeys,
+
+    /// The remaining email credits of a subuser are not enough for a send.
+    #[error("{needed} recipients exceed the {remaining} remaining credits")]
+    QuotaExceeded {
+        /// The number of recipients which were to be sent to.
+        needed: u64,
+        /// The number of credits left.
+        remaining: u64,
+    },
+
+    /// The client side rate limit of a sender is exhausted.
+    #[error("the rate limit is exhausted, retry in {retry_in:?}")]
+    RateLimited {
+        /// The time until a send is allowed again.
+        retry_in: std::time::Duration,
+    },
+
+    /// A job was still running when the polling policy gave up on it.
+    #[error("the job did not finish within {elapsed:?}")]
+    PollTimedOut {
+        /// The time spent polling.
+        elapsed: std::time::Duration,
+    },
+
+    /// Sending would exceed the daily budget configured for a key.
+    #[error("the daily budget of {limit} for `{key}` is exhausted")]
+    BudgetExceeded {
+        /// The budget key which is over its limit.
+        key: String,
+        /// The daily limit of the key.
+        limit: u64,
+        /// The time until the budget resets at midnight UTC.
+        resets_in: std::time::Duration,
+    },
+
+    /// A scheduled send time is outside of the window accepted by SendGrid.
+    #[error("invalid send_at: {0}")]
+    InvalidSendAt(String),
+
+    /// The message has no personalizations, so it has no recipients.
+    #[error("the message has no personalizations")]
+    NoPersonalizations,
+
+    /// The message has more personalizations than SendGrid accepts in one request.
+    #[error("the message has {count} personalizations which exceeds the limit of {limit}")]
+    TooManyPersonalizations {
+        /// The number of personalizations in the message.
+        count: usize,
+        /// The largest number of personalizations accepted.
+        limit: usize,
+    },
+
+    /// The message has no from address and the sender has no default from address.
+    #[error("the message has no from address")]
+    MissingFrom,
+
+    /// The personalization at this index has no to address, which SendGrid requires.
+    #[error("personalization {0} has no to address")]
+    MissingTo(usize),
+
+    /// The message has more recipients than SendGrid accepts in one request.
+    #[error("personalization {personalization} brings the recipient count to {count} which exceeds the limit of {limit}")]
+    TooManyRecipients {
+        /// The index of the personalization at which the limit is exceeded.
+        personalization: usize,
+        /// The number of recipients up to and including that personalization.
+        count: usize,
+        /// The largest number of recipients accepted.
+        limit: usize,
+    },
+
+    /// The message has neither content nor a template.
+    #[error("the message has neither content nor a template id")]
+    MissingContent,
+
+    /// The message has no subject and neither has the personalization at this index, nor is a
+    /// template used.
+    #[error("personalization {0} has no subject and the message has neither a subject nor a template id")]
+    MissingSubject(usize),
+
+    /// The personalization at this index has substitutions, which dynamic templates ignore.
+    #[error("personalization {0} uses substitutions with a dynamic template, use dynamic template data instead")]
+    SubstitutionsWithDynamicTemplate(usize),
+
+    /// The personalization at this index has dynamic template data, which legacy templates
+    /// ignore.
+    #[error("personalization {0} uses dynamic template data with a legacy template, use substitutions instead")]
+    DynamicDataWithLegacyTemplate(usize),
+
+    /// An inline attachment with this content id is not referenced by the HTML content.
+    #[error("the inline image `{0}` is not referenced as `cid:{0}` by the HTML content")]
+    UnreferencedInlineImage(String),
+
+    /// The serialized message is larger than SendGrid accepts.
+    #[error("the message is {size} bytes which exceeds the limit of {limit} bytes")]
+    PayloadTooLarge {
+        /// The size of the serialized message in bytes.
+        size: usize,
+        /// The largest size accepted in bytes.
+        limit: usize,
+    },
+
+    /// The custom args of a message do not match the schema registered on the sender.
+    #[error("invalid custom args: {0}")]
+    InvalidCustomArg(String),
+
+    /// A header is one of the [`RESERVED_HEADERS`](crate::headers::RESERVED_HEADERS) which
+    /// SendGrid does not allow to be set.
+    #[error("the `{0}` header is reserved and can not be set")]
+    ReservedHeader(String),
+
+    /// The global sender was used before it was installed with `init`.
+    #[error("the global sender has not been initialized")]
+    GlobalSenderNotInitialized,
+
+    /// The global sender was installed more than once.
+    #[error("the global sender has already been initialized")]
+    GlobalSenderAlreadyInitialized,
+
+    /// An Event Webhook OAuth token or client credential was not valid.
+    #[error("the webhook token or credentials are not valid")]
+    InvalidWebhookToken,
+
+    /// A failure reported by a user provided persistence store.
+    #[error("Store Error: `{0}`")]
+    Store(Box<dyn std::error::Error + Send + Sync>),
+
     /// SendGrid returned an unsuccessful HTTP status code.
     #[error("Request failed: `{0}`")]
     RequestNotSuccessful(#[from] RequestNotSuccessful),
 }
 
+impl SendgridError {
+    /// Whether sending again may succeed. This is the classification the crate's own retries
+    /// use, so retry loops written around the client should use it too.
+    ///
+    /// Retryable errors are:
+    ///
+    /// * failures to connect and timeouts, where the request may not have reached SendGrid
+    /// * `408 Request Timeout` and `429 Too Many Requests`
+    /// * `500 Internal Server Error`, `502 Bad Gateway`, `503 Service Unavailable` and
+    ///   `504 Gateway Timeout`
+    ///
+    /// Every other error, including other status codes and errors raised before a request is
+    /// made, would fail the same way again.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            SendgridError::ReqwestError(e) => e.is_connect() || e.is_timeout(),
+            SendgridError::RequestNotSuccessful(e) => matches!(
+                e.status,
+                StatusCode::REQUEST_TIMEOUT
+                    | StatusCode::TOO_MANY_REQUESTS
+                    | StatusCode::INTERNAL_SERVER_ERROR
+                    | StatusCode::BAD_GATEWAY
+                    | StatusCode::SERVICE_UNAVAILABLE
+                    | StatusCode::GATEWAY_TIMEOUT
+            ),
+            _ => false,
+        }
+    }
+
+    // The kind of an error which is not a response from SendGrid, reported by the otel and
+    // metrics features.
+    #[cfg(any(feature = "metrics", feature = "otel"))]
+    pub(crate) fn error_type(&self) -> &'static str {
+        match self {
+            SendgridError::ReqwestError(e) if e.is_timeout() => "timeout",
+            SendgridError::ReqwestError(e) if e.is_connect() => "connect",
+            SendgridError::ReqwestError(_) => "http",
+            SendgridError::Io(_) => "io",
+            _ => "_OTHER",
+        }
+    }
+}
+
 /// A type alias used throughout the library for concise error notation.
 pub type SendgridResult<T> = Result<T, SendgridError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(code: u16) -> SendgridError {
+        RequestNotSuccessful::new(StatusCode::from_u16(code).unwrap(), String::new()).into()
+    }
+
+    #[test]
+    fn retryable_status_codes() {
+        for code in [408, 429, 500, 502, 503, 504] {
+            assert!(status(code).is_retryable(), "{} should be retryable", code);
+        }
+        for code in [400, 401, 403, 404, 413, 501, 505] {
+            assert!(
+                !status(code).is_retryable(),
+                "{} should not be retryable",
+                code
+            );
+        }
+        assert!(!SendgridError::NoPersonalizations.is_retryable());
+    }
+
+    #[tokio::test]
+    async fn connection_failures_are_retryable() {
+        let error: SendgridError = reqwest::get("http://127.0.0.1:1").await.unwrap_err().into();
+        assert!(error.is_retryable());
+
+        let error: SendgridError = reqwest::get("not a url").await.unwrap_err().into();
+        assert!(!error.is_retryable());
+    }
+}
@@ -0,0 +1,57 @@
+//! A process wide default [`Sender`] for small applications and examples.
+//!
+//! The sender must be installed once with [`init`] before [`global`] can return it:
+//!
+//! ```no_run
+//! # async fn run() -> sendgrid::SendgridResult<()> {
+//! use sendgrid::v3::{Email, Message, Personalization, Sender};
+//!
+//! sendgrid::init(Sender::new(String::from("SG.my.api.key"), None))?;
+//!
+//! let message = Message::new(Email::new("from@example.com"))
+//!     .add_personalization(Personalization::new(Email::new("to@example.com")));
+//! sendgrid::global()?.send(&message).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::sync::OnceLock;
+
+use crate::error::{SendgridError, SendgridResult};
+use crate::v3::Sender;
+
+static GLOBAL: OnceLock<Sender> = OnceLock::new();
+
+/// Install the global sender. This fails if a sender has already been installed.
+pub fn init(sender: Sender) -> SendgridResult<()> {
+    GLOBAL
+        .set(sender)
+        .map_err(|_| SendgridError::GlobalSenderAlreadyInitialized)
+}
+
+/// The global sender installed with [`init`].
+pub fn global() -> SendgridResult<&'static Sender> {
+    GLOBAL
+        .get()
+        .ok_or(SendgridError::GlobalSenderNotInitialized)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn init_once() {
+        assert!(matches!(
+            global(),
+            Err(SendgridError::GlobalSenderNotInitialized)
+        ));
+
+        init(Sender::new(String::from("SG.first"), None)).unwrap();
+        assert!(global().is_ok());
+        assert!(matches!(
+            init(Sender::new(String::from("SG.second"), None)),
+            Err(SendgridError::GlobalSenderAlreadyInitialized)
+        ));
+    }
+}
@@ -0,0 +1,55 @@
+//! Email headers which SendGrid sets itself and rejects when they are supplied by the caller.
+
+use crate::error::{SendgridError, SendgridResult};
+
+/// Header names which may not be set on a message, in lowercase. Names are matched case
+/// insensitively.
+pub const RESERVED_HEADERS: &[&str] = &[
+    "x-sg-id",
+    "x-sg-eid",
+    "received",
+    "dkim-signature",
+    "content-type",
+    "content-transfer-encoding",
+    "to",
+    "from",
+    "subject",
+    "reply-to",
+    "cc",
+    "bcc",
+];
+
+/// Returns true if `name` is one of the [`RESERVED_HEADERS`].
+pub fn is_reserved(name: &str) -> bool {
+    RESERVED_HEADERS
+        .iter()
+        .any(|reserved| reserved.eq_ignore_ascii_case(name.trim()))
+}
+
+pub(crate) fn check_headers<'a, I>(names: I) -> SendgridResult<()>
+where
+    I: IntoIterator<Item = &'a String>,
+{
+    match names.into_iter().find(|name| is_reserved(name)) {
+        Some(name) => Err(SendgridError::ReservedHeader(name.clone())),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reserved_names_ignore_case() {
+        assert!(is_reserved("DKIM-Signature"));
+        assert!(is_reserved("content-type"));
+        assert!(!is_reserved("X-Campaign"));
+
+        let names = [String::from("X-Campaign"), String::from("Received")];
+        assert!(matches!(
+            check_headers(&names),
+            Err(SendgridError::ReservedHeader(name)) if name == "Received"
+        ));
+    }
+}
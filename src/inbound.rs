@@ -0,0 +1,453 @@
+//! Helpers for handling the payloads posted by the SendGrid Inbound Parse webhook.
+//!
+//! The webhook posts a `multipart/form-data` body. These helpers work on the fields once your web
+//! framework has split the body into parts, so they are independent of any particular server.
+//! See the [webhook docs](https://www.twilio.com/docs/sendgrid/for-developers/parsing-email/setting-up-the-inbound-parse-webhook)
+//! for the full list of fields.
+
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+
+use serde::Deserialize;
+
+use crate::error::{SendgridError, SendgridResult};
+
+/// Metadata describing one attachment, as found in the `attachment-info` field.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+pub struct AttachmentInfo {
+    /// The filename of the attachment.
+    pub filename: String,
+
+    /// The name of the attachment, usually the same as the filename.
+    #[serde(default)]
+    pub name: Option<String>,
+
+    /// The MIME type of the attachment.
+    #[serde(rename = "type")]
+    pub mime_type: String,
+
+    /// The content id of the attachment when it is referenced inline.
+    #[serde(rename = "content-id", default)]
+    pub content_id: Option<String>,
+
+    /// The character set of the attachment, if any.
+    #[serde(default)]
+    pub charset: Option<String>,
+}
+
+/// A decoded attachment from an inbound email.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InboundAttachment {
+    /// The form field the attachment was posted in, for example `attachment1`.
+    pub field: String,
+
+    /// The metadata of the attachment.
+    pub info: AttachmentInfo,
+
+    /// The raw bytes of the attachment.
+    pub content: Vec<u8>,
+}
+
+/// Parse the `attachment-info` field into a list of form field names and attachment metadata,
+/// ordered by the attachment number.
+pub fn parse_attachment_info(json: &str) -> SendgridResult<Vec<(String, AttachmentInfo)>> {
+    let map: HashMap<String, AttachmentInfo> = serde_json::from_str(json)?;
+    let mut infos: Vec<(String, AttachmentInfo)> = map.into_iter().collect();
+    infos.sort_by_key(|(field, _)| attachment_number(field));
+    Ok(infos)
+}
+
+/// Decode every attachment listed in the `attachment-info` field. `parts` maps the form field
+/// names of the posted file parts to their contents.
+pub fn decode_attachments(
+    attachment_info: &str,
+    parts: &mut HashMap<String, Vec<u8>>,
+) -> SendgridResult<Vec<InboundAttachment>> {
+    parse_attachment_info(attachment_info)?
+        .into_iter()
+        .map(|(field, info)| match parts.remove(&field) {
+            Some(content) => Ok(InboundAttachment {
+                field,
+                info,
+                content,
+            }),
+            None => Err(SendgridError::InboundParse(format!(
+                "missing attachment part `{}`",
+                field
+            ))),
+        })
+        .collect()
+}
+
+/// Stream a single attachment part from `reader` into `writer` without buffering it in memory,
+/// failing once more than `limit` bytes have been read. Returns the number of bytes copied.
+pub fn copy_attachment<R: Read, W: Write>(
+    reader: R,
+    writer: &mut W,
+    limit: u64,
+) -> SendgridResult<u64> {
+    // Read one byte past the limit so an attachment of exactly `limit` bytes is accepted.
+    let copied = io::copy(&mut reader.take(limit.saturating_add(1)), writer)?;
+    if copied > limit {
+        return Err(SendgridError::InboundParse(format!(
+            "attachment exceeds the limit of {} bytes",
+            limit
+        )));
+    }
+
+    Ok(copied)
+}
+
+/// The result of an SPF or DKIM check performed by SendGrid on an inbound email.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AuthResult {
+    /// The check passed.
+    Pass,
+    /// The check failed.
+    Fail,
+    /// The check failed but the domain does not ask for rejection.
+    SoftFail,
+    /// The domain makes no assertion.
+    Neutral,
+    /// No record was published.
+    None,
+    /// A temporary error occurred during the check.
+    TempError,
+    /// The published record could not be interpreted.
+    PermError,
+    /// A value this library does not know about.
+    Other(String),
+}
+
+impl AuthResult {
+    fn parse(value: &str) -> AuthResult {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "pass" => AuthResult::Pass,
+            "fail" => AuthResult::Fail,
+            "softfail" => AuthResult::SoftFail,
+            "neutral" => AuthResult::Neutral,
+            "none" => AuthResult::None,
+            "temperror" => AuthResult::TempError,
+            "permerror" => AuthResult::PermError,
+            other => AuthResult::Other(other.to_string()),
+        }
+    }
+}
+
+/// The fields of an email posted by the Inbound Parse webhook, keyed by form field name. Only the
+/// text fields are kept here; attachments are decoded with [`decode_attachments`].
+#[derive(Clone, Debug, Default)]
+pub struct InboundEmail {
+    fields: HashMap<String, String>,
+}
+
+impl InboundEmail {
+    /// Construct an inbound email from the text fields of the posted form.
+    pub fn from_fields(fields: HashMap<String, String>) -> InboundEmail {
+        InboundEmail { fields }
+    }
+
+    /// Returns the raw value of a form field.
+    pub fn field(&self, name: &str) -> Option<&str> {
+        self.fields.get(name).map(String::as_str)
+    }
+
+    /// The `From` field of the email.
+    pub fn from(&self) -> Option<&str> {
+        self.field("from")
+    }
+
+    /// The `To` field of the email.
+    pub fn to(&self) -> Option<&str> {
+        self.field("to")
+    }
+
+    /// The subject of the email.
+    pub fn subject(&self) -> Option<&str> {
+        self.field("subject")
+    }
+
+    /// The plain text body of the email.
+    pub fn text(&self) -> Option<&str> {
+        self.field("text")
+    }
+
+    /// The HTML body of the email.
+    pub fn html(&self) -> Option<&str> {
+        self.field("html")
+    }
+
+    /// Look up a header by name (case insensitive) in the raw `headers` field. Folded header
+    /// lines are joined.
+    pub fn header(&self, name: &str) -> Option<String> {
+        let headers = self.field("headers")?;
+        let mut value: Option<String> = None;
+        for line in headers.lines() {
+            if line.starts_with([' ', '\t']) {
+                if let Some(v) = value.as_mut() {
+                    v.push(' ');
+                    v.push_str(line.trim());
+                }
+                continue;
+            }
+            if value.is_some() {
+                break;
+            }
+            if let Some((key, rest)) = line.split_once(':') {
+                if key.trim().eq_ignore_ascii_case(name) {
+                    value = Some(rest.trim().to_string());
+                }
+            }
+        }
+        value
+    }
+
+    /// The `Message-ID` header of the email.
+    pub fn message_id(&self) -> Option<String> {
+        self.header("Message-ID")
+    }
+
+    /// The message ids listed in the `References` header of the email, oldest first.
+    pub fn references(&self) -> Vec<String> {
+        self.header("References")
+            .map(|refs| refs.split_whitespace().map(String::from).collect())
+            .unwrap_or_default()
+    }
+
+    /// The SPF result computed by SendGrid.
+    pub fn spf(&self) -> Option<AuthResult> {
+        self.field("SPF").map(AuthResult::parse)
+    }
+
+    /// The DKIM results computed by SendGrid, one per signing domain. The field is formatted as
+    /// `{@example.com : pass, @other.com : fail}`.
+    pub fn dkim(&self) -> Vec<(String, AuthResult)> {
+        let raw = match self.field("dkim") {
+            Some(raw) => raw.trim().trim_start_matches('{').trim_end_matches('}'),
+            None => return Vec::new(),
+        };
+        raw.split(',')
+            .filter_map(|entry| entry.split_once(':'))
+            .map(|(domain, result)| {
+                (
+                    domain.trim().trim_start_matches('@').to_string(),
+                    AuthResult::parse(result),
+                )
+            })
+            .collect()
+    }
+
+    /// The SpamAssassin score, only present when spam checking is enabled for the parse setting.
+    pub fn spam_score(&self) -> Option<f64> {
+        self.field("spam_score")?.trim().parse().ok()
+    }
+
+    /// The SpamAssassin report, only present when spam checking is enabled for the parse setting.
+    pub fn spam_report(&self) -> Option<&str> {
+        self.field("spam_report")
+    }
+}
+
+/// What to do with an inbound email according to a [`Policy`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Decision {
+    /// The email looks legitimate.
+    Accept,
+    /// The email is suspicious and should be held for review.
+    Quarantine,
+    /// The email should be dropped.
+    Reject,
+}
+
+/// A configurable spam policy for inbound email.
+#[must_use]
+#[derive(Clone, Debug, Default)]
+pub struct Policy {
+    quarantine_score: Option<f64>,
+    reject_score: Option<f64>,
+    require_spf: bool,
+    require_dkim: bool,
+}
+
+impl Policy {
+    /// Construct a policy which accepts every email.
+    pub fn new() -> Policy {
+        Policy::default()
+    }
+
+    /// A strict policy: SPF and DKIM must pass, emails scoring 5 or more are quarantined and
+    /// emails scoring 10 or more are rejected.
+    pub fn default_strict() -> Policy {
+        Policy::new()
+            .set_quarantine_score(5.0)
+            .set_reject_score(10.0)
+            .set_require_spf(true)
+            .set_require_dkim(true)
+    }
+
+    /// Quarantine emails with a spam score at or above this value.
+    pub fn set_quarantine_score(mut self, score: f64) -> Policy {
+        self.quarantine_score = Some(score);
+        self
+    }
+
+    /// Reject emails with a spam score at or above this value.
+    pub fn set_reject_score(mut self, score: f64) -> Policy {
+        self.reject_score = Some(score);
+        self
+    }
+
+    /// Quarantine emails whose SPF check did not pass and reject emails where it failed.
+    pub fn set_require_spf(mut self, require: bool) -> Policy {
+        self.require_spf = require;
+        self
+    }
+
+    /// Quarantine emails without at least one passing DKIM signature.
+    pub fn set_require_dkim(mut self, require: bool) -> Policy {
+        self.require_dkim = require;
+        self
+    }
+
+    /// Decide what to do with an inbound email. The most severe applicable decision wins.
+    pub fn evaluate(&self, email: &InboundEmail) -> Decision {
+        let mut decision = Decision::Accept;
+
+        if let Some(score) = email.spam_score() {
+            if self.reject_score.is_some_and(|limit| score >= limit) {
+                return Decision::Reject;
+            }
+            if self.quarantine_score.is_some_and(|limit| score >= limit) {
+                decision = Decision::Quarantine;
+            }
+        }
+
+        if self.require_spf {
+            match email.spf() {
+                Some(AuthResult::Pass) => {}
+                Some(AuthResult::Fail) => return Decision::Reject,
+                _ => decision = Decision::Quarantine,
+            }
+        }
+
+        if self.require_dkim
+            && !email
+                .dkim()
+                .iter()
+                .any(|(_, result)| *result == AuthResult::Pass)
+        {
+            decision = Decision::Quarantine;
+        }
+
+        decision
+    }
+}
+
+// Attachment fields are named `attachment1`, `attachment2`, ... so sort them numerically.
+fn attachment_number(field: &str) -> (u64, String) {
+    let digits = field.trim_start_matches(|c: char| !c.is_ascii_digit());
+    (digits.parse().unwrap_or(u64::MAX), field.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INFO: &str = r#"{
+        "attachment10": {"filename": "j.txt", "name": "j.txt", "type": "text/plain"},
+        "attachment2": {"filename": "logo.png", "type": "image/png", "content-id": "ii_123"}
+    }"#;
+
+    #[test]
+    fn attachment_info_is_ordered() {
+        let infos = parse_attachment_info(INFO).unwrap();
+        assert_eq!(infos[0].0, "attachment2");
+        assert_eq!(infos[0].1.content_id.as_deref(), Some("ii_123"));
+        assert_eq!(infos[1].0, "attachment10");
+        assert_eq!(infos[1].1.mime_type, "text/plain");
+    }
+
+    #[test]
+    fn decode_attachment_parts() {
+        let mut parts = HashMap::new();
+        parts.insert(String::from("attachment2"), b"png".to_vec());
+        parts.insert(String::from("attachment10"), b"text".to_vec());
+
+        let attachments = decode_attachments(INFO, &mut parts).unwrap();
+        assert_eq!(attachments.len(), 2);
+        assert_eq!(attachments[0].info.filename, "logo.png");
+        assert_eq!(attachments[0].content, b"png");
+        assert_eq!(attachments[1].content, b"text");
+
+        assert!(matches!(
+            decode_attachments(INFO, &mut HashMap::new()),
+            Err(SendgridError::InboundParse(_))
+        ));
+    }
+
+    fn email(fields: &[(&str, &str)]) -> InboundEmail {
+        InboundEmail::from_fields(
+            fields
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn inbound_email_accessors() {
+        let email = email(&[
+            ("SPF", "softfail"),
+            ("dkim", "{@example.com : pass, @other.com : fail}"),
+            ("spam_score", "1.5"),
+            (
+                "headers",
+                "Message-ID: <a@b>\nReferences: <x@y>\n <z@y>\nSubject: hi",
+            ),
+        ]);
+        assert_eq!(email.spf(), Some(AuthResult::SoftFail));
+        assert_eq!(
+            email.dkim(),
+            vec![
+                (String::from("example.com"), AuthResult::Pass),
+                (String::from("other.com"), AuthResult::Fail)
+            ]
+        );
+        assert_eq!(email.spam_score(), Some(1.5));
+        assert_eq!(email.header("message-id").as_deref(), Some("<a@b>"));
+        assert_eq!(email.header("References").as_deref(), Some("<x@y> <z@y>"));
+        assert_eq!(email.header("X-Missing"), None);
+        assert_eq!(email.message_id().as_deref(), Some("<a@b>"));
+        assert_eq!(email.references(), vec!["<x@y>", "<z@y>"]);
+    }
+
+    #[test]
+    fn strict_policy() {
+        let policy = Policy::default_strict();
+        let good = [("SPF", "pass"), ("dkim", "{@example.com : pass}")];
+        assert_eq!(policy.evaluate(&email(&good)), Decision::Accept);
+
+        let mut spammy = good.to_vec();
+        spammy.push(("spam_score", "6"));
+        assert_eq!(policy.evaluate(&email(&spammy)), Decision::Quarantine);
+        spammy.push(("spam_score", "12"));
+        assert_eq!(policy.evaluate(&email(&spammy)), Decision::Reject);
+
+        assert_eq!(
+            policy.evaluate(&email(&[("SPF", "fail")])),
+            Decision::Reject
+        );
+        assert_eq!(
+            policy.evaluate(&email(&[("SPF", "pass")])),
+            Decision::Quarantine
+        );
+        assert_eq!(Policy::new().evaluate(&email(&[])), Decision::Accept);
+    }
+
+    #[test]
+    fn copy_attachment_limit() {
+        let mut out = Vec::new();
+        assert_eq!(copy_attachment(&b"12345"[..], &mut out, 5).unwrap(), 5);
+        assert!(copy_attachment(&b"123456"[..], &mut Vec::new(), 5).is_err());
+    }
+}
@@ -0,0 +1,166 @@
+//! Hooks around the requests of a sender or client.
+//!
+//! A [`SendInterceptor`] registered with
+//! [`Sender::add_interceptor`](crate::v3::Sender::add_interceptor) or
+//! [`SGClient::add_interceptor`](crate::SGClient::add_interceptor) sees every mail send request
+//! before it is sent and every response received, for both the async and the blocking client. It
+//! can add headers, for example for an authenticating proxy, log requests for an audit trail or
+//! record metrics. Interceptors run in the order they were added, once per attempt of a retried
+//! send.
+
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+use reqwest::header::HeaderMap;
+use reqwest::StatusCode;
+
+use crate::error::SendgridResult;
+
+/// A request about to be sent.
+#[derive(Debug)]
+pub struct InterceptedRequest<'a> {
+    /// The URL the request is sent to.
+    pub url: &'a str,
+
+    /// The headers of the request, which may be changed.
+    pub headers: &'a mut HeaderMap,
+
+    /// The body of the request.
+    pub body: &'a str,
+}
+
+/// A response which has been received. Its body has not been read yet.
+#[derive(Debug)]
+pub struct InterceptedResponse<'a> {
+    /// The URL the request was sent to.
+    pub url: &'a str,
+
+    /// The status code of the response.
+    pub status: StatusCode,
+
+    /// The headers of the response.
+    pub headers: &'a HeaderMap,
+
+    /// The time from sending the request until the response headers were received.
+    pub elapsed: Duration,
+}
+
+/// Observes and changes the requests of a sender or client.
+pub trait SendInterceptor: Send + Sync {
+    /// Called before a request is sent. Returning an error fails the send with that error
+    /// without sending the request.
+    fn before_send(&self, request: &mut InterceptedRequest<'_>) -> SendgridResult<()> {
+        let _ = request;
+        Ok(())
+    }
+
+    /// Called when a response is received, whether its status is successful or not. It is not
+    /// called when no response was received, for example because the connection failed.
+    fn after_send(&self, response: &InterceptedResponse<'_>) {
+        let _ = response;
+    }
+}
+
+// The interceptors registered on a sender or client.
+#[derive(Clone, Default)]
+pub(crate) struct Interceptors(Vec<Arc<dyn SendInterceptor>>);
+
+impl fmt::Debug for Interceptors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Interceptors({})", self.0.len())
+    }
+}
+
+impl Interceptors {
+    pub(crate) fn add<I: SendInterceptor + 'static>(&mut self, interceptor: I) {
+        self.0.push(Arc::new(interceptor));
+    }
+
+    pub(crate) fn before_send(
+        &self,
+        url: &str,
+        headers: &mut HeaderMap,
+        body: &str,
+    ) -> SendgridResult<()> {
+        for interceptor in &self.0 {
+            interceptor.before_send(&mut InterceptedRequest { url, headers, body })?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn after_send(
+        &self,
+        url: &str,
+        status: StatusCode,
+        headers: &HeaderMap,
+        elapsed: Duration,
+    ) {
+        let response = InterceptedResponse {
+            url,
+            status,
+            headers,
+            elapsed,
+        };
+        for interceptor in &self.0 {
+            interceptor.after_send(&response);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use reqwest::header::{HeaderValue, AUTHORIZATION};
+
+    use super::*;
+    use crate::error::SendgridError;
+    use crate::v3::dev_server::CaptureServer;
+    use crate::v3::{Email, Message, Personalization, Sender};
+
+    #[derive(Default)]
+    struct Gateway {
+        statuses: Mutex<Vec<StatusCode>>,
+    }
+
+    impl SendInterceptor for Arc<Gateway> {
+        fn before_send(&self, request: &mut InterceptedRequest<'_>) -> SendgridResult<()> {
+            if request.body.contains("blocked@example.com") {
+                return Err(SendgridError::Merge(String::from("blocked")));
+            }
+            request
+                .headers
+                .insert(AUTHORIZATION, HeaderValue::from_static("Bearer SG.gateway"));
+            Ok(())
+        }
+
+        fn after_send(&self, response: &InterceptedResponse<'_>) {
+            self.statuses.lock().unwrap().push(response.status);
+        }
+    }
+
+    #[tokio::test]
+    async fn interceptors_see_requests_and_responses() {
+        let server = CaptureServer::start().await.unwrap();
+        let gateway = Arc::new(Gateway::default());
+        // Without the header injected by the interceptor the capture server rejects the key.
+        let mut sender = Sender::new(String::new(), None);
+        sender.set_host(server.url()).unwrap();
+        sender.add_interceptor(gateway.clone());
+
+        let message = |to: &str| {
+            Message::new(Email::new("from@example.com"))
+                .add_personalization(Personalization::new(Email::new(to)))
+                .set_text("hi")
+        };
+        sender.send(&message("to@example.com")).await.unwrap();
+        assert!(matches!(
+            sender.send(&message("blocked@example.com")).await,
+            Err(SendgridError::Merge(_))
+        ));
+
+        assert_eq!(gateway.statuses.lock().unwrap()[..], [StatusCode::ACCEPTED]);
+        assert_eq!(server.captured().len(), 1);
+    }
+}
@@ -22,9 +22,69 @@
 //! The projects has the following feature flags:
 //! * `rustls`: this feature flag switches the default SSL provider in the operating system (usually
 //!   OpenSSL) with RusTLS, which is a TLS implementation in Rust.
+//! * `rustls-webpki-roots`: this feature flag uses RusTLS with the Mozilla root certificates
+//!   bundled into the binary instead of the system trust store, so mail can be sent from
+//!   containers without one such as `scratch` images. Clients passed in by the caller are used as
+//!   they are.
 //! * `native-tls`: enabled by default, this feature flag enabled the default SSL provider in the
 //!   operating system (usually OpenSSL).
 //! * `blocking`: this feature flag allows you to construct a synchronous `SGClient`.
+//! * `accounts`: this feature flag enables `accounts`, a client for the account provisioning API
+//!   used by Twilio SendGrid resellers.
+//! * `actix`: this feature flag enables `v3::multipart`, which builds attachments from the file
+//!   fields of actix-web multipart uploads.
+//! * `async-fs`: this feature flag adds `v3::Attachment::from_path_async` and
+//!   `v3::Attachment::from_async_read`, which read attachments with tokio.
+//! * `axum`: this feature flag enables `v3::multipart`, which builds attachments from the file
+//!   fields of axum multipart uploads.
+//! * `cancellation`: this feature flag adds methods taking a tokio-util `CancellationToken` to
+//!   the consumer, outbox relay and watchdog and to chunked sends, which stop them early and
+//!   report what was done.
+//! * `chrono`: this feature flag enables `v3::send_at`, which schedules personalizations with a
+//!   `chrono::DateTime<Utc>` and checks the 72 hour scheduling window.
+//! * `concurrency-limit`: this feature flag adds `v3::Sender::set_max_concurrency`, which caps the
+//!   requests in flight across all clones of a sender.
+//! * `consumer`: this feature flag enables `v3::consumer`, an adapter that sends serialized
+//!   messages consumed from a queue such as Kafka or NATS. It also enables `retry`.
+//! * `csv`: this feature flag enables `v3::merge`, which builds one personalization per row of a
+//!   CSV file for mail merges.
+//! * `dev-server`: this feature flag enables `v3::dev_server`, an in-memory server implementing
+//!   the mail send endpoint which captures messages for local development and tests.
+//! * `global`: this feature flag enables `init` and `global`, which install and return a process
+//!   wide default `v3::Sender`.
+//! * `layout`: this feature flag enables `v3::layout`, which renders headings, paragraphs and
+//!   buttons into HTML that works in Outlook and other email clients.
+//! * `metrics`: this feature flag reports counters of sends and failed sends by status, and
+//!   histograms of request duration and payload size, for every send of a `v3::Sender` or
+//!   `SGClient` through the `metrics` crate.
+//! * `mime`: this feature flag adds conversions from `mime::Mime` to `v3::Mime`.
+//! * `mime-guess`: this feature flag makes `v3::Attachment::from_path` infer the mime type of an
+//!   attachment from its file extension.
+//! * `otel`: this feature flag reports an OpenTelemetry span and metrics following the messaging
+//!   semantic conventions for every send, using the global tracer and meter providers.
+//! * `outbox`: this feature flag enables `v3::outbox`, a relay for the transactional outbox
+//!   pattern on top of your own database. It also enables `retry`.
+//! * `poll`: this feature flag enables `poll`, which checks the status of long running jobs such
+//!   as imports and exports with a growing interval until they finish.
+//! * `rate-limit`: this feature flag enables `v3::rate_limit`, a client side limiter for the rate
+//!   of mail sends which can wrap sends or be set on a `Sender`.
+//! * `rate-limit-redis`: this feature flag adds `v3::rate_limit::RedisRateLimitStore`, which
+//!   shares one rate limit between processes through Redis.
+//! * `reply-token`: this feature flag enables `reply_token`, which signs conversation tokens into
+//!   plus-addressed reply addresses.
+//! * `retry`: this feature flag enables `v3::retry`, which retries throttled and failed sends with
+//!   exponential backoff.
+//! * `send-and-forget`: this feature flag adds `v3::Sender::send_and_forget`, which sends a
+//!   message on a spawned task and reports the outcome only to the hooks of the sender.
+//! * `stats`: this feature flag enables `stats`, a client for the global email statistics and
+//!   subuser credits APIs, and `v3::quota`, which checks the credits of a subuser before a bulk
+//!   send.
+//! * `tz`: this feature flag enables `v3::schedule`, which computes `send_at` timestamps from a
+//!   recipient's local time zone.
+//! * `watchdog`: this feature flag enables `watchdog`, a background task which calls back when the
+//!   bounce or spam report rate of the account exceeds a threshold.
+//! * `webhook-oauth`: this feature flag enables `webhook`, which issues and verifies the OAuth
+//!   bearer tokens sent by the Event Webhook.
 //!
 //! ## Build Dependencies
 //! This library utilises [reqwest](https://crates.io/crates/reqwest). Follow the instructions on
@@ -54,12 +114,43 @@
 //! ## License
 //! MIT
 
+#[cfg(feature = "accounts")]
+pub mod accounts;
+mod api;
 mod client;
+pub mod dns;
 /// Contains the error type used in this library.
 pub mod error;
+#[cfg(feature = "global")]
+mod global;
+pub mod headers;
+pub mod inbound;
+pub mod interceptor;
 mod mail;
+#[cfg(feature = "poll")]
+pub mod poll;
+pub mod region;
+#[cfg(feature = "reply-token")]
+pub mod reply_token;
+pub mod response;
+#[cfg(feature = "metrics")]
+mod send_metrics;
+pub mod sso;
+#[cfg(feature = "stats")]
+pub mod stats;
+#[cfg(feature = "otel")]
+mod telemetry;
+mod tls;
 pub mod v3;
+#[cfg(feature = "watchdog")]
+pub mod watchdog;
+#[cfg(feature = "webhook-oauth")]
+pub mod webhook;
 
 pub use client::SGClient;
 pub use error::{SendgridError, SendgridResult};
+#[cfg(feature = "global")]
+pub use global::{global, init};
 pub use mail::{Destination, Mail};
+pub use region::Region;
+pub use response::{RateLimitInfo, ResponseExt, SendResponse};
@@ -3,6 +3,9 @@ use std::fs::File;
 use std::io::Read;
 use std::path::Path;
 
+use serde::Serialize;
+use serde_json::Value;
+
 use crate::{SendgridError, SendgridResult};
 
 macro_rules! add_field {
@@ -44,7 +47,7 @@ macro_rules! add_field {
 }
 
 /// A destination is a combination of an email address and a name to whom emails can be sent.
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct Destination<'a> {
     /// The email address to which the email will be sent.
     pub address: &'a str,
@@ -61,45 +64,59 @@ impl<'a> From<(&'a str, &'a str)> for Destination<'a> {
 
 /// This is a representation of a valid SendGrid message. It has support for
 /// all of the fields in the V2 API.
-#[derive(Debug, Default)]
+///
+/// Mail serializes to a structured JSON representation, empty fields are omitted.
+#[derive(Debug, Default, Serialize)]
 pub struct Mail<'a> {
     /// The list of people to whom the email will be sent.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
     pub to: Vec<Destination<'a>>,
 
     /// The list of people that are CC'd in this email.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
     pub cc: Vec<&'a str>,
 
     /// The list of people that are BCC'd in this email.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
     pub bcc: Vec<&'a str>,
 
     /// The email address that will be used as sender.
+    #[serde(skip_serializing_if = "str::is_empty")]
     pub from: &'a str,
 
     /// The subject field of the email.
+    #[serde(skip_serializing_if = "str::is_empty")]
     pub subject: &'a str,
 
     /// When the client is sufficiently modern (this should almost always be the case), the email is
     /// displayed as HTML.
+    #[serde(skip_serializing_if = "str::is_empty")]
     pub html: &'a str,
 
     /// This is used as a fallback when either the client is too old or the HTML field was not
     /// provided.
+    #[serde(skip_serializing_if = "str::is_empty")]
     pub text: &'a str,
 
     /// This is the name that will be used as sender.
+    #[serde(skip_serializing_if = "str::is_empty")]
     pub from_name: &'a str,
 
     /// This is the email address that is used as a reply to field.
+    #[serde(skip_serializing_if = "str::is_empty")]
     pub reply_to: &'a str,
 
     /// The date added to the header of this email. For example `Thu, 21 Dec 2000 16:01:07 +0200`.
+    #[serde(skip_serializing_if = "str::is_empty")]
     pub date: &'a str,
 
     /// The attachments of this email, smaller than 7MB.
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
     pub attachments: HashMap<String, String>,
 
     /// Content IDs of the files to be used as inline images. Content IDs should match the content
     /// IDS used in the HTML markup.
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
     pub content: HashMap<String, &'a str>,
 
     /// A collection of key/value pairs in JSON format. This is specifically for non-SendGrid custom
@@ -109,9 +126,11 @@ pub struct Mail<'a> {
     /// ```json
     /// {"X-Accept-Language": "en", "X-Mailer": "MyApp"}
     /// ```
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
     pub headers: HashMap<String, &'a str>,
 
     /// The `X-SMTPAPI` header that is used.
+    #[serde(skip_serializing_if = "str::is_empty")]
     pub x_smtpapi: &'a str,
 }
 
@@ -216,8 +235,32 @@ impl<'a> Mail<'a> {
         add_header <- headers: &'a str
     );
 
-    /// Used internally for string encoding. Not needed for message building.
+    /// Serialize the message to JSON, for example to log or persist it. This is not the form
+    /// encoding sent to the V2 API.
+    pub fn to_json(&self) -> SendgridResult<String> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    /// Serialize the message to a JSON value.
+    pub fn to_json_value(&self) -> SendgridResult<Value> {
+        Ok(serde_json::to_value(self)?)
+    }
+
+    /// Used internally for string encoding. Not needed for message building. Names containing
+    /// line breaks or control characters other than tabs are rejected. Other names, including
+    /// names with emoji, are sent as UTF-8.
+    pub(crate) fn checked_from_name(&self) -> SendgridResult<&'a str> {
+        let name = self.from_name;
+        if name.chars().any(|c| c.is_control() && c != '\t') {
+            return Err(SendgridError::InvalidFromName(name.to_string()));
+        }
+        Ok(name)
+    }
+
+    /// Used internally for string encoding. Not needed for message building. Fails if a header
+    /// is reserved.
     pub(crate) fn make_header_string(&mut self) -> SendgridResult<String> {
+        crate::headers::check_headers(self.headers.keys())?;
         let string = serde_json::to_string(&self.headers)?;
         Ok(string)
     }
@@ -229,3 +272,36 @@ impl<'a> Mail<'a> {
         add_x_smtpapi = x_smtpapi: &'a str
     );
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn to_json_omits_empty_fields() {
+        let mail = Mail::new()
+            .add_to(("to@example.com", "To").into())
+            .add_from("from@example.com")
+            .add_subject("Hello")
+            .add_text("Hi");
+        let expected = r#"{"to":[{"address":"to@example.com","name":"To"}],"from":"from@example.com","subject":"Hello","text":"Hi"}"#;
+        assert_eq!(mail.to_json().unwrap(), expected);
+    }
+
+    #[test]
+    fn from_names_are_checked() {
+        let mail = Mail::new().add_from_name("Plain Name");
+        assert_eq!(mail.checked_from_name().unwrap(), "Plain Name");
+
+        let mail = Mail::new().add_from_name("Café\t🚀");
+        assert_eq!(mail.checked_from_name().unwrap(), "Café\t🚀");
+
+        for name in ["Evil\r\nBcc: victim@example.com", "Evil\nName", "Bell\u{7}"] {
+            let mail = Mail::new().add_from_name(name);
+            assert!(matches!(
+                mail.checked_from_name(),
+                Err(SendgridError::InvalidFromName(_))
+            ));
+        }
+    }
+}
@@ -0,0 +1,288 @@
+//! Poll long running jobs until they finish.
+//!
+//! Endpoints which start a job, such as contact imports and exports, return a job id whose status
+//! has to be checked until the job is done. [`poll_job`] runs such a check with a growing
+//! interval until it reports a result or the [`PollPolicy`] gives up, so clients of these
+//! endpoints do not each need their own loop.
+//!
+//! ```no_run
+//! # async fn run() -> sendgrid::SendgridResult<()> {
+//! use sendgrid::poll::{poll_job, JobStatus, PollPolicy};
+//!
+//! # async fn export_status(id: &str) -> sendgrid::SendgridResult<Option<String>> { Ok(None) }
+//! let url = poll_job(
+//!     || async {
+//!         Ok(match export_status("job-id").await? {
+//!             Some(url) => JobStatus::Done(url),
+//!             None => JobStatus::Pending { progress: None },
+//!         })
+//!     },
+//!     &PollPolicy::new().set_on_progress(|progress| println!("{:?}", progress)),
+//! )
+//! .await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::fmt;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::error::{SendgridError, SendgridResult};
+
+type ProgressCallback = Arc<dyn Fn(&JobProgress) + Send + Sync>;
+
+/// The status of a job reported by one check.
+#[derive(Clone, Debug, PartialEq)]
+pub enum JobStatus<T> {
+    /// The job is still running.
+    Pending {
+        /// The completed fraction of the job between 0 and 1, if the endpoint reports it.
+        progress: Option<f64>,
+    },
+
+    /// The job has finished with this result.
+    Done(T),
+}
+
+/// The state of a job which is still running, passed to the progress callback after every check.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct JobProgress {
+    /// The number of checks made so far.
+    pub checks: u32,
+
+    /// The time since polling started.
+    pub elapsed: Duration,
+
+    /// The completed fraction reported by the last check, if any.
+    pub progress: Option<f64>,
+
+    /// The time until the next check.
+    pub next_check_in: Duration,
+}
+
+/// How often a job is checked and for how long.
+#[must_use]
+#[derive(Clone)]
+pub struct PollPolicy {
+    initial_interval: Duration,
+    max_interval: Duration,
+    multiplier: f64,
+    max_duration: Option<Duration>,
+    on_progress: Option<ProgressCallback>,
+}
+
+impl Default for PollPolicy {
+    fn default() -> Self {
+        PollPolicy {
+            initial_interval: Duration::from_secs(1),
+            max_interval: Duration::from_secs(30),
+            multiplier: 2.0,
+            max_duration: Some(Duration::from_secs(30 * 60)),
+            on_progress: None,
+        }
+    }
+}
+
+impl fmt::Debug for PollPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PollPolicy")
+            .field("initial_interval", &self.initial_interval)
+            .field("max_interval", &self.max_interval)
+            .field("multiplier", &self.multiplier)
+            .field("max_duration", &self.max_duration)
+            .field("on_progress", &self.on_progress.is_some())
+            .finish()
+    }
+}
+
+impl PollPolicy {
+    /// Construct a policy checking after 1 second, doubling the interval up to 30 seconds and
+    /// giving up after 30 minutes.
+    pub fn new() -> PollPolicy {
+        PollPolicy::default()
+    }
+
+    /// Set the interval before the second check. The first check is made immediately.
+    pub fn set_initial_interval(mut self, interval: Duration) -> PollPolicy {
+        self.initial_interval = interval;
+        self
+    }
+
+    /// Set the longest interval between two checks.
+    pub fn set_max_interval(mut self, interval: Duration) -> PollPolicy {
+        self.max_interval = interval;
+        self
+    }
+
+    /// Set the factor the interval grows by after every check. Factors below 1, infinity and NaN
+    /// are treated as 1.
+    pub fn set_multiplier(mut self, multiplier: f64) -> PollPolicy {
+        self.multiplier = if multiplier.is_finite() {
+            multiplier.max(1.0)
+        } else {
+            1.0
+        };
+        self
+    }
+
+    /// Set how long to poll before failing with [`SendgridError::PollTimedOut`], or `None` to
+    /// poll until the job finishes.
+    pub fn set_max_duration(mut self, max_duration: Option<Duration>) -> PollPolicy {
+        self.max_duration = max_duration;
+        self
+    }
+
+    /// Call `on_progress` after every check which finds the job still running.
+    pub fn set_on_progress<F>(mut self, on_progress: F) -> PollPolicy
+    where
+        F: Fn(&JobProgress) + Send + Sync + 'static,
+    {
+        self.on_progress = Some(Arc::new(on_progress));
+        self
+    }
+}
+
+/// Call `check` until it reports the job as done and return its result. Errors returned by
+/// `check` end polling immediately.
+pub async fn poll_job<T, F, Fut>(mut check: F, policy: &PollPolicy) -> SendgridResult<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = SendgridResult<JobStatus<T>>>,
+{
+    let start = Instant::now();
+    let mut interval = policy.initial_interval;
+    let mut checks = 0;
+    loop {
+        checks += 1;
+        let progress = match check().await? {
+            JobStatus::Done(result) => return Ok(result),
+            JobStatus::Pending { progress } => progress,
+        };
+
+        let elapsed = start.elapsed();
+        let mut wait = interval.min(policy.max_interval);
+        if let Some(max_duration) = policy.max_duration {
+            if elapsed >= max_duration {
+                return Err(SendgridError::PollTimedOut { elapsed });
+            }
+            wait = wait.min(max_duration - elapsed);
+        }
+        if let Some(on_progress) = &policy.on_progress {
+            on_progress(&JobProgress {
+                checks,
+                elapsed,
+                progress,
+                next_check_in: wait,
+            });
+        }
+
+        tokio::time::sleep(wait).await;
+        interval = Duration::try_from_secs_f64(interval.as_secs_f64() * policy.multiplier)
+            .map_or(policy.max_interval, |next| next.min(policy.max_interval));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn jobs_are_polled_until_done() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let recorded = seen.clone();
+        let policy = PollPolicy::new()
+            .set_initial_interval(Duration::from_millis(1))
+            .set_on_progress(move |progress| {
+                recorded.lock().unwrap().push((
+                    progress.checks,
+                    progress.progress,
+                    progress.next_check_in,
+                ))
+            });
+
+        let mut checks = 0;
+        let result = poll_job(
+            || {
+                checks += 1;
+                let status = match checks {
+                    3 => JobStatus::Done("https://example.com/export.csv"),
+                    n => JobStatus::Pending {
+                        progress: Some(f64::from(n) / 3.0),
+                    },
+                };
+                async move { Ok(status) }
+            },
+            &policy,
+        )
+        .await;
+
+        assert_eq!(result.unwrap(), "https://example.com/export.csv");
+        assert_eq!(
+            seen.lock().unwrap()[..],
+            [
+                (1, Some(1.0 / 3.0), Duration::from_millis(1)),
+                (2, Some(2.0 / 3.0), Duration::from_millis(2)),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn polling_gives_up() {
+        let policy = PollPolicy::new()
+            .set_initial_interval(Duration::from_millis(5))
+            .set_max_duration(Some(Duration::from_millis(20)));
+        let result: SendgridResult<()> = poll_job(
+            || async { Ok(JobStatus::Pending { progress: None }) },
+            &policy,
+        )
+        .await;
+        assert!(matches!(result, Err(SendgridError::PollTimedOut { .. })));
+
+        let failing: SendgridResult<()> =
+            poll_job(|| async { Err(SendgridError::Cancelled) }, &policy).await;
+        assert!(matches!(failing, Err(SendgridError::Cancelled)));
+    }
+
+    #[tokio::test]
+    async fn intervals_stay_below_the_maximum() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let recorded = seen.clone();
+        let policy = PollPolicy::new()
+            .set_initial_interval(Duration::from_millis(1))
+            .set_max_interval(Duration::from_millis(2))
+            .set_multiplier(1e300)
+            .set_on_progress(move |progress| recorded.lock().unwrap().push(progress.next_check_in));
+
+        let mut checks = 0;
+        let result = poll_job(
+            || {
+                checks += 1;
+                let status = match checks {
+                    4 => JobStatus::Done(()),
+                    _ => JobStatus::Pending { progress: None },
+                };
+                async move { Ok(status) }
+            },
+            &policy,
+        )
+        .await;
+
+        assert!(result.is_ok());
+        assert_eq!(
+            seen.lock().unwrap()[..],
+            [
+                Duration::from_millis(1),
+                Duration::from_millis(2),
+                Duration::from_millis(2),
+            ]
+        );
+        assert_eq!(
+            PollPolicy::new().set_multiplier(f64::INFINITY).multiplier,
+            1.0
+        );
+    }
+}
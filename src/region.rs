@@ -0,0 +1,60 @@
+//! The regional API endpoints of SendGrid.
+//!
+//! Accounts with EU data residency must send through the EU endpoint. Select it with
+//! [`Sender::with_region`](crate::v3::Sender::with_region),
+//! [`SenderBuilder::set_region`](crate::v3::SenderBuilder::set_region) or
+//! [`SGClient::set_region`](crate::SGClient::set_region) instead of setting the host by hand.
+
+/// A region whose API endpoint is used.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Region {
+    /// The global endpoint, `api.sendgrid.com`.
+    #[default]
+    Global,
+
+    /// The EU endpoint, `api.eu.sendgrid.com`, which keeps data in the EU.
+    Eu,
+}
+
+impl Region {
+    /// The base URL of the API in this region.
+    pub fn base_url(&self) -> &'static str {
+        match self {
+            Region::Global => "https://api.sendgrid.com",
+            Region::Eu => "https://api.eu.sendgrid.com",
+        }
+    }
+
+    /// The URL of the v3 mail send endpoint in this region.
+    pub fn mail_send_url(&self) -> String {
+        format!("{}/v3/mail/send", self.base_url())
+    }
+
+    /// The URL of the v2 mail send endpoint in this region.
+    pub fn v2_mail_send_url(&self) -> String {
+        format!("{}/api/mail.send.json?", self.base_url())
+    }
+
+    // The host name of the API in this region.
+    pub(crate) fn host(&self) -> &'static str {
+        self.base_url().trim_start_matches("https://")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn regional_urls() {
+        assert_eq!(
+            Region::default().mail_send_url(),
+            "https://api.sendgrid.com/v3/mail/send"
+        );
+        assert_eq!(
+            Region::Eu.v2_mail_send_url(),
+            "https://api.eu.sendgrid.com/api/mail.send.json?"
+        );
+        assert_eq!(Region::Eu.host(), "api.eu.sendgrid.com");
+    }
+}
@@ -0,0 +1,113 @@
+//! Signed conversation tokens carried in plus-addressed reply addresses.
+//!
+//! A token such as a ticket id is encoded into the reply-to address of an outbound message, for
+//! example `support+<token>.<signature>@example.com`. When the recipient replies, the address the
+//! reply was sent to is posted by the Inbound Parse webhook and [`ReplyTokenSigner::decode`]
+//! recovers the token after verifying its HMAC-SHA256 signature.
+
+use data_encoding::BASE32_NOPAD;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::error::{SendgridError, SendgridResult};
+
+type HmacSha256 = Hmac<Sha256>;
+
+// The signature is truncated to keep addresses short while still being infeasible to forge.
+const SIGNATURE_LEN: usize = 10;
+
+/// Encodes and verifies signed tokens in reply addresses.
+#[derive(Clone)]
+pub struct ReplyTokenSigner {
+    key: Vec<u8>,
+}
+
+impl ReplyTokenSigner {
+    /// Construct a new signer with a secret key.
+    pub fn new<K: Into<Vec<u8>>>(key: K) -> ReplyTokenSigner {
+        ReplyTokenSigner { key: key.into() }
+    }
+
+    /// Build a reply address of the form `local+token.signature@domain`. The token is base32
+    /// encoded in lowercase since some mail servers do not preserve the case of the local part.
+    pub fn encode(&self, local: &str, domain: &str, token: &str) -> String {
+        let payload = BASE32_NOPAD.encode(token.as_bytes()).to_ascii_lowercase();
+        let signature = BASE32_NOPAD
+            .encode(&self.sign(payload.as_bytes())[..SIGNATURE_LEN])
+            .to_ascii_lowercase();
+        format!("{}+{}.{}@{}", local, payload, signature, domain)
+    }
+
+    /// Recover the token from a reply address, verifying its signature. Display names and angle
+    /// brackets such as `Support <support+...@example.com>` are accepted.
+    pub fn decode(&self, address: &str) -> SendgridResult<String> {
+        let address = match (address.rfind('<'), address.rfind('>')) {
+            (Some(start), Some(end)) if start < end => &address[start + 1..end],
+            _ => address.trim(),
+        };
+        let local = address
+            .rsplit_once('@')
+            .map(|(local, _)| local)
+            .ok_or(SendgridError::InvalidReplyToken)?;
+        let (payload, signature) = local
+            .split_once('+')
+            .and_then(|(_, tagged)| tagged.rsplit_once('.'))
+            .ok_or(SendgridError::InvalidReplyToken)?;
+
+        let payload = payload.to_ascii_lowercase();
+        let signature = BASE32_NOPAD
+            .decode(signature.to_ascii_uppercase().as_bytes())
+            .map_err(|_| SendgridError::InvalidReplyToken)?;
+        if signature.len() != SIGNATURE_LEN {
+            return Err(SendgridError::InvalidReplyToken);
+        }
+        let mut mac = self.mac();
+        mac.update(payload.as_bytes());
+        mac.verify_truncated_left(&signature)
+            .map_err(|_| SendgridError::InvalidReplyToken)?;
+
+        let token = BASE32_NOPAD
+            .decode(payload.to_ascii_uppercase().as_bytes())
+            .map_err(|_| SendgridError::InvalidReplyToken)?;
+        String::from_utf8(token).map_err(|_| SendgridError::InvalidReplyToken)
+    }
+
+    fn mac(&self) -> HmacSha256 {
+        // HMAC accepts keys of any length so this can not fail.
+        HmacSha256::new_from_slice(&self.key).unwrap()
+    }
+
+    fn sign(&self, data: &[u8]) -> Vec<u8> {
+        let mut mac = self.mac();
+        mac.update(data);
+        mac.finalize().into_bytes().to_vec()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let signer = ReplyTokenSigner::new("secret");
+        let address = signer.encode("support", "example.com", "ticket-42");
+        assert!(address.starts_with("support+"));
+        assert!(address.ends_with("@example.com"));
+        assert_eq!(signer.decode(&address).unwrap(), "ticket-42");
+
+        let display = format!("Support <{}>", address.to_ascii_uppercase());
+        assert_eq!(signer.decode(&display).unwrap(), "ticket-42");
+    }
+
+    #[test]
+    fn rejects_forged_tokens() {
+        let signer = ReplyTokenSigner::new("secret");
+        let address = signer.encode("support", "example.com", "ticket-42");
+
+        assert!(ReplyTokenSigner::new("other").decode(&address).is_err());
+        let tampered = address.replacen("support+", "support+a", 1);
+        assert!(signer.decode(&tampered).is_err());
+        assert!(signer.decode("support@example.com").is_err());
+    }
+}
@@ -0,0 +1,276 @@
+//! Information SendGrid returns in response headers.
+//!
+//! Every response of the API carries the rate limit of the endpoint that was called. It is read
+//! from successful responses with [`ResponseExt::rate_limit`] and from failed requests with the
+//! [`rate_limit`](crate::error::RequestNotSuccessful::rate_limit) field of the error. Throttled
+//! requests also carry a `Retry-After` header, read the same way.
+//!
+//! [`SendResponse`] wraps the response of a send with typed accessors for this information, while
+//! still giving access to the underlying response and reading its body within a size limit.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::StatusCode;
+use serde::de::DeserializeOwned;
+
+use crate::error::{SendgridError, SendgridResult};
+use crate::v3::archive::MESSAGE_ID_HEADER;
+
+/// The default limit of the body read by [`SendResponse::bytes`] and [`SendResponse::json`].
+pub const DEFAULT_BODY_LIMIT: usize = 1024 * 1024;
+
+/// The header carrying the number of requests allowed per rate limit window.
+pub const RATE_LIMIT_LIMIT_HEADER: &str = "X-RateLimit-Limit";
+
+/// The header carrying the number of requests left in the current window.
+pub const RATE_LIMIT_REMAINING_HEADER: &str = "X-RateLimit-Remaining";
+
+/// The header carrying the Unix time in seconds at which the current window ends.
+pub const RATE_LIMIT_RESET_HEADER: &str = "X-RateLimit-Reset";
+
+/// The rate limit of an endpoint as reported in response headers.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RateLimitInfo {
+    /// The number of requests allowed per window.
+    pub limit: Option<u64>,
+
+    /// The number of requests left in the current window.
+    pub remaining: Option<u64>,
+
+    /// The Unix time in seconds at which the current window ends.
+    pub reset: Option<u64>,
+}
+
+impl RateLimitInfo {
+    /// Read the rate limit headers, returning `None` when the response carries none of them.
+    pub fn from_headers(headers: &HeaderMap) -> Option<RateLimitInfo> {
+        let get = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.trim().parse().ok())
+        };
+        let info = RateLimitInfo {
+            limit: get(RATE_LIMIT_LIMIT_HEADER),
+            remaining: get(RATE_LIMIT_REMAINING_HEADER),
+            reset: get(RATE_LIMIT_RESET_HEADER),
+        };
+        (info != RateLimitInfo::default()).then_some(info)
+    }
+
+    /// The time until the current window ends, or zero if it already has.
+    pub fn reset_in(&self) -> Option<Duration> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        self.reset
+            .map(|reset| Duration::from_secs(reset.saturating_sub(now)))
+    }
+}
+
+/// Read the `Retry-After` header. Only the number of seconds form is understood, which is the
+/// form SendGrid uses.
+pub fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    headers
+        .get(RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse().ok())
+        .map(Duration::from_secs)
+}
+
+/// Reads SendGrid specific information from responses.
+pub trait ResponseExt {
+    /// The rate limit reported by the response, if any.
+    fn rate_limit(&self) -> Option<RateLimitInfo>;
+
+    /// How long the response asks to wait before trying again, see [`retry_after`].
+    fn retry_after(&self) -> Option<Duration>;
+}
+
+impl ResponseExt for reqwest::Response {
+    fn rate_limit(&self) -> Option<RateLimitInfo> {
+        RateLimitInfo::from_headers(self.headers())
+    }
+
+    fn retry_after(&self) -> Option<Duration> {
+        retry_after(self.headers())
+    }
+}
+
+#[cfg(feature = "blocking")]
+impl ResponseExt for reqwest::blocking::Response {
+    fn rate_limit(&self) -> Option<RateLimitInfo> {
+        RateLimitInfo::from_headers(self.headers())
+    }
+
+    fn retry_after(&self) -> Option<Duration> {
+        retry_after(self.headers())
+    }
+}
+
+/// The response of a successful send.
+///
+/// ```no_run
+/// # async fn run(sender: sendgrid::v3::Sender, message: sendgrid::v3::Message) -> sendgrid::SendgridResult<()> {
+/// use sendgrid::response::SendResponse;
+///
+/// let resp = SendResponse::from(sender.send(&message).await?);
+/// println!("accepted as {:?}", resp.message_id());
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct SendResponse {
+    inner: reqwest::Response,
+    body_limit: usize,
+}
+
+impl From<reqwest::Response> for SendResponse {
+    fn from(inner: reqwest::Response) -> Self {
+        SendResponse {
+            inner,
+            body_limit: DEFAULT_BODY_LIMIT,
+        }
+    }
+}
+
+impl SendResponse {
+    /// Set the largest body [`SendResponse::bytes`] and [`SendResponse::json`] read. Defaults to
+    /// [`DEFAULT_BODY_LIMIT`].
+    pub fn set_body_limit(mut self, limit: usize) -> SendResponse {
+        self.body_limit = limit;
+        self
+    }
+
+    /// The status code of the response.
+    pub fn status(&self) -> StatusCode {
+        self.inner.status()
+    }
+
+    /// The headers of the response, including any added by proxies.
+    pub fn headers(&self) -> &HeaderMap {
+        self.inner.headers()
+    }
+
+    /// The id SendGrid assigned to the message, from the `X-Message-Id` header.
+    pub fn message_id(&self) -> Option<&str> {
+        self.headers()
+            .get(MESSAGE_ID_HEADER)
+            .and_then(|value| value.to_str().ok())
+    }
+
+    /// The rate limit reported by the response, if any.
+    pub fn rate_limit(&self) -> Option<RateLimitInfo> {
+        self.inner.rate_limit()
+    }
+
+    /// The underlying response.
+    pub fn into_inner(self) -> reqwest::Response {
+        self.inner
+    }
+
+    /// Read the body, failing with [`SendgridError::ResponseTooLarge`] if it is longer than the
+    /// body limit.
+    pub async fn bytes(mut self) -> SendgridResult<Vec<u8>> {
+        let limit = self.body_limit;
+        let too_large = || SendgridError::ResponseTooLarge { limit };
+        if self
+            .inner
+            .content_length()
+            .is_some_and(|length| length > limit as u64)
+        {
+            return Err(too_large());
+        }
+
+        let mut body = Vec::new();
+        while let Some(chunk) = self.inner.chunk().await? {
+            if body.len() + chunk.len() > limit {
+                return Err(too_large());
+            }
+            body.extend_from_slice(&chunk);
+        }
+        Ok(body)
+    }
+
+    /// Read the body within the body limit and deserialize it from JSON.
+    pub async fn json<T: DeserializeOwned>(self) -> SendgridResult<T> {
+        Ok(serde_json::from_slice(&self.bytes().await?)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::header::HeaderValue;
+
+    use super::*;
+
+    #[test]
+    fn rate_limit_headers_are_parsed() {
+        assert_eq!(RateLimitInfo::from_headers(&HeaderMap::new()), None);
+
+        let mut headers = HeaderMap::new();
+        headers.insert(RATE_LIMIT_LIMIT_HEADER, HeaderValue::from_static("600"));
+        headers.insert(RATE_LIMIT_REMAINING_HEADER, HeaderValue::from_static("599"));
+        headers.insert(RATE_LIMIT_RESET_HEADER, HeaderValue::from_static("1"));
+        let info = RateLimitInfo::from_headers(&headers).unwrap();
+        assert_eq!(
+            info,
+            RateLimitInfo {
+                limit: Some(600),
+                remaining: Some(599),
+                reset: Some(1),
+            }
+        );
+        assert_eq!(info.reset_in(), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn retry_after_seconds_are_parsed() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(retry_after(&headers), None);
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("30"));
+        assert_eq!(retry_after(&headers), Some(Duration::from_secs(30)));
+        headers.insert(
+            RETRY_AFTER,
+            HeaderValue::from_static("Wed, 21 Oct 2015 07:28:00 GMT"),
+        );
+        assert_eq!(retry_after(&headers), None);
+    }
+
+    #[tokio::test]
+    async fn bodies_are_read_within_the_limit() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut request = [0; 1024];
+                let _ = stream.read(&mut request).await.unwrap();
+                let body = r#"{"status":"queued"}"#;
+                let head = format!(
+                    "HTTP/1.1 202 Accepted\r\nX-Message-Id: abc123\r\nContent-Length: {}\r\n\r\n",
+                    body.len()
+                );
+                stream.write_all(head.as_bytes()).await.unwrap();
+                stream.write_all(body.as_bytes()).await.unwrap();
+            }
+        });
+
+        let get = || async { SendResponse::from(reqwest::get(&url).await.unwrap()) };
+        let resp = get().await;
+        assert_eq!(resp.status(), StatusCode::ACCEPTED);
+        assert_eq!(resp.message_id(), Some("abc123"));
+        let body: serde_json::Value = resp.json().await.unwrap();
+        assert_eq!(body["status"], "queued");
+
+        assert!(matches!(
+            get().await.set_body_limit(4).bytes().await,
+            Err(SendgridError::ResponseTooLarge { limit: 4 })
+        ));
+        assert_eq!(get().await.into_inner().status(), StatusCode::ACCEPTED);
+    }
+}
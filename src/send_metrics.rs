@@ -0,0 +1,172 @@
+// Metrics for mail sends reported through the `metrics` facade. Nothing is recorded until the
+// application installs a recorder, such as the Prometheus exporter.
+//
+// * `sendgrid_sends_total`: a counter of sends, labelled with the `client` making them.
+// * `sendgrid_send_failures_total`: a counter of failed sends, additionally labelled with the
+//   `status` code of the response or, when there was none, the kind of error.
+// * `sendgrid_request_duration_seconds`: a histogram of the time taken by a send, including
+//   retries.
+// * `sendgrid_payload_size_bytes`: a histogram of the size of the request bodies.
+
+use std::sync::Once;
+use std::time::Instant;
+
+use metrics::{counter, describe_counter, describe_histogram, histogram, Unit};
+use reqwest::StatusCode;
+
+use crate::error::SendgridError;
+
+const SENDS: &str = "sendgrid_sends_total";
+const FAILURES: &str = "sendgrid_send_failures_total";
+const DURATION: &str = "sendgrid_request_duration_seconds";
+const PAYLOAD_SIZE: &str = "sendgrid_payload_size_bytes";
+
+fn describe() {
+    static DESCRIBE: Once = Once::new();
+    DESCRIBE.call_once(|| {
+        describe_counter!(SENDS, Unit::Count, "Number of mail sends.");
+        describe_counter!(FAILURES, Unit::Count, "Number of failed mail sends.");
+        describe_histogram!(DURATION, Unit::Seconds, "Duration of mail sends.");
+        describe_histogram!(
+            PAYLOAD_SIZE,
+            Unit::Bytes,
+            "Size of mail send request bodies."
+        );
+    });
+}
+
+/// Measures a single send from start to finish.
+pub(crate) struct SendMetrics {
+    client: &'static str,
+    start: Instant,
+}
+
+impl SendMetrics {
+    /// Start measuring a send by `client`, either `"v3"` for a `v3::Sender` or `"v2"` for an
+    /// `SGClient`, with a request body of `payload_size` bytes.
+    pub(crate) fn start(client: &'static str, payload_size: usize) -> SendMetrics {
+        describe();
+        counter!(SENDS, "client" => client).increment(1);
+        histogram!(PAYLOAD_SIZE, "client" => client).record(payload_size as f64);
+        SendMetrics {
+            client,
+            start: Instant::now(),
+        }
+    }
+
+    /// Record the duration and, if the send failed, the failure.
+    pub(crate) fn finish(self, result: Result<StatusCode, &SendgridError>) {
+        histogram!(DURATION, "client" => self.client).record(self.start.elapsed().as_secs_f64());
+        if let Err(e) = result {
+            let status = match e {
+                SendgridError::RequestNotSuccessful(e) => e.status.as_str().to_string(),
+                e => e.error_type().to_string(),
+            };
+            counter!(FAILURES, "client" => self.client, "status" => status).increment(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    use metrics::{
+        Counter, CounterFn, Gauge, Histogram, HistogramFn, Key, KeyName, Metadata, Recorder,
+        SharedString,
+    };
+
+    use crate::v3::dev_server::CaptureServer;
+    use crate::v3::{Email, Message, Personalization, Sender};
+
+    #[derive(Default)]
+    struct Value(AtomicU64);
+
+    impl CounterFn for Value {
+        fn increment(&self, value: u64) {
+            self.0.fetch_add(value, Ordering::Relaxed);
+        }
+
+        fn absolute(&self, value: u64) {
+            self.0.store(value, Ordering::Relaxed);
+        }
+    }
+
+    impl HistogramFn for Value {
+        fn record(&self, _: f64) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    // Counts increments of counters and records into histograms, keyed by name and labels.
+    #[derive(Default)]
+    struct TestRecorder(Mutex<HashMap<String, Arc<Value>>>);
+
+    impl TestRecorder {
+        fn value(&self, key: &Key) -> Arc<Value> {
+            let mut labels: Vec<_> = key
+                .labels()
+                .map(|l| format!("{}={}", l.key(), l.value()))
+                .collect();
+            labels.sort();
+            let name = format!("{}{{{}}}", key.name(), labels.join(","));
+            self.0.lock().unwrap().entry(name).or_default().clone()
+        }
+
+        fn get(&self, name: &str) -> u64 {
+            self.0
+                .lock()
+                .unwrap()
+                .get(name)
+                .map_or(0, |v| v.0.load(Ordering::Relaxed))
+        }
+    }
+
+    impl Recorder for TestRecorder {
+        fn describe_counter(&self, _: KeyName, _: Option<metrics::Unit>, _: SharedString) {}
+        fn describe_gauge(&self, _: KeyName, _: Option<metrics::Unit>, _: SharedString) {}
+        fn describe_histogram(&self, _: KeyName, _: Option<metrics::Unit>, _: SharedString) {}
+
+        fn register_counter(&self, key: &Key, _: &Metadata<'_>) -> Counter {
+            Counter::from_arc(self.value(key))
+        }
+
+        fn register_gauge(&self, _: &Key, _: &Metadata<'_>) -> Gauge {
+            Gauge::noop()
+        }
+
+        fn register_histogram(&self, key: &Key, _: &Metadata<'_>) -> Histogram {
+            Histogram::from_arc(self.value(key))
+        }
+    }
+
+    #[tokio::test]
+    async fn sends_are_measured() {
+        let recorder = TestRecorder::default();
+        let _guard = metrics::set_default_local_recorder(&recorder);
+
+        let server = CaptureServer::start().await.unwrap();
+        let message = Message::new(Email::new("from@example.com"))
+            .add_personalization(Personalization::new(Email::new("to@example.com")));
+        let mut sender = Sender::new(String::from("SG.dev"), None);
+        sender.set_host(server.url()).unwrap();
+        sender.send(&message).await.unwrap();
+
+        let mut sender = Sender::new(String::new(), None);
+        sender.set_host(server.url()).unwrap();
+        assert!(sender.send(&message).await.is_err());
+
+        assert_eq!(recorder.get("sendgrid_sends_total{client=v3}"), 2);
+        assert_eq!(
+            recorder.get("sendgrid_send_failures_total{client=v3,status=401}"),
+            1
+        );
+        assert_eq!(
+            recorder.get("sendgrid_request_duration_seconds{client=v3}"),
+            2
+        );
+        assert_eq!(recorder.get("sendgrid_payload_size_bytes{client=v3}"), 2);
+    }
+}
@@ -0,0 +1,209 @@
+//! Wraps the single sign-on (SSO) endpoints available to SendGrid enterprise accounts.
+//!
+//! See the [SSO API docs](https://www.twilio.com/docs/sendgrid/api-reference/single-sign-on-settings)
+//! for details on the individual fields.
+
+use reqwest::{Client, Method};
+use serde::{Deserialize, Serialize};
+
+use crate::api::ApiClient;
+use crate::error::SendgridResult;
+
+/// An SSO integration with an identity provider.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct SsoIntegration {
+    /// The id assigned by SendGrid.
+    #[serde(default, skip_serializing)]
+    pub id: Option<String>,
+
+    /// The name of the integration.
+    pub name: String,
+
+    /// Whether the integration is enabled.
+    pub enabled: bool,
+
+    /// The IdP's SAML POST endpoint.
+    pub signin_url: String,
+
+    /// The URL users are redirected to after signing out.
+    pub signout_url: String,
+
+    /// The entity id of the IdP.
+    pub entity_id: String,
+
+    /// Whether the integration has been completed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub completed_integration: Option<bool>,
+
+    /// The single sign-on URL to configure in the IdP.
+    #[serde(default, skip_serializing)]
+    pub single_signon_url: Option<String>,
+
+    /// The audience URL to configure in the IdP.
+    #[serde(default, skip_serializing)]
+    pub audience_url: Option<String>,
+
+    /// A Unix timestamp of the last update.
+    #[serde(default, skip_serializing)]
+    pub last_updated: Option<u64>,
+}
+
+/// A certificate used to verify the SAML responses of an integration.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct SsoCertificate {
+    /// The id assigned by SendGrid.
+    #[serde(default, skip_serializing)]
+    pub id: Option<u64>,
+
+    /// The PEM encoded public certificate of the IdP.
+    pub public_certificate: String,
+
+    /// Whether the certificate is enabled.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+
+    /// The id of the integration the certificate belongs to. The API spells this field
+    /// `intergration_id` in responses.
+    #[serde(alias = "intergration_id")]
+    pub integration_id: String,
+
+    /// A Unix timestamp from which the certificate is valid.
+    #[serde(default, skip_serializing)]
+    pub not_before: Option<u64>,
+
+    /// A Unix timestamp after which the certificate is no longer valid.
+    #[serde(default, skip_serializing)]
+    pub not_after: Option<u64>,
+}
+
+/// A client for the SSO integration and certificate endpoints.
+#[derive(Clone, Debug)]
+pub struct SsoClient {
+    api: ApiClient,
+}
+
+impl SsoClient {
+    /// Construct a new SSO client. The `client` parameter is optional and `None` uses the
+    /// default.
+    pub fn new(api_key: String, client: Option<Client>) -> SsoClient {
+        SsoClient {
+            api: ApiClient::new(api_key, client),
+        }
+    }
+
+    /// Sets the base URL to use for the API, for example `https://api.sendgrid.com`.
+    pub fn set_host<S: Into<String>>(&mut self, host: S) {
+        self.api.set_base_url(host.into());
+    }
+
+    /// List all SSO integrations.
+    pub async fn list_integrations(&self) -> SendgridResult<Vec<SsoIntegration>> {
+        self.api.get("/v3/sso/integrations").await
+    }
+
+    /// Get an SSO integration by id.
+    pub async fn get_integration(&self, id: &str) -> SendgridResult<SsoIntegration> {
+        self.api.get(&format!("/v3/sso/integrations/{}", id)).await
+    }
+
+    /// Create an SSO integration.
+    pub async fn create_integration(
+        &self,
+        integration: &SsoIntegration,
+    ) -> SendgridResult<SsoIntegration> {
+        self.api
+            .send_json(Method::POST, "/v3/sso/integrations", integration)
+            .await
+    }
+
+    /// Update an SSO integration.
+    pub async fn update_integration(
+        &self,
+        id: &str,
+        integration: &SsoIntegration,
+    ) -> SendgridResult<SsoIntegration> {
+        self.api
+            .send_json(
+                Method::PATCH,
+                &format!("/v3/sso/integrations/{}", id),
+                integration,
+            )
+            .await
+    }
+
+    /// Delete an SSO integration.
+    pub async fn delete_integration(&self, id: &str) -> SendgridResult<()> {
+        self.api
+            .delete(&format!("/v3/sso/integrations/{}", id))
+            .await
+    }
+
+    /// List the certificates of an SSO integration.
+    pub async fn list_certificates(
+        &self,
+        integration_id: &str,
+    ) -> SendgridResult<Vec<SsoCertificate>> {
+        self.api
+            .get(&format!(
+                "/v3/sso/integrations/{}/certificates",
+                integration_id
+            ))
+            .await
+    }
+
+    /// Get an SSO certificate by id.
+    pub async fn get_certificate(&self, id: u64) -> SendgridResult<SsoCertificate> {
+        self.api.get(&format!("/v3/sso/certificates/{}", id)).await
+    }
+
+    /// Create an SSO certificate.
+    pub async fn create_certificate(
+        &self,
+        certificate: &SsoCertificate,
+    ) -> SendgridResult<SsoCertificate> {
+        self.api
+            .send_json(Method::POST, "/v3/sso/certificates", certificate)
+            .await
+    }
+
+    /// Update an SSO certificate.
+    pub async fn update_certificate(
+        &self,
+        id: u64,
+        certificate: &SsoCertificate,
+    ) -> SendgridResult<SsoCertificate> {
+        self.api
+            .send_json(
+                Method::PATCH,
+                &format!("/v3/sso/certificates/{}", id),
+                certificate,
+            )
+            .await
+    }
+
+    /// Delete an SSO certificate.
+    pub async fn delete_certificate(&self, id: u64) -> SendgridResult<()> {
+        self.api
+            .delete(&format!("/v3/sso/certificates/{}", id))
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn certificate_accepts_misspelled_integration_id() {
+        let json = r#"{"public_certificate":"PEM","id":7,"not_before":1,"not_after":2,"intergration_id":"abc"}"#;
+        let cert: SsoCertificate = serde_json::from_str(json).unwrap();
+        assert_eq!(cert.id, Some(7));
+        assert_eq!(cert.integration_id, "abc");
+
+        let body = serde_json::to_string(&cert).unwrap();
+        assert_eq!(
+            body,
+            r#"{"public_certificate":"PEM","integration_id":"abc"}"#
+        );
+    }
+}
@@ -0,0 +1,287 @@
+//! Wraps the global email statistics API and the subuser credits API.
+//!
+//! See the [stats docs](https://www.twilio.com/docs/sendgrid/api-reference/stats/retrieve-global-email-statistics)
+//! for details on the individual metrics.
+
+use reqwest::{Client, Method};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::api::ApiClient;
+use crate::error::SendgridResult;
+
+/// The email metrics of one period.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(default)]
+pub struct Metrics {
+    /// The number of emails requested to be sent.
+    pub requests: u64,
+
+    /// The number of emails delivered to the receiving server.
+    pub delivered: u64,
+
+    /// The number of emails that bounced.
+    pub bounces: u64,
+
+    /// The number of emails dropped because their address bounced before.
+    pub bounce_drops: u64,
+
+    /// The number of emails blocked by the receiving server.
+    pub blocks: u64,
+
+    /// The number of emails deferred by the receiving server.
+    pub deferred: u64,
+
+    /// The number of emails marked as spam by their recipients.
+    pub spam_reports: u64,
+
+    /// The number of emails dropped because their recipient reported spam before.
+    pub spam_report_drops: u64,
+
+    /// The number of emails dropped because their address is invalid.
+    pub invalid_emails: u64,
+
+    /// The number of emails opened.
+    pub opens: u64,
+
+    /// The number of distinct recipients who opened an email.
+    pub unique_opens: u64,
+
+    /// The number of links clicked.
+    pub clicks: u64,
+
+    /// The number of distinct recipients who clicked a link.
+    pub unique_clicks: u64,
+
+    /// The number of recipients who unsubscribed.
+    pub unsubscribes: u64,
+
+    /// The number of emails dropped because their recipient unsubscribed before.
+    pub unsubscribe_drops: u64,
+
+    /// The number of emails processed by SendGrid.
+    pub processed: u64,
+}
+
+impl Metrics {
+    /// The share of requested emails which bounced, between 0 and 1.
+    pub fn bounce_rate(&self) -> f64 {
+        ratio(self.bounces, self.requests)
+    }
+
+    /// The share of delivered emails which were reported as spam, between 0 and 1.
+    pub fn spam_report_rate(&self) -> f64 {
+        ratio(self.spam_reports, self.delivered)
+    }
+}
+
+impl std::ops::Add for Metrics {
+    type Output = Metrics;
+
+    fn add(self, other: Metrics) -> Metrics {
+        Metrics {
+            requests: self.requests + other.requests,
+            delivered: self.delivered + other.delivered,
+            bounces: self.bounces + other.bounces,
+            bounce_drops: self.bounce_drops + other.bounce_drops,
+            blocks: self.blocks + other.blocks,
+            deferred: self.deferred + other.deferred,
+            spam_reports: self.spam_reports + other.spam_reports,
+            spam_report_drops: self.spam_report_drops + other.spam_report_drops,
+            invalid_emails: self.invalid_emails + other.invalid_emails,
+            opens: self.opens + other.opens,
+            unique_opens: self.unique_opens + other.unique_opens,
+            clicks: self.clicks + other.clicks,
+            unique_clicks: self.unique_clicks + other.unique_clicks,
+            unsubscribes: self.unsubscribes + other.unsubscribes,
+            unsubscribe_drops: self.unsubscribe_drops + other.unsubscribe_drops,
+            processed: self.processed + other.processed,
+        }
+    }
+}
+
+fn ratio(part: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        part as f64 / total as f64
+    }
+}
+
+/// The metrics of one stats entry.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct StatsEntry {
+    /// The metrics of the entry.
+    #[serde(default)]
+    pub metrics: Metrics,
+}
+
+/// The statistics of one day, week or month.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct PeriodStats {
+    /// The first day of the period as `YYYY-MM-DD`.
+    pub date: String,
+
+    /// The entries of the period. Global stats have a single entry.
+    #[serde(default)]
+    pub stats: Vec<StatsEntry>,
+}
+
+impl PeriodStats {
+    /// The metrics of every entry of the period added up.
+    pub fn metrics(&self) -> Metrics {
+        self.stats
+            .iter()
+            .fold(Metrics::default(), |total, entry| total + entry.metrics)
+    }
+}
+
+/// How the email credits of a subuser are limited.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CreditType {
+    /// The subuser can send without limit.
+    Unlimited,
+
+    /// The credits reset every period.
+    Recurring,
+
+    /// The credits are used up once and do not reset.
+    Nonrecurring,
+}
+
+/// The email credits of a subuser.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct Credits {
+    /// How the credits are limited.
+    #[serde(rename = "type")]
+    pub credit_type: CreditType,
+
+    /// How often recurring credits reset, for example `daily`.
+    #[serde(default)]
+    pub reset_frequency: Option<String>,
+
+    /// The number of credits left.
+    #[serde(default)]
+    pub remain: Option<u64>,
+
+    /// The number of credits per period.
+    #[serde(default)]
+    pub total: Option<u64>,
+
+    /// The number of credits used.
+    #[serde(default)]
+    pub used: Option<u64>,
+}
+
+impl Credits {
+    /// The number of emails the subuser can still send, or `None` when it is unlimited.
+    pub fn remaining(&self) -> Option<u64> {
+        match self.credit_type {
+            CreditType::Unlimited => None,
+            _ => Some(self.remain.unwrap_or(0)),
+        }
+    }
+}
+
+/// A client for the stats endpoints.
+#[derive(Clone, Debug)]
+pub struct StatsClient {
+    api: ApiClient,
+}
+
+impl StatsClient {
+    /// Construct a new stats client. The `client` parameter is optional and `None` uses the
+    /// default.
+    pub fn new(api_key: String, client: Option<Client>) -> StatsClient {
+        StatsClient {
+            api: ApiClient::new(api_key, client),
+        }
+    }
+
+    /// Sets the base URL to use for the API, for example `https://api.sendgrid.com`.
+    pub fn set_host<S: Into<String>>(&mut self, host: S) {
+        self.api.set_base_url(host.into());
+    }
+
+    /// Get the global statistics per day from `start_date` until `end_date`, or until today if
+    /// it is `None`. Dates are formatted as `YYYY-MM-DD`.
+    pub async fn global_stats(
+        &self,
+        start_date: &str,
+        end_date: Option<&str>,
+    ) -> SendgridResult<Vec<PeriodStats>> {
+        let mut query = vec![("start_date", start_date)];
+        if let Some(end_date) = end_date {
+            query.push(("end_date", end_date));
+        }
+
+        let request = self.api.request(Method::GET, "/v3/stats")?.query(&query);
+        let resp = self.api.execute(request).await?;
+        Ok(serde_json::from_slice(&resp.bytes().await?)?)
+    }
+
+    /// Get the email credits of a subuser.
+    pub async fn subuser_credits(&self, subuser: &str) -> SendgridResult<Credits> {
+        self.api.get(&credits_path(subuser)).await
+    }
+
+    /// Get the number of emails a subuser can still send, or `None` when it is unlimited. Use
+    /// with [`Sender::send_chunked_within_quota`](crate::v3::Sender::send_chunked_within_quota)
+    /// to check the quota before a bulk send.
+    pub async fn quota_check(&self, subuser: &str) -> SendgridResult<Option<u64>> {
+        Ok(self.subuser_credits(subuser).await?.remaining())
+    }
+}
+
+// The path of the credits of `subuser`, with the name percent-encoded as a single path segment.
+fn credits_path(subuser: &str) -> String {
+    let mut url = Url::parse("https://api.sendgrid.com/v3/subusers").expect("valid URL");
+    url.path_segments_mut()
+        .expect("URL has a path")
+        .push(subuser)
+        .push("credits");
+    url.path().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rates_from_global_stats() {
+        let body = r#"[
+            {"date":"2024-05-01","stats":[{"metrics":{"requests":100,"delivered":90,"bounces":5,"spam_reports":1}}]},
+            {"date":"2024-05-02","stats":[{"metrics":{"requests":100,"delivered":90,"bounces":15,"spam_reports":0}}]}
+        ]"#;
+        let stats: Vec<PeriodStats> = serde_json::from_str(body).unwrap();
+        let total = stats
+            .iter()
+            .fold(Metrics::default(), |total, period| total + period.metrics());
+
+        assert_eq!(total.bounce_rate(), 0.1);
+        assert_eq!(total.spam_report_rate(), 1.0 / 180.0);
+        assert_eq!(Metrics::default().bounce_rate(), 0.0);
+    }
+
+    #[test]
+    fn remaining_credits() {
+        let credits: Credits = serde_json::from_str(
+            r#"{"type":"recurring","reset_frequency":"daily","remain":150,"total":200,"used":50}"#,
+        )
+        .unwrap();
+        assert_eq!(credits.remaining(), Some(150));
+
+        let credits: Credits = serde_json::from_str(r#"{"type":"unlimited"}"#).unwrap();
+        assert_eq!(credits.remaining(), None);
+    }
+
+    #[test]
+    fn subusers_are_encoded() {
+        assert_eq!(credits_path("jane"), "/v3/subusers/jane/credits");
+        assert_eq!(
+            credits_path("jane doe/../?x#"),
+            "/v3/subusers/jane%20doe%2F..%2F%3Fx%23/credits"
+        );
+    }
+}
@@ -0,0 +1,189 @@
+// OpenTelemetry spans and metrics for mail sends, following the messaging semantic conventions.
+// Everything is reported to the global tracer and meter providers, so nothing is recorded until
+// the application installs them.
+
+use std::sync::OnceLock;
+use std::time::Instant;
+
+use opentelemetry::global::{self, BoxedSpan};
+use opentelemetry::metrics::{Counter, Histogram};
+use opentelemetry::trace::{Span, SpanKind, Status, Tracer};
+use opentelemetry::KeyValue;
+use reqwest::StatusCode;
+use url::Url;
+
+use crate::error::SendgridError;
+
+const INSTRUMENTATION_SCOPE: &str = "sendgrid";
+
+struct Instruments {
+    duration: Histogram<f64>,
+    sent: Counter<u64>,
+}
+
+fn instruments() -> &'static Instruments {
+    static INSTRUMENTS: OnceLock<Instruments> = OnceLock::new();
+    INSTRUMENTS.get_or_init(|| {
+        let meter = global::meter(INSTRUMENTATION_SCOPE);
+        Instruments {
+            duration: meter
+                .f64_histogram("messaging.client.operation.duration")
+                .with_unit("s")
+                .with_description("Duration of mail send requests.")
+                .build(),
+            sent: meter
+                .u64_counter("messaging.client.sent.messages")
+                .with_unit("{message}")
+                .with_description("Number of messages sent.")
+                .build(),
+        }
+    })
+}
+
+/// Tracks a single send request from start to finish.
+pub(crate) struct SendTelemetry {
+    span: BoxedSpan,
+    attributes: Vec<KeyValue>,
+    start: Instant,
+}
+
+impl SendTelemetry {
+    /// Start a span for a send to `url`, the URL of the endpoint. Only its host is reported as
+    /// the destination, so the attribute has a low cardinality.
+    pub(crate) fn start(url: &str) -> SendTelemetry {
+        let attributes = vec![
+            KeyValue::new("messaging.system", "sendgrid"),
+            KeyValue::new("messaging.operation.name", "send"),
+            KeyValue::new("messaging.operation.type", "publish"),
+            KeyValue::new("messaging.destination.name", destination_name(url)),
+        ];
+        let tracer = global::tracer(INSTRUMENTATION_SCOPE);
+        let span = tracer
+            .span_builder("send sendgrid")
+            .with_kind(SpanKind::Producer)
+            .with_attributes(attributes.clone())
+            .start(&tracer);
+
+        SendTelemetry {
+            span,
+            attributes,
+            start: Instant::now(),
+        }
+    }
+
+    /// End the span and record the metrics for the outcome of the request.
+    pub(crate) fn finish(mut self, result: Result<StatusCode, &SendgridError>) {
+        let status = match result {
+            Ok(status) => Some(status),
+            Err(SendgridError::RequestNotSuccessful(e)) => Some(e.status),
+            Err(_) => None,
+        };
+        let mut outcome = Vec::new();
+        if let Some(status) = status {
+            outcome.push(KeyValue::new(
+                "http.response.status_code",
+                i64::from(status.as_u16()),
+            ));
+        }
+        if let Err(e) = result {
+            let error_type = match status {
+                Some(status) => status.as_str().to_string(),
+                None => e.error_type().to_string(),
+            };
+            outcome.push(KeyValue::new("error.type", error_type));
+            self.span.set_status(Status::error(e.to_string()));
+        }
+
+        self.span.set_attributes(outcome.clone());
+        self.span.end();
+        self.attributes.extend(outcome);
+
+        let instruments = instruments();
+        instruments
+            .duration
+            .record(self.start.elapsed().as_secs_f64(), &self.attributes);
+        if result.is_ok() {
+            instruments.sent.add(1, &self.attributes);
+        }
+    }
+}
+
+// The host of `url`, or `url` itself if it has none.
+fn destination_name(url: &str) -> String {
+    Url::parse(url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_string))
+        .unwrap_or_else(|| url.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use opentelemetry::trace::Status;
+    use opentelemetry_sdk::testing::trace::InMemorySpanExporter;
+    use opentelemetry_sdk::trace::TracerProvider;
+
+    use super::*;
+    use crate::error::RequestNotSuccessful;
+
+    fn attribute(attributes: &[KeyValue], key: &str) -> Option<String> {
+        attributes
+            .iter()
+            .find(|kv| kv.key.as_str() == key)
+            .map(|kv| kv.value.to_string())
+    }
+
+    #[test]
+    fn destinations_are_hosts() {
+        assert_eq!(
+            destination_name("https://api.sendgrid.com/v3/mail/send"),
+            "api.sendgrid.com"
+        );
+        assert_eq!(
+            destination_name("http://127.0.0.1:8080/proxy/v3/mail/send?x=1"),
+            "127.0.0.1"
+        );
+        assert_eq!(destination_name("not a url"), "not a url");
+    }
+
+    #[test]
+    fn sends_are_traced() {
+        let exporter = InMemorySpanExporter::default();
+        let provider = TracerProvider::builder()
+            .with_simple_exporter(exporter.clone())
+            .build();
+        global::set_tracer_provider(provider);
+
+        // Other tests may send while the provider is installed, so spans are told apart by host.
+        SendTelemetry::start("https://accepted.example.com/v3/mail/send")
+            .finish(Ok(StatusCode::ACCEPTED));
+        let error = RequestNotSuccessful::new(StatusCode::UNAUTHORIZED, String::new()).into();
+        SendTelemetry::start("https://rejected.example.com/v3/mail/send").finish(Err(&error));
+
+        let spans = exporter.get_finished_spans().unwrap();
+        let span = |host: &str| {
+            spans
+                .iter()
+                .find(|span| {
+                    attribute(&span.attributes, "messaging.destination.name").as_deref()
+                        == Some(host)
+                })
+                .unwrap()
+        };
+
+        let accepted = span("accepted.example.com");
+        assert_eq!(accepted.name, "send sendgrid");
+        assert_eq!(accepted.span_kind, SpanKind::Producer);
+        assert_eq!(
+            attribute(&accepted.attributes, "http.response.status_code").as_deref(),
+            Some("202")
+        );
+        assert_eq!(accepted.status, Status::Unset);
+
+        let rejected = span("rejected.example.com");
+        assert_eq!(
+            attribute(&rejected.attributes, "error.type").as_deref(),
+            Some("401")
+        );
+        assert!(matches!(rejected.status, Status::Error { .. }));
+    }
+}
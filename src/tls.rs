@@ -0,0 +1,24 @@
+// Clients built by this crate use rustls when one of the rustls features is enabled, even if the
+// default native TLS backend is enabled as well, so the bundled root certificates are used in
+// containers without a system trust store.
+
+pub(crate) fn client_builder() -> reqwest::ClientBuilder {
+    let builder = reqwest::Client::builder();
+    #[cfg(any(feature = "rustls", feature = "rustls-webpki-roots"))]
+    let builder = builder.use_rustls_tls();
+    builder
+}
+
+pub(crate) fn default_client() -> reqwest::Client {
+    client_builder()
+        .build()
+        .expect("the TLS backend could not be initialized")
+}
+
+#[cfg(feature = "blocking")]
+pub(crate) fn blocking_client_builder() -> reqwest::blocking::ClientBuilder {
+    let builder = reqwest::blocking::Client::builder();
+    #[cfg(any(feature = "rustls", feature = "rustls-webpki-roots"))]
+    let builder = builder.use_rustls_tls();
+    builder
+}
@@ -0,0 +1,282 @@
+//! Retain exact copies of sent payloads.
+//!
+//! An [`ArchiveSink`] registered with
+//! [`Sender::set_archive_sink`](crate::v3::Sender::set_archive_sink) is called after every send
+//! with the JSON body that was posted, the message id assigned by SendGrid and the outcome of the
+//! request. The body is redacted according to the
+//! [redaction level](crate::v3::Sender::set_redaction_level) of the sender, which redacts nothing
+//! by default. [`FileArchiveSink`] writes each record to its own file; other storage such as S3 can
+//! be supported by implementing the trait.
+
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use reqwest::header::HeaderMap;
+use reqwest::StatusCode;
+use serde::Serialize;
+
+use crate::error::SendgridError;
+
+/// The response header carrying the id SendGrid assigned to an accepted message.
+pub const MESSAGE_ID_HEADER: &str = "X-Message-Id";
+
+/// The outcome of a send.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ArchiveOutcome {
+    /// SendGrid accepted the message.
+    Accepted {
+        /// The HTTP status code of the response.
+        status: u16,
+    },
+
+    /// SendGrid rejected the message or the request failed.
+    Failed {
+        /// The HTTP status code of the response, if one was received.
+        status: Option<u16>,
+        /// A description of the failure.
+        error: String,
+    },
+}
+
+/// A sent payload handed to an [`ArchiveSink`].
+#[derive(Clone, Debug, Serialize)]
+pub struct ArchiveRecord<'a> {
+    /// The id SendGrid assigned to the message, if it was accepted.
+    pub message_id: Option<&'a str>,
+
+    /// The outcome of the send.
+    pub outcome: ArchiveOutcome,
+
+    /// The JSON body that was posted, redacted according to the redaction level of the sender.
+    pub payload: &'a str,
+}
+
+impl<'a> ArchiveRecord<'a> {
+    pub(crate) fn new(
+        payload: &'a str,
+        result: Result<(StatusCode, &'a HeaderMap), &SendgridError>,
+    ) -> ArchiveRecord<'a> {
+        match result {
+            Ok((status, headers)) => ArchiveRecord {
+                message_id: headers
+                    .get(MESSAGE_ID_HEADER)
+                    .and_then(|id| id.to_str().ok()),
+                outcome: ArchiveOutcome::Accepted {
+                    status: status.as_u16(),
+                },
+                payload,
+            },
+            Err(e) => ArchiveRecord {
+                message_id: None,
+                outcome: ArchiveOutcome::Failed {
+                    status: match e {
+                        SendgridError::RequestNotSuccessful(e) => Some(e.status.as_u16()),
+                        _ => None,
+                    },
+                    error: e.to_string(),
+                },
+                payload,
+            },
+        }
+    }
+}
+
+/// Receives a copy of every payload sent.
+///
+/// The message has already been handed to SendGrid when the sink is called, so failures to
+/// archive can not fail the send and must be handled by the sink. Async sends call the sink on
+/// the runtime, so sinks which block, for example on I/O, should hand the work to
+/// `tokio::task::spawn_blocking` like [`FileArchiveSink`] does.
+pub trait ArchiveSink: Send + Sync {
+    /// Archive a sent payload.
+    fn archive(&self, record: &ArchiveRecord<'_>);
+}
+
+// The sink registered on a sender.
+#[derive(Clone)]
+pub(crate) struct SharedArchiveSink(pub(crate) Arc<dyn ArchiveSink>);
+
+impl fmt::Debug for SharedArchiveSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ArchiveSink")
+    }
+}
+
+/// An [`ArchiveSink`] which discards every record.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NoopArchiveSink;
+
+impl ArchiveSink for NoopArchiveSink {
+    fn archive(&self, _record: &ArchiveRecord<'_>) {}
+}
+
+type ErrorCallback = Arc<dyn Fn(io::Error) + Send + Sync>;
+
+/// An [`ArchiveSink`] which writes every record as a JSON file into a directory.
+///
+/// Files are named after the time of the send in milliseconds and the message id, or `failed`
+/// for messages which were not accepted. Inside a tokio runtime the files are written on its
+/// blocking thread pool, so sends do not wait for them.
+#[derive(Clone)]
+pub struct FileArchiveSink {
+    dir: PathBuf,
+    sequence: Arc<AtomicU64>,
+    on_error: Option<ErrorCallback>,
+}
+
+impl FileArchiveSink {
+    /// Construct a sink writing into `dir`, which must exist.
+    pub fn new<P: Into<PathBuf>>(dir: P) -> FileArchiveSink {
+        FileArchiveSink {
+            dir: dir.into(),
+            sequence: Arc::new(AtomicU64::new(0)),
+            on_error: None,
+        }
+    }
+
+    /// Call `on_error` when a record could not be written.
+    pub fn set_on_error<F>(mut self, on_error: F) -> FileArchiveSink
+    where
+        F: Fn(io::Error) + Send + Sync + 'static,
+    {
+        self.on_error = Some(Arc::new(on_error));
+        self
+    }
+
+    /// Write a record and return the path of its file.
+    pub fn write(&self, record: &ArchiveRecord<'_>) -> io::Result<PathBuf> {
+        let (path, contents) = self.file(record)?;
+        std::fs::write(&path, contents)?;
+        Ok(path)
+    }
+
+    // The path and contents of the file of a record.
+    fn file(&self, record: &ArchiveRecord<'_>) -> io::Result<(PathBuf, Vec<u8>)> {
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        // The sequence number keeps files written in the same millisecond apart.
+        let sequence = self.sequence.fetch_add(1, Ordering::Relaxed);
+        let id: String = record
+            .message_id
+            .unwrap_or("failed")
+            .chars()
+            .filter(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+            .collect();
+
+        let path = self
+            .dir
+            .join(format!("{}-{}-{}.json", millis, sequence, id));
+        Ok((path, serde_json::to_vec_pretty(record)?))
+    }
+
+    fn report(&self, result: io::Result<()>) {
+        if let (Err(e), Some(on_error)) = (result, &self.on_error) {
+            on_error(e);
+        }
+    }
+}
+
+impl ArchiveSink for FileArchiveSink {
+    fn archive(&self, record: &ArchiveRecord<'_>) {
+        let (path, contents) = match self.file(record) {
+            Ok(file) => file,
+            Err(e) => return self.report(Err(e)),
+        };
+        match tokio::runtime::Handle::try_current() {
+            Ok(runtime) => {
+                let sink = self.clone();
+                runtime.spawn_blocking(move || sink.report(std::fs::write(path, contents)));
+            }
+            Err(_) => self.report(std::fs::write(path, contents)),
+        }
+    }
+}
+
+impl fmt::Debug for FileArchiveSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FileArchiveSink")
+            .field("dir", &self.dir)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::header::HeaderValue;
+
+    use super::*;
+    use crate::error::RequestNotSuccessful;
+
+    #[test]
+    fn records_are_written_to_files() {
+        let dir = std::env::temp_dir().join(format!("sendgrid-archive-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let sink = FileArchiveSink::new(&dir);
+
+        let mut headers = HeaderMap::new();
+        headers.insert(MESSAGE_ID_HEADER, HeaderValue::from_static("abc123"));
+        let record =
+            ArchiveRecord::new(r#"{"subject":"hi"}"#, Ok((StatusCode::ACCEPTED, &headers)));
+        let path = sink.write(&record).unwrap();
+        assert!(path.to_str().unwrap().ends_with("-abc123.json"));
+
+        let written: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        assert_eq!(written["message_id"], "abc123");
+        assert_eq!(written["outcome"]["accepted"]["status"], 202);
+        assert_eq!(written["payload"], r#"{"subject":"hi"}"#);
+
+        let error = RequestNotSuccessful::new(StatusCode::BAD_REQUEST, String::new()).into();
+        let record = ArchiveRecord::new("{}", Err(&error));
+        assert_eq!(
+            record.outcome,
+            ArchiveOutcome::Failed {
+                status: Some(400),
+                error: error.to_string()
+            }
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn records_are_written_off_the_runtime() {
+        let dir = std::env::temp_dir().join(format!("sendgrid-archive-rt-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (tx, rx) = std::sync::mpsc::channel();
+        let missing = dir.join("missing");
+        let sink = FileArchiveSink::new(&missing).set_on_error(move |e| tx.send(e).unwrap());
+
+        let headers = HeaderMap::new();
+        sink.archive(&ArchiveRecord::new(
+            "{}",
+            Ok((StatusCode::ACCEPTED, &headers)),
+        ));
+        let error = tokio::task::spawn_blocking(move || rx.recv().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::NotFound);
+
+        let sink = FileArchiveSink::new(&dir);
+        sink.archive(&ArchiveRecord::new(
+            "{}",
+            Ok((StatusCode::ACCEPTED, &headers)),
+        ));
+        for _ in 0..100 {
+            if std::fs::read_dir(&dir).unwrap().count() > 0 {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,53 @@
+use std::fmt;
+use std::sync::{Arc, OnceLock};
+
+use reqwest::blocking::Client;
+
+use crate::error::SendgridResult;
+use crate::tls;
+
+type Build = Arc<dyn Fn() -> reqwest::Result<Client> + Send + Sync>;
+
+// The blocking client of a sender. reqwest's blocking client runs its own runtime, which panics
+// when it is dropped inside an async context, so a sender which is only used from async code
+// must never create one. The client is built on first use instead and shared between clones.
+#[derive(Clone)]
+pub(crate) struct BlockingClient {
+    client: Arc<OnceLock<Client>>,
+    build: Build,
+}
+
+impl BlockingClient {
+    pub(crate) fn lazy<F>(build: F) -> BlockingClient
+    where
+        F: Fn() -> reqwest::Result<Client> + Send + Sync + 'static,
+    {
+        BlockingClient {
+            client: Arc::new(OnceLock::new()),
+            build: Arc::new(build),
+        }
+    }
+
+    pub(crate) fn ready(client: Client) -> BlockingClient {
+        BlockingClient {
+            client: Arc::new(OnceLock::from(client)),
+            build: Arc::new(|| tls::blocking_client_builder().build()),
+        }
+    }
+
+    pub(crate) fn get(&self) -> SendgridResult<&Client> {
+        if let Some(client) = self.client.get() {
+            return Ok(client);
+        }
+        let client = (self.build)()?;
+        Ok(self.client.get_or_init(|| client))
+    }
+}
+
+impl fmt::Debug for BlockingClient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BlockingClient")
+            .field("built", &self.client.get().is_some())
+            .finish()
+    }
+}
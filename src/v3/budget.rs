@@ -0,0 +1,181 @@
+//! Daily send budgets per API key, tenant or category.
+//!
+//! A [`Budget`] counts the recipients of every message sent through it against daily limits and
+//! refuses to send once a limit would be exceeded. This protects the account quota from runaway
+//! loops. Counters are kept in a [`BudgetStore`] so they can be shared between processes.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use reqwest::Response;
+
+use crate::error::{SendgridError, SendgridResult};
+use crate::v3::{Message, Sender};
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Persists the counters of a [`Budget`]. Days are numbered from the Unix epoch in UTC.
+pub trait BudgetStore {
+    /// Atomically add `amount` to the counter of `key` for `day` and return the new total.
+    fn increment(
+        &self,
+        key: &str,
+        day: u64,
+        amount: u64,
+    ) -> impl Future<Output = SendgridResult<u64>> + Send;
+
+    /// Subtract `amount` from the counter of `key` for `day`. This is used to roll back an
+    /// increment which went over the limit.
+    fn release(
+        &self,
+        key: &str,
+        day: u64,
+        amount: u64,
+    ) -> impl Future<Output = SendgridResult<()>> + Send;
+}
+
+/// A [`BudgetStore`] which keeps its counters in memory.
+#[derive(Debug, Default)]
+pub struct MemoryBudgetStore {
+    counters: Mutex<HashMap<(String, u64), u64>>,
+}
+
+impl BudgetStore for MemoryBudgetStore {
+    async fn increment(&self, key: &str, day: u64, amount: u64) -> SendgridResult<u64> {
+        let mut counters = self.counters.lock().unwrap();
+        // Counters from previous days are no longer needed.
+        counters.retain(|(_, d), _| *d >= day);
+        let counter = counters.entry((key.to_string(), day)).or_insert(0);
+        *counter += amount;
+        Ok(*counter)
+    }
+
+    async fn release(&self, key: &str, day: u64, amount: u64) -> SendgridResult<()> {
+        if let Some(counter) = self
+            .counters
+            .lock()
+            .unwrap()
+            .get_mut(&(key.to_string(), day))
+        {
+            *counter = counter.saturating_sub(amount);
+        }
+        Ok(())
+    }
+}
+
+/// Enforces daily recipient limits per budget key.
+#[must_use]
+pub struct Budget<S> {
+    store: S,
+    limits: HashMap<String, u64>,
+    default_limit: Option<u64>,
+}
+
+impl<S: BudgetStore> Budget<S> {
+    /// Construct a budget without any limits.
+    pub fn new(store: S) -> Self {
+        Budget {
+            store,
+            limits: HashMap::new(),
+            default_limit: None,
+        }
+    }
+
+    /// Set the daily limit for a key, such as a tenant id or category name.
+    pub fn set_limit<K: Into<String>>(mut self, key: K, limit: u64) -> Self {
+        self.limits.insert(key.into(), limit);
+        self
+    }
+
+    /// Set the daily limit for keys without a limit of their own. Without a default, such keys
+    /// are not limited.
+    pub fn set_default_limit(mut self, limit: u64) -> Self {
+        self.default_limit = Some(limit);
+        self
+    }
+
+    /// Reserve `amount` sends for every key. Either all reservations succeed or none are kept
+    /// and [`SendgridError::BudgetExceeded`] is returned for the first key over its limit.
+    pub async fn reserve(&self, keys: &[&str], amount: u64) -> SendgridResult<()> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let day = now / SECONDS_PER_DAY;
+
+        let mut reserved = Vec::with_capacity(keys.len());
+        for key in keys {
+            let limit = match self.limits.get(*key).copied().or(self.default_limit) {
+                Some(limit) => limit,
+                None => continue,
+            };
+
+            let used = match self.store.increment(key, day, amount).await {
+                Ok(used) => used,
+                Err(e) => {
+                    self.release_all(&reserved, day, amount).await;
+                    return Err(e);
+                }
+            };
+            reserved.push(*key);
+
+            if used > limit {
+                self.release_all(&reserved, day, amount).await;
+                return Err(SendgridError::BudgetExceeded {
+                    key: key.to_string(),
+                    limit,
+                    resets_in: Duration::from_secs((day + 1) * SECONDS_PER_DAY - now),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reserve the recipients of the message against every key and send it.
+    pub async fn send(
+        &self,
+        sender: &Sender,
+        keys: &[&str],
+        mail: &Message,
+    ) -> SendgridResult<Response> {
+        self.reserve(keys, mail.recipient_count() as u64).await?;
+        sender.send(mail).await
+    }
+
+    async fn release_all(&self, keys: &[&str], day: u64, amount: u64) {
+        for key in keys {
+            // Failing to roll back only makes the budget stricter, so the error is ignored.
+            let _ = self.store.release(key, day, amount).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn reservations_are_limited() {
+        let budget = Budget::new(MemoryBudgetStore::default())
+            .set_limit("tenant", 5)
+            .set_default_limit(10);
+
+        budget.reserve(&["tenant", "news"], 4).await.unwrap();
+        let err = budget.reserve(&["news", "tenant"], 2).await.unwrap_err();
+        match err {
+            SendgridError::BudgetExceeded { key, limit, .. } => {
+                assert_eq!(key, "tenant");
+                assert_eq!(limit, 5);
+            }
+            e => panic!("unexpected error {}", e),
+        }
+
+        // The failed reservation was rolled back for both keys.
+        budget.reserve(&["tenant"], 1).await.unwrap();
+        budget.reserve(&["news"], 6).await.unwrap();
+        assert!(budget.reserve(&["news"], 1).await.is_err());
+    }
+}
@@ -0,0 +1,182 @@
+//! A message builder which only builds complete messages.
+//!
+//! [`Message`] accepts its fields in any order, so a message without recipients or without a body
+//! is only caught by [`Message::check_sendable`] or by the API. [`MessageBuilder`] tracks in its
+//! type whether a recipient and a body were added, and only offers
+//! [`build`](MessageBuilder::build) once both were:
+//!
+//! ```
+//! use sendgrid::v3::builder::MessageBuilder;
+//! use sendgrid::v3::Email;
+//!
+//! let message = MessageBuilder::new(Email::new("from@example.com"))
+//!     .add_to(Email::new("to@example.com"))
+//!     .set_subject("Hello")
+//!     .set_text("Hi there")
+//!     .build();
+//! ```
+//!
+//! A message without a body does not compile:
+//!
+//! ```compile_fail
+//! use sendgrid::v3::builder::MessageBuilder;
+//! use sendgrid::v3::Email;
+//!
+//! let message = MessageBuilder::new(Email::new("from@example.com"))
+//!     .add_to(Email::new("to@example.com"))
+//!     .build();
+//! ```
+
+use std::marker::PhantomData;
+
+use crate::v3::message::MailSettings;
+use crate::v3::{
+    Attachment, Category, Content, Email, Message, Personalization, TrackingSettings, ASM,
+};
+
+/// The builder has no recipients yet.
+#[derive(Clone, Copy, Debug)]
+pub struct NoRecipients;
+
+/// The builder has at least one personalization.
+#[derive(Clone, Copy, Debug)]
+pub struct HasRecipients;
+
+/// The builder has neither content nor a template id yet.
+#[derive(Clone, Copy, Debug)]
+pub struct NoBody;
+
+/// The builder has content or a template id.
+#[derive(Clone, Copy, Debug)]
+pub struct HasBody;
+
+/// Builds a [`Message`] which has at least one personalization and either content or a
+/// template id.
+#[must_use]
+#[derive(Clone, Debug)]
+pub struct MessageBuilder<R, B> {
+    message: Message,
+    state: PhantomData<(R, B)>,
+}
+
+impl MessageBuilder<NoRecipients, NoBody> {
+    /// Start building a message from `from`.
+    pub fn new(from: Email) -> MessageBuilder<NoRecipients, NoBody> {
+        MessageBuilder {
+            message: Message::new(from),
+            state: PhantomData,
+        }
+    }
+}
+
+impl<R, B> MessageBuilder<R, B> {
+    // Apply a setter, moving to the state `R2` and `B2`.
+    fn then<R2, B2>(self, f: impl FnOnce(Message) -> Message) -> MessageBuilder<R2, B2> {
+        MessageBuilder {
+            message: f(self.message),
+            state: PhantomData,
+        }
+    }
+
+    /// Add a to recipient, see [`Message::add_to`].
+    pub fn add_to(self, to: Email) -> MessageBuilder<HasRecipients, B> {
+        self.then(|m| m.add_to(to))
+    }
+
+    /// Add a personalization.
+    pub fn add_personalization(self, p: Personalization) -> MessageBuilder<HasRecipients, B> {
+        self.then(|m| m.add_personalization(p))
+    }
+
+    /// Add content.
+    pub fn add_content(self, c: Content) -> MessageBuilder<R, HasBody> {
+        self.then(|m| m.add_content(c))
+    }
+
+    /// Set the plain text body.
+    pub fn set_text<S: Into<String>>(self, text: S) -> MessageBuilder<R, HasBody> {
+        self.then(|m| m.set_text(text))
+    }
+
+    /// Set the HTML body.
+    pub fn set_html<S: Into<String>>(self, html: S) -> MessageBuilder<R, HasBody> {
+        self.then(|m| m.set_html(html))
+    }
+
+    /// Set the template id.
+    pub fn set_template_id(self, template_id: &str) -> MessageBuilder<R, HasBody> {
+        self.then(|m| m.set_template_id(template_id))
+    }
+
+    /// Set the subject.
+    pub fn set_subject(self, subject: &str) -> MessageBuilder<R, B> {
+        self.then(|m| m.set_subject(subject))
+    }
+
+    /// Set the Reply-To header.
+    pub fn set_reply_to(self, reply_to: Email) -> MessageBuilder<R, B> {
+        self.then(|m| m.set_reply_to(reply_to))
+    }
+
+    /// Add an attachment.
+    pub fn add_attachment(self, a: Attachment) -> MessageBuilder<R, B> {
+        self.then(|m| m.add_attachment(a))
+    }
+
+    /// Add a category.
+    pub fn add_category<C: Into<Category>>(self, category: C) -> MessageBuilder<R, B> {
+        self.then(|m| m.add_category(category))
+    }
+
+    /// Add a header.
+    pub fn add_header<K: Into<String>, V: Into<String>>(
+        self,
+        name: K,
+        value: V,
+    ) -> MessageBuilder<R, B> {
+        self.then(|m| m.add_header(name, value))
+    }
+
+    /// Set the tracking settings.
+    pub fn set_tracking_settings(self, settings: TrackingSettings) -> MessageBuilder<R, B> {
+        self.then(|m| m.set_tracking_settings(settings))
+    }
+
+    /// Set the unsubscribe group settings.
+    pub fn set_asm(self, asm: ASM) -> MessageBuilder<R, B> {
+        self.then(|m| m.set_asm(asm))
+    }
+
+    /// Set the mail settings.
+    pub fn set_mail_settings(self, settings: MailSettings) -> MessageBuilder<R, B> {
+        self.then(|m| m.set_mail_settings(settings))
+    }
+}
+
+impl MessageBuilder<HasRecipients, HasBody> {
+    /// Build the message.
+    pub fn build(self) -> Message {
+        self.message
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn built_messages_are_sendable() {
+        let message = MessageBuilder::new(Email::new("from@example.com"))
+            .set_template_id("d-123")
+            .add_personalization(Personalization::new(Email::new("to@example.com")))
+            .build();
+        assert!(message.check_sendable().is_ok());
+
+        let message = MessageBuilder::new(Email::new("from@example.com"))
+            .set_subject("Hello")
+            .add_to(Email::new("to@example.com"))
+            .set_html("<p>Hi</p>")
+            .build();
+        assert!(message.check_sendable().is_ok());
+    }
+}
@@ -0,0 +1,117 @@
+//! Route a share of messages to an alternate template or IP pool.
+//!
+//! A [`Canary`] picks messages by hashing the address of their first recipient, so a recipient
+//! always lands in the same group. Canary messages are tagged with a category which makes it easy
+//! to compare the two groups in the SendGrid statistics.
+
+use crate::v3::fingerprint::fnv1a;
+use crate::v3::Message;
+
+const DEFAULT_CATEGORY: &str = "canary";
+
+/// Routes a percentage of messages to an alternate template id and/or IP pool.
+#[must_use]
+#[derive(Clone, Debug)]
+pub struct Canary {
+    percentage: u8,
+    template_id: Option<String>,
+    ip_pool_name: Option<String>,
+    category: String,
+}
+
+impl Canary {
+    /// Construct a canary routing `percentage` percent of messages. Values above 100 are treated
+    /// as 100.
+    pub fn new(percentage: u8) -> Canary {
+        Canary {
+            percentage: percentage.min(100),
+            template_id: None,
+            ip_pool_name: None,
+            category: String::from(DEFAULT_CATEGORY),
+        }
+    }
+
+    /// Set the template id used for canary messages.
+    pub fn set_template_id(mut self, template_id: &str) -> Canary {
+        self.template_id = Some(String::from(template_id));
+        self
+    }
+
+    /// Set the IP pool used for canary messages.
+    pub fn set_ip_pool_name(mut self, ip_pool_name: &str) -> Canary {
+        self.ip_pool_name = Some(String::from(ip_pool_name));
+        self
+    }
+
+    /// Set the category added to canary messages. Defaults to `canary`.
+    pub fn set_category(mut self, category: &str) -> Canary {
+        self.category = String::from(category);
+        self
+    }
+
+    /// Whether the message falls into the canary group. Messages without recipients never do.
+    pub fn is_canary(&self, message: &Message) -> bool {
+        let recipient = match message.personalizations.first().and_then(|p| p.to.first()) {
+            Some(email) => email.email.to_lowercase(),
+            None => return false,
+        };
+
+        fnv1a(recipient.as_bytes()) % 100 < u64::from(self.percentage)
+    }
+
+    /// Apply the canary settings to the message if it falls into the canary group.
+    pub fn route(&self, message: Message) -> Message {
+        if !self.is_canary(&message) {
+            return message;
+        }
+
+        let mut message = message.add_category(&self.category);
+        if let Some(template_id) = &self.template_id {
+            message = message.set_template_id(template_id);
+        }
+        if let Some(ip_pool_name) = &self.ip_pool_name {
+            message = message.set_ip_pool_name(ip_pool_name);
+        }
+        message
+    }
+}
+
+// A small hash which, unlike the standard library hasher, is stable across Rust releases.
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::v3::{Email, Personalization};
+
+    fn message(to: &str) -> Message {
+        Message::new(Email::new("from_email@test.com"))
+            .set_template_id("d-stable")
+            .add_personalization(Personalization::new(Email::new(to)))
+    }
+
+    #[test]
+    fn routing_is_deterministic() {
+        let canary = Canary::new(30).set_template_id("d-canary");
+        let picked = (0..1000)
+            .filter(|i| canary.is_canary(&message(&format!("user{}@test.com", i))))
+            .count();
+        assert!((200..400).contains(&picked), "picked {}", picked);
+
+        let a = canary.is_canary(&message("Someone@test.com"));
+        assert_eq!(a, canary.is_canary(&message("someone@test.com")));
+    }
+
+    #[test]
+    fn canary_messages_are_tagged() {
+        let routed = Canary::new(100)
+            .set_template_id("d-canary")
+            .set_ip_pool_name("canary_pool")
+            .route(message("to_email@test.com"));
+        assert_eq!(routed.template_id.as_deref(), Some("d-canary"));
+        assert_eq!(routed.ip_pool_name.as_deref(), Some("canary_pool"));
+        assert_eq!(routed.categories, Some(vec![String::from("canary")]));
+
+        let untouched = Canary::new(0).route(message("to_email@test.com"));
+        assert_eq!(untouched.template_id.as_deref(), Some("d-stable"));
+        assert!(untouched.categories.is_none());
+    }
+}
@@ -0,0 +1,278 @@
+//! An adapter for sending messages consumed from a queue such as Kafka or NATS.
+//!
+//! The adapter is transport agnostic: it accepts any [`Stream`] of serialized [`Message`] JSON
+//! payloads, deserializes, validates and sends each one, retries transient failures with a
+//! [`RetryPolicy`] and hands payloads that could not be delivered to a dead letter callback.
+
+use std::future::Future;
+use std::time::Duration;
+
+use futures::{Stream, StreamExt};
+#[cfg(feature = "cancellation")]
+use tokio_util::sync::CancellationToken;
+
+use crate::error::SendgridError;
+use crate::v3::retry::RetryPolicy;
+use crate::v3::{Message, Sender};
+
+/// A payload that could not be sent along with the error of the last attempt.
+#[derive(Debug)]
+pub struct DeadLetter {
+    /// The raw payload as it was received from the stream.
+    pub payload: Vec<u8>,
+
+    /// The error that caused the payload to be given up on.
+    pub error: SendgridError,
+}
+
+/// A summary of the payloads processed by [`Consumer::run`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ConsumerReport {
+    /// The number of messages that were sent successfully.
+    pub sent: usize,
+
+    /// The number of payloads handed to the dead letter callback.
+    pub dead_lettered: usize,
+
+    /// Whether the run was stopped by its cancellation token before the stream ended.
+    #[cfg(feature = "cancellation")]
+    pub cancelled: bool,
+}
+
+/// Consumes serialized messages from a stream and sends them with a [`Sender`].
+#[must_use]
+pub struct Consumer<F> {
+    sender: Sender,
+    dead_letter: F,
+    retry_policy: RetryPolicy,
+}
+
+impl<F> Consumer<F>
+where
+    F: FnMut(DeadLetter),
+{
+    /// Construct a new consumer. Payloads that fail to deserialize or to send after all attempts
+    /// are passed to `dead_letter`.
+    ///
+    /// The consumer retries with the retry policy of `sender`, or with [`RetryPolicy::default`] if
+    /// it has none. The sender itself then no longer retries, so attempts are not multiplied.
+    pub fn new(mut sender: Sender, dead_letter: F) -> Self {
+        let retry_policy = sender.retry_policy.take().unwrap_or_default();
+        Consumer {
+            sender,
+            dead_letter,
+            retry_policy,
+        }
+    }
+
+    /// Set how failed sends are retried.
+    pub fn set_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Drain the stream, sending every payload it yields. This resolves once the stream ends.
+    pub async fn run<S>(&mut self, stream: S) -> ConsumerReport
+    where
+        S: Stream<Item = Vec<u8>>,
+    {
+        self.drive(stream, |delay| async move {
+            tokio::time::sleep(delay).await;
+            true
+        })
+        .await
+    }
+
+    /// Like [`Consumer::run`], but stops taking payloads from the stream once `token` is
+    /// cancelled. A request in flight is completed, while a payload waiting to be retried is
+    /// handed to the dead letter callback with [`SendgridError::Cancelled`].
+    #[cfg(feature = "cancellation")]
+    pub async fn run_until_cancelled<S>(
+        &mut self,
+        stream: S,
+        token: &CancellationToken,
+    ) -> ConsumerReport
+    where
+        S: Stream<Item = Vec<u8>>,
+    {
+        let stream = stream.take_until(token.cancelled());
+        let mut report = self
+            .drive(stream, |delay| async move {
+                token
+                    .run_until_cancelled(tokio::time::sleep(delay))
+                    .await
+                    .is_some()
+            })
+            .await;
+        report.cancelled = token.is_cancelled();
+        report
+    }
+
+    // Process every payload of the stream, pausing between attempts with `wait`, which returns
+    // false when the consumer should stop retrying.
+    async fn drive<S, W, Fut>(&mut self, stream: S, wait: W) -> ConsumerReport
+    where
+        S: Stream<Item = Vec<u8>>,
+        W: Fn(Duration) -> Fut,
+        Fut: Future<Output = bool>,
+    {
+        let mut report = ConsumerReport::default();
+        let mut stream = std::pin::pin!(stream);
+
+        while let Some(payload) = stream.next().await {
+            match self.process(&payload, &wait).await {
+                Ok(()) => report.sent += 1,
+                Err(error) => {
+                    report.dead_lettered += 1;
+                    (self.dead_letter)(DeadLetter { payload, error });
+                }
+            }
+        }
+
+        report
+    }
+
+    async fn process<W, Fut>(&self, payload: &[u8], wait: &W) -> Result<(), SendgridError>
+    where
+        W: Fn(Duration) -> Fut,
+        Fut: Future<Output = bool>,
+    {
+        let message: Message = serde_json::from_slice(payload)?;
+        message.validate()?;
+
+        let mut attempt = 1;
+        loop {
+            match self.sender.send(&message).await {
+                Ok(_) => return Ok(()),
+                Err(e) => match self.retry_policy.delay(attempt, &e) {
+                    Some(delay) if !wait(delay).await => return Err(SendgridError::Cancelled),
+                    Some(_) => attempt += 1,
+                    None => return Err(e),
+                },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use reqwest::StatusCode;
+
+    use super::*;
+    use crate::v3::dev_server::CaptureServer;
+
+    const PAYLOAD: &[u8] = br#"{"from":{"email":"from_email@test.com"},"subject":"","personalizations":[{"to":[{"email":"to_email@test.com"}]}]}"#;
+
+    fn sender(server: &CaptureServer) -> Sender {
+        let mut sender = Sender::new(String::from("key"), None);
+        sender.set_host(server.url()).unwrap();
+        sender
+    }
+
+    #[tokio::test]
+    async fn undecodable_payloads_are_dead_lettered() {
+        let server = CaptureServer::start().await.unwrap();
+        server.fail_next(StatusCode::SERVICE_UNAVAILABLE, 2);
+        let sender = sender(&server);
+
+        let mut dead = Vec::new();
+        let report = Consumer::new(sender, |letter: DeadLetter| dead.push(letter))
+            .set_retry_policy(
+                RetryPolicy::new()
+                    .set_max_attempts(2)
+                    .set_initial_delay(Duration::from_millis(1)),
+            )
+            .run(futures::stream::iter(vec![
+                b"not json".to_vec(),
+                PAYLOAD.to_vec(),
+                PAYLOAD.to_vec(),
+            ]))
+            .await;
+
+        assert_eq!(report.sent, 1);
+        assert_eq!(report.dead_lettered, 2);
+        assert!(matches!(dead[0].error, SendgridError::JSONDecode(_)));
+        assert!(matches!(
+            &dead[1].error,
+            SendgridError::RequestNotSuccessful(e) if e.status == StatusCode::SERVICE_UNAVAILABLE
+        ));
+        assert_eq!(server.captured().len(), 1);
+    }
+
+    #[cfg(feature = "cancellation")]
+    #[tokio::test]
+    async fn cancellation_stops_retries() {
+        let server = CaptureServer::start().await.unwrap();
+        server.fail_next(StatusCode::SERVICE_UNAVAILABLE, 1);
+        let sender = sender(&server);
+        let token = CancellationToken::new();
+
+        let mut dead = Vec::new();
+        let mut consumer = Consumer::new(sender, |letter: DeadLetter| dead.push(letter))
+            .set_retry_policy(RetryPolicy::new().set_initial_delay(Duration::from_secs(3600)));
+        let cancel = async {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            token.cancel();
+        };
+        let stream =
+            futures::stream::iter(vec![PAYLOAD.to_vec()]).chain(futures::stream::pending());
+        let (report, _) = tokio::join!(consumer.run_until_cancelled(stream, &token), cancel);
+        drop(consumer);
+
+        assert_eq!(
+            report,
+            ConsumerReport {
+                sent: 0,
+                dead_lettered: 1,
+                cancelled: true,
+            }
+        );
+        assert!(matches!(dead[0].error, SendgridError::Cancelled));
+    }
+
+    #[tokio::test]
+    async fn retries_follow_the_senders_policy() {
+        struct CountingInterceptor(Arc<AtomicUsize>);
+
+        impl crate::interceptor::SendInterceptor for CountingInterceptor {
+            fn before_send(
+                &self,
+                _request: &mut crate::interceptor::InterceptedRequest<'_>,
+            ) -> crate::SendgridResult<()> {
+                self.0.fetch_add(1, Ordering::Relaxed);
+                Ok(())
+            }
+        }
+
+        let server = CaptureServer::start().await.unwrap();
+        server.fail_next(StatusCode::SERVICE_UNAVAILABLE, 4);
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let mut sender = sender(&server);
+        sender.add_interceptor(CountingInterceptor(attempts.clone()));
+        // A delay this long would overflow if it were doubled without saturating.
+        sender.set_retry_policy(
+            RetryPolicy::new()
+                .set_max_attempts(4)
+                .set_initial_delay(Duration::MAX)
+                .set_jitter(false),
+        );
+
+        let delays = Mutex::new(Vec::new());
+        let mut consumer = Consumer::new(sender, |_: DeadLetter| {});
+        let report = consumer
+            .drive(futures::stream::iter(vec![PAYLOAD.to_vec()]), |delay| {
+                delays.lock().unwrap().push(delay);
+                async { true }
+            })
+            .await;
+
+        assert_eq!(report.dead_lettered, 1);
+        // The sender no longer retries on its own, so each attempt of the consumer is one request.
+        assert_eq!(attempts.load(Ordering::Relaxed), 4);
+        assert_eq!(*delays.lock().unwrap(), [Duration::from_secs(30); 3]);
+    }
+}
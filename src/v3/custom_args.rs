@@ -0,0 +1,141 @@
+//! Validate the custom args attached to messages against a schema.
+//!
+//! Custom args are echoed back in Event Webhook payloads, which makes them the usual way to
+//! correlate events with application records. A [`CustomArgSchema`] registered on a [`Sender`]
+//! makes sure every message carries the args an application relies on, failing before the message
+//! is sent rather than when an event can not be matched.
+//!
+//! [`Sender`]: crate::v3::Sender
+
+use crate::error::{SendgridError, SendgridResult};
+use crate::v3::{Message, Personalization};
+
+/// The kind of value expected for a custom arg. Custom args are always sent as strings, this
+/// checks that the string parses as the given kind.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArgType {
+    /// Any string.
+    String,
+
+    /// A signed integer.
+    Integer,
+
+    /// `true` or `false`.
+    Boolean,
+}
+
+impl ArgType {
+    fn accepts(self, value: &str) -> bool {
+        match self {
+            ArgType::String => true,
+            ArgType::Integer => value.parse::<i64>().is_ok(),
+            ArgType::Boolean => value == "true" || value == "false",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct ArgSpec {
+    key: String,
+    arg_type: ArgType,
+    required: bool,
+}
+
+/// The custom args expected on every personalization of a message.
+#[derive(Clone, Debug, Default)]
+pub struct CustomArgSchema {
+    args: Vec<ArgSpec>,
+}
+
+impl CustomArgSchema {
+    /// Construct an empty schema.
+    pub fn new() -> CustomArgSchema {
+        CustomArgSchema::default()
+    }
+
+    /// Require the custom arg `key` with a value of the given type.
+    pub fn require<S: Into<String>>(self, key: S, arg_type: ArgType) -> CustomArgSchema {
+        self.add(key.into(), arg_type, true)
+    }
+
+    /// Allow the custom arg `key`, checking its type when present.
+    pub fn optional<S: Into<String>>(self, key: S, arg_type: ArgType) -> CustomArgSchema {
+        self.add(key.into(), arg_type, false)
+    }
+
+    fn add(mut self, key: String, arg_type: ArgType, required: bool) -> CustomArgSchema {
+        self.args.retain(|spec| spec.key != key);
+        self.args.push(ArgSpec {
+            key,
+            arg_type,
+            required,
+        });
+        self
+    }
+
+    /// Check the custom args of every personalization of `message`.
+    pub fn validate(&self, message: &Message) -> SendgridResult<()> {
+        message
+            .personalizations
+            .iter()
+            .try_for_each(|p| self.validate_personalization(p))
+    }
+
+    fn validate_personalization(&self, personalization: &Personalization) -> SendgridResult<()> {
+        for spec in &self.args {
+            let value = personalization
+                .custom_args
+                .as_ref()
+                .and_then(|args| args.get(&spec.key));
+            match value {
+                None if spec.required => {
+                    return Err(SendgridError::InvalidCustomArg(format!(
+                        "missing required custom arg `{}`",
+                        spec.key
+                    )));
+                }
+                Some(value) if !spec.arg_type.accepts(value) => {
+                    return Err(SendgridError::InvalidCustomArg(format!(
+                        "custom arg `{}` is not a valid {:?}: {:?}",
+                        spec.key, spec.arg_type, value
+                    )));
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::v3::{Email, SGMap};
+
+    fn message(args: &[(&str, &str)]) -> Message {
+        let args: SGMap = args
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        Message::new(Email::new("from@example.com"))
+            .add_personalization(Personalization::new(Email::new("to@example.com")))
+            .add_personalization(
+                Personalization::new(Email::new("other@example.com")).add_custom_args(args),
+            )
+    }
+
+    #[test]
+    fn required_args_must_be_present_and_typed() {
+        let schema = CustomArgSchema::new()
+            .require("tenant_id", ArgType::String)
+            .optional("notification_id", ArgType::Integer);
+
+        assert!(schema.validate(&message(&[("tenant_id", "acme")])).is_err());
+
+        let message = message(&[("tenant_id", "acme"), ("notification_id", "x")]);
+        assert!(schema.validate(&message).is_err());
+
+        let schema = CustomArgSchema::new().optional("tenant_id", ArgType::String);
+        assert!(schema.validate(&message).is_ok());
+    }
+}
@@ -0,0 +1,470 @@
+//! An in-memory capture server for local development and end to end tests.
+//!
+//! [`CaptureServer`] listens on a local port and implements the shape of the v3 mail send
+//! endpoint: it accepts authenticated JSON posts, answers `202 Accepted` like SendGrid does and
+//! keeps every message it receives so it can be inspected later. Bodies larger than
+//! [`MAX_PAYLOAD_SIZE`] are answered with `413 Payload Too Large` without being read.
+//!
+//! Failures and slow responses can be simulated: [`CaptureServer::fail_next`] answers the next
+//! sends with an error status, and [`CaptureServer::pause`] holds responses back until
+//! [`CaptureServer::resume`] is called, with [`CaptureServer::in_flight`] counting the requests
+//! waiting for an answer. Point a [`Sender`] at it with
+//! [`Sender::set_host`] to run the whole send path without credentials or network access.
+//!
+//! ```no_run
+//! # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+//! use sendgrid::v3::dev_server::CaptureServer;
+//! use sendgrid::v3::{Email, Message, Personalization, Sender};
+//!
+//! let server = CaptureServer::start().await?;
+//! let mut sender = Sender::new(String::from("SG.dev"), None);
+//! sender.set_host(server.url())?;
+//!
+//! let message = Message::new(Email::new("from@example.com"))
+//!     .add_personalization(Personalization::new(Email::new("to@example.com")));
+//! sender.send(&message).await?;
+//! assert_eq!(server.captured().len(), 1);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`MAX_PAYLOAD_SIZE`]: crate::v3::MAX_PAYLOAD_SIZE
+//! [`Sender`]: crate::v3::Sender
+//! [`Sender::set_host`]: crate::v3::Sender::set_host
+
+use std::collections::VecDeque;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use reqwest::StatusCode;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+use crate::v3::{Message, MAX_PAYLOAD_SIZE};
+
+const MAIL_SEND_PATH: &str = "/v3/mail/send";
+
+// The rate limit reported on every response.
+const RATE_LIMIT: u64 = 600;
+
+/// A local server which captures the messages posted to its mail send endpoint.
+///
+/// The server runs on the current tokio runtime and stops when it is dropped.
+pub struct CaptureServer {
+    addr: SocketAddr,
+    state: Arc<State>,
+    task: JoinHandle<()>,
+}
+
+// The state shared by the connections of a server.
+struct State {
+    captured: Mutex<Vec<Vec<u8>>>,
+    errors: Mutex<Vec<String>>,
+    failures: Mutex<VecDeque<StatusCode>>,
+    in_flight: AtomicUsize,
+    paused: watch::Sender<bool>,
+}
+
+impl State {
+    fn new() -> State {
+        State {
+            captured: Mutex::new(Vec::new()),
+            errors: Mutex::new(Vec::new()),
+            failures: Mutex::new(VecDeque::new()),
+            in_flight: AtomicUsize::new(0),
+            paused: watch::Sender::new(false),
+        }
+    }
+}
+
+impl CaptureServer {
+    /// Start a capture server on a random local port.
+    pub async fn start() -> io::Result<CaptureServer> {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).await?;
+        let addr = listener.local_addr()?;
+        let state = Arc::new(State::new());
+
+        let shared = state.clone();
+        let task = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let state = shared.clone();
+                tokio::spawn(async move {
+                    // A broken connection only affects that one request.
+                    if let Err(e) = handle(stream, &state).await {
+                        state.errors.lock().unwrap().push(e.to_string());
+                    }
+                });
+            }
+        });
+
+        Ok(CaptureServer { addr, state, task })
+    }
+
+    /// The address the server is listening on.
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// The mail send URL to pass to [`Sender::set_host`](crate::v3::Sender::set_host).
+    pub fn url(&self) -> String {
+        format!("http://{}{}", self.addr, MAIL_SEND_PATH)
+    }
+
+    /// The messages received so far, oldest first.
+    pub fn captured(&self) -> Vec<Message> {
+        self.state
+            .captured
+            .lock()
+            .unwrap()
+            .iter()
+            // Bodies are only stored after they deserialized successfully.
+            .map(|body| serde_json::from_slice(body).unwrap())
+            .collect()
+    }
+
+    /// The raw JSON bodies received so far, oldest first.
+    pub fn captured_json(&self) -> Vec<serde_json::Value> {
+        self.state
+            .captured
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|body| serde_json::from_slice(body).unwrap())
+            .collect()
+    }
+
+    /// The errors of the connections which failed so far, oldest first.
+    pub fn errors(&self) -> Vec<String> {
+        self.state.errors.lock().unwrap().clone()
+    }
+
+    /// Forget every message and error received so far.
+    pub fn clear(&self) {
+        self.state.captured.lock().unwrap().clear();
+        self.state.errors.lock().unwrap().clear();
+    }
+
+    /// Answer the next `count` authorized mail sends with `status` instead of capturing them.
+    pub fn fail_next(&self, status: StatusCode, count: usize) {
+        self.state
+            .failures
+            .lock()
+            .unwrap()
+            .extend(std::iter::repeat_n(status, count));
+    }
+
+    /// Hold back the responses to requests until [`CaptureServer::resume`] is called.
+    pub fn pause(&self) {
+        self.state.paused.send_replace(true);
+    }
+
+    /// Answer the requests held back by [`CaptureServer::pause`] and stop holding them back.
+    pub fn resume(&self) {
+        self.state.paused.send_replace(false);
+    }
+
+    /// The number of requests which were received and not answered yet.
+    pub fn in_flight(&self) -> usize {
+        self.state.in_flight.load(Ordering::SeqCst)
+    }
+}
+
+impl Drop for CaptureServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn handle<S>(stream: S, state: &State) -> io::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut reader = BufReader::new(stream);
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).await?;
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let path = parts.next().unwrap_or_default().to_string();
+
+    let mut content_length = 0;
+    let mut authorized = false;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).await? == 0 {
+            break;
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            let value = value.trim();
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.parse().unwrap_or(0);
+            } else if name.eq_ignore_ascii_case("authorization") {
+                authorized = value.starts_with("Bearer ") && value.len() > "Bearer ".len();
+            }
+        }
+    }
+
+    // The body is only read when its size is within the limit of the real endpoint.
+    let mut body = Vec::new();
+    if content_length <= MAX_PAYLOAD_SIZE {
+        body.resize(content_length, 0);
+        reader.read_exact(&mut body).await?;
+    }
+
+    let (status, response) = if content_length > MAX_PAYLOAD_SIZE {
+        (
+            StatusCode::PAYLOAD_TOO_LARGE,
+            error_body("payload too large"),
+        )
+    } else if path != MAIL_SEND_PATH {
+        (StatusCode::NOT_FOUND, error_body("not found"))
+    } else if method != "POST" {
+        (
+            StatusCode::METHOD_NOT_ALLOWED,
+            error_body("method not allowed"),
+        )
+    } else if !authorized {
+        (
+            StatusCode::UNAUTHORIZED,
+            error_body("The provided authorization grant is invalid, expired, or revoked"),
+        )
+    } else if let Some(status) = state.failures.lock().unwrap().pop_front() {
+        (
+            status,
+            error_body(status.canonical_reason().unwrap_or("failure")),
+        )
+    } else {
+        match serde_json::from_slice::<Message>(&body) {
+            Ok(_) => {
+                state.captured.lock().unwrap().push(body);
+                (StatusCode::ACCEPTED, String::new())
+            }
+            Err(e) => (StatusCode::BAD_REQUEST, error_body(&e.to_string())),
+        }
+    };
+
+    state.in_flight.fetch_add(1, Ordering::SeqCst);
+    let result = respond(reader.into_inner(), status, &response, state).await;
+    state.in_flight.fetch_sub(1, Ordering::SeqCst);
+    result
+}
+
+async fn respond<S>(
+    mut stream: S,
+    status: StatusCode,
+    response: &str,
+    state: &State,
+) -> io::Result<()>
+where
+    S: AsyncWrite + Unpin,
+{
+    // Waiting only fails once the watch sender is dropped, which `state` prevents.
+    let _ = state.paused.subscribe().wait_for(|paused| !paused).await;
+
+    // SendGrid reports the rate limit of the endpoint on every response.
+    let reset = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| now.as_secs() + 60);
+    let head = format!(
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\
+         X-RateLimit-Limit: {}\r\nX-RateLimit-Remaining: {}\r\nX-RateLimit-Reset: {}\r\n\
+         Connection: close\r\n\r\n",
+        status,
+        response.len(),
+        RATE_LIMIT,
+        RATE_LIMIT - 1,
+        reset
+    );
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+fn error_body(message: &str) -> String {
+    serde_json::json!({ "errors": [{ "message": message }] }).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::response::ResponseExt;
+    use crate::v3::{Email, Personalization, Sender};
+
+    #[tokio::test]
+    async fn captures_sent_messages() {
+        let server = CaptureServer::start().await.unwrap();
+        let mut sender = Sender::new(String::from("SG.dev"), None);
+        sender.set_host(server.url()).unwrap();
+
+        let message = Message::new(Email::new("from@example.com"))
+            .set_subject("captured")
+            .add_personalization(Personalization::new(Email::new("to@example.com")));
+        let resp = sender.send(&message).await.unwrap();
+        assert_eq!(resp.status(), 202);
+        let rate_limit = resp.rate_limit().unwrap();
+        assert_eq!(rate_limit.limit, Some(RATE_LIMIT));
+        assert_eq!(rate_limit.remaining, Some(RATE_LIMIT - 1));
+
+        let captured = server.captured_json();
+        assert_eq!(captured.len(), 1);
+        assert_eq!(captured[0]["subject"], "captured");
+        assert_eq!(server.captured().len(), 1);
+        assert!(server.errors().is_empty());
+
+        server.clear();
+        assert!(server.captured().is_empty());
+    }
+
+    #[tokio::test]
+    async fn warm_up_connects() {
+        let server = CaptureServer::start().await.unwrap();
+        let mut sender = Sender::new(String::from("SG.dev"), None);
+        sender.set_host(server.url()).unwrap();
+        assert!(sender.warm_up().await.is_ok());
+
+        sender.set_host("http://127.0.0.1:1").unwrap();
+        assert!(sender.warm_up().await.is_err());
+    }
+
+    // Serves the capture endpoint over TLS with a certificate for localhost issued by the test CA
+    // in `tests/fixtures/tls`.
+    #[cfg(feature = "rustls-webpki-roots")]
+    async fn start_tls(state: Arc<State>) -> SocketAddr {
+        use tokio_rustls::rustls::pki_types::pem::PemObject;
+        use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+        use tokio_rustls::rustls::ServerConfig;
+        use tokio_rustls::TlsAcceptor;
+
+        let fixtures = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/tls");
+        let certs = CertificateDer::pem_file_iter(fixtures.join("localhost.pem"))
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let key = PrivateKeyDer::from_pem_file(fixtures.join("localhost.key")).unwrap();
+        let config = ServerConfig::builder()
+            .with_no_client_auth()
+            .with_single_cert(certs, key)
+            .unwrap();
+        let acceptor = TlsAcceptor::from(Arc::new(config));
+
+        let listener = TcpListener::bind(("127.0.0.1", 0)).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let (acceptor, state) = (acceptor.clone(), state.clone());
+                tokio::spawn(async move {
+                    if let Ok(stream) = acceptor.accept(stream).await {
+                        let _ = handle(stream, &state).await;
+                    }
+                });
+            }
+        });
+        addr
+    }
+
+    #[cfg(feature = "rustls-webpki-roots")]
+    #[tokio::test]
+    async fn sends_over_tls_with_bundled_roots() {
+        let state = Arc::new(State::new());
+        let addr = start_tls(state.clone()).await;
+        let url = format!("https://localhost:{}{}", addr.port(), MAIL_SEND_PATH);
+        let message = Message::new(Email::new("from@example.com"))
+            .add_personalization(Personalization::new(Email::new("to@example.com")));
+
+        // The default client only trusts the bundled roots, which do not include the test CA.
+        let mut sender = Sender::new(String::from("SG.dev"), None);
+        sender.set_host(url.clone()).unwrap();
+        let error = sender.send(&message).await.unwrap_err();
+        assert!(format!("{:?}", error).contains("UnknownIssuer"));
+        assert!(state.captured.lock().unwrap().is_empty());
+
+        let ca = std::fs::read(
+            std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/tls/ca.pem"),
+        )
+        .unwrap();
+        let client = crate::tls::client_builder()
+            .add_root_certificate(reqwest::Certificate::from_pem(&ca).unwrap())
+            .build()
+            .unwrap();
+        let mut sender = Sender::new(String::from("SG.dev"), Some(client));
+        sender.set_host(url).unwrap();
+        assert_eq!(sender.send(&message).await.unwrap().status(), 202);
+        assert_eq!(state.captured.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn rejects_missing_credentials() {
+        let server = CaptureServer::start().await.unwrap();
+        let mut sender = Sender::new(String::new(), None);
+        sender.set_host(server.url()).unwrap();
+
+        let message = Message::new(Email::new("from@example.com"))
+            .add_personalization(Personalization::new(Email::new("to@example.com")));
+        assert!(sender.send(&message).await.is_err());
+        assert!(server.captured().is_empty());
+    }
+
+    #[tokio::test]
+    async fn rejects_large_bodies() {
+        let server = CaptureServer::start().await.unwrap();
+        let mut stream = tokio::net::TcpStream::connect(server.addr()).await.unwrap();
+        let head = format!(
+            "POST {} HTTP/1.1\r\nAuthorization: Bearer SG.dev\r\nContent-Length: {}\r\n\r\n",
+            MAIL_SEND_PATH,
+            MAX_PAYLOAD_SIZE + 1
+        );
+        stream.write_all(head.as_bytes()).await.unwrap();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 413 Payload Too Large"));
+        assert!(server.captured().is_empty());
+    }
+
+    #[tokio::test]
+    async fn records_connection_errors() {
+        let server = CaptureServer::start().await.unwrap();
+        let mut stream = tokio::net::TcpStream::connect(server.addr()).await.unwrap();
+        let head = format!(
+            "POST {} HTTP/1.1\r\nContent-Length: 10\r\n\r\nshort",
+            MAIL_SEND_PATH
+        );
+        stream.write_all(head.as_bytes()).await.unwrap();
+        drop(stream);
+
+        let start = std::time::Instant::now();
+        while server.errors().is_empty() {
+            assert!(start.elapsed() < std::time::Duration::from_secs(5));
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        server.clear();
+        assert!(server.errors().is_empty());
+    }
+
+    #[tokio::test]
+    async fn simulates_failures() {
+        let server = CaptureServer::start().await.unwrap();
+        let mut sender = Sender::new(String::from("SG.dev"), None);
+        sender.set_host(server.url()).unwrap();
+        let message = Message::new(Email::new("from@example.com"))
+            .add_personalization(Personalization::new(Email::new("to@example.com")));
+
+        server.fail_next(StatusCode::SERVICE_UNAVAILABLE, 1);
+        assert!(matches!(
+            sender.send(&message).await,
+            Err(crate::SendgridError::RequestNotSuccessful(e)) if e.status == 503
+        ));
+        assert!(server.captured().is_empty());
+        assert_eq!(sender.send(&message).await.unwrap().status(), 202);
+        assert_eq!(server.captured().len(), 1);
+    }
+}
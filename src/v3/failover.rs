@@ -0,0 +1,196 @@
+//! Fail over to other hosts when a send fails, without breaking data residency.
+//!
+//! A [`FailoverSender`] tries a primary [`Sender`] and then each fallback in turn when the
+//! previous one failed with a transient error. Traffic pinned to the EU host is never failed over
+//! to a host outside the EU unless that is explicitly allowed, and every decision is reported to
+//! a callback so that it can be logged.
+
+use std::fmt;
+use std::sync::Arc;
+
+use reqwest::Response;
+
+use crate::error::SendgridResult;
+use crate::region::Region;
+use crate::v3::{Message, Sender};
+
+/// A decision taken by a [`FailoverSender`] after a transient failure.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FailoverDecision {
+    /// The message is retried on the `to` host.
+    FailedOver {
+        /// The host which failed.
+        from: String,
+        /// The host which is tried next.
+        to: String,
+    },
+
+    /// The `to` host was skipped because it would move EU pinned traffic out of the EU.
+    Refused {
+        /// The host which failed.
+        from: String,
+        /// The host which was skipped.
+        to: String,
+    },
+}
+
+type DecisionCallback = Arc<dyn Fn(&FailoverDecision) + Send + Sync>;
+
+/// Sends with a primary sender and fails over to fallback senders on transient errors.
+#[derive(Clone)]
+pub struct FailoverSender {
+    senders: Vec<Sender>,
+    allow_leaving_eu: bool,
+    on_decision: Option<DecisionCallback>,
+}
+
+impl FailoverSender {
+    /// Construct a failover sender which sends with `primary` first.
+    pub fn new(primary: Sender) -> FailoverSender {
+        FailoverSender {
+            senders: vec![primary],
+            allow_leaving_eu: false,
+            on_decision: None,
+        }
+    }
+
+    /// Add a sender to fail over to, after the ones added before it.
+    pub fn add_fallback(mut self, sender: Sender) -> FailoverSender {
+        self.senders.push(sender);
+        self
+    }
+
+    /// Allow failing over from the EU host to hosts outside the EU. Defaults to false.
+    pub fn set_allow_leaving_eu(mut self, allow_leaving_eu: bool) -> FailoverSender {
+        self.allow_leaving_eu = allow_leaving_eu;
+        self
+    }
+
+    /// Call `on_decision` whenever the sender fails over or refuses to.
+    pub fn set_on_decision<F>(mut self, on_decision: F) -> FailoverSender
+    where
+        F: Fn(&FailoverDecision) + Send + Sync + 'static,
+    {
+        self.on_decision = Some(Arc::new(on_decision));
+        self
+    }
+
+    /// Send the message, failing over on transient errors. The error of the last sender tried is
+    /// returned if none succeeded.
+    pub async fn send(&self, mail: &Message) -> SendgridResult<Response> {
+        let primary = &self.senders[0];
+        let mut current = primary;
+        let mut result = primary.send(mail).await;
+
+        for fallback in &self.senders[1..] {
+            match &result {
+                Err(e) if e.is_retryable() => {}
+                _ => break,
+            }
+
+            let decision = self.decide(primary, current, fallback);
+            if let Some(on_decision) = &self.on_decision {
+                on_decision(&decision);
+            }
+            if let FailoverDecision::FailedOver { .. } = decision {
+                current = fallback;
+                result = fallback.send(mail).await;
+            }
+        }
+
+        result
+    }
+
+    fn decide(&self, primary: &Sender, from: &Sender, to: &Sender) -> FailoverDecision {
+        let from = from.host.clone();
+        let to_host = to.host.clone();
+        if is_eu(&primary.host) && !is_eu(&to.host) && !self.allow_leaving_eu {
+            FailoverDecision::Refused { from, to: to_host }
+        } else {
+            FailoverDecision::FailedOver { from, to: to_host }
+        }
+    }
+}
+
+impl fmt::Debug for FailoverSender {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FailoverSender")
+            .field("senders", &self.senders)
+            .field("allow_leaving_eu", &self.allow_leaving_eu)
+            .finish()
+    }
+}
+
+fn is_eu(host: &str) -> bool {
+    url::Url::parse(host)
+        .ok()
+        .and_then(|url| {
+            url.host_str()
+                .map(|h| h.eq_ignore_ascii_case(Region::Eu.host()))
+        })
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use reqwest::StatusCode;
+
+    use super::*;
+    use crate::v3::dev_server::CaptureServer;
+    use crate::v3::{Email, Personalization};
+
+    fn sender(host: &str) -> Sender {
+        let mut sender = Sender::new(String::from("SG.key"), None);
+        sender.set_host(host).unwrap();
+        sender
+    }
+
+    #[test]
+    fn eu_traffic_stays_in_the_eu() {
+        let eu = Sender::with_region(String::from("SG.key"), Region::Eu);
+        let global = Sender::with_region(String::from("SG.key"), Region::Global);
+        let failover = FailoverSender::new(eu.clone());
+
+        assert!(matches!(
+            failover.decide(&eu, &eu, &global),
+            FailoverDecision::Refused { .. }
+        ));
+        assert!(matches!(
+            failover.decide(&global, &global, &eu),
+            FailoverDecision::FailedOver { .. }
+        ));
+        assert!(matches!(
+            failover
+                .set_allow_leaving_eu(true)
+                .decide(&eu, &eu, &global),
+            FailoverDecision::FailedOver { .. }
+        ));
+    }
+
+    #[tokio::test]
+    async fn transient_errors_fail_over() {
+        let primary = CaptureServer::start().await.unwrap();
+        let fallback = CaptureServer::start().await.unwrap();
+        primary.fail_next(StatusCode::SERVICE_UNAVAILABLE, 1);
+        let decisions = Arc::new(Mutex::new(Vec::new()));
+        let log = decisions.clone();
+        let failover = FailoverSender::new(sender(&primary.url()))
+            .add_fallback(sender(&fallback.url()))
+            .set_on_decision(move |decision| log.lock().unwrap().push(decision.clone()));
+
+        let message = Message::new(Email::new("from@example.com"))
+            .add_personalization(Personalization::new(Email::new("to@example.com")));
+        assert_eq!(failover.send(&message).await.unwrap().status(), 202);
+        assert!(primary.captured().is_empty());
+        assert_eq!(fallback.captured().len(), 1);
+        assert_eq!(
+            decisions.lock().unwrap()[..],
+            [FailoverDecision::FailedOver {
+                from: primary.url(),
+                to: fallback.url(),
+            }]
+        );
+    }
+}
@@ -0,0 +1,110 @@
+//! Stable fingerprints of the semantic content of a message.
+
+use serde_json::Value;
+
+use crate::error::SendgridResult;
+use crate::v3::{canonicalize, Message};
+
+// Headers which differ between otherwise identical sends.
+const VOLATILE_HEADERS: [&str; 2] = ["date", "message-id"];
+
+impl Message {
+    /// A stable hash of the content of this message, formatted as 16 hex digits. Fields which
+    /// change between retries of the same logical send, such as `send_at`, the batch id and the
+    /// `Message-ID` and `Date` headers, are excluded, so the fingerprint can be used as a dedupe
+    /// or idempotency key. The fingerprint does not depend on the order of map entries.
+    pub fn fingerprint(&self) -> SendgridResult<String> {
+        let mut value = self.to_json_value()?;
+        strip_volatile(&mut value);
+
+        let canonical = serde_json::to_vec(&canonicalize(value))?;
+        Ok(format!("{:016x}", fnv1a(&canonical)))
+    }
+}
+
+fn strip_volatile(message: &mut Value) {
+    let Some(message) = message.as_object_mut() else {
+        return;
+    };
+    message.remove("batch_id");
+    strip_headers(message.get_mut("headers"));
+
+    if let Some(personalizations) = message
+        .get_mut("personalizations")
+        .and_then(Value::as_array_mut)
+    {
+        for p in personalizations.iter_mut().filter_map(Value::as_object_mut) {
+            p.remove("send_at");
+            strip_headers(p.get_mut("headers"));
+        }
+    }
+}
+
+fn strip_headers(headers: Option<&mut Value>) {
+    if let Some(headers) = headers.and_then(Value::as_object_mut) {
+        headers.retain(|name, _| !VOLATILE_HEADERS.contains(&name.to_ascii_lowercase().as_str()));
+    }
+}
+
+/// The 64 bit FNV-1a hash of `data`.
+pub(crate) fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::v3::{Email, Message, Personalization, SGMap};
+
+    fn message(send_at: u64, message_id: &str) -> Message {
+        let mut headers = SGMap::new();
+        headers.insert(String::from("Message-ID"), String::from(message_id));
+        headers.insert(String::from("X-Tenant"), String::from("acme"));
+        Message::new(Email::new("from@example.com"))
+            .set_subject("Hello")
+            .add_headers(headers)
+            .add_personalization(
+                Personalization::new(Email::new("to@example.com")).set_send_at(send_at),
+            )
+    }
+
+    #[test]
+    fn volatile_fields_are_ignored() {
+        let a = message(1, "<a@example.com>").set_batch_id("batch-a");
+        let b = message(2, "<b@example.com>");
+        assert_eq!(a.fingerprint().unwrap(), b.fingerprint().unwrap());
+        assert_eq!(a.fingerprint().unwrap().len(), 16);
+
+        let c = message(1, "<a@example.com>").set_subject("Goodbye");
+        assert_ne!(a.fingerprint().unwrap(), c.fingerprint().unwrap());
+    }
+
+    #[test]
+    fn map_order_is_ignored() {
+        let forward = Message::new(Email::new("from@example.com"))
+            .add_header("X-Tenant", "acme")
+            .add_header("X-Campaign", "spring")
+            .add_personalization(
+                Personalization::new(Email::new("to@example.com"))
+                    .add_custom_arg("order", "1")
+                    .add_custom_arg("customer", "2")
+                    .add_substitution("-name-", "Jane")
+                    .add_substitution("-city-", "Berlin"),
+            );
+        let backward = Message::new(Email::new("from@example.com"))
+            .add_header("X-Campaign", "spring")
+            .add_header("X-Tenant", "acme")
+            .add_personalization(
+                Personalization::new(Email::new("to@example.com"))
+                    .add_substitution("-city-", "Berlin")
+                    .add_substitution("-name-", "Jane")
+                    .add_custom_arg("customer", "2")
+                    .add_custom_arg("order", "1"),
+            );
+        assert_eq!(
+            forward.fingerprint().unwrap(),
+            backward.fingerprint().unwrap()
+        );
+    }
+}
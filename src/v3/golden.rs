@@ -0,0 +1,152 @@
+// Golden tests locking the mail send wire format. Every optional field of the v3 types is set in
+// at least one fixture under `tests/fixtures/v3`. Run with `SENDGRID_RECORD=1 cargo test` to
+// re-record the fixtures after an intentional change to the format.
+//
+// Maps and sets hold a single entry so that the serialized order is deterministic.
+
+use serde::Serialize;
+use serde_json::json;
+
+use crate::v3::message::{
+    BypassBounceManagement, BypassFilterSettings, BypassListManagement, BypassSpamManagement,
+    BypassUnsubscribeManagement, Footer, GranularBypassFilterSettings, MailSettings, SandboxMode,
+    TopLevelBypassFilterSettings,
+};
+use crate::v3::{
+    Attachment, ClickTrackingSetting, Content, Disposition, Email, GanalyticsSetting, Message,
+    OpenTrackingSetting, Personalization, SubscriptionTrackingSetting, TrackingSettings, ASM,
+};
+
+fn check_golden<T: Serialize>(name: &str, value: &T) {
+    let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures/v3")
+        .join(format!("{}.json", name));
+    let body = serde_json::to_string_pretty(value).unwrap();
+
+    if std::env::var_os("SENDGRID_RECORD").is_some() {
+        std::fs::write(&path, format!("{}\n", body)).unwrap();
+        return;
+    }
+
+    let want = std::fs::read_to_string(&path)
+        .unwrap_or_else(|_| panic!("missing fixture {}", path.display()));
+    assert_eq!(body, want.trim_end(), "fixture {} does not match", name);
+}
+
+fn tracking_settings() -> TrackingSettings {
+    TrackingSettings::new()
+        .set_click_tracking(
+            ClickTrackingSetting::new()
+                .set_enable(true)
+                .set_enable_text(false),
+        )
+        .set_open_tracking(
+            OpenTrackingSetting::new()
+                .set_enable(true)
+                .set_substitution_tag("%open%"),
+        )
+        .set_subscription_tracking(
+            SubscriptionTrackingSetting::new()
+                .set_enable(true)
+                .set_text("Unsubscribe: <% %>")
+                .set_html("<a href=\"<% %>\">Unsubscribe</a>")
+                .set_substitution_tag("%unsubscribe%"),
+        )
+        .set_ganalytics(
+            GanalyticsSetting::new()
+                .set_enable(true)
+                .set_utm_source("newsletter")
+                .set_utm_medium("email")
+                .set_utm_term("term")
+                .set_utm_content("content")
+                .set_utm_campaign("launch"),
+        )
+}
+
+fn granular_mail_settings() -> MailSettings {
+    MailSettings::new()
+        .set_bypass_filter_settings(BypassFilterSettings::Granular(
+            GranularBypassFilterSettings::new()
+                .set_bypass_spam_management(BypassSpamManagement::new().set_enable(true))
+                .set_bypass_bounce_management(BypassBounceManagement::new().set_enable(false))
+                .set_bypass_unsubscribe_management(
+                    BypassUnsubscribeManagement::new().set_enable(true),
+                ),
+        ))
+        .set_footer(
+            Footer::new()
+                .set_enable(true)
+                .set_text(String::from("Footer"))
+                .set_html(String::from("<p>Footer</p>")),
+        )
+        .set_sandbox_mode(SandboxMode::new().set_enable(true))
+}
+
+fn personalization() -> Personalization {
+    Personalization::new(Email::new("to@example.com").set_name("To"))
+        .add_cc(Email::new("cc@example.com").set_name("Cc"))
+        .add_bcc(Email::new("bcc@example.com"))
+        .set_subject("Personal subject")
+        .add_header("X-Personal", "yes")
+        .add_substitution("-name-", "Ada")
+        .add_custom_arg("tenant_id", "acme")
+        .add_dynamic_template_data_json(&json!({ "order": { "id": 42 } }))
+        .unwrap()
+        .set_send_at(1_700_000_000)
+}
+
+#[test]
+fn golden_personalization_all_fields() {
+    check_golden("personalization_all_fields", &personalization());
+}
+
+#[test]
+fn golden_tracking_settings_all_fields() {
+    check_golden("tracking_settings_all_fields", &tracking_settings());
+}
+
+#[test]
+fn golden_mail_settings_granular_bypass() {
+    check_golden("mail_settings_granular_bypass", &granular_mail_settings());
+}
+
+#[test]
+fn golden_mail_settings_bypass_list_management() {
+    let settings = MailSettings::new().set_bypass_filter_settings(BypassFilterSettings::TopLevel(
+        TopLevelBypassFilterSettings::new()
+            .set_bypass_list_management(BypassListManagement::new().set_enable(true)),
+    ));
+    check_golden("mail_settings_bypass_list_management", &settings);
+}
+
+#[test]
+fn golden_message_all_fields() {
+    let message = Message::new(Email::new("from@example.com").set_name("From"))
+        .set_subject("Subject")
+        .add_personalization(personalization())
+        .add_category("receipts")
+        .set_ip_pool_name("transactional")
+        .set_reply_to(Email::new("reply@example.com").set_name("Reply"))
+        .add_content(Content::text("Hello"))
+        .add_content(Content::html("<p>Hello</p>"))
+        .add_attachment(
+            Attachment::new()
+                .set_content(b"hello")
+                .set_filename("hello.txt")
+                .set_mime_type("text/plain")
+                .set_disposition(Disposition::Inline)
+                .set_content_id("hello"),
+        )
+        .set_template_id("d-123")
+        .set_batch_id("batch-1")
+        .set_tracking_settings(tracking_settings())
+        .set_asm(
+            ASM::new()
+                .set_group_id(7)
+                .set_groups_to_display([7])
+                .unwrap(),
+        )
+        .set_mail_settings(granular_mail_settings())
+        .add_header("X-Campaign", "launch");
+    check_golden("message_all_fields", &message);
+}
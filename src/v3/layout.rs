@@ -0,0 +1,203 @@
+//! Render simple transactional emails without writing HTML.
+//!
+//! [`EmailLayout`] stacks headings, paragraphs and buttons into a single centered column built
+//! from tables, which is what Outlook and other old rendering engines lay out reliably. Buttons
+//! are drawn with VML in Outlook so they keep their padding and background.
+//!
+//! ```
+//! use sendgrid::v3::layout::EmailLayout;
+//! use sendgrid::v3::{Email, Message};
+//!
+//! let layout = EmailLayout::new()
+//!     .header("Confirm your address")
+//!     .paragraph("Click the button below to confirm your email address.")
+//!     .button("https://example.com/confirm?token=abc", "Confirm");
+//!
+//! let message = Message::new(Email::new("from@example.com"))
+//!     .add_content(layout.to_text_content())
+//!     .add_content(layout.to_content());
+//! ```
+
+use crate::v3::preheader::escape_html;
+use crate::v3::Content;
+
+// The width of the column in pixels.
+const WIDTH: u32 = 600;
+
+const FONT: &str = "font-family:Helvetica,Arial,sans-serif;";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Block {
+    Header(String),
+    Paragraph(String),
+    Button { url: String, label: String },
+}
+
+/// A single column email layout.
+#[must_use]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EmailLayout {
+    blocks: Vec<Block>,
+    lang: String,
+    accent_color: String,
+}
+
+impl Default for EmailLayout {
+    fn default() -> Self {
+        EmailLayout {
+            blocks: Vec::new(),
+            lang: String::from("en"),
+            accent_color: String::from("#1a73e8"),
+        }
+    }
+}
+
+impl EmailLayout {
+    /// Construct an empty layout.
+    pub fn new() -> EmailLayout {
+        EmailLayout::default()
+    }
+
+    /// Set the language of the text, which screen readers use to pick a voice. Defaults to `en`.
+    pub fn set_lang<S: Into<String>>(mut self, lang: S) -> EmailLayout {
+        self.lang = lang.into();
+        self
+    }
+
+    /// Set the background color of buttons as a CSS hex color. Defaults to `#1a73e8`.
+    pub fn set_accent_color<S: Into<String>>(mut self, color: S) -> EmailLayout {
+        self.accent_color = color.into();
+        self
+    }
+
+    /// Add a heading.
+    pub fn header<S: Into<String>>(mut self, text: S) -> EmailLayout {
+        self.blocks.push(Block::Header(text.into()));
+        self
+    }
+
+    /// Add a paragraph of text.
+    pub fn paragraph<S: Into<String>>(mut self, text: S) -> EmailLayout {
+        self.blocks.push(Block::Paragraph(text.into()));
+        self
+    }
+
+    /// Add a button linking to `url`.
+    pub fn button<U: Into<String>, L: Into<String>>(mut self, url: U, label: L) -> EmailLayout {
+        self.blocks.push(Block::Button {
+            url: url.into(),
+            label: label.into(),
+        });
+        self
+    }
+
+    /// Render the layout as an HTML document. Text is escaped.
+    pub fn render(&self) -> String {
+        let mut rows = String::new();
+        for block in &self.blocks {
+            rows.push_str("<tr><td style=\"padding:0 24px 16px 24px;\">");
+            match block {
+                Block::Header(text) => rows.push_str(&format!(
+                    "<h1 style=\"margin:0;{}font-size:24px;line-height:32px;color:#202124;\">{}</h1>",
+                    FONT,
+                    escape_html(text)
+                )),
+                Block::Paragraph(text) => rows.push_str(&format!(
+                    "<p style=\"margin:0;{}font-size:16px;line-height:24px;color:#3c4043;\">{}</p>",
+                    FONT,
+                    escape_html(text)
+                )),
+                Block::Button { url, label } => rows.push_str(&self.render_button(url, label)),
+            }
+            rows.push_str("</td></tr>");
+        }
+
+        format!(
+            concat!(
+                "<!DOCTYPE html><html lang=\"{lang}\" xmlns:v=\"urn:schemas-microsoft-com:vml\">",
+                "<head><meta charset=\"utf-8\">",
+                "<meta name=\"viewport\" content=\"width=device-width,initial-scale=1\">",
+                "</head><body style=\"margin:0;padding:0;background-color:#f1f3f4;\">",
+                "<table role=\"presentation\" width=\"100%\" cellpadding=\"0\" cellspacing=\"0\" ",
+                "border=\"0\" style=\"background-color:#f1f3f4;\"><tr><td align=\"center\" ",
+                "style=\"padding:24px 0;\">",
+                "<table role=\"presentation\" width=\"{width}\" cellpadding=\"0\" cellspacing=\"0\" ",
+                "border=\"0\" style=\"width:100%;max-width:{width}px;background-color:#ffffff;\">",
+                "<tr><td style=\"height:24px;\"></td></tr>{rows}<tr><td style=\"height:8px;\"></td></tr>",
+                "</table></td></tr></table></body></html>"
+            ),
+            lang = escape_html(&self.lang),
+            width = WIDTH,
+            rows = rows,
+        )
+    }
+
+    fn render_button(&self, url: &str, label: &str) -> String {
+        let url = escape_html(url);
+        let label = escape_html(label);
+        let color = escape_html(&self.accent_color);
+        format!(
+            concat!(
+                "<!--[if mso]><v:roundrect href=\"{url}\" style=\"height:44px;v-text-anchor:middle;",
+                "width:220px;\" arcsize=\"10%\" stroke=\"f\" fillcolor=\"{color}\"><center ",
+                "style=\"color:#ffffff;{font}font-size:16px;font-weight:bold;\">{label}</center>",
+                "</v:roundrect><![endif]--><!--[if !mso]><!-->",
+                "<a href=\"{url}\" style=\"display:inline-block;background-color:{color};",
+                "color:#ffffff;{font}font-size:16px;font-weight:bold;line-height:44px;",
+                "padding:0 24px;border-radius:4px;text-decoration:none;\">{label}</a><!--<![endif]-->"
+            ),
+            url = url,
+            color = color,
+            font = FONT,
+            label = label,
+        )
+    }
+
+    /// Render the layout as plain text, with buttons written as their label and URL.
+    pub fn render_text(&self) -> String {
+        self.blocks
+            .iter()
+            .map(|block| match block {
+                Block::Header(text) | Block::Paragraph(text) => text.clone(),
+                Block::Button { url, label } => format!("{}: {}", label, url),
+            })
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+
+    /// Render the layout into `text/html` content.
+    pub fn to_content(&self) -> Content {
+        Content::html(self.render())
+    }
+
+    /// Render the layout into `text/plain` content, the alternative for clients without HTML.
+    pub fn to_text_content(&self) -> Content {
+        Content::text(self.render_text())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn layouts_are_rendered() {
+        let layout = EmailLayout::new()
+            .header("Hi <Ada>")
+            .paragraph("Your report is ready.")
+            .button("https://example.com/r?a=1&b=2", "Open");
+
+        let html = layout.render();
+        assert!(html.starts_with("<!DOCTYPE html><html lang=\"en\""));
+        assert!(html.contains(">Hi &lt;Ada&gt;</h1>"));
+        assert!(html.contains("<v:roundrect href=\"https://example.com/r?a=1&amp;b=2\""));
+        assert!(html.contains("<a href=\"https://example.com/r?a=1&amp;b=2\""));
+        assert_eq!(html.matches("role=\"presentation\"").count(), 2);
+
+        assert_eq!(
+            layout.render_text(),
+            "Hi <Ada>\n\nYour report is ready.\n\nOpen: https://example.com/r?a=1&b=2"
+        );
+        assert_eq!(layout.to_content().content_type, "text/html");
+    }
+}
@@ -0,0 +1,247 @@
+//! Build personalizations for a mail merge from a CSV file.
+//!
+//! [`CsvMerge`] turns every row of a CSV file into a [`Personalization`]. One column holds the
+//! recipient's email address and another, optionally, their name. The remaining columns become
+//! substitutions for legacy templates or dynamic template data for dynamic templates.
+//!
+//! ```
+//! # fn main() -> sendgrid::SendgridResult<()> {
+//! use sendgrid::v3::merge::{CsvMerge, MergeData};
+//!
+//! let data = "email,name,plan\nada@example.com,Ada,pro\n";
+//! let mut reader = csv::Reader::from_reader(data.as_bytes());
+//! let personalizations = CsvMerge::new("email")
+//!     .set_name_column("name")
+//!     .set_data(MergeData::DynamicTemplateData)
+//!     .personalizations(&mut reader)?;
+//! assert_eq!(personalizations.len(), 1);
+//! # Ok(())
+//! # }
+//! ```
+
+use std::io::Read;
+
+use csv::StringRecord;
+use serde_json::{Map, Value};
+
+use crate::error::{SendgridError, SendgridResult};
+use crate::v3::{Email, Personalization};
+
+/// Where the data columns of a row are put.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum MergeData {
+    /// Substitutions for legacy templates. Each column name is wrapped in the given prefix and
+    /// suffix to form the substitution tag, for example `-name-`.
+    Substitutions {
+        /// Prepended to the column name.
+        prefix: String,
+        /// Appended to the column name.
+        suffix: String,
+    },
+
+    /// Dynamic template data, with one string field per column.
+    #[default]
+    DynamicTemplateData,
+}
+
+/// Maps the columns of a CSV file to personalizations.
+#[must_use]
+#[derive(Clone, Debug)]
+pub struct CsvMerge {
+    email_column: String,
+    name_column: Option<String>,
+    data: MergeData,
+}
+
+impl CsvMerge {
+    /// Construct a merge which reads the recipient address from `email_column` and puts every
+    /// other column into dynamic template data.
+    pub fn new<S: Into<String>>(email_column: S) -> CsvMerge {
+        CsvMerge {
+            email_column: email_column.into(),
+            name_column: None,
+            data: MergeData::default(),
+        }
+    }
+
+    /// Read the recipient name from `name_column`.
+    pub fn set_name_column<S: Into<String>>(mut self, name_column: S) -> CsvMerge {
+        self.name_column = Some(name_column.into());
+        self
+    }
+
+    /// Set where the data columns are put.
+    pub fn set_data(mut self, data: MergeData) -> CsvMerge {
+        self.data = data;
+        self
+    }
+
+    /// Build one personalization per row of `reader`, whose first row must be the header.
+    pub fn personalizations<R: Read>(
+        &self,
+        reader: &mut csv::Reader<R>,
+    ) -> SendgridResult<Vec<Personalization>> {
+        let headers = reader.headers()?.clone();
+        let (email_index, name_index) = self.column_indexes(&headers)?;
+
+        let mut personalizations = Vec::new();
+        for (row, record) in reader.records().enumerate() {
+            let record = record?;
+            match self.merge(&headers, &record, email_index, name_index)? {
+                Some(p) => personalizations.push(p),
+                None => {
+                    return Err(SendgridError::Merge(format!(
+                        "row {} has no email address",
+                        row + 1
+                    )))
+                }
+            }
+        }
+
+        Ok(personalizations)
+    }
+
+    /// Build the personalization of a single `record` of a CSV file whose header is `headers`.
+    pub fn personalization(
+        &self,
+        headers: &StringRecord,
+        record: &StringRecord,
+    ) -> SendgridResult<Personalization> {
+        let (email_index, name_index) = self.column_indexes(headers)?;
+        self.merge(headers, record, email_index, name_index)?
+            .ok_or_else(|| SendgridError::Merge(String::from("the record has no email address")))
+    }
+
+    fn column_indexes(&self, headers: &StringRecord) -> SendgridResult<(usize, Option<usize>)> {
+        let email_index = column_index(headers, &self.email_column)?;
+        let name_index = match &self.name_column {
+            Some(column) => Some(column_index(headers, column)?),
+            None => None,
+        };
+        Ok((email_index, name_index))
+    }
+
+    // Returns `None` if the record has no email address.
+    fn merge(
+        &self,
+        headers: &StringRecord,
+        record: &StringRecord,
+        email_index: usize,
+        name_index: Option<usize>,
+    ) -> SendgridResult<Option<Personalization>> {
+        let email = record.get(email_index).unwrap_or_default().trim();
+        if email.is_empty() {
+            return Ok(None);
+        }
+
+        let mut to = Email::new(email);
+        if let Some(name) = name_index.and_then(|i| record.get(i)) {
+            if !name.is_empty() {
+                to = to.set_name(name);
+            }
+        }
+
+        let fields = headers
+            .iter()
+            .zip(record.iter())
+            .enumerate()
+            .filter(|(i, _)| *i != email_index && Some(*i) != name_index)
+            .map(|(_, field)| field);
+
+        let p = Personalization::new(to);
+        Ok(Some(match &self.data {
+            MergeData::Substitutions { prefix, suffix } => p.add_substitutions(
+                fields.map(|(column, value)| (format!("{}{}{}", prefix, column, suffix), value)),
+            ),
+            MergeData::DynamicTemplateData => {
+                let data: Map<String, Value> = fields
+                    .map(|(column, value)| (column.to_string(), Value::from(value)))
+                    .collect();
+                p.add_dynamic_template_data_json(&data)?
+            }
+        }))
+    }
+}
+
+impl Personalization {
+    /// Build a personalization from a CSV record whose header is `headers`. The `email` column
+    /// holds the recipient address, the optional `name` column their name and every other column
+    /// becomes dynamic template data. Use [`CsvMerge`] to choose the columns.
+    pub fn from_csv_record(
+        headers: &StringRecord,
+        record: &StringRecord,
+    ) -> SendgridResult<Personalization> {
+        let mut merge = CsvMerge::new("email");
+        if headers.iter().any(|header| header == "name") {
+            merge = merge.set_name_column("name");
+        }
+        merge.personalization(headers, record)
+    }
+}
+
+fn column_index(headers: &StringRecord, column: &str) -> SendgridResult<usize> {
+    headers
+        .iter()
+        .position(|header| header == column)
+        .ok_or_else(|| SendgridError::Merge(format!("the CSV file has no `{}` column", column)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DATA: &str = "email,name,plan\nada@example.com,Ada,pro\ngrace@example.com,,free\n";
+
+    #[test]
+    fn rows_become_personalizations() {
+        let mut reader = csv::Reader::from_reader(DATA.as_bytes());
+        let personalizations = CsvMerge::new("email")
+            .set_name_column("name")
+            .set_data(MergeData::Substitutions {
+                prefix: String::from("-"),
+                suffix: String::from("-"),
+            })
+            .personalizations(&mut reader)
+            .unwrap();
+
+        let json = serde_json::to_string(&personalizations).unwrap();
+        let expected = r#"[{"to":[{"email":"ada@example.com","name":"Ada"}],"substitutions":{"-plan-":"pro"}},{"to":[{"email":"grace@example.com"}],"substitutions":{"-plan-":"free"}}]"#;
+        assert_eq!(json, expected);
+
+        let mut reader = csv::Reader::from_reader(DATA.as_bytes());
+        let personalizations = CsvMerge::new("email")
+            .personalizations(&mut reader)
+            .unwrap();
+        let value = serde_json::to_value(&personalizations).unwrap();
+        assert_eq!(value[0]["dynamic_template_data"]["name"], "Ada");
+        assert_eq!(value[1]["dynamic_template_data"]["plan"], "free");
+    }
+
+    #[test]
+    fn records_become_personalizations() {
+        let headers = StringRecord::from(vec!["email", "name", "plan"]);
+        let record = StringRecord::from(vec!["ada@example.com", "Ada", "pro"]);
+        let value =
+            serde_json::to_value(Personalization::from_csv_record(&headers, &record).unwrap())
+                .unwrap();
+        assert_eq!(value["to"][0]["name"], "Ada");
+        assert_eq!(value["dynamic_template_data"]["plan"], "pro");
+        assert!(value["dynamic_template_data"].get("name").is_none());
+
+        let headers = StringRecord::from(vec!["email", "plan"]);
+        let record = StringRecord::from(vec!["", "pro"]);
+        assert!(matches!(
+            Personalization::from_csv_record(&headers, &record),
+            Err(SendgridError::Merge(_))
+        ));
+    }
+
+    #[test]
+    fn missing_columns_are_reported() {
+        let mut reader = csv::Reader::from_reader(DATA.as_bytes());
+        assert!(matches!(
+            CsvMerge::new("address").personalizations(&mut reader),
+            Err(SendgridError::Merge(_))
+        ));
+    }
+}
@@ -1,13 +1,18 @@
 //! Provides types related to [`crate::v3::Message`].
 
-use serde::Serialize;
+use serde::{Deserialize, Deserializer, Serialize};
 
 /// The settings to use when sending the [`crate::v3::Message`].
 /// See the [api docs](https://www.twilio.com/docs/sendgrid/api-reference/mail-send/mail-send#request-body)
 /// for details.
-#[derive(Default, Serialize)]
+#[must_use]
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
 pub struct MailSettings {
-    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    #[serde(
+        flatten,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "deserialize_bypass_filter_settings"
+    )]
     bypass_filter_settings: Option<BypassFilterSettings>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -24,7 +29,7 @@ pub struct MailSettings {
 /// `bypass_unsubscribe_management`) are ignored.
 ///
 /// See: <https://www.twilio.com/docs/sendgrid/ui/sending-email/index-suppressions#bypass-filters-and-v3-mail-send>
-#[derive(Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
 #[serde(untagged)]
 pub enum BypassFilterSettings {
     /// Variant to configure bypassing all list suppressions with the `bypass_list_management` field.
@@ -34,23 +39,44 @@ pub enum BypassFilterSettings {
     Granular(GranularBypassFilterSettings),
 }
 
+// The flattened, untagged settings match any mail settings object, so settings without any bypass
+// filter would otherwise come back as an empty granular filter.
+fn deserialize_bypass_filter_settings<'de, D>(
+    deserializer: D,
+) -> Result<Option<BypassFilterSettings>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let settings = Option::<BypassFilterSettings>::deserialize(deserializer)?;
+    Ok(settings.filter(|settings| match settings {
+        BypassFilterSettings::TopLevel(_) => true,
+        BypassFilterSettings::Granular(granular) => {
+            granular.bypass_spam_management.is_some()
+                || granular.bypass_bounce_management.is_some()
+                || granular.bypass_unsubscribe_management.is_some()
+        }
+    }))
+}
+
 /// Used to configure bypassing all list suppressions with the `bypass_list_management` field.
-#[derive(Default, Serialize)]
+#[must_use]
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
 pub struct TopLevelBypassFilterSettings {
-    #[serde(default)]
     bypass_list_management: BypassListManagement,
 }
 
 // TODO: Make a single type with the boolean enable field?
 /// Used for the bypass list management setting.
-#[derive(Default, Serialize)]
+#[must_use]
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
 pub struct BypassListManagement {
     enable: bool,
 }
 
 /// Used to configure bypassing specific list suppressions with the `bypass_spam_management`,
 /// `bypass_bounce_management`, and `bypass_unsubscribe_management` fields.
-#[derive(Default, Serialize)]
+#[must_use]
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
 pub struct GranularBypassFilterSettings {
     #[serde(skip_serializing_if = "Option::is_none")]
     bypass_spam_management: Option<BypassSpamManagement>,
@@ -63,25 +89,29 @@ pub struct GranularBypassFilterSettings {
 }
 
 /// Used for the bypass spam management setting.
-#[derive(Default, Serialize)]
+#[must_use]
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
 pub struct BypassSpamManagement {
     enable: bool,
 }
 
 /// Used for the bypass bounce management setting.
-#[derive(Default, Serialize)]
+#[must_use]
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
 pub struct BypassBounceManagement {
     enable: bool,
 }
 
 /// Used for the bypass unsubscribe management setting.
-#[derive(Default, Serialize)]
+#[must_use]
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
 pub struct BypassUnsubscribeManagement {
     enable: bool,
 }
 
 /// Used to provide a footer for the [`crate::v3::Message`].
-#[derive(Default, Serialize)]
+#[must_use]
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
 pub struct Footer {
     enable: bool,
 
@@ -93,7 +123,8 @@ pub struct Footer {
 }
 
 /// Used for the sandbox mode setting.
-#[derive(Default, Serialize)]
+#[must_use]
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
 pub struct SandboxMode {
     enable: bool,
 }
@@ -121,6 +152,11 @@ impl MailSettings {
         self.sandbox_mode = Some(sandbox_mode);
         self
     }
+
+    /// Whether sandbox mode is enabled.
+    pub fn sandbox_mode_enabled(&self) -> bool {
+        self.sandbox_mode.as_ref().is_some_and(|mode| mode.enable)
+    }
 }
 
 impl TopLevelBypassFilterSettings {
@@ -1,38 +1,221 @@
 //! This module encompasses all types needed to send mail using version 3 of the mail
 //! send API.
 
-use std::collections::{HashMap, HashSet};
+use std::borrow::Cow;
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use data_encoding::BASE64;
+use futures::StreamExt;
+use indexmap::IndexMap;
 use reqwest::header::{self, HeaderMap, HeaderValue, InvalidHeaderValue};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use serde_json::{to_value, value::Value, value::Value::Object, Map};
 
+use crate::dns::DnsOptions;
 use crate::error::{RequestNotSuccessful, SendgridError, SendgridResult};
-use crate::v3::message::MailSettings;
+use crate::headers::check_headers;
+use crate::inbound::InboundEmail;
+use crate::interceptor::{Interceptors, SendInterceptor};
+use crate::region::Region;
+use crate::response::{ResponseExt, SendResponse};
+use crate::tls;
+use crate::v3::archive::{ArchiveRecord, ArchiveSink, SharedArchiveSink};
+#[cfg(feature = "blocking")]
+use crate::v3::blocking_client::BlockingClient;
+use crate::v3::custom_args::CustomArgSchema;
+use crate::v3::message::{MailSettings, SandboxMode};
+#[cfg(feature = "rate-limit")]
+use crate::v3::rate_limit::SenderRateLimit;
+use crate::v3::redaction::{RedactionLevel, SharedRedactionLevel};
+use crate::v3::request_id::RequestIdProvider;
+#[cfg(feature = "retry")]
+use crate::v3::retry::RetryPolicy;
+use crate::v3::subject::SubjectNormalization;
+use crate::v3::utm::UtmTagging;
 #[cfg(feature = "blocking")]
 use reqwest::blocking::Response as BlockingResponse;
-use reqwest::{Client, Response};
+use reqwest::{Client, Response, StatusCode};
 
+pub mod archive;
+#[cfg(feature = "blocking")]
+mod blocking_client;
+pub mod budget;
+pub mod builder;
+pub mod canary;
+#[cfg(feature = "consumer")]
+pub mod consumer;
+pub mod custom_args;
+#[cfg(any(test, feature = "dev-server"))]
+pub mod dev_server;
+pub mod failover;
+mod fingerprint;
+#[cfg(test)]
+mod golden;
+#[cfg(feature = "layout")]
+pub mod layout;
+#[cfg(feature = "csv")]
+pub mod merge;
 pub mod message;
+#[cfg(any(feature = "axum", feature = "actix"))]
+pub mod multipart;
+#[cfg(feature = "outbox")]
+pub mod outbox;
+#[cfg(all(test, feature = "blocking"))]
+mod parity;
+pub mod preheader;
+#[cfg(feature = "stats")]
+pub mod quota;
+#[cfg(feature = "rate-limit")]
+pub mod rate_limit;
+pub mod redaction;
+pub mod request_id;
+#[cfg(feature = "retry")]
+pub mod retry;
+pub mod rotating;
+#[cfg(feature = "tz")]
+pub mod schedule;
+#[cfg(feature = "chrono")]
+pub mod send_at;
+mod sender_builder;
+pub mod sink;
+pub mod subject;
+pub mod utm;
+
+pub use sender_builder::SenderBuilder;
 
 const V3_API_URL: &str = "https://api.sendgrid.com/v3/mail/send";
+const MAIL_SEND_PATH: &str = "/v3/mail/send";
+
+const USER_AGENT: &str = "sendgrid-rs";
+
+/// The header which makes a parent account act as one of its subusers.
+pub const ON_BEHALF_OF_HEADER: &str = "on-behalf-of";
+
+/// The most personalizations SendGrid accepts in one request.
+pub const MAX_PERSONALIZATIONS: usize = 1000;
+
+/// The most to, cc and bcc recipients SendGrid accepts in one request, across all personalizations.
+pub const MAX_RECIPIENTS: usize = 1000;
+
+/// The most unsubscribe groups which can be displayed on the unsubscribe preferences page.
+pub const MAX_GROUPS_TO_DISPLAY: usize = 25;
+
+/// The largest mail send payload SendGrid accepts, including base64 encoded attachments.
+pub const MAX_PAYLOAD_SIZE: usize = 30 * 1024 * 1024;
+
+// A multiple of 3 so that every chunk but the last encodes without base64 padding.
+const ATTACHMENT_CHUNK_SIZE: usize = 3 * 4096;
 
 /// Just a redefinition of a map to store string keys and values.
 pub type SGMap = HashMap<String, String>;
 
+// Headers, substitutions and custom args keep their insertion order so the JSON body is
+// deterministic.
+type OrderedMap = IndexMap<String, String>;
+
 /// Used to send a V3 message body.
-#[derive(Clone, Debug)]
+#[derive(Clone)]
 pub struct Sender {
     api_key: String,
     client: Client,
     #[cfg(feature = "blocking")]
-    blocking_client: reqwest::blocking::Client,
+    blocking_client: BlockingClient,
     host: String,
+    user_agent: String,
+    on_behalf_of: Option<String>,
+    client_authorization: bool,
+    custom_arg_schema: Option<CustomArgSchema>,
+    validate: bool,
+    validate_size: bool,
+    check_sendable: bool,
+    sandbox: bool,
+    request_id_provider: Option<RequestIdProvider>,
+    archive_sink: Option<SharedArchiveSink>,
+    interceptors: Interceptors,
+    subject_normalization: Option<SubjectNormalization>,
+    utm_tagging: Option<UtmTagging>,
+    redaction: SharedRedactionLevel,
+    #[cfg(feature = "concurrency-limit")]
+    concurrency: Option<Arc<tokio::sync::Semaphore>>,
+    #[cfg(feature = "rate-limit")]
+    rate_limit: Option<SenderRateLimit>,
+    #[cfg(feature = "retry")]
+    retry_policy: Option<RetryPolicy>,
+    defaults: MessageDefaults,
+}
+
+// The API key is left out of the debug output so senders can be logged.
+impl std::fmt::Debug for Sender {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut debug = f.debug_struct("Sender");
+        debug
+            .field("api_key", &"[redacted]")
+            .field("client", &self.client)
+            .field("host", &self.host)
+            .field("user_agent", &self.user_agent)
+            .field("on_behalf_of", &self.on_behalf_of)
+            .field("client_authorization", &self.client_authorization)
+            .field("custom_arg_schema", &self.custom_arg_schema)
+            .field("validate", &self.validate)
+            .field("validate_size", &self.validate_size)
+            .field("check_sendable", &self.check_sendable)
+            .field("sandbox", &self.sandbox)
+            .field("request_id_provider", &self.request_id_provider)
+            .field("archive_sink", &self.archive_sink)
+            .field("interceptors", &self.interceptors)
+            .field("subject_normalization", &self.subject_normalization)
+            .field("utm_tagging", &self.utm_tagging)
+            .field("redaction", &self.redaction.get());
+        #[cfg(feature = "concurrency-limit")]
+        debug.field("concurrency", &self.concurrency);
+        #[cfg(feature = "rate-limit")]
+        debug.field("rate_limit", &self.rate_limit);
+        #[cfg(feature = "retry")]
+        debug.field("retry_policy", &self.retry_policy);
+        debug.field("defaults", &self.defaults).finish()
+    }
+}
+
+// Values applied to messages which do not set them.
+#[derive(Clone, Debug, Default)]
+struct MessageDefaults {
+    from: Option<Email>,
+    from_name: Option<String>,
+    reply_to: Option<Email>,
+}
+
+impl MessageDefaults {
+    fn applies_to(&self, mail: &Message) -> bool {
+        (self.from.is_some() && mail.from.email.is_empty())
+            || (self.from_name.is_some() && mail.from.name.is_none())
+            || (self.reply_to.is_some() && mail.reply_to.is_none())
+    }
+
+    fn apply(&self, mut mail: Message) -> Message {
+        if mail.from.email.is_empty() {
+            if let Some(from) = &self.from {
+                mail.from.email = from.email.clone();
+                mail.from.name = mail.from.name.or_else(|| from.name.clone());
+            }
+        }
+        if mail.from.name.is_none() {
+            mail.from.name = self.from_name.clone();
+        }
+        if mail.reply_to.is_none() {
+            mail.reply_to = self.reply_to.clone();
+        }
+        mail
+    }
 }
 
 /// Used for open tracking settings.
-#[derive(Clone, Serialize)]
+#[must_use]
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
 pub struct OpenTrackingSetting {
     /// Whether or not to enable open tracking.
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -44,15 +227,32 @@ pub struct OpenTrackingSetting {
 }
 
 /// Used for subscription tracking settings.
-#[derive(Clone, Serialize)]
+#[must_use]
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
 pub struct SubscriptionTrackingSetting {
     /// Whether or not to enable subscription tracking.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub enable: Option<bool>,
+
+    /// Text to be appended to the email with the subscription tracking link. The text may include
+    /// `<% %>` to mark where the link should go.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+
+    /// HTML to be appended to the email with the subscription tracking link. The HTML may include
+    /// `<% %>` to mark where the link should go.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub html: Option<String>,
+
+    /// A tag that will be replaced with the unsubscribe URL. When used, it overrides `text` and
+    /// `html`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub substitution_tag: Option<String>,
 }
 
 /// Used for click tracking settings.
-#[derive(Clone, Serialize)]
+#[must_use]
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
 pub struct ClickTrackingSetting {
     /// Whether or not to enable click tracking.
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -63,8 +263,39 @@ pub struct ClickTrackingSetting {
     pub enable_text: Option<bool>,
 }
 
+/// Used for Google Analytics tracking settings. The `utm_*` values are appended to the links in
+/// the message.
+#[must_use]
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct GanalyticsSetting {
+    /// Whether or not to enable Google Analytics tracking.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub enable: Option<bool>,
+
+    /// Name of the referrer source, for example Google or a newsletter.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub utm_source: Option<String>,
+
+    /// Name of the marketing medium, for example email.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub utm_medium: Option<String>,
+
+    /// Used to identify any paid keywords.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub utm_term: Option<String>,
+
+    /// Used to differentiate the campaign from advertisements.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub utm_content: Option<String>,
+
+    /// The name of the campaign.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub utm_campaign: Option<String>,
+}
+
 /// Used for all tracking settings.
-#[derive(Clone, Serialize)]
+#[must_use]
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
 pub struct TrackingSettings {
     /// Used for click tracking settings.
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -77,14 +308,27 @@ pub struct TrackingSettings {
     /// Used for subscription tracking settings.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub subscription_tracking: Option<SubscriptionTrackingSetting>,
+
+    /// Used for Google Analytics tracking settings.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ganalytics: Option<GanalyticsSetting>,
 }
 
 /// The main structure for a V3 API mail send call. This is composed of many other smaller
 /// structures used to add lots of customization to your message.
-#[derive(Serialize)]
+///
+/// The default message has an empty from address, which is filled in by the sender when it has a
+/// default from address, see [`Sender::set_default_from`].
+#[must_use]
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
 pub struct Message {
     from: Email,
+
+    // Messages using a template often leave the subject to the template.
+    #[serde(default)]
     subject: String,
+
+    #[serde(default)]
     personalizations: Vec<Personalization>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -105,6 +349,9 @@ pub struct Message {
     #[serde(skip_serializing_if = "Option::is_none")]
     template_id: Option<String>,
 
+    #[serde(skip_serializing_if = "Option::is_none")]
+    batch_id: Option<String>,
+
     #[serde(skip_serializing_if = "Option::is_none")]
     tracking_settings: Option<TrackingSettings>,
 
@@ -113,10 +360,14 @@ pub struct Message {
 
     #[serde(skip_serializing_if = "Option::is_none")]
     mail_settings: Option<MailSettings>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    headers: Option<OrderedMap>,
 }
 
 /// An email with a required address and an optional name field.
-#[derive(Clone, Serialize)]
+#[must_use]
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
 pub struct Email {
     email: String,
 
@@ -125,7 +376,8 @@ pub struct Email {
 }
 
 /// The body of an email with the content type and the message.
-#[derive(Clone, Default, Serialize)]
+#[must_use]
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
 pub struct Content {
     #[serde(rename = "type")]
     content_type: String,
@@ -134,7 +386,8 @@ pub struct Content {
 
 /// A personalization block for a V3 message. It has to at least contain one email as a to
 /// address. All other fields are optional.
-#[derive(Default, Serialize)]
+#[must_use]
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
 pub struct Personalization {
     to: Vec<Email>,
 
@@ -148,13 +401,13 @@ pub struct Personalization {
     subject: Option<String>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
-    headers: Option<SGMap>,
+    headers: Option<OrderedMap>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
-    substitutions: Option<SGMap>,
+    substitutions: Option<OrderedMap>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
-    custom_args: Option<SGMap>,
+    custom_args: Option<OrderedMap>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
     dynamic_template_data: Option<Map<String, Value>>,
@@ -163,25 +416,212 @@ pub struct Personalization {
     send_at: Option<u64>,
 }
 
+/// A category used to group messages in the SendGrid statistics. Categories convert from strings
+/// and from any type implementing [`CategoryName`].
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Category(String);
+
+impl Category {
+    /// Construct a category with the given name.
+    pub fn new<S: Into<String>>(name: S) -> Category {
+        Category(name.into())
+    }
+
+    /// The name of the category.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+/// Implemented by types, usually enums, which name the categories used across an application so
+/// that category names are defined once and checked at compile time.
+///
+/// ```
+/// use sendgrid::v3::{CategoryName, Email, Message};
+///
+/// enum Categories {
+///     Welcome,
+///     PasswordReset,
+/// }
+///
+/// impl CategoryName for Categories {
+///     fn category_name(&self) -> &str {
+///         match self {
+///             Categories::Welcome => "welcome",
+///             Categories::PasswordReset => "password-reset",
+///         }
+///     }
+/// }
+///
+/// let message = Message::new(Email::new("from@example.com")).add_category(Categories::Welcome);
+/// ```
+pub trait CategoryName {
+    /// The name of the category.
+    fn category_name(&self) -> &str;
+}
+
+impl<T: CategoryName> From<T> for Category {
+    fn from(category: T) -> Category {
+        Category::new(category.category_name())
+    }
+}
+
+impl From<&str> for Category {
+    fn from(name: &str) -> Category {
+        Category::new(name)
+    }
+}
+
+impl From<&String> for Category {
+    fn from(name: &String) -> Category {
+        Category::new(name.as_str())
+    }
+}
+
+impl From<String> for Category {
+    fn from(name: String) -> Category {
+        Category::new(name)
+    }
+}
+
 /// The Content-Disposition of the attachment specifying how you would like the attachment to be
 /// displayed. For example, inline results in the attached file being displayed automatically
 /// within the message. By specifying attachment, it will prompt the user to either view or
 /// download the file.
-#[derive(Clone, Copy, Serialize)]
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
 pub enum Disposition {
     /// Displayed automatically within the message.
     #[serde(rename = "inline")]
     Inline,
 
-    /// Displayed as an attached file.
+    /// Displayed as an attached file. This is the default used by SendGrid.
+    #[default]
     #[serde(rename = "attachment")]
     Attachment,
 }
 
+/// Common attachment mime types, accepted by [`Attachment::set_mime_type`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Mime {
+    /// `application/pdf`
+    Pdf,
+    /// `image/png`
+    Png,
+    /// `image/jpeg`
+    Jpeg,
+    /// `image/gif`
+    Gif,
+    /// `image/svg+xml`
+    Svg,
+    /// `text/csv`
+    Csv,
+    /// `text/plain`
+    PlainText,
+    /// `text/html`
+    Html,
+    /// `text/x-amp-html`
+    AmpHtml,
+    /// `text/calendar`
+    Calendar,
+    /// `application/json`
+    Json,
+    /// `application/zip`
+    Zip,
+    /// `application/vnd.openxmlformats-officedocument.wordprocessingml.document`
+    Docx,
+    /// `application/vnd.openxmlformats-officedocument.spreadsheetml.sheet`
+    Xlsx,
+    /// `application/octet-stream`
+    OctetStream,
+    /// Any other mime type.
+    Other(String),
+}
+
+impl Mime {
+    const KNOWN: [Mime; 15] = [
+        Mime::Pdf,
+        Mime::Png,
+        Mime::Jpeg,
+        Mime::Gif,
+        Mime::Svg,
+        Mime::Csv,
+        Mime::PlainText,
+        Mime::Html,
+        Mime::AmpHtml,
+        Mime::Calendar,
+        Mime::Json,
+        Mime::Zip,
+        Mime::Docx,
+        Mime::Xlsx,
+        Mime::OctetStream,
+    ];
+
+    /// Look up the mime type named `essence`, such as `image/png`, ignoring case and parameters.
+    pub fn new(essence: &str) -> Mime {
+        let essence = essence.split(';').next().unwrap_or_default().trim();
+        Mime::KNOWN
+            .into_iter()
+            .find(|mime| mime.as_str().eq_ignore_ascii_case(essence))
+            .unwrap_or_else(|| Mime::Other(essence.to_ascii_lowercase()))
+    }
+
+    /// The mime type as a string, such as `application/pdf`.
+    pub fn as_str(&self) -> &str {
+        match self {
+            Mime::Pdf => "application/pdf",
+            Mime::Png => "image/png",
+            Mime::Jpeg => "image/jpeg",
+            Mime::Gif => "image/gif",
+            Mime::Svg => "image/svg+xml",
+            Mime::Csv => "text/csv",
+            Mime::PlainText => "text/plain",
+            Mime::Html => "text/html",
+            Mime::AmpHtml => "text/x-amp-html",
+            Mime::Calendar => "text/calendar",
+            Mime::Json => "application/json",
+            Mime::Zip => "application/zip",
+            Mime::Docx => "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+            Mime::Xlsx => "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+            Mime::OctetStream => "application/octet-stream",
+            Mime::Other(mime) => mime,
+        }
+    }
+}
+
+impl std::fmt::Display for Mime {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl From<Mime> for String {
+    fn from(mime: Mime) -> String {
+        match mime {
+            Mime::Other(mime) => mime,
+            mime => mime.as_str().to_string(),
+        }
+    }
+}
+
+#[cfg(feature = "mime")]
+impl From<mime::Mime> for Mime {
+    fn from(mime: mime::Mime) -> Mime {
+        Mime::new(mime.essence_str())
+    }
+}
+
+#[cfg(feature = "mime")]
+impl From<&mime::Mime> for Mime {
+    fn from(mime: &mime::Mime) -> Mime {
+        Mime::new(mime.essence_str())
+    }
+}
+
 /// An attachment block for a V3 message. Content and filename are required. If the
 /// mime_type is unspecified, the email will use Sendgrid's default for attachments
 /// which is 'application/octet-stream'.
-#[derive(Default, Serialize)]
+#[must_use]
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
 pub struct Attachment {
     content: String,
 
@@ -198,23 +638,29 @@ pub struct Attachment {
 }
 
 /// An object allowing you to specify how to handle unsubscribes.
-#[derive(Default, Serialize)]
+#[must_use]
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
 pub struct ASM {
     group_id: u32,
-    groups_to_display: HashSet<u32>,
+
+    #[serde(default)]
+    groups_to_display: BTreeSet<u32>,
 }
 
 impl Sender {
     /// Construct a new V3 message sender. The `client` parameter is optional and `None` uses the
     /// default.
+    ///
+    /// Default headers of the client are sent, except for `Authorization`, `Content-Type` and
+    /// `User-Agent`, which the sender sets itself and which are never sent twice. See
+    /// [`Sender::set_client_authorization`] to authenticate with the client's header instead.
     pub fn new(api_key: String, client: Option<Client>) -> Sender {
-        Sender {
+        Sender::with_clients(
             api_key,
-            client: client.unwrap_or_default(),
+            client.unwrap_or_else(tls::default_client),
             #[cfg(feature = "blocking")]
-            blocking_client: reqwest::blocking::Client::new(),
-            host: V3_API_URL.to_string(),
-        }
+            BlockingClient::lazy(|| tls::blocking_client_builder().build()),
+        )
     }
 
     /// Construct a new V3 message sender with a blocking client. The `client` parameter is
@@ -223,492 +669,3223 @@ impl Sender {
     pub fn new_blocking(
         api_key: String,
         blocking_client: Option<reqwest::blocking::Client>,
+    ) -> Sender {
+        let blocking_client = match blocking_client {
+            Some(client) => BlockingClient::ready(client),
+            None => BlockingClient::lazy(|| tls::blocking_client_builder().build()),
+        };
+        Sender::with_clients(api_key, tls::default_client(), blocking_client)
+    }
+
+    /// Construct a new V3 message sender whose clients use the given DNS options.
+    pub fn with_dns_options(api_key: String, dns: &DnsOptions) -> SendgridResult<Sender> {
+        Ok(Sender::with_clients(
+            api_key,
+            dns.apply(tls::client_builder()).build()?,
+            #[cfg(feature = "blocking")]
+            {
+                let dns = dns.clone();
+                BlockingClient::lazy(move || {
+                    dns.apply_blocking(tls::blocking_client_builder()).build()
+                })
+            },
+        ))
+    }
+
+    /// Construct a new V3 message sender which sends through the endpoint of `region`.
+    pub fn with_region(api_key: String, region: Region) -> Sender {
+        let mut sender = Sender::new(api_key, None);
+        sender.host = region.mail_send_url();
+        sender
+    }
+
+    fn with_clients(
+        api_key: String,
+        client: Client,
+        #[cfg(feature = "blocking")] blocking_client: BlockingClient,
     ) -> Sender {
         Sender {
             api_key,
-            client: Client::new(),
+            client,
             #[cfg(feature = "blocking")]
-            blocking_client: blocking_client.unwrap_or_default(),
+            blocking_client,
             host: V3_API_URL.to_string(),
+            user_agent: String::from(USER_AGENT),
+            on_behalf_of: None,
+            client_authorization: false,
+            custom_arg_schema: None,
+            validate: false,
+            validate_size: false,
+            check_sendable: false,
+            sandbox: false,
+            request_id_provider: None,
+            archive_sink: None,
+            interceptors: Interceptors::default(),
+            subject_normalization: None,
+            utm_tagging: None,
+            redaction: SharedRedactionLevel::default(),
+            #[cfg(feature = "concurrency-limit")]
+            concurrency: None,
+            #[cfg(feature = "rate-limit")]
+            rate_limit: None,
+            #[cfg(feature = "retry")]
+            retry_policy: None,
+            defaults: MessageDefaults::default(),
         }
     }
 
     /// Sets the host to use for the API. This is useful if you are using a proxy or a local
-    /// development server. It should be a full URL, including the protocol.
-    pub fn set_host<S: Into<String>>(&mut self, host: S) {
-        self.host = host.into();
+    /// development server. It must be an `http` or `https` URL. Trailing slashes are removed and
+    /// the mail send path is appended to a URL without a path, so `http://localhost:3000` sends
+    /// to `http://localhost:3000/v3/mail/send`.
+    pub fn set_host<S: Into<String>>(&mut self, host: S) -> SendgridResult<()> {
+        self.host = normalize_host(&host.into())?;
+        Ok(())
     }
 
-    fn get_headers(&self) -> Result<HeaderMap, InvalidHeaderValue> {
-        let mut headers = HeaderMap::with_capacity(3);
-        headers.insert(
-            header::AUTHORIZATION,
-            HeaderValue::from_str(&format!("Bearer {}", self.api_key.clone()))?,
-        );
-        headers.insert(
-            header::CONTENT_TYPE,
-            HeaderValue::from_static("application/json"),
-        );
-        headers.insert(header::USER_AGENT, HeaderValue::from_static("sendgrid-rs"));
-        Ok(headers)
+    /// Send every message as the subuser `subuser` of the account the API key belongs to, see
+    /// [`PreparedSend::set_on_behalf_of`] to do so for a single message.
+    pub fn set_on_behalf_of<S: Into<String>>(&mut self, subuser: S) {
+        self.on_behalf_of = Some(subuser.into());
     }
 
-    /// Send a V3 message and return the HTTP response or an error.
-    pub async fn send(&self, mail: &Message) -> SendgridResult<Response> {
-        let headers = self.get_headers()?;
-
-        let resp = self
-            .client
-            .post(&self.host)
-            .headers(headers)
-            .body(mail.gen_json())
-            .send()
-            .await?;
-
-        if resp.error_for_status_ref().is_err() {
-            return Err(RequestNotSuccessful::new(resp.status(), resp.text().await?).into());
-        }
-
-        Ok(resp)
+    /// Send the `Authorization` header set as a default header of the client, for example by a
+    /// gateway specific client, instead of the one built from the API key. Defaults to false.
+    pub fn set_client_authorization(&mut self, client_authorization: bool) {
+        self.client_authorization = client_authorization;
     }
 
-    #[cfg(feature = "blocking")]
-    /// Send a V3 message and return the HTTP response or an error.
-    pub fn blocking_send(&self, mail: &Message) -> SendgridResult<BlockingResponse> {
-        let headers = self.get_headers()?;
-        let body = mail.gen_json();
-
-        let resp = self
-            .blocking_client
-            .post(&self.host)
-            .headers(headers)
-            .body(body)
-            .send()?;
-
-        if resp.error_for_status_ref().is_err() {
-            return Err(RequestNotSuccessful::new(resp.status(), resp.text()?).into());
-        }
-
-        Ok(resp)
+    /// Register a schema which the custom args of every message are checked against before it is
+    /// sent.
+    pub fn set_custom_arg_schema(&mut self, schema: CustomArgSchema) {
+        self.custom_arg_schema = Some(schema);
     }
-}
 
-impl Message {
-    /// Construct a new V3 message.
-    pub fn new(from: Email) -> Message {
-        Message {
-            from,
-            subject: String::new(),
-            personalizations: Vec::new(),
-            reply_to: None,
-            content: None,
-            attachments: None,
-            template_id: None,
-            categories: None,
-            ip_pool_name: None,
-            tracking_settings: None,
-            asm: None,
-            mail_settings: None,
-        }
+    /// Run [`Message::validate`] on every message before it is sent. Reserved headers are
+    /// rejected either way.
+    pub fn set_validate(&mut self, validate: bool) {
+        self.validate = validate;
     }
 
-    /// Set the from address.
-    pub fn set_from(mut self, from: Email) -> Message {
-        self.from = from;
-        self
+    /// Run [`Message::check_sendable`] on every message before it is sent.
+    pub fn set_check_sendable(&mut self, check_sendable: bool) {
+        self.check_sendable = check_sendable;
     }
 
-    /// Set the Reply-To header.
-    pub fn set_reply_to(mut self, reply_to: Email) -> Message {
-        self.reply_to = Some(reply_to);
-        self
+    /// Enable sandbox mode in the mail settings of every message sent, so SendGrid validates
+    /// messages but never delivers them, whatever their own settings are. This is meant for
+    /// staging environments.
+    pub fn set_sandbox(&mut self, sandbox: bool) {
+        self.sandbox = sandbox;
     }
 
-    /// Set the subject.
-    pub fn set_subject(mut self, subject: &str) -> Message {
-        self.subject = String::from(subject);
-        self
+    /// Check the serialized size of every message against [`MAX_PAYLOAD_SIZE`] before it is sent,
+    /// returning [`SendgridError::PayloadTooLarge`] instead of waiting for SendGrid to reject it.
+    pub fn set_validate_size(&mut self, validate_size: bool) {
+        self.validate_size = validate_size;
     }
 
-    /// Set the template id.
-    pub fn set_template_id(mut self, template_id: &str) -> Message {
-        self.template_id = Some(String::from(template_id));
-        self
+    /// Set the from address used for messages whose from address is empty, see
+    /// [`Message::default`].
+    pub fn set_default_from(&mut self, from: Email) {
+        self.defaults.from = Some(from);
     }
 
-    /// Set the IP pool name.
-    pub fn set_ip_pool_name(mut self, ip_pool_name: &str) -> Message {
-        self.ip_pool_name = Some(String::from(ip_pool_name));
-        self
+    /// Set the from name used for messages whose from address has no name.
+    pub fn set_default_from_name<S: Into<String>>(&mut self, from_name: S) {
+        self.defaults.from_name = Some(from_name.into());
     }
 
-    /// Set tracking settings.
-    pub fn set_tracking_settings(mut self, tracking_settings: TrackingSettings) -> Message {
-        self.tracking_settings = Some(tracking_settings);
-        self
+    /// Set the reply to address used for messages without one.
+    pub fn set_default_reply_to(&mut self, reply_to: Email) {
+        self.defaults.reply_to = Some(reply_to);
     }
 
-    /// Set unsubscribe settings.
-    pub fn set_asm(mut self, asm: ASM) -> Message {
-        self.asm = Some(asm);
-        self
+    /// Register a provider for the current request id. When it returns an id, messages without
+    /// one are sent with it, see [`Message::set_request_id`].
+    pub fn set_request_id_provider(&mut self, provider: RequestIdProvider) {
+        self.request_id_provider = Some(provider);
     }
 
-    /// Set mail settings.
-    pub fn set_mail_settings(mut self, mail_settings: MailSettings) -> Message {
-        self.mail_settings = Some(mail_settings);
-        self
+    /// Normalize the subjects of every message before it is sent.
+    pub fn set_subject_normalization(&mut self, normalization: SubjectNormalization) {
+        self.subject_normalization = Some(normalization);
     }
 
-    /// Add a category.
-    pub fn add_category(mut self, category: &str) -> Message {
-        self.categories
-            .get_or_insert_with(Vec::new)
-            .push(String::from(category));
-        self
+    /// Add UTM parameters to the links of the HTML content of every message before it is sent.
+    pub fn set_utm_tagging(&mut self, tagging: UtmTagging) {
+        self.utm_tagging = Some(tagging);
     }
 
-    /// Add multiple categories.
-    pub fn add_categories(mut self, categories: &[String]) -> Message {
-        self.categories
-            .get_or_insert_with(Vec::new)
-            .extend_from_slice(categories);
-        self
+    /// Allow at most `limit` requests of this sender and all of its clones to be in flight at
+    /// once. Further sends wait until a request finishes. Clones made before this call are not
+    /// affected. A limit of 0 is treated as 1, and limits above
+    /// [`Semaphore::MAX_PERMITS`](tokio::sync::Semaphore::MAX_PERMITS) as that maximum.
+    #[cfg(feature = "concurrency-limit")]
+    pub fn set_max_concurrency(&mut self, limit: usize) {
+        let limit = limit.clamp(1, tokio::sync::Semaphore::MAX_PERMITS);
+        self.concurrency = Some(Arc::new(tokio::sync::Semaphore::new(limit)));
     }
 
-    /// Add content to the message.
-    pub fn add_content(mut self, c: Content) -> Message {
-        self.content.get_or_insert_with(Vec::new).push(c);
-        self
+    /// Retry failed sends according to `policy`. Only the final attempt is archived.
+    #[cfg(feature = "retry")]
+    pub fn set_retry_policy(&mut self, policy: RetryPolicy) {
+        self.retry_policy = Some(policy);
     }
 
-    /// Add a personalization to the message.
-    pub fn add_personalization(mut self, p: Personalization) -> Message {
-        self.personalizations.push(p);
-        self
+    /// Set how much personal data is redacted from archived payloads and debug output. The level
+    /// is shared with every clone of this sender and can be changed while they are in use.
+    /// Defaults to [`RedactionLevel::None`].
+    pub fn set_redaction_level(&self, level: RedactionLevel) {
+        self.redaction.set(level);
     }
 
-    /// Add an attachment to the message.
-    pub fn add_attachment(mut self, a: Attachment) -> Message {
-        self.attachments.get_or_insert_with(Vec::new).push(a);
-        self
+    /// The current redaction level, see [`Sender::set_redaction_level`].
+    pub fn redaction_level(&self) -> RedactionLevel {
+        self.redaction.get()
     }
 
-    fn gen_json(&self) -> String {
-        serde_json::to_string(self).unwrap()
+    /// Register a sink which receives the payload, message id and outcome of every send.
+    pub fn set_archive_sink<A: ArchiveSink + 'static>(&mut self, sink: A) {
+        self.archive_sink = Some(SharedArchiveSink(Arc::new(sink)));
     }
-}
 
-impl Email {
-    /// Construct a new email type with name set as None.
-    ///
-    /// ```rust
-    /// use sendgrid::v3::Email;
-    ///
-    /// let my_email = Email::new("test@mail.com");
-    /// ```
-    pub fn new<S: Into<String>>(email: S) -> Email {
-        Email {
-            email: email.into(),
-            name: None,
-        }
+    /// Register an interceptor which sees every request before it is sent and every response
+    /// received. Interceptors run in the order they were added.
+    pub fn add_interceptor<I: SendInterceptor + 'static>(&mut self, interceptor: I) {
+        self.interceptors.add(interceptor);
     }
 
-    /// Set an optional name.
-    ///
-    /// ```rust
-    /// use sendgrid::v3::Email;
-    ///
-    /// let my_email = Email::new("test@mail.com").set_name("My Name");
-    /// ```
-    pub fn set_name<S: Into<String>>(mut self, name: S) -> Email {
-        self.name = Some(name.into());
-        self
+    fn get_headers(&self) -> Result<HeaderMap, InvalidHeaderValue> {
+        // Inserting replaces any default header of the client with the same name when the
+        // request is sent, so each of these is sent exactly once.
+        let mut headers = HeaderMap::with_capacity(3);
+        if !self.client_authorization {
+            headers.insert(
+                header::AUTHORIZATION,
+                HeaderValue::from_str(&format!("Bearer {}", self.api_key.clone()))?,
+            );
+        }
+        headers.insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("application/json"),
+        );
+        headers.insert(header::USER_AGENT, HeaderValue::from_str(&self.user_agent)?);
+        if let Some(subuser) = &self.on_behalf_of {
+            headers.insert(ON_BEHALF_OF_HEADER, HeaderValue::from_str(subuser)?);
+        }
+        Ok(headers)
     }
-}
 
-impl Content {
-    /// Construct a new content type.
-    pub fn new() -> Content {
-        Content::default()
+    /// Open a connection to the configured host so the first send does not pay for DNS and TLS
+    /// setup. The connection is kept in the client's pool. Any HTTP response counts as success,
+    /// only connection failures are returned as errors.
+    pub async fn warm_up(&self) -> SendgridResult<()> {
+        self.client.head(&self.host).send().await?;
+        Ok(())
     }
 
-    /// Set the type of this content.
-    pub fn set_content_type<S: Into<String>>(mut self, content_type: S) -> Content {
-        self.content_type = content_type.into();
-        self
+    #[cfg(feature = "blocking")]
+    /// Open a connection to the configured host with the blocking client, see
+    /// [`Sender::warm_up`].
+    pub fn blocking_warm_up(&self) -> SendgridResult<()> {
+        self.blocking_client.get()?.head(&self.host).send()?;
+        Ok(())
     }
 
-    /// Set the corresponding message for this content.
+    /// Check and serialize a message without sending it. The returned [`PreparedSend`] performs
+    /// the HTTP call when it is committed, which lets the expensive work happen inside a database
+    /// transaction and the network call after the transaction commits.
+    pub fn prepare(&self, mail: &Message) -> SendgridResult<PreparedSend> {
+        let mut mail = Cow::Borrowed(mail);
+        if self.defaults.applies_to(&mail) {
+            mail = Cow::Owned(self.defaults.apply(mail.into_owned()));
+        }
+        if let Some(normalization) = &self.subject_normalization {
+            if !normalization.is_normalized(&mail) {
+                mail = Cow::Owned(mail.into_owned().normalize_subject(normalization));
+            }
+        }
+        if let Some(tagging) = &self.utm_tagging {
+            mail = Cow::Owned(mail.into_owned().tag_links(tagging));
+        }
+        if self.sandbox
+            && !mail
+                .mail_settings
+                .as_ref()
+                .is_some_and(MailSettings::sandbox_mode_enabled)
+        {
+            let mut owned = mail.into_owned();
+            let settings = owned.mail_settings.take().unwrap_or_default();
+            owned.mail_settings =
+                Some(settings.set_sandbox_mode(SandboxMode::new().set_enable(true)));
+            mail = Cow::Owned(owned);
+        }
+        // Injected before validation, so a schema may require the request id arg.
+        if let Some(provider) = &self.request_id_provider {
+            if !mail.has_request_id() {
+                if let Some(request_id) = provider.current() {
+                    mail = Cow::Owned(mail.into_owned().set_request_id(&request_id));
+                }
+            }
+        }
+
+        // SendGrid rejects these with a 400, so there is no point in making the request.
+        if mail.from.email.is_empty() {
+            return Err(SendgridError::MissingFrom);
+        }
+        if mail.personalizations.is_empty() {
+            return Err(SendgridError::NoPersonalizations);
+        }
+        // Like the V2 client, reserved headers are rejected even without validation.
+        mail.check_headers()?;
+        if self.validate {
+            mail.validate()?;
+        }
+        if self.check_sendable {
+            mail.check_sendable()?;
+        }
+        if let Some(schema) = &self.custom_arg_schema {
+            schema.validate(&mail)?;
+        }
+
+        let body = mail.to_json()?;
+        if self.validate_size {
+            check_payload_size(body.len())?;
+        }
+
+        Ok(PreparedSend {
+            sender: self.clone(),
+            headers: self.get_headers()?,
+            body,
+            deadline: None,
+        })
+    }
+
+    /// Send a V3 message and return the HTTP response or an error.
+    pub async fn send(&self, mail: &Message) -> SendgridResult<Response> {
+        self.prepare(mail)?.commit().await
+    }
+
+    /// Send a V3 message as the subuser `subuser`, see [`PreparedSend::set_on_behalf_of`].
+    pub async fn send_on_behalf_of(
+        &self,
+        mail: &Message,
+        subuser: &str,
+    ) -> SendgridResult<Response> {
+        self.prepare(mail)?
+            .set_on_behalf_of(subuser)?
+            .commit()
+            .await
+    }
+
+    /// Send a V3 message authenticated with `api_key` instead of the key of this sender, see
+    /// [`PreparedSend::set_api_key`]. This lets one sender and its connection pool send for many
+    /// accounts, for example one per tenant.
+    pub async fn send_with_key(&self, mail: &Message, api_key: &str) -> SendgridResult<Response> {
+        self.prepare(mail)?.set_api_key(api_key)?.commit().await
+    }
+
+    #[cfg(feature = "blocking")]
+    /// Send a V3 message and return the HTTP response or an error.
+    pub fn blocking_send(&self, mail: &Message) -> SendgridResult<BlockingResponse> {
+        self.prepare(mail)?.blocking_commit()
+    }
+
+    /// Check and serialize a message, then send it on a task spawned on the current tokio
+    /// runtime and return without waiting for the outcome. Errors of [`Sender::prepare`] are
+    /// returned immediately. The outcome of the send, after any retries of the sender's retry
+    /// policy, is only reported to the archive sink, the interceptors and the metrics of the
+    /// sender. The returned handle may be awaited on shutdown to let the send finish.
+    ///
+    /// # Panics
+    ///
+    /// Panics when called outside of a tokio runtime.
+    #[cfg(feature = "send-and-forget")]
+    pub fn send_and_forget(&self, mail: &Message) -> SendgridResult<tokio::task::JoinHandle<()>> {
+        let prepared = self.prepare(mail)?;
+        Ok(tokio::spawn(async move {
+            // The outcome has been reported to the hooks of the sender.
+            let _ = prepared.commit().await;
+        }))
+    }
+
+    /// Send a message with sandbox mode enabled, so SendGrid validates it without delivering it.
+    /// Returns the JSON payload that [`Sender::send`] would send and the outcome reported by
+    /// SendGrid. Errors raised before the request is made, such as those of
+    /// [`Sender::prepare`], are returned directly. Dry runs are not archived and not recorded by
+    /// the metrics and telemetry of the sender.
+    pub async fn dry_run(&self, mail: &Message) -> SendgridResult<DryRun> {
+        let prepared = self.prepare(mail)?;
+        let payload = prepared.body.clone();
+        let outcome = prepared.sandboxed()?.post().await;
+        Ok(DryRun {
+            payload,
+            outcome: outcome.map(|resp| resp.status()),
+        })
+    }
+
+    #[cfg(feature = "blocking")]
+    /// Like [`Sender::dry_run`] with the blocking client.
+    pub fn blocking_dry_run(&self, mail: &Message) -> SendgridResult<DryRun> {
+        let prepared = self.prepare(mail)?;
+        let payload = prepared.body.clone();
+        let outcome = prepared.sandboxed()?.blocking_post();
+        Ok(DryRun {
+            payload,
+            outcome: outcome.map(|resp| resp.status()),
+        })
+    }
+
+    /// Send a V3 message, giving up at `deadline`, see [`PreparedSend::set_deadline`].
+    pub async fn send_with_deadline(
+        &self,
+        mail: &Message,
+        deadline: Instant,
+    ) -> SendgridResult<Response> {
+        self.prepare(mail)?.set_deadline(deadline).commit().await
+    }
+
+    #[cfg(feature = "blocking")]
+    /// Like [`Sender::send_with_deadline`] with the blocking client.
+    pub fn blocking_send_with_deadline(
+        &self,
+        mail: &Message,
+        deadline: Instant,
+    ) -> SendgridResult<BlockingResponse> {
+        self.prepare(mail)?.set_deadline(deadline).blocking_commit()
+    }
+
+    #[cfg(feature = "blocking")]
+    /// Like [`Sender::send_with_key`] with the blocking client.
+    pub fn blocking_send_with_key(
+        &self,
+        mail: &Message,
+        api_key: &str,
+    ) -> SendgridResult<BlockingResponse> {
+        self.prepare(mail)?.set_api_key(api_key)?.blocking_commit()
+    }
+
+    /// Send a message with any number of personalizations by splitting it into requests within
+    /// the personalization and recipient limits, see [`Message::chunks`]. The requests are sent
+    /// one after another and a result is returned for each, in order, so that failed chunks can
+    /// be retried on their own.
+    pub async fn send_chunked(&self, mail: &Message) -> Vec<SendgridResult<Response>> {
+        let mut results = Vec::new();
+        for chunk in mail.chunks() {
+            results.push(self.send(&chunk).await);
+        }
+        results
+    }
+
+    /// Send many messages with at most `max_concurrency` requests in flight at once. A result is
+    /// returned for each message, in the order of `messages`, however the sends complete.
+    pub async fn send_batch<'a, I>(
+        &self,
+        messages: I,
+        max_concurrency: usize,
+    ) -> Vec<SendgridResult<SendResponse>>
+    where
+        I: IntoIterator<Item = &'a Message>,
+    {
+        futures::stream::iter(messages)
+            .map(|message| async move { self.send(message).await.map(SendResponse::from) })
+            .buffered(max_concurrency.max(1))
+            .collect()
+            .await
+    }
+
+    /// Like [`Sender::send_chunked`], but stops sending chunks once `token` is cancelled. A chunk
+    /// in flight is completed and every chunk which was not sent yields
+    /// [`SendgridError::Cancelled`], so the results still line up with [`Message::chunks`].
+    #[cfg(feature = "cancellation")]
+    pub async fn send_chunked_until_cancelled(
+        &self,
+        mail: &Message,
+        token: &tokio_util::sync::CancellationToken,
+    ) -> Vec<SendgridResult<Response>> {
+        let mut results = Vec::new();
+        for chunk in mail.chunks() {
+            if token.is_cancelled() {
+                results.push(Err(SendgridError::Cancelled));
+            } else {
+                results.push(self.send(&chunk).await);
+            }
+        }
+        results
+    }
+
+    #[cfg(feature = "blocking")]
+    /// Like [`Sender::send_chunked`] with the blocking client.
+    pub fn blocking_send_chunked(&self, mail: &Message) -> Vec<SendgridResult<BlockingResponse>> {
+        mail.chunks()
+            .iter()
+            .map(|chunk| self.blocking_send(chunk))
+            .collect()
+    }
+}
+
+// Check that `host` is a URL requests can be sent to and normalize it, see `Sender::set_host`.
+fn normalize_host(host: &str) -> SendgridResult<String> {
+    let invalid = |reason: &str| SendgridError::InvalidHost {
+        host: host.to_string(),
+        reason: reason.to_string(),
+    };
+    let mut url = url::Url::parse(host.trim()).map_err(|e| match e {
+        url::ParseError::RelativeUrlWithoutBase => invalid("the URL has no scheme"),
+        e => invalid(&e.to_string()),
+    })?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(invalid("the URL must start with http:// or https://"));
+    }
+    if url.host_str().is_none_or(str::is_empty) {
+        return Err(invalid("the URL has no host"));
+    }
+
+    let path = url.path().trim_end_matches('/').to_string();
+    if path.is_empty() {
+        url.set_path(MAIL_SEND_PATH);
+    } else {
+        url.set_path(&path);
+    }
+    Ok(url.into())
+}
+
+/// The result of [`Sender::dry_run`].
+#[derive(Debug)]
+pub struct DryRun {
+    /// The JSON body which would have been sent without sandbox mode.
+    pub payload: String,
+
+    /// The status of SendGrid's response if it accepted the message, or the error it reported.
+    pub outcome: SendgridResult<StatusCode>,
+}
+
+impl DryRun {
+    /// Whether SendGrid accepted the message.
+    pub fn is_valid(&self) -> bool {
+        self.outcome.is_ok()
+    }
+}
+
+/// A message which has been checked and serialized by [`Sender::prepare`] and is ready to be sent.
+#[derive(Clone)]
+pub struct PreparedSend {
+    sender: Sender,
+    headers: HeaderMap,
+    body: String,
+    deadline: Option<Instant>,
+}
+
+impl std::fmt::Debug for PreparedSend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let level = self.sender.redaction_level();
+        f.debug_struct("PreparedSend")
+            .field("host", &self.sender.host)
+            .field("body", &level.redact_payload(&self.body))
+            .field("deadline", &self.deadline)
+            .finish()
+    }
+}
+
+impl PreparedSend {
+    /// The JSON body that will be sent.
+    pub fn body(&self) -> &str {
+        &self.body
+    }
+
+    /// Send the message as the subuser `subuser` of the account the API key belongs to, by
+    /// setting the `on-behalf-of` header. This replaces a subuser set on the sender.
+    pub fn set_on_behalf_of(mut self, subuser: &str) -> SendgridResult<PreparedSend> {
+        self.headers
+            .insert(ON_BEHALF_OF_HEADER, HeaderValue::from_str(subuser)?);
+        Ok(self)
+    }
+
+    /// Authenticate the request with `api_key` instead of the key of the sender. This also
+    /// replaces an authorization header set on the sender's client.
+    pub fn set_api_key(mut self, api_key: &str) -> SendgridResult<PreparedSend> {
+        self.headers.insert(
+            header::AUTHORIZATION,
+            HeaderValue::from_str(&format!("Bearer {}", api_key))?,
+        );
+        Ok(self)
+    }
+
+    /// Give up on the send at `deadline`, which bounds the time spent on every attempt, on
+    /// waiting for the rate limiter or a concurrency permit and on backoff between retries. A
+    /// retry whose backoff would end after the deadline is not made, and an attempt which cannot
+    /// start before it fails with [`SendgridError::DeadlineExceeded`]. The HTTP request times out
+    /// at the deadline.
+    pub fn set_deadline(mut self, deadline: Instant) -> PreparedSend {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    // Enable sandbox mode in the body, keeping the other mail settings.
+    fn sandboxed(mut self) -> SendgridResult<PreparedSend> {
+        let mut body: Value = serde_json::from_str(&self.body)?;
+        if let Some(body) = body.as_object_mut() {
+            let settings = body
+                .entry("mail_settings")
+                .or_insert_with(|| Object(Map::new()));
+            if let Some(settings) = settings.as_object_mut() {
+                settings.insert(
+                    String::from("sandbox_mode"),
+                    serde_json::json!({ "enable": true }),
+                );
+            }
+        }
+        self.body = body.to_string();
+        Ok(self)
+    }
+
+    // The time left until the deadline, if there is one.
+    fn remaining(&self) -> SendgridResult<Option<Duration>> {
+        match self.deadline {
+            Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+                Some(remaining) if !remaining.is_zero() => Ok(Some(remaining)),
+                _ => Err(SendgridError::DeadlineExceeded),
+            },
+            None => Ok(None),
+        }
+    }
+
+    // Wait for `future`, failing with `SendgridError::DeadlineExceeded` if the deadline passes
+    // first.
+    #[cfg(any(feature = "rate-limit", feature = "concurrency-limit"))]
+    async fn before_deadline<F: std::future::Future>(
+        &self,
+        future: F,
+    ) -> SendgridResult<F::Output> {
+        match self.remaining()? {
+            Some(remaining) => tokio::time::timeout(remaining, future)
+                .await
+                .map_err(|_| SendgridError::DeadlineExceeded),
+            None => Ok(future.await),
+        }
+    }
+
+    // Take a permit of the concurrency limit. Without a runtime to time out on, a send with a
+    // deadline polls for the permit until the deadline passes.
+    #[cfg(all(feature = "blocking", feature = "concurrency-limit"))]
+    fn blocking_permit(
+        &self,
+        semaphore: Arc<tokio::sync::Semaphore>,
+    ) -> SendgridResult<Option<tokio::sync::OwnedSemaphorePermit>> {
+        if self.deadline.is_none() {
+            return Ok(futures::executor::block_on(semaphore.acquire_owned()).ok());
+        }
+        loop {
+            match semaphore.clone().try_acquire_owned() {
+                Ok(permit) => return Ok(Some(permit)),
+                Err(tokio::sync::TryAcquireError::Closed) => return Ok(None),
+                Err(tokio::sync::TryAcquireError::NoPermits) => {
+                    let remaining = self.remaining()?.unwrap_or_default();
+                    std::thread::sleep(remaining.min(Duration::from_millis(10)));
+                }
+            }
+        }
+    }
+
+    // Whether waiting for `delay` ends at or after the deadline.
+    #[cfg(feature = "retry")]
+    fn expires_within(&self, delay: Duration) -> bool {
+        self.deadline
+            .is_some_and(|deadline| Instant::now() + delay >= deadline)
+    }
+
+    /// Send the prepared message and return the HTTP response or an error.
+    pub async fn commit(self) -> SendgridResult<Response> {
+        #[cfg(feature = "otel")]
+        let telemetry = crate::telemetry::SendTelemetry::start(&self.sender.host);
+        #[cfg(feature = "metrics")]
+        let metrics = crate::send_metrics::SendMetrics::start("v3", self.body.len());
+
+        let archive = self.sender.archive_sink.clone().map(|sink| {
+            (
+                sink,
+                self.sender.redaction_level().redact_payload(&self.body),
+            )
+        });
+        let result = self.post().await;
+
+        #[cfg(feature = "otel")]
+        telemetry.finish(result.as_ref().map(Response::status));
+        #[cfg(feature = "metrics")]
+        metrics.finish(result.as_ref().map(Response::status));
+        if let Some((sink, body)) = archive {
+            let outcome = result.as_ref().map(|resp| (resp.status(), resp.headers()));
+            sink.0.archive(&ArchiveRecord::new(&body, outcome));
+        }
+        result
+    }
+
+    async fn post(self) -> SendgridResult<Response> {
+        #[cfg(feature = "retry")]
+        if let Some(policy) = self.sender.retry_policy.clone() {
+            let mut attempt = 1;
+            loop {
+                match self.clone().post_once().await {
+                    Err(e) => match policy.delay(attempt, &e) {
+                        Some(delay) if !self.expires_within(delay) => {
+                            tokio::time::sleep(delay).await
+                        }
+                        _ => return Err(e),
+                    },
+                    result => return result,
+                }
+                attempt += 1;
+            }
+        }
+        self.post_once().await
+    }
+
+    async fn post_once(mut self) -> SendgridResult<Response> {
+        #[cfg(feature = "rate-limit")]
+        if let Some(rate_limit) = &self.sender.rate_limit {
+            self.before_deadline(rate_limit.acquire()).await??;
+        }
+        #[cfg(feature = "concurrency-limit")]
+        let _permit = match self.sender.concurrency.clone() {
+            Some(semaphore) => self.before_deadline(semaphore.acquire_owned()).await?.ok(),
+            None => None,
+        };
+
+        let timeout = self.remaining()?;
+        let interceptors = &self.sender.interceptors;
+        interceptors.before_send(&self.sender.host, &mut self.headers, &self.body)?;
+        let start = Instant::now();
+        let mut request = self
+            .sender
+            .client
+            .post(&self.sender.host)
+            .headers(self.headers)
+            .body(self.body);
+        if let Some(timeout) = timeout {
+            request = request.timeout(timeout);
+        }
+        let resp = request.send().await?;
+        interceptors.after_send(
+            &self.sender.host,
+            resp.status(),
+            resp.headers(),
+            start.elapsed(),
+        );
+
+        if resp.error_for_status_ref().is_err() {
+            let (rate_limit, retry_after) = (resp.rate_limit(), resp.retry_after());
+            let error = RequestNotSuccessful::new(resp.status(), resp.text().await?)
+                .with_rate_limit(rate_limit)
+                .with_retry_after(retry_after);
+            return Err(error.into());
+        }
+
+        Ok(resp)
+    }
+
+    #[cfg(feature = "blocking")]
+    /// Send the prepared message and return the HTTP response or an error.
+    pub fn blocking_commit(self) -> SendgridResult<BlockingResponse> {
+        #[cfg(feature = "otel")]
+        let telemetry = crate::telemetry::SendTelemetry::start(&self.sender.host);
+        #[cfg(feature = "metrics")]
+        let metrics = crate::send_metrics::SendMetrics::start("v3", self.body.len());
+
+        let archive = self.sender.archive_sink.clone().map(|sink| {
+            (
+                sink,
+                self.sender.redaction_level().redact_payload(&self.body),
+            )
+        });
+        let result = self.blocking_post();
+
+        #[cfg(feature = "otel")]
+        telemetry.finish(result.as_ref().map(BlockingResponse::status));
+        #[cfg(feature = "metrics")]
+        metrics.finish(result.as_ref().map(BlockingResponse::status));
+        if let Some((sink, body)) = archive {
+            let outcome = result.as_ref().map(|resp| (resp.status(), resp.headers()));
+            sink.0.archive(&ArchiveRecord::new(&body, outcome));
+        }
+        result
+    }
+
+    #[cfg(feature = "blocking")]
+    fn blocking_post(self) -> SendgridResult<BlockingResponse> {
+        #[cfg(feature = "retry")]
+        if let Some(policy) = self.sender.retry_policy.clone() {
+            let mut attempt = 1;
+            loop {
+                match self.clone().blocking_post_once() {
+                    Err(e) => match policy.delay(attempt, &e) {
+                        Some(delay) if !self.expires_within(delay) => std::thread::sleep(delay),
+                        _ => return Err(e),
+                    },
+                    result => return result,
+                }
+                attempt += 1;
+            }
+        }
+        self.blocking_post_once()
+    }
+
+    #[cfg(feature = "blocking")]
+    fn blocking_post_once(mut self) -> SendgridResult<BlockingResponse> {
+        #[cfg(feature = "rate-limit")]
+        if let Some(rate_limit) = &self.sender.rate_limit {
+            rate_limit.blocking_acquire(self.deadline)?;
+        }
+        #[cfg(feature = "concurrency-limit")]
+        let _permit = match self.sender.concurrency.clone() {
+            Some(semaphore) => self.blocking_permit(semaphore)?,
+            None => None,
+        };
+
+        let timeout = self.remaining()?;
+        let interceptors = &self.sender.interceptors;
+        interceptors.before_send(&self.sender.host, &mut self.headers, &self.body)?;
+        let start = Instant::now();
+        let mut request = self
+            .sender
+            .blocking_client
+            .get()?
+            .post(&self.sender.host)
+            .headers(self.headers)
+            .body(self.body);
+        if let Some(timeout) = timeout {
+            request = request.timeout(timeout);
+        }
+        let resp = request.send()?;
+        interceptors.after_send(
+            &self.sender.host,
+            resp.status(),
+            resp.headers(),
+            start.elapsed(),
+        );
+
+        if resp.error_for_status_ref().is_err() {
+            let (rate_limit, retry_after) = (resp.rate_limit(), resp.retry_after());
+            let error = RequestNotSuccessful::new(resp.status(), resp.text()?)
+                .with_rate_limit(rate_limit)
+                .with_retry_after(retry_after);
+            return Err(error.into());
+        }
+
+        Ok(resp)
+    }
+}
+
+impl Default for Message {
+    fn default() -> Self {
+        Message::new(Email::new(""))
+    }
+}
+
+impl Message {
+    /// Construct a new V3 message.
+    pub fn new(from: Email) -> Message {
+        Message {
+            from,
+            subject: String::new(),
+            personalizations: Vec::new(),
+            reply_to: None,
+            content: None,
+            attachments: None,
+            template_id: None,
+            batch_id: None,
+            categories: None,
+            ip_pool_name: None,
+            tracking_settings: None,
+            asm: None,
+            mail_settings: None,
+            headers: None,
+        }
+    }
+
+    /// Set the from address.
+    pub fn set_from(mut self, from: Email) -> Message {
+        self.from = from;
+        self
+    }
+
+    /// Set the Reply-To header.
+    pub fn set_reply_to(mut self, reply_to: Email) -> Message {
+        self.reply_to = Some(reply_to);
+        self
+    }
+
+    /// Set the subject.
+    pub fn set_subject(mut self, subject: &str) -> Message {
+        self.subject = String::from(subject);
+        self
+    }
+
+    /// Set the template id.
+    pub fn set_template_id(mut self, template_id: &str) -> Message {
+        self.template_id = Some(String::from(template_id));
+        self
+    }
+
+    /// Set the batch id. Scheduled messages sharing a batch id can be paused or cancelled together
+    /// through the scheduled sends API.
+    pub fn set_batch_id(mut self, batch_id: &str) -> Message {
+        self.batch_id = Some(String::from(batch_id));
+        self
+    }
+
+    /// Set the IP pool name.
+    pub fn set_ip_pool_name(mut self, ip_pool_name: &str) -> Message {
+        self.ip_pool_name = Some(String::from(ip_pool_name));
+        self
+    }
+
+    /// Set tracking settings.
+    pub fn set_tracking_settings(mut self, tracking_settings: TrackingSettings) -> Message {
+        self.tracking_settings = Some(tracking_settings);
+        self
+    }
+
+    /// Set unsubscribe settings.
+    pub fn set_asm(mut self, asm: ASM) -> Message {
+        self.asm = Some(asm);
+        self
+    }
+
+    /// Set mail settings.
+    pub fn set_mail_settings(mut self, mail_settings: MailSettings) -> Message {
+        self.mail_settings = Some(mail_settings);
+        self
+    }
+
+    /// Add a category.
+    pub fn add_category<C: Into<Category>>(mut self, category: C) -> Message {
+        self.categories
+            .get_or_insert_with(Vec::new)
+            .push(category.into().0);
+        self
+    }
+
+    /// Add multiple categories.
+    pub fn add_categories(mut self, categories: &[String]) -> Message {
+        self.categories
+            .get_or_insert_with(Vec::new)
+            .extend_from_slice(categories);
+        self
+    }
+
+    /// Add content to the message.
+    pub fn add_content(mut self, c: Content) -> Message {
+        self.content.get_or_insert_with(Vec::new).push(c);
+        self.sort_content()
+    }
+
+    /// Set the plain text body, replacing any existing `text/plain` content.
+    pub fn set_text<S: Into<String>>(mut self, text: S) -> Message {
+        let content = self.content.get_or_insert_with(Vec::new);
+        content.retain(|c| !c.content_type.eq_ignore_ascii_case("text/plain"));
+        content.push(Content::text(text));
+        self.sort_content()
+    }
+
+    /// Set the HTML body, replacing any existing `text/html` content.
+    pub fn set_html<S: Into<String>>(mut self, html: S) -> Message {
+        let content = self.content.get_or_insert_with(Vec::new);
+        content.retain(|c| !c.content_type.eq_ignore_ascii_case("text/html"));
+        content.push(Content::html(html));
+        self.sort_content()
+    }
+
+    /// Set the AMP body, replacing any existing `text/x-amp-html` content. Email clients without
+    /// AMP support show the HTML body instead, so a message with an AMP body should have one too.
+    pub fn set_amp_html<S: Into<String>>(mut self, amp_html: S) -> Message {
+        let content = self.content.get_or_insert_with(Vec::new);
+        content.retain(|c| !c.content_type.eq_ignore_ascii_case("text/x-amp-html"));
+        content.push(Content::amp_html(amp_html));
+        self.sort_content()
+    }
+
+    /// Order the content parts the way the API requires: `text/plain` first, then
+    /// `text/x-amp-html`, then `text/html`, then any other types in the order they were added.
+    /// The content setters keep this order, this is only needed for messages built another way,
+    /// for example deserialized ones.
+    pub fn sort_content(mut self) -> Message {
+        if let Some(content) = &mut self.content {
+            content.sort_by_key(Content::rank);
+        }
+        self
+    }
+
+    /// Add a to recipient. Recipients are added to the first personalization of the message,
+    /// which is created when the message has none, so simple messages do not need to build a
+    /// [`Personalization`].
+    pub fn add_to(mut self, to: Email) -> Message {
+        match self.personalizations.first_mut() {
+            Some(p) => p.to.push(to),
+            None => self.personalizations.push(Personalization::new(to)),
+        }
+        self
+    }
+
+    /// Add several to recipients, see [`Message::add_to`].
+    pub fn add_recipients<I: IntoIterator<Item = Email>>(self, recipients: I) -> Message {
+        recipients.into_iter().fold(self, Message::add_to)
+    }
+
+    /// Add a personalization to the message.
+    pub fn add_personalization(mut self, p: Personalization) -> Message {
+        self.personalizations.push(p);
+        self
+    }
+
+    /// Add an attachment to the message.
+    pub fn add_attachment(mut self, a: Attachment) -> Message {
+        self.attachments.get_or_insert_with(Vec::new).push(a);
+        self
+    }
+
+    /// Add an image which the HTML content shows inline with `<img src="cid:content_id">`. The
+    /// attachment is given an inline disposition and the content id.
+    pub fn add_inline_image<S: Into<String>>(self, image: Attachment, content_id: S) -> Message {
+        self.add_attachment(
+            image
+                .set_disposition(Disposition::Inline)
+                .set_content_id(content_id),
+        )
+    }
+
+    /// Like [`Message::add_inline_image`] with an image read from a file.
+    pub fn add_inline_image_path<P, S>(self, path: P, content_id: S) -> SendgridResult<Message>
+    where
+        P: AsRef<Path>,
+        S: Into<String>,
+    {
+        Ok(self.add_inline_image(Attachment::from_path(path)?, content_id))
+    }
+
+    /// Check that the HTML content references every inline attachment as `cid:<content id>`.
+    pub fn check_inline_images(&self) -> SendgridResult<()> {
+        let html: Vec<&str> = self
+            .content
+            .iter()
+            .flatten()
+            .filter(|c| c.content_type.eq_ignore_ascii_case("text/html"))
+            .map(|c| c.value.as_str())
+            .collect();
+
+        for attachment in self.attachments.iter().flatten() {
+            if attachment.disposition != Some(Disposition::Inline) {
+                continue;
+            }
+            if let Some(content_id) = &attachment.content_id {
+                let reference = format!("cid:{}", content_id);
+                if !html.iter().any(|html| html.contains(&reference)) {
+                    return Err(SendgridError::UnreferencedInlineImage(content_id.clone()));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The total number of to, cc and bcc recipients across all personalizations.
+    pub fn recipient_count(&self) -> usize {
+        self.personalizations
+            .iter()
+            .map(Personalization::recipient_count)
+            .sum()
+    }
+
+    /// Add headers which apply to every personalization of the message. Accepts an [`SGMap`] or
+    /// any other iterator of name and value pairs.
+    pub fn add_headers<I, K, V>(mut self, headers: I) -> Message
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: Into<String>,
+        V: Into<String>,
+    {
+        self.headers
+            .get_or_insert_with(OrderedMap::new)
+            .extend(headers.into_iter().map(|(k, v)| (k.into(), v.into())));
+        self
+    }
+
+    /// Add a single header which applies to every personalization of the message.
+    pub fn add_header<K: Into<String>, V: Into<String>>(mut self, name: K, value: V) -> Message {
+        self.headers
+            .get_or_insert_with(OrderedMap::new)
+            .insert(name.into(), value.into());
+        self
+    }
+
+    /// Thread this message as a reply by setting the `In-Reply-To` and `References` headers.
+    /// `in_reply_to` is the `Message-ID` of the email being replied to and `references` are the
+    /// ids from that email's own `References` header, oldest first.
+    pub fn set_thread<S: AsRef<str>>(self, in_reply_to: &str, references: &[S]) -> Message {
+        let in_reply_to = bracket_message_id(in_reply_to);
+        let mut chain: Vec<String> = references
+            .iter()
+            .map(|r| bracket_message_id(r.as_ref()))
+            .filter(|r| *r != in_reply_to)
+            .collect();
+        chain.push(in_reply_to.clone());
+
+        self.add_headers([
+            (String::from("In-Reply-To"), in_reply_to),
+            (String::from("References"), chain.join(" ")),
+        ])
+    }
+
+    /// Thread this message as a reply to an email received through the Inbound Parse webhook.
+    /// The message is left untouched if the inbound email has no `Message-ID` header.
+    pub fn reply_to_inbound(self, email: &InboundEmail) -> Message {
+        match email.message_id() {
+            Some(id) => self.set_thread(&id, &email.references()),
+            None => self,
+        }
+    }
+
+    /// Whether the message has at least one recipient.
+    pub fn has_recipients(&self) -> bool {
+        self.recipient_count() > 0
+    }
+
+    /// Check the message against the personalization and recipient limits of the mail send API,
+    /// that its template data matches the kind of template used and that none of its headers are
+    /// [reserved](crate::headers::RESERVED_HEADERS).
+    pub fn validate(&self) -> SendgridResult<()> {
+        if self.personalizations.is_empty() {
+            return Err(SendgridError::NoPersonalizations);
+        }
+        if self.personalizations.len() > MAX_PERSONALIZATIONS {
+            return Err(SendgridError::TooManyPersonalizations {
+                count: self.personalizations.len(),
+                limit: MAX_PERSONALIZATIONS,
+            });
+        }
+
+        let mut count = 0;
+        for (index, p) in self.personalizations.iter().enumerate() {
+            if p.to.is_empty() {
+                return Err(SendgridError::MissingTo(index));
+            }
+            count += p.recipient_count();
+            if count > MAX_RECIPIENTS {
+                return Err(SendgridError::TooManyRecipients {
+                    personalization: index,
+                    count,
+                    limit: MAX_RECIPIENTS,
+                });
+            }
+        }
+
+        if let Some(template_id) = &self.template_id {
+            let dynamic = template_id.starts_with("d-");
+            for (index, p) in self.personalizations.iter().enumerate() {
+                if dynamic && p.substitutions.as_ref().is_some_and(|s| !s.is_empty()) {
+                    return Err(SendgridError::SubstitutionsWithDynamicTemplate(index));
+                }
+                if !dynamic
+                    && p.dynamic_template_data
+                        .as_ref()
+                        .is_some_and(|d| !d.is_empty())
+                {
+                    return Err(SendgridError::DynamicDataWithLegacyTemplate(index));
+                }
+            }
+        }
+
+        self.check_headers()
+    }
+
+    // Check that none of the headers of the message or its personalizations are reserved.
+    fn check_headers(&self) -> SendgridResult<()> {
+        check_headers(self.headers.iter().flat_map(OrderedMap::keys))?;
+        for p in &self.personalizations {
+            check_headers(p.headers.iter().flat_map(OrderedMap::keys))?;
+        }
+        Ok(())
+    }
+
+    /// Check that the message has a body and a subject. Messages without a template need
+    /// content, and a subject either on the message or on every personalization.
+    pub fn check_sendable(&self) -> SendgridResult<()> {
+        if self.template_id.is_some() {
+            return Ok(());
+        }
+        if self.content.as_ref().is_none_or(Vec::is_empty) {
+            return Err(SendgridError::MissingContent);
+        }
+        if self.subject.is_empty() {
+            if let Some(index) = self
+                .personalizations
+                .iter()
+                .position(|p| p.subject.as_ref().is_none_or(String::is_empty))
+            {
+                return Err(SendgridError::MissingSubject(index));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Split the message into messages with at most [`MAX_PERSONALIZATIONS`] personalizations and
+    /// [`MAX_RECIPIENTS`] recipients each. Every other field is copied to each message. A single
+    /// personalization with too many recipients can not be split and ends up in a chunk of its
+    /// own, which fails to validate.
+    pub fn chunks(&self) -> Vec<Message> {
+        let mut chunks = Vec::new();
+        let mut current: Vec<Personalization> = Vec::new();
+        let mut recipients = 0;
+
+        for p in &self.personalizations {
+            let count = p.recipient_count();
+            if !current.is_empty()
+                && (current.len() == MAX_PERSONALIZATIONS || recipients + count > MAX_RECIPIENTS)
+            {
+                chunks.push(std::mem::take(&mut current));
+                recipients = 0;
+            }
+            current.push(p.clone());
+            recipients += count;
+        }
+        if !current.is_empty() || chunks.is_empty() {
+            chunks.push(current);
+        }
+
+        chunks
+            .into_iter()
+            .map(|personalizations| Message {
+                personalizations,
+                ..self.without_personalizations()
+            })
+            .collect()
+    }
+
+    // A copy of the message without its personalizations, which may be large.
+    fn without_personalizations(&self) -> Message {
+        Message {
+            from: self.from.clone(),
+            subject: self.subject.clone(),
+            personalizations: Vec::new(),
+            reply_to: self.reply_to.clone(),
+            content: self.content.clone(),
+            attachments: self.attachments.clone(),
+            template_id: self.template_id.clone(),
+            batch_id: self.batch_id.clone(),
+            categories: self.categories.clone(),
+            ip_pool_name: self.ip_pool_name.clone(),
+            tracking_settings: self.tracking_settings.clone(),
+            asm: self.asm.clone(),
+            mail_settings: self.mail_settings.clone(),
+            headers: self.headers.clone(),
+        }
+    }
+
+    /// Check that the serialized message, including its base64 encoded attachments, is no larger
+    /// than [`MAX_PAYLOAD_SIZE`].
+    pub fn validate_size(&self) -> SendgridResult<()> {
+        let mut counter = ByteCounter(0);
+        serde_json::to_writer(&mut counter, self)?;
+        check_payload_size(counter.0)
+    }
+
+    /// Serialize the message to the JSON body sent to the mail send endpoint.
+    pub fn to_json(&self) -> SendgridResult<String> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    /// Serialize the message to a JSON value, for example to log or persist it.
+    pub fn to_json_value(&self) -> SendgridResult<Value> {
+        Ok(to_value(self)?)
+    }
+
+    /// Serialize the message to JSON with the keys of every object sorted, including those of
+    /// headers, substitutions and other maps. The output only depends on the contents of the
+    /// message, which makes it suitable for snapshot tests. It is not the order sent to the API.
+    pub fn to_canonical_json(&self) -> SendgridResult<String> {
+        Ok(serde_json::to_string(&canonicalize(to_value(self)?))?)
+    }
+}
+
+// Sort the keys of every object. The `preserve_order` feature of serde_json, which another crate
+// may enable, would otherwise keep the insertion order.
+fn canonicalize(value: Value) -> Value {
+    match value {
+        Value::Object(map) => {
+            let sorted: std::collections::BTreeMap<_, _> = map
+                .into_iter()
+                .map(|(key, value)| (key, canonicalize(value)))
+                .collect();
+            Value::Object(sorted.into_iter().collect())
+        }
+        Value::Array(values) => Value::Array(values.into_iter().map(canonicalize).collect()),
+        value => value,
+    }
+}
+
+fn check_payload_size(size: usize) -> SendgridResult<()> {
+    if size > MAX_PAYLOAD_SIZE {
+        return Err(SendgridError::PayloadTooLarge {
+            size,
+            limit: MAX_PAYLOAD_SIZE,
+        });
+    }
+    Ok(())
+}
+
+// Counts the bytes written to it, used to size a message without buffering its JSON.
+struct ByteCounter(usize);
+
+impl io::Write for ByteCounter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0 += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn attachment_filename(path: &Path) -> SendgridResult<&str> {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .ok_or(SendgridError::InvalidFilename)
+}
+
+// Message ids are written as `<id@domain>` in headers.
+fn bracket_message_id(id: &str) -> String {
+    let id = id.trim().trim_start_matches('<').trim_end_matches('>');
+    format!("<{}>", id)
+}
+
+impl Email {
+    /// Construct a new email type with name set as None.
+    ///
+    /// ```rust
+    /// use sendgrid::v3::Email;
+    ///
+    /// let my_email = Email::new("test@mail.com");
+    /// ```
+    pub fn new<S: Into<String>>(email: S) -> Email {
+        Email {
+            email: email.into(),
+            name: None,
+        }
+    }
+
+    /// Set an optional name.
+    ///
+    /// ```rust
+    /// use sendgrid::v3::Email;
+    ///
+    /// let my_email = Email::new("test@mail.com").set_name("My Name");
+    /// ```
+    pub fn set_name<S: Into<String>>(mut self, name: S) -> Email {
+        self.name = Some(name.into());
+        self
+    }
+}
+
+impl Content {
+    /// Construct a new content type.
+    pub fn new() -> Content {
+        Content::default()
+    }
+
+    /// Set the type of this content.
+    pub fn set_content_type<S: Into<String>>(mut self, content_type: S) -> Content {
+        self.content_type = content_type.into();
+        self
+    }
+
+    /// Set the corresponding message for this content.
     pub fn set_value<S: Into<String>>(mut self, value: S) -> Content {
         self.value = value.into();
         self
     }
+
+    // The position of this part in the content array required by the API.
+    fn rank(&self) -> u8 {
+        if self.content_type.eq_ignore_ascii_case("text/plain") {
+            0
+        } else if self.content_type.eq_ignore_ascii_case("text/x-amp-html") {
+            1
+        } else if self.content_type.eq_ignore_ascii_case("text/html") {
+            2
+        } else {
+            3
+        }
+    }
+
+    /// Construct a `text/plain` content part.
+    pub fn text<S: Into<String>>(value: S) -> Content {
+        Content::new()
+            .set_content_type("text/plain")
+            .set_value(value)
+    }
+
+    /// Construct a `text/html` content part.
+    pub fn html<S: Into<String>>(value: S) -> Content {
+        Content::new()
+            .set_content_type("text/html")
+            .set_value(value)
+    }
+
+    /// Construct a `text/x-amp-html` content part for AMP for Email.
+    pub fn amp_html<S: Into<String>>(value: S) -> Content {
+        Content::new()
+            .set_content_type("text/x-amp-html")
+            .set_value(value)
+    }
+}
+
+impl Personalization {
+    /// Construct a new personalization block for this message with a single to address.
+    pub fn new(email: Email) -> Personalization {
+        Personalization {
+            to: vec![email],
+            ..Default::default()
+        }
+    }
+
+    /// Construct a new personalization block for this message with more than one address.
+    pub fn new_many(email: Vec<Email>) -> Personalization {
+        Personalization {
+            to: email,
+            ..Default::default()
+        }
+    }
+
+    /// The number of to, cc and bcc recipients of this personalization.
+    pub fn recipient_count(&self) -> usize {
+        self.to.len() + self.cc.as_ref().map_or(0, Vec::len) + self.bcc.as_ref().map_or(0, Vec::len)
+    }
+
+    /// Check that the personalization can be sent on its own and return it. It needs at least
+    /// one to address, at most [`MAX_RECIPIENTS`] recipients and no reserved headers. Errors
+    /// refer to it as personalization 0. This allows building a personalization from
+    /// `Personalization::default()` and checking it once it is complete.
+    pub fn try_into_valid(self) -> SendgridResult<Personalization> {
+        if self.to.is_empty() {
+            return Err(SendgridError::MissingTo(0));
+        }
+        let count = self.recipient_count();
+        if count > MAX_RECIPIENTS {
+            return Err(SendgridError::TooManyRecipients {
+                personalization: 0,
+                count,
+                limit: MAX_RECIPIENTS,
+            });
+        }
+        check_headers(self.headers.iter().flat_map(OrderedMap::keys))?;
+        Ok(self)
+    }
+
+    /// Add a to field.
+    pub fn add_to(mut self, to: Email) -> Personalization {
+        self.to.push(to);
+        self
+    }
+
+    /// Add a CC field.
+    pub fn add_cc(mut self, cc: Email) -> Personalization {
+        self.cc
+            .get_or_insert_with(|| Vec::with_capacity(1))
+            .push(cc);
+        self
+    }
+
+    /// Add a BCC field.
+    pub fn add_bcc(mut self, bcc: Email) -> Personalization {
+        self.bcc
+            .get_or_insert_with(|| Vec::with_capacity(1))
+            .push(bcc);
+        self
+    }
+
+    /// Add a headers field. Accepts an [`SGMap`] or any other iterator of key and value pairs.
+    pub fn add_headers<I, K, V>(mut self, headers: I) -> Personalization
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: Into<String>,
+        V: Into<String>,
+    {
+        self.headers
+            .get_or_insert_with(OrderedMap::new)
+            .extend(headers.into_iter().map(|(k, v)| (k.into(), v.into())));
+        self
+    }
+
+    /// Add a single header.
+    pub fn add_header<K: Into<String>, V: Into<String>>(
+        mut self,
+        name: K,
+        value: V,
+    ) -> Personalization {
+        self.headers
+            .get_or_insert_with(OrderedMap::new)
+            .insert(name.into(), value.into());
+        self
+    }
+
+    /// Add a custom_args field. Accepts an [`SGMap`] or any other iterator of key and value pairs.
+    pub fn add_custom_args<I, K, V>(mut self, custom_args: I) -> Personalization
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: Into<String>,
+        V: Into<String>,
+    {
+        self.custom_args
+            .get_or_insert_with(OrderedMap::new)
+            .extend(custom_args.into_iter().map(|(k, v)| (k.into(), v.into())));
+        self
+    }
+
+    /// Add a single custom arg.
+    pub fn add_custom_arg<K: Into<String>, V: Into<String>>(
+        mut self,
+        key: K,
+        value: V,
+    ) -> Personalization {
+        self.custom_args
+            .get_or_insert_with(OrderedMap::new)
+            .insert(key.into(), value.into());
+        self
+    }
+
+    /// Add a substitutions field. Accepts an [`SGMap`] or any other iterator of key and value
+    /// pairs.
+    pub fn add_substitutions<I, K, V>(mut self, substitutions: I) -> Personalization
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: Into<String>,
+        V: Into<String>,
+    {
+        self.substitutions
+            .get_or_insert_with(OrderedMap::new)
+            .extend(substitutions.into_iter().map(|(k, v)| (k.into(), v.into())));
+        self
+    }
+
+    /// Add a single substitution.
+    pub fn add_substitution<K: Into<String>, V: Into<String>>(
+        mut self,
+        tag: K,
+        value: V,
+    ) -> Personalization {
+        self.substitutions
+            .get_or_insert_with(OrderedMap::new)
+            .insert(tag.into(), value.into());
+        self
+    }
+
+    /// Add a dynamic template data field.
+    pub fn add_dynamic_template_data(mut self, dynamic_template_data: SGMap) -> Personalization {
+        // We can safely unwrap & unreachable here since SGMap will always serialize
+        // to a JSON object.
+        let new_vals = match to_value(dynamic_template_data).unwrap() {
+            Object(map) => map,
+            _ => unreachable!(),
+        };
+        self.dynamic_template_data
+            .get_or_insert_with(|| Map::with_capacity(new_vals.len()))
+            .extend(new_vals);
+        self
+    }
+
+    /// Add a dynamic template data fields from a JSON object.
+    pub fn add_dynamic_template_data_json<T: Serialize + ?Sized>(
+        mut self,
+        json_object: &T,
+    ) -> SendgridResult<Personalization> {
+        let new_vals = match to_value(json_object)? {
+            Object(map) => map,
+            _ => return Err(SendgridError::InvalidTemplateValue),
+        };
+        self.dynamic_template_data
+            .get_or_insert_with(|| Map::with_capacity(new_vals.len()))
+            .extend(new_vals);
+        Ok(self)
+    }
+
+    /// Build one personalization per record, sent to the record's email with the record's data
+    /// as its dynamic template data. The data must serialize to a JSON object.
+    pub fn from_records<I, T>(records: I) -> SendgridResult<Vec<Personalization>>
+    where
+        I: IntoIterator<Item = (Email, T)>,
+        T: Serialize,
+    {
+        records
+            .into_iter()
+            .map(|(to, data)| Personalization::new(to).add_dynamic_template_data_json(&data))
+            .collect()
+    }
+
+    /// Set the subject.
+    pub fn set_subject(mut self, subject: &str) -> Personalization {
+        self.subject = Some(String::from(subject));
+        self
+    }
+
+    /// Set send at.
+    pub fn set_send_at(mut self, send_at: u64) -> Personalization {
+        self.send_at = Some(send_at);
+        self
+    }
+}
+
+impl Attachment {
+    /// Construct a new attachment for this message.
+    pub fn new() -> Attachment {
+        Attachment::default()
+    }
+
+    /// Construct an attachment from a file, using the file name of `path` as its filename. With
+    /// the `mime-guess` feature the mime type is inferred from the file extension.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> SendgridResult<Attachment> {
+        let path = path.as_ref();
+        let filename = attachment_filename(path)?;
+        let attachment = Attachment::from_reader(fs::File::open(path)?, filename)?;
+        Ok(attachment.guess_mime_type(path))
+    }
+
+    /// Like [`Attachment::from_path`] but reads the file with `tokio::fs` so the executor is not
+    /// blocked.
+    #[cfg(feature = "async-fs")]
+    pub async fn from_path_async<P: AsRef<Path>>(path: P) -> SendgridResult<Attachment> {
+        let path = path.as_ref();
+        let filename = attachment_filename(path)?;
+        let file = tokio::fs::File::open(path).await?;
+        let attachment = Attachment::from_async_read(file, filename).await?;
+        Ok(attachment.guess_mime_type(path))
+    }
+
+    /// Construct an attachment by reading `reader` to the end. The content is base64 encoded as
+    /// it is read so the raw bytes are never held in memory all at once.
+    pub fn from_reader<R: Read, S: Into<String>>(
+        mut reader: R,
+        filename: S,
+    ) -> SendgridResult<Attachment> {
+        let base64 = BASE64;
+        let mut content = String::new();
+        let mut encoder = base64.new_encoder(&mut content);
+        let mut buf = [0; ATTACHMENT_CHUNK_SIZE];
+        loop {
+            match reader.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => encoder.append(&buf[..n]),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        encoder.finalize();
+
+        Ok(Attachment::new()
+            .set_base64_content(content)
+            .set_filename(filename))
+    }
+
+    /// Like [`Attachment::from_reader`] for an asynchronous reader.
+    #[cfg(feature = "async-fs")]
+    pub async fn from_async_read<R, S>(mut reader: R, filename: S) -> SendgridResult<Attachment>
+    where
+        R: tokio::io::AsyncRead + Unpin,
+        S: Into<String>,
+    {
+        use tokio::io::AsyncReadExt;
+
+        let base64 = BASE64;
+        let mut content = String::new();
+        let mut encoder = base64.new_encoder(&mut content);
+        let mut buf = vec![0; ATTACHMENT_CHUNK_SIZE];
+        loop {
+            match reader.read(&mut buf).await? {
+                0 => break,
+                n => encoder.append(&buf[..n]),
+            }
+        }
+        encoder.finalize();
+
+        Ok(Attachment::new()
+            .set_base64_content(content)
+            .set_filename(filename))
+    }
+
+    #[cfg(feature = "mime-guess")]
+    fn guess_mime_type(self, path: &Path) -> Attachment {
+        match mime_guess::from_path(path).first() {
+            Some(mime) => self.set_mime_type(mime.essence_str()),
+            None => self,
+        }
+    }
+
+    #[cfg(not(feature = "mime-guess"))]
+    fn guess_mime_type(self, _path: &Path) -> Attachment {
+        self
+    }
+
+    /// The raw body of the attachment.
+    pub fn set_content(mut self, c: &[u8]) -> Attachment {
+        self.content = BASE64.encode(c);
+        self
+    }
+
+    /// The base64 body of the attachment.
+    pub fn set_base64_content<S: Into<String>>(mut self, c: S) -> Attachment {
+        self.content = c.into();
+        self
+    }
+
+    /// Sets the filename for the attachment.
+    pub fn set_filename<S: Into<String>>(mut self, filename: S) -> Attachment {
+        self.filename = filename.into();
+        self
+    }
+
+    /// Set an optional mime type, for example [`Mime::Pdf`]. Sendgrid will default to
+    /// 'application/octet-stream' if unspecified.
+    pub fn set_mime_type<S: Into<String>>(mut self, mime: S) -> Attachment {
+        self.mime_type = Some(mime.into());
+        self
+    }
+
+    /// Set an optional content id.
+    pub fn set_content_id<S: Into<String>>(mut self, content_id: S) -> Attachment {
+        self.content_id = Some(content_id.into());
+        self
+    }
+
+    /// Set an optional content id.
+    #[deprecated(since = "0.24.0", note = "use `set_content_id` instead")]
+    pub fn set_content_idm<S: Into<String>>(self, content_id: S) -> Attachment {
+        self.set_content_id(content_id)
+    }
+
+    /// Set an optional disposition.
+    pub fn set_disposition(mut self, disposition: Disposition) -> Attachment {
+        self.disposition = Some(disposition);
+        self
+    }
+}
+
+impl TrackingSettings {
+    /// Construct new tracking settings with every setting left to the account defaults.
+    pub fn new() -> TrackingSettings {
+        TrackingSettings::default()
+    }
+
+    /// Disable click, open, subscription and Google Analytics tracking for this message.
+    pub fn disable_all() -> TrackingSettings {
+        TrackingSettings::new()
+            .enable_click(false)
+            .enable_open(false)
+            .enable_subscription(false)
+            .enable_ganalytics(false)
+    }
+
+    /// Enable or disable click tracking, keeping any other click tracking options.
+    pub fn enable_click(mut self, enable: bool) -> TrackingSettings {
+        let setting = self.click_tracking.take().unwrap_or_default();
+        self.click_tracking = Some(setting.set_enable(enable));
+        self
+    }
+
+    /// Enable or disable open tracking, keeping any other open tracking options.
+    pub fn enable_open(mut self, enable: bool) -> TrackingSettings {
+        let setting = self.open_tracking.take().unwrap_or_default();
+        self.open_tracking = Some(setting.set_enable(enable));
+        self
+    }
+
+    /// Enable or disable subscription tracking, keeping any other subscription tracking options.
+    pub fn enable_subscription(mut self, enable: bool) -> TrackingSettings {
+        let setting = self.subscription_tracking.take().unwrap_or_default();
+        self.subscription_tracking = Some(setting.set_enable(enable));
+        self
+    }
+
+    /// Enable or disable Google Analytics tracking, keeping any `utm_*` values.
+    pub fn enable_ganalytics(mut self, enable: bool) -> TrackingSettings {
+        let setting = self.ganalytics.take().unwrap_or_default();
+        self.ganalytics = Some(setting.set_enable(enable));
+        self
+    }
+
+    /// Set the click tracking settings.
+    pub fn set_click_tracking(mut self, click_tracking: ClickTrackingSetting) -> TrackingSettings {
+        self.click_tracking = Some(click_tracking);
+        self
+    }
+
+    /// Set the open tracking settings.
+    pub fn set_open_tracking(mut self, open_tracking: OpenTrackingSetting) -> TrackingSettings {
+        self.open_tracking = Some(open_tracking);
+        self
+    }
+
+    /// Set the subscription tracking settings.
+    pub fn set_subscription_tracking(
+        mut self,
+        subscription_tracking: SubscriptionTrackingSetting,
+    ) -> TrackingSettings {
+        self.subscription_tracking = Some(subscription_tracking);
+        self
+    }
+
+    /// Set the Google Analytics tracking settings.
+    pub fn set_ganalytics(mut self, ganalytics: GanalyticsSetting) -> TrackingSettings {
+        self.ganalytics = Some(ganalytics);
+        self
+    }
+}
+
+impl ClickTrackingSetting {
+    /// Construct a new click tracking setting with every field unset.
+    pub fn new() -> ClickTrackingSetting {
+        ClickTrackingSetting::default()
+    }
+
+    /// Enable or disable click tracking.
+    pub fn set_enable(mut self, enable: bool) -> ClickTrackingSetting {
+        self.enable = Some(enable);
+        self
+    }
+
+    /// Enable or disable click tracking in the text/plain content.
+    pub fn set_enable_text(mut self, enable_text: bool) -> ClickTrackingSetting {
+        self.enable_text = Some(enable_text);
+        self
+    }
+}
+
+impl OpenTrackingSetting {
+    /// Construct a new open tracking setting with every field unset.
+    pub fn new() -> OpenTrackingSetting {
+        OpenTrackingSetting::default()
+    }
+
+    /// Enable or disable open tracking.
+    pub fn set_enable(mut self, enable: bool) -> OpenTrackingSetting {
+        self.enable = Some(enable);
+        self
+    }
+
+    /// Set the tag that is replaced with the open tracking pixel.
+    pub fn set_substitution_tag<S: Into<String>>(
+        mut self,
+        substitution_tag: S,
+    ) -> OpenTrackingSetting {
+        self.substitution_tag = Some(substitution_tag.into());
+        self
+    }
+}
+
+impl SubscriptionTrackingSetting {
+    /// Construct a new subscription tracking setting with every field unset.
+    pub fn new() -> SubscriptionTrackingSetting {
+        SubscriptionTrackingSetting::default()
+    }
+
+    /// Enable or disable subscription tracking.
+    pub fn set_enable(mut self, enable: bool) -> SubscriptionTrackingSetting {
+        self.enable = Some(enable);
+        self
+    }
+
+    /// Set the plain text unsubscribe footer.
+    pub fn set_text<S: Into<String>>(mut self, text: S) -> SubscriptionTrackingSetting {
+        self.text = Some(text.into());
+        self
+    }
+
+    /// Set the HTML unsubscribe footer.
+    pub fn set_html<S: Into<String>>(mut self, html: S) -> SubscriptionTrackingSetting {
+        self.html = Some(html.into());
+        self
+    }
+
+    /// Set the tag that is replaced with the unsubscribe URL.
+    pub fn set_substitution_tag<S: Into<String>>(
+        mut self,
+        substitution_tag: S,
+    ) -> SubscriptionTrackingSetting {
+        self.substitution_tag = Some(substitution_tag.into());
+        self
+    }
+}
+
+impl GanalyticsSetting {
+    /// Construct a new Google Analytics setting with every field unset.
+    pub fn new() -> GanalyticsSetting {
+        GanalyticsSetting::default()
+    }
+
+    /// Enable or disable Google Analytics tracking.
+    pub fn set_enable(mut self, enable: bool) -> GanalyticsSetting {
+        self.enable = Some(enable);
+        self
+    }
+
+    /// Set the `utm_source` parameter.
+    pub fn set_utm_source<S: Into<String>>(mut self, utm_source: S) -> GanalyticsSetting {
+        self.utm_source = Some(utm_source.into());
+        self
+    }
+
+    /// Set the `utm_medium` parameter.
+    pub fn set_utm_medium<S: Into<String>>(mut self, utm_medium: S) -> GanalyticsSetting {
+        self.utm_medium = Some(utm_medium.into());
+        self
+    }
+
+    /// Set the `utm_term` parameter.
+    pub fn set_utm_term<S: Into<String>>(mut self, utm_term: S) -> GanalyticsSetting {
+        self.utm_term = Some(utm_term.into());
+        self
+    }
+
+    /// Set the `utm_content` parameter.
+    pub fn set_utm_content<S: Into<String>>(mut self, utm_content: S) -> GanalyticsSetting {
+        self.utm_content = Some(utm_content.into());
+        self
+    }
+
+    /// Set the `utm_campaign` parameter.
+    pub fn set_utm_campaign<S: Into<String>>(mut self, utm_campaign: S) -> GanalyticsSetting {
+        self.utm_campaign = Some(utm_campaign.into());
+        self
+    }
+}
+
+impl ASM {
+    /// Construct an object allowing you to specify how to handle unsubscribes.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// The unsubscribe group to associate with this email.
+    pub fn set_group_id(mut self, group_id: u32) -> Self {
+        self.group_id = group_id;
+        self
+    }
+
+    /// Set the unsubscribe groups that you would like to be displayed on the unsubscribe
+    /// preferences page. Groups are serialized in ascending order and at most
+    /// [`MAX_GROUPS_TO_DISPLAY`] are allowed.
+    pub fn set_groups_to_display<I>(mut self, groups_to_display: I) -> SendgridResult<Self>
+    where
+        I: IntoIterator<Item = u32>,
+    {
+        let groups_to_display: BTreeSet<u32> = groups_to_display.into_iter().collect();
+        check_groups_to_display(groups_to_display.len())?;
+
+        self.groups_to_display = groups_to_display;
+        Ok(self)
+    }
+
+    /// Add an unsubscribe group to display on the unsubscribe preferences page.
+    pub fn add_group_to_display(mut self, group_id: u32) -> SendgridResult<Self> {
+        if !self.groups_to_display.contains(&group_id) {
+            check_groups_to_display(self.groups_to_display.len() + 1)?;
+            self.groups_to_display.insert(group_id);
+        }
+        Ok(self)
+    }
+}
+
+fn check_groups_to_display(count: usize) -> SendgridResult<()> {
+    if count > MAX_GROUPS_TO_DISPLAY {
+        return Err(SendgridError::TooManyGroupsToDisplay {
+            count,
+            limit: MAX_GROUPS_TO_DISPLAY,
+        });
+    }
+    Ok(())
 }
 
-impl Personalization {
-    /// Construct a new personalization block for this message with a single to address.
-    pub fn new(email: Email) -> Personalization {
-        Personalization {
-            to: vec![email],
-            ..Default::default()
+#[cfg(test)]
+mod tests {
+    use crate::error::SendgridError;
+    use crate::v3::message::{Footer, MailSettings, SandboxMode};
+    use crate::v3::{
+        Attachment, CategoryName, ClickTrackingSetting, Content, Email, GanalyticsSetting, Message,
+        Mime, OpenTrackingSetting, Personalization, SGMap, Sender, SubscriptionTrackingSetting,
+        TrackingSettings, ASM, MAX_GROUPS_TO_DISPLAY, MAX_PAYLOAD_SIZE, MAX_PERSONALIZATIONS,
+        MAX_RECIPIENTS, ON_BEHALF_OF_HEADER,
+    };
+    use serde::Serialize;
+    use std::collections::HashSet;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    #[derive(Serialize)]
+    struct OuterModel {
+        inners: Vec<InnerModel>,
+    }
+
+    #[derive(Serialize)]
+    struct InnerModel {
+        x: String,
+        y: String,
+        z: String,
+    }
+
+    #[test]
+    fn ip_pool_name() {
+        let json_str = Message::new(Email::new("from_email@test.com"))
+            .add_personalization(Personalization::new(Email::new("to_email@test.com")))
+            .set_ip_pool_name("test_ip_pool")
+            .to_json()
+            .unwrap();
+        let expected = r#"{"from":{"email":"from_email@test.com"},"subject":"","personalizations":[{"to":[{"email":"to_email@test.com"}]}],"ip_pool_name":"test_ip_pool"}"#;
+        assert_eq!(json_str, expected);
+    }
+
+    #[test]
+    fn batch_id() {
+        let json_str = Message::new(Email::new("from_email@test.com"))
+            .add_personalization(Personalization::new(Email::new("to_email@test.com")))
+            .set_batch_id("test_batch_id")
+            .to_json()
+            .unwrap();
+        let expected = r#"{"from":{"email":"from_email@test.com"},"subject":"","personalizations":[{"to":[{"email":"to_email@test.com"}]}],"batch_id":"test_batch_id"}"#;
+        assert_eq!(json_str, expected);
+    }
+
+    #[test]
+    fn single_category() {
+        let json_str = Message::new(Email::new("from_email@test.com"))
+            .add_personalization(Personalization::new(Email::new("to_email@test.com")))
+            .add_category("test_category")
+            .to_json()
+            .unwrap();
+        let expected = r#"{"from":{"email":"from_email@test.com"},"subject":"","personalizations":[{"to":[{"email":"to_email@test.com"}]}],"categories":["test_category"]}"#;
+        assert_eq!(json_str, expected);
+    }
+
+    #[test]
+    fn click_tracking_setting() {
+        let json_str = Message::new(Email::new("from_email@test.com"))
+            .add_personalization(Personalization::new(Email::new("to_email@test.com")))
+            .set_tracking_settings(TrackingSettings {
+                click_tracking: Some(ClickTrackingSetting {
+                    enable: Some(true),
+                    enable_text: None,
+                }),
+                open_tracking: None,
+                subscription_tracking: None,
+                ganalytics: None,
+            })
+            .to_json()
+            .unwrap();
+        let expected = r#"{"from":{"email":"from_email@test.com"},"subject":"","personalizations":[{"to":[{"email":"to_email@test.com"}]}],"tracking_settings":{"click_tracking":{"enable":true}}}"#;
+        assert_eq!(json_str, expected);
+    }
+
+    #[test]
+    fn open_tracking_setting() {
+        let json_str = Message::new(Email::new("from_email@test.com"))
+            .add_personalization(Personalization::new(Email::new("to_email@test.com")))
+            .set_tracking_settings(TrackingSettings {
+                click_tracking: None,
+                open_tracking: Some(OpenTrackingSetting {
+                    enable: Some(true),
+                    substitution_tag: None,
+                }),
+                subscription_tracking: None,
+                ganalytics: None,
+            })
+            .to_json()
+            .unwrap();
+        let expected = r#"{"from":{"email":"from_email@test.com"},"subject":"","personalizations":[{"to":[{"email":"to_email@test.com"}]}],"tracking_settings":{"open_tracking":{"enable":true}}}"#;
+        assert_eq!(json_str, expected);
+    }
+
+    #[test]
+    fn subscription_tracking_setting() {
+        let json_str = Message::new(Email::new("from_email@test.com"))
+            .add_personalization(Personalization::new(Email::new("to_email@test.com")))
+            .set_tracking_settings(TrackingSettings {
+                click_tracking: None,
+                open_tracking: None,
+                subscription_tracking: Some(SubscriptionTrackingSetting {
+                    enable: Some(true),
+                    ..Default::default()
+                }),
+                ganalytics: None,
+            })
+            .to_json()
+            .unwrap();
+        let expected = r#"{"from":{"email":"from_email@test.com"},"subject":"","personalizations":[{"to":[{"email":"to_email@test.com"}]}],"tracking_settings":{"subscription_tracking":{"enable":true}}}"#;
+        assert_eq!(json_str, expected);
+    }
+
+    #[test]
+    fn subscription_tracking_footer() {
+        let json_str = Message::new(Email::new("from_email@test.com"))
+            .add_personalization(Personalization::new(Email::new("to_email@test.com")))
+            .set_tracking_settings(TrackingSettings {
+                click_tracking: None,
+                open_tracking: None,
+                subscription_tracking: Some(
+                    SubscriptionTrackingSetting::new()
+                        .set_enable(true)
+                        .set_text("Unsubscribe: <% %>")
+                        .set_html("<a href=\"<% %>\">Unsubscribe</a>")
+                        .set_substitution_tag("[unsubscribe]"),
+                ),
+                ganalytics: None,
+            })
+            .to_json()
+            .unwrap();
+        let expected = r#"{"from":{"email":"from_email@test.com"},"subject":"","personalizations":[{"to":[{"email":"to_email@test.com"}]}],"tracking_settings":{"subscription_tracking":{"enable":true,"text":"Unsubscribe: <% %>","html":"<a href=\"<% %>\">Unsubscribe</a>","substitution_tag":"[unsubscribe]"}}}"#;
+        assert_eq!(json_str, expected);
+    }
+
+    #[test]
+    fn ganalytics_setting() {
+        let json_str = Message::new(Email::new("from_email@test.com"))
+            .add_personalization(Personalization::new(Email::new("to_email@test.com")))
+            .set_tracking_settings(TrackingSettings {
+                click_tracking: None,
+                open_tracking: None,
+                subscription_tracking: None,
+                ganalytics: Some(
+                    GanalyticsSetting::new()
+                        .set_enable(true)
+                        .set_utm_source("newsletter")
+                        .set_utm_medium("email")
+                        .set_utm_campaign("launch"),
+                ),
+            })
+            .to_json()
+            .unwrap();
+        let expected = r#"{"from":{"email":"from_email@test.com"},"subject":"","personalizations":[{"to":[{"email":"to_email@test.com"}]}],"tracking_settings":{"ganalytics":{"enable":true,"utm_source":"newsletter","utm_medium":"email","utm_campaign":"launch"}}}"#;
+        assert_eq!(json_str, expected);
+    }
+
+    #[test]
+    fn tracking_settings_builder() {
+        let json_str = Message::new(Email::new("from_email@test.com"))
+            .add_personalization(Personalization::new(Email::new("to_email@test.com")))
+            .set_tracking_settings(
+                TrackingSettings::disable_all()
+                    .set_click_tracking(ClickTrackingSetting::new().set_enable_text(false))
+                    .enable_click(true),
+            )
+            .to_json()
+            .unwrap();
+        let expected = r#"{"from":{"email":"from_email@test.com"},"subject":"","personalizations":[{"to":[{"email":"to_email@test.com"}]}],"tracking_settings":{"click_tracking":{"enable":true,"enable_text":false},"open_tracking":{"enable":false},"subscription_tracking":{"enable":false},"ganalytics":{"enable":false}}}"#;
+        assert_eq!(json_str, expected);
+    }
+
+    #[test]
+    fn text_and_html_shortcuts() {
+        let json_str = Message::new(Email::new("from_email@test.com"))
+            .add_personalization(Personalization::new(Email::new("to_email@test.com")))
+            .set_html("<p>old</p>")
+            .set_html("<p>hi</p>")
+            .set_text("hi")
+            .to_json()
+            .unwrap();
+        let expected = r#"{"from":{"email":"from_email@test.com"},"subject":"","personalizations":[{"to":[{"email":"to_email@test.com"}]}],"content":[{"type":"text/plain","value":"hi"},{"type":"text/html","value":"<p>hi</p>"}]}"#;
+        assert_eq!(json_str, expected);
+    }
+
+    #[test]
+    fn attachment_from_path() {
+        let path = std::env::temp_dir().join("sendgrid-attachment-from-path.txt");
+        std::fs::write(&path, "hello").unwrap();
+        let attachment = Attachment::from_path(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(attachment.filename, "sendgrid-attachment-from-path.txt");
+        assert_eq!(attachment.content, "aGVsbG8=");
+        #[cfg(feature = "mime-guess")]
+        assert_eq!(attachment.mime_type.as_deref(), Some("text/plain"));
+
+        assert!(Attachment::from_path("/does/not/exist.txt").is_err());
+    }
+
+    #[test]
+    fn prepare_serializes_without_sending() {
+        let message = Message::new(Email::new("from_email@test.com"))
+            .add_personalization(Personalization::new(Email::new("to_email@test.com")));
+        let sender = Sender::new(String::from("SG.key"), None);
+
+        let prepared = sender.prepare(&message).unwrap();
+        assert_eq!(prepared.body(), message.to_json().unwrap());
+    }
+
+    #[cfg(feature = "async-fs")]
+    #[tokio::test]
+    async fn attachment_from_path_async() {
+        let path = std::env::temp_dir().join("sendgrid-attachment-from-path-async.txt");
+        std::fs::write(&path, "hello").unwrap();
+        let attachment = Attachment::from_path_async(&path).await.unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            attachment.filename,
+            "sendgrid-attachment-from-path-async.txt"
+        );
+        assert_eq!(attachment.content, "aGVsbG8=");
+        assert!(Attachment::from_path_async("/does/not/exist.txt")
+            .await
+            .is_err());
+    }
+
+    #[test]
+    fn named_categories() {
+        enum Categories {
+            Welcome,
+        }
+
+        impl CategoryName for Categories {
+            fn category_name(&self) -> &str {
+                match self {
+                    Categories::Welcome => "welcome",
+                }
+            }
+        }
+
+        let json_str = Message::new(Email::new("from_email@test.com"))
+            .add_personalization(Personalization::new(Email::new("to_email@test.com")))
+            .add_category(Categories::Welcome)
+            .add_category(String::from("onboarding"))
+            .to_json()
+            .unwrap();
+        let expected = r#"{"from":{"email":"from_email@test.com"},"subject":"","personalizations":[{"to":[{"email":"to_email@test.com"}]}],"categories":["welcome","onboarding"]}"#;
+        assert_eq!(json_str, expected);
+    }
+
+    #[test]
+    fn attachment_from_reader() {
+        let data: Vec<u8> = (0..=255).cycle().take(100_000).collect();
+        let attachment = Attachment::from_reader(&data[..], "data.bin").unwrap();
+        assert_eq!(attachment.filename, "data.bin");
+        assert_eq!(attachment.content, data_encoding::BASE64.encode(&data));
+    }
+
+    #[test]
+    fn payload_size_is_validated() {
+        let message = Message::new(Email::new("from_email@test.com"))
+            .add_personalization(Personalization::new(Email::new("to_email@test.com")));
+        assert!(message.validate_size().is_ok());
+
+        let message = message.add_attachment(
+            Attachment::new()
+                .set_filename("large.bin")
+                .set_base64_content("A".repeat(MAX_PAYLOAD_SIZE)),
+        );
+        match message.validate_size() {
+            Err(SendgridError::PayloadTooLarge { size, limit }) => {
+                assert!(size > limit);
+                assert_eq!(limit, MAX_PAYLOAD_SIZE);
+            }
+            _ => panic!("expected PayloadTooLarge"),
+        }
+
+        let mut sender = Sender::new(String::from("SG.key"), None);
+        assert!(sender.prepare(&message).is_ok());
+        sender.set_validate_size(true);
+        assert!(sender.prepare(&message).is_err());
+    }
+
+    #[test]
+    fn singular_setters() {
+        let json_str = Message::new(Email::new("from_email@test.com"))
+            .add_header("X-Campaign", "launch")
+            .add_personalization(
+                Personalization::new(Email::new("to_email@test.com"))
+                    .add_header("X-Tenant", "acme")
+                    .add_custom_arg("tenant_id", "acme")
+                    .add_substitution("-name-", "Ada"),
+            )
+            .add_attachment(
+                Attachment::new()
+                    .set_filename("logo.png")
+                    .set_content_id("logo"),
+            )
+            .to_json()
+            .unwrap();
+        let expected = r#"{"from":{"email":"from_email@test.com"},"subject":"","personalizations":[{"to":[{"email":"to_email@test.com"}],"headers":{"X-Tenant":"acme"},"substitutions":{"-name-":"Ada"},"custom_args":{"tenant_id":"acme"}}],"attachments":[{"content":"","filename":"logo.png","content_id":"logo"}],"headers":{"X-Campaign":"launch"}}"#;
+        assert_eq!(json_str, expected);
+    }
+
+    #[test]
+    fn messages_are_clone_and_debug() {
+        let message = Message::new(Email::new("from_email@test.com"))
+            .add_personalization(Personalization::new(Email::new("to_email@test.com")))
+            .set_asm(ASM::new().set_group_id(1));
+        let copy = message.clone();
+        assert_eq!(copy.to_json().unwrap(), message.to_json().unwrap());
+        assert!(format!("{:?}", message).contains("to_email@test.com"));
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let message = Message::new(Email::new("from_email@test.com").set_name("From"))
+            .set_subject("Round trip")
+            .set_text("Hi")
+            .set_html("<p>Hi</p>")
+            .set_template_id("d-123")
+            .add_category("receipts")
+            .add_header("X-Campaign", "launch")
+            .add_attachment(
+                Attachment::new()
+                    .set_content(b"hello")
+                    .set_filename("hello.txt")
+                    .set_mime_type("text/plain"),
+            )
+            .set_asm(ASM::new().set_group_id(7))
+            .set_tracking_settings(TrackingSettings::disable_all())
+            .set_mail_settings(
+                MailSettings::new().set_sandbox_mode(SandboxMode::new().set_enable(true)),
+            )
+            .add_personalization(
+                Personalization::new(Email::new("to_email@test.com"))
+                    .add_custom_arg("tenant_id", "acme")
+                    .set_send_at(1_700_000_000),
+            );
+
+        let json = message.to_json().unwrap();
+        let parsed: Message = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, message);
+
+        // Fields with defaults may be left out of hand written specs.
+        let spec = r#"{"from":{"email":"from_email@test.com"},"template_id":"d-123","asm":{"group_id":1}}"#;
+        let parsed: Message = serde_json::from_str(spec).unwrap();
+        assert_eq!(parsed.subject, "");
+        assert!(parsed.personalizations.is_empty());
+    }
+
+    #[test]
+    fn messages_compare_by_value() {
+        let build = |subject: &str| {
+            Message::new(Email::new("from_email@test.com"))
+                .set_subject(subject)
+                .add_content(Content::text("Hi"))
+                .add_personalization(
+                    Personalization::new(Email::new("to_email@test.com"))
+                        .add_custom_arg("a", "1")
+                        .add_custom_arg("b", "2"),
+                )
+        };
+        assert_eq!(build("Hello"), build("Hello"));
+        assert_ne!(build("Hello"), build("Goodbye"));
+        assert_eq!(
+            Email::new("to_email@test.com").set_name("To"),
+            Email::new("to_email@test.com").set_name("To")
+        );
+    }
+
+    #[test]
+    fn add_to_reuses_default_personalization() {
+        let json_str = Message::new(Email::new("from_email@test.com"))
+            .add_to(Email::new("a@test.com"))
+            .add_recipients(vec![Email::new("b@test.com"), Email::new("c@test.com")])
+            .to_json()
+            .unwrap();
+        let expected = r#"{"from":{"email":"from_email@test.com"},"subject":"","personalizations":[{"to":[{"email":"a@test.com"},{"email":"b@test.com"},{"email":"c@test.com"}]}]}"#;
+        assert_eq!(json_str, expected);
+    }
+
+    #[test]
+    fn recipient_limits_are_validated() {
+        let message = (0..MAX_PERSONALIZATIONS).fold(
+            Message::new(Email::new("from_email@test.com")),
+            |message, i| {
+                message.add_personalization(Personalization::new(Email::new(format!(
+                    "to{}@test.com",
+                    i
+                ))))
+            },
+        );
+        assert!(message.validate().is_ok());
+
+        let over = message
+            .clone()
+            .add_personalization(Personalization::new(Email::new("extra@test.com")));
+        match over.validate() {
+            Err(SendgridError::TooManyPersonalizations { count, .. }) => assert_eq!(count, 1001),
+            _ => panic!("expected TooManyPersonalizations"),
+        }
+
+        let over = Message::new(Email::new("from_email@test.com"))
+            .add_personalization(Personalization::new(Email::new("a@test.com")))
+            .add_personalization(Personalization::new_many(
+                (0..MAX_RECIPIENTS)
+                    .map(|i| Email::new(format!("to{}@test.com", i)))
+                    .collect(),
+            ));
+        match over.validate() {
+            Err(SendgridError::TooManyRecipients {
+                personalization,
+                count,
+                ..
+            }) => {
+                assert_eq!(personalization, 1);
+                assert_eq!(count, 1001);
+            }
+            _ => panic!("expected TooManyRecipients"),
         }
+
+        let mut sender = Sender::new(String::from("SG.key"), None);
+        assert!(sender.prepare(&over).is_ok());
+        sender.set_validate(true);
+        assert!(sender.prepare(&over).is_err());
     }
 
-    /// Construct a new personalization block for this message with more than one address.
-    pub fn new_many(email: Vec<Email>) -> Personalization {
-        Personalization {
-            to: email,
-            ..Default::default()
-        }
+    #[test]
+    fn messages_without_personalizations_are_rejected() {
+        let message = Message::new(Email::new("from_email@test.com"));
+        assert!(!message.has_recipients());
+        assert!(matches!(
+            message.validate(),
+            Err(SendgridError::NoPersonalizations)
+        ));
+        assert!(matches!(
+            Sender::new(String::from("SG.key"), None).prepare(&message),
+            Err(SendgridError::NoPersonalizations)
+        ));
+        assert!(message
+            .add_to(Email::new("to_email@test.com"))
+            .has_recipients());
     }
 
-    /// Add a to field.
-    pub fn add_to(mut self, to: Email) -> Personalization {
-        self.to.push(to);
-        self
+    #[test]
+    fn sender_defaults_fill_missing_fields() {
+        let mut sender = Sender::new(String::from("SG.key"), None);
+        let message = Message::default().add_to(Email::new("to_email@test.com"));
+        assert!(matches!(
+            sender.prepare(&message),
+            Err(SendgridError::MissingFrom)
+        ));
+
+        sender.set_default_from(Email::new("noreply@test.com"));
+        sender.set_default_from_name("Brand");
+        sender.set_default_reply_to(Email::new("support@test.com"));
+
+        let message = Message::default().add_to(Email::new("to_email@test.com"));
+        let prepared = sender.prepare(&message).unwrap();
+        let expected = r#"{"from":{"email":"noreply@test.com","name":"Brand"},"subject":"","personalizations":[{"to":[{"email":"to_email@test.com"}]}],"reply_to":{"email":"support@test.com"}}"#;
+        assert_eq!(prepared.body(), expected);
+
+        let message = Message::new(Email::new("from_email@test.com").set_name("Me"))
+            .set_reply_to(Email::new("reply@test.com"))
+            .add_to(Email::new("to_email@test.com"));
+        let prepared = sender.prepare(&message).unwrap();
+        assert_eq!(prepared.body(), message.to_json().unwrap());
     }
 
-    /// Add a CC field.
-    pub fn add_cc(mut self, cc: Email) -> Personalization {
-        self.cc
-            .get_or_insert_with(|| Vec::with_capacity(1))
-            .push(cc);
-        self
+    #[test]
+    fn content_is_ordered() {
+        let json_str = Message::new(Email::new("from_email@test.com"))
+            .add_to(Email::new("to_email@test.com"))
+            .add_content(
+                Content::new()
+                    .set_content_type("text/calendar")
+                    .set_value("cal"),
+            )
+            .add_content(Content::html("<p>hi</p>"))
+            .add_content(Content::text("hi"))
+            .to_json()
+            .unwrap();
+        let expected = r#"{"from":{"email":"from_email@test.com"},"subject":"","personalizations":[{"to":[{"email":"to_email@test.com"}]}],"content":[{"type":"text/plain","value":"hi"},{"type":"text/html","value":"<p>hi</p>"},{"type":"text/calendar","value":"cal"}]}"#;
+        assert_eq!(json_str, expected);
     }
 
-    /// Add a BCC field.
-    pub fn add_bcc(mut self, bcc: Email) -> Personalization {
-        self.bcc
-            .get_or_insert_with(|| Vec::with_capacity(1))
-            .push(bcc);
-        self
+    #[test]
+    fn amp_content_is_between_text_and_html() {
+        let message = Message::new(Email::new("from_email@test.com"))
+            .set_html("<p>hi</p>")
+            .set_amp_html("<html amp4email></html>")
+            .set_text("hi")
+            .set_amp_html("<html amp4email>hi</html>");
+        let types: Vec<_> = message
+            .content
+            .iter()
+            .flatten()
+            .map(|c| c.content_type.as_str())
+            .collect();
+        assert_eq!(types, ["text/plain", "text/x-amp-html", "text/html"]);
+        assert_eq!(
+            message.content.unwrap()[1].value,
+            "<html amp4email>hi</html>"
+        );
     }
 
-    /// Add a headers field.
-    pub fn add_headers(mut self, headers: SGMap) -> Personalization {
-        self.headers
-            .get_or_insert_with(|| SGMap::with_capacity(headers.len()))
-            .extend(headers);
-        self
+    #[test]
+    fn reserved_headers_are_rejected() {
+        let message = Message::new(Email::new("from_email@test.com"))
+            .add_personalization(
+                Personalization::new(Email::new("to_email@test.com"))
+                    .add_header("DKIM-Signature", "v=1"),
+            )
+            .add_header("X-Campaign", "launch");
+        assert!(matches!(
+            message.validate(),
+            Err(SendgridError::ReservedHeader(name)) if name == "DKIM-Signature"
+        ));
+
+        let message = message.add_header("content-type", "text/plain");
+        assert!(matches!(
+            message.validate(),
+            Err(SendgridError::ReservedHeader(name)) if name == "content-type"
+        ));
+
+        let mut sender = Sender::new(String::from("SG.key"), None);
+        sender.set_validate(false);
+        assert!(matches!(
+            sender.prepare(&message),
+            Err(SendgridError::ReservedHeader(name)) if name == "content-type"
+        ));
     }
 
-    /// Add a custom_args field.
-    pub fn add_custom_args(mut self, custom_args: SGMap) -> Personalization {
-        self.custom_args
-            .get_or_insert_with(|| SGMap::with_capacity(custom_args.len()))
-            .extend(custom_args);
-        self
+    #[test]
+    fn incomplete_messages_are_not_sendable() {
+        let message = Message::new(Email::new("from_email@test.com"))
+            .add_personalization(Personalization::new(Email::new("to_email@test.com")));
+        assert!(matches!(
+            message.check_sendable(),
+            Err(SendgridError::MissingContent)
+        ));
+        assert!(message
+            .clone()
+            .set_template_id("d-123")
+            .check_sendable()
+            .is_ok());
+
+        let message = message.set_text("hi");
+        assert!(matches!(
+            message.check_sendable(),
+            Err(SendgridError::MissingSubject(0))
+        ));
+        assert!(message
+            .clone()
+            .set_subject("Hello")
+            .check_sendable()
+            .is_ok());
+
+        let mut sender = Sender::new(String::from("SG.key"), None);
+        assert!(sender.prepare(&message).is_ok());
+        sender.set_check_sendable(true);
+        assert!(sender.prepare(&message).is_err());
     }
 
-    /// Add a substitutions field.
-    pub fn add_substitutions(mut self, substitutions: SGMap) -> Personalization {
-        self.substitutions
-            .get_or_insert_with(|| SGMap::with_capacity(substitutions.len()))
-            .extend(substitutions);
-        self
+    #[test]
+    fn groups_to_display_are_ordered() {
+        let asm = ASM::new()
+            .set_group_id(1)
+            .set_groups_to_display([9, 3])
+            .unwrap()
+            .add_group_to_display(5)
+            .unwrap()
+            .add_group_to_display(3)
+            .unwrap();
+        assert_eq!(
+            serde_json::to_string(&asm).unwrap(),
+            r#"{"group_id":1,"groups_to_display":[3,5,9]}"#
+        );
+
+        let full = ASM::new()
+            .set_groups_to_display(0..MAX_GROUPS_TO_DISPLAY as u32)
+            .unwrap();
+        match full.add_group_to_display(100) {
+            Err(SendgridError::TooManyGroupsToDisplay { count, limit }) => {
+                assert_eq!(count, 26);
+                assert_eq!(limit, 25);
+            }
+            _ => panic!("expected TooManyGroupsToDisplay"),
+        }
     }
 
-    /// Add a dynamic template data field.
-    pub fn add_dynamic_template_data(mut self, dynamic_template_data: SGMap) -> Personalization {
-        // We can safely unwrap & unreachable here since SGMap will always serialize
-        // to a JSON object.
-        let new_vals = match to_value(dynamic_template_data).unwrap() {
-            Object(map) => map,
-            _ => unreachable!(),
+    #[test]
+    fn template_data_must_match_template() {
+        let substitutions =
+            Personalization::new(Email::new("to_email@test.com")).add_substitution("-name-", "Ada");
+        let dynamic = Personalization::new(Email::new("to_email@test.com"))
+            .add_dynamic_template_data_json(&serde_json::json!({ "name": "Ada" }))
+            .unwrap();
+        let message = |template_id: &str, p: &Personalization| {
+            Message::new(Email::new("from_email@test.com"))
+                .set_template_id(template_id)
+                .add_personalization(p.clone())
         };
-        self.dynamic_template_data
-            .get_or_insert_with(|| Map::with_capacity(new_vals.len()))
-            .extend(new_vals);
-        self
+
+        assert!(message("d-123", &dynamic).validate().is_ok());
+        assert!(message("legacy-123", &substitutions).validate().is_ok());
+        assert!(matches!(
+            message("d-123", &substitutions).validate(),
+            Err(SendgridError::SubstitutionsWithDynamicTemplate(0))
+        ));
+        assert!(matches!(
+            message("legacy-123", &dynamic).validate(),
+            Err(SendgridError::DynamicDataWithLegacyTemplate(0))
+        ));
     }
 
-    /// Add a dynamic template data fields from a JSON object.
-    pub fn add_dynamic_template_data_json<T: Serialize + ?Sized>(
-        mut self,
-        json_object: &T,
-    ) -> SendgridResult<Personalization> {
-        let new_vals = match to_value(json_object)? {
-            Object(map) => map,
-            _ => return Err(SendgridError::InvalidTemplateValue),
-        };
-        self.dynamic_template_data
-            .get_or_insert_with(|| Map::with_capacity(new_vals.len()))
-            .extend(new_vals);
-        Ok(self)
+    #[test]
+    fn non_ascii_from_name() {
+        let message = Message::new(Email::new("from_email@test.com").set_name("Café 🚀"))
+            .add_to(Email::new("to_email@test.com"));
+        let json_str = message.to_json().unwrap();
+        let expected = r#"{"from":{"email":"from_email@test.com","name":"Café 🚀"},"subject":"","personalizations":[{"to":[{"email":"to_email@test.com"}]}]}"#;
+        assert_eq!(json_str, expected);
+
+        let decoded: Message = serde_json::from_str(&json_str).unwrap();
+        assert_eq!(decoded, message);
     }
 
-    /// Set the subject.
-    pub fn set_subject(mut self, subject: &str) -> Personalization {
-        self.subject = Some(String::from(subject));
-        self
+    #[test]
+    fn inline_images_are_referenced() {
+        let image = Attachment::new()
+            .set_content(b"png")
+            .set_filename("logo.png")
+            .set_mime_type("image/png");
+        let message = Message::new(Email::new("from_email@test.com"))
+            .add_to(Email::new("to_email@test.com"))
+            .set_html(r#"<img src="cid:logo">"#)
+            .add_inline_image(image.clone(), "logo");
+        assert!(message.check_inline_images().is_ok());
+
+        let value = message.to_json_value().unwrap();
+        assert_eq!(value["attachments"][0]["disposition"], "inline");
+        assert_eq!(value["attachments"][0]["content_id"], "logo");
+
+        let message = message.add_inline_image(image, "banner");
+        assert!(matches!(
+            message.check_inline_images(),
+            Err(SendgridError::UnreferencedInlineImage(id)) if id == "banner"
+        ));
     }
 
-    /// Set send at.
-    pub fn set_send_at(mut self, send_at: u64) -> Personalization {
-        self.send_at = Some(send_at);
-        self
+    #[test]
+    fn maps_from_iterators() {
+        let p = Personalization::new(Email::new("to_email@test.com"))
+            .add_headers([("X-One", "1")])
+            .add_custom_args(vec![(String::from("tenant"), "acme")])
+            .add_substitutions(std::iter::once(("-name-", String::from("Ada"))));
+        let json_str = Message::new(Email::new("from_email@test.com"))
+            .add_personalization(p)
+            .add_headers(SGMap::from([(String::from("X-Two"), String::from("2"))]))
+            .to_json()
+            .unwrap();
+        let expected = r#"{"from":{"email":"from_email@test.com"},"subject":"","personalizations":[{"to":[{"email":"to_email@test.com"}],"headers":{"X-One":"1"},"substitutions":{"-name-":"Ada"},"custom_args":{"tenant":"acme"}}],"headers":{"X-Two":"2"}}"#;
+        assert_eq!(json_str, expected);
     }
-}
 
-impl Attachment {
-    /// Construct a new attachment for this message.
-    pub fn new() -> Attachment {
-        Attachment::default()
+    #[test]
+    fn maps_keep_insertion_order() {
+        let p = Personalization::new(Email::new("to_email@test.com"))
+            .add_header("X-Zulu", "1")
+            .add_header("X-Alpha", "2")
+            .add_substitution("-z-", "1")
+            .add_substitution("-a-", "2")
+            .add_custom_arg("zulu", "1")
+            .add_custom_arg("alpha", "2");
+        let json_str = serde_json::to_string(&p).unwrap();
+        let expected = r#"{"to":[{"email":"to_email@test.com"}],"headers":{"X-Zulu":"1","X-Alpha":"2"},"substitutions":{"-z-":"1","-a-":"2"},"custom_args":{"zulu":"1","alpha":"2"}}"#;
+        assert_eq!(json_str, expected);
+
+        let decoded: Personalization = serde_json::from_str(&json_str).unwrap();
+        assert_eq!(serde_json::to_string(&decoded).unwrap(), expected);
     }
 
-    /// The raw body of the attachment.
-    pub fn set_content(mut self, c: &[u8]) -> Attachment {
-        self.content = BASE64.encode(c);
-        self
+    #[test]
+    fn personalizations_from_records() {
+        #[derive(Serialize)]
+        struct Order {
+            id: u32,
+            total: &'static str,
+        }
+
+        let personalizations = Personalization::from_records([
+            (
+                Email::new("ada@test.com"),
+                Order {
+                    id: 1,
+                    total: "$10",
+                },
+            ),
+            (
+                Email::new("grace@test.com"),
+                Order {
+                    id: 2,
+                    total: "$20",
+                },
+            ),
+        ])
+        .unwrap();
+        let json_str = serde_json::to_string(&personalizations).unwrap();
+        let expected = r#"[{"to":[{"email":"ada@test.com"}],"dynamic_template_data":{"id":1,"total":"$10"}},{"to":[{"email":"grace@test.com"}],"dynamic_template_data":{"id":2,"total":"$20"}}]"#;
+        assert_eq!(json_str, expected);
+
+        assert!(matches!(
+            Personalization::from_records([(Email::new("ada@test.com"), 42)]),
+            Err(SendgridError::InvalidTemplateValue)
+        ));
     }
 
-    /// The base64 body of the attachment.
-    pub fn set_base64_content<S: Into<String>>(mut self, c: S) -> Attachment {
-        self.content = c.into();
-        self
+    #[test]
+    fn messages_are_chunked() {
+        let to = |i: usize| Email::new(format!("to{}@test.com", i));
+        let message = (0..2500).fold(
+            Message::new(Email::new("from_email@test.com")).set_subject("Hi"),
+            |m, i| m.add_personalization(Personalization::new(to(i))),
+        );
+        let chunks = message.chunks();
+        assert_eq!(
+            chunks
+                .iter()
+                .map(|c| c.personalizations.len())
+                .collect::<Vec<_>>(),
+            vec![1000, 1000, 500]
+        );
+        assert!(chunks
+            .iter()
+            .all(|c| c.validate().is_ok() && c.subject == "Hi"));
+
+        // Recipients are limited across personalizations too.
+        let big = |start: usize| {
+            (start..start + 600).fold(Personalization::new(to(start)), |p, i| p.add_cc(to(i + 1)))
+        };
+        let message = Message::new(Email::new("from_email@test.com"))
+            .add_personalization(big(0))
+            .add_personalization(big(1000));
+        assert_eq!(message.chunks().len(), 2);
+
+        let empty = Message::new(Email::new("from_email@test.com"));
+        assert_eq!(empty.chunks().len(), 1);
     }
 
-    /// Sets the filename for the attachment.
-    pub fn set_filename<S: Into<String>>(mut self, filename: S) -> Attachment {
-        self.filename = filename.into();
-        self
+    #[test]
+    fn mime_constants() {
+        let attachment = Attachment::new()
+            .set_content(b"%PDF")
+            .set_filename("invoice.pdf")
+            .set_mime_type(Mime::Pdf);
+        assert_eq!(attachment.mime_type.as_deref(), Some("application/pdf"));
+
+        assert_eq!(Mime::new("Image/PNG; charset=binary"), Mime::Png);
+        assert_eq!(
+            Mime::new("application/x-custom"),
+            Mime::Other(String::from("application/x-custom"))
+        );
+        assert_eq!(String::from(Mime::Csv), "text/csv");
     }
 
-    /// Set an optional mime type. Sendgrid will default to 'application/octet-stream'
-    /// if unspecified.
-    pub fn set_mime_type<S: Into<String>>(mut self, mime: S) -> Attachment {
-        self.mime_type = Some(mime.into());
-        self
+    #[test]
+    fn canonical_json_sorts_keys() {
+        let build = |headers: [(&str, &str); 2]| {
+            Message::new(Email::new("from_email@test.com"))
+                .set_subject("hi")
+                .add_personalization(
+                    Personalization::new(Email::new("to_email@test.com")).add_headers(headers),
+                )
+                .to_canonical_json()
+                .unwrap()
+        };
+
+        let json = build([("X-B", "2"), ("X-A", "1")]);
+        assert_eq!(json, build([("X-A", "1"), ("X-B", "2")]));
+        let expected = r#"{"from":{"email":"from_email@test.com"},"personalizations":[{"headers":{"X-A":"1","X-B":"2"},"to":[{"email":"to_email@test.com"}]}],"subject":"hi"}"#;
+        assert_eq!(json, expected);
     }
 
-    /// Set an optional content id.
-    pub fn set_content_idm<S: Into<String>>(mut self, content_id: S) -> Attachment {
-        self.content_id = Some(content_id.into());
-        self
+    #[test]
+    fn debug_output_hides_the_api_key() {
+        let sender = Sender::new(String::from("SG.secret"), None);
+        let debug = format!("{:?}", sender);
+        assert!(!debug.contains("SG.secret"));
+        assert!(debug.contains("api_key: \"[redacted]\""));
     }
 
-    /// Set an optional disposition.
-    pub fn set_disposition(mut self, disposition: Disposition) -> Attachment {
-        self.disposition = Some(disposition);
-        self
+    #[cfg(feature = "concurrency-limit")]
+    #[tokio::test]
+    async fn clones_share_the_concurrency_limit() {
+        let mut sender = Sender::new(String::from("SG.key"), None);
+        sender.set_max_concurrency(1);
+        let clone = sender.clone();
+
+        let semaphore = sender.concurrency.clone().unwrap();
+        let permit = semaphore.acquire().await.unwrap();
+        assert_eq!(clone.concurrency.as_ref().unwrap().available_permits(), 0);
+        drop(permit);
+        assert_eq!(clone.concurrency.as_ref().unwrap().available_permits(), 1);
+
+        sender.set_max_concurrency(0);
+        assert_eq!(sender.concurrency.as_ref().unwrap().available_permits(), 1);
+        sender.set_max_concurrency(usize::MAX);
+        assert_eq!(
+            sender.concurrency.as_ref().unwrap().available_permits(),
+            tokio::sync::Semaphore::MAX_PERMITS
+        );
     }
-}
 
-impl ASM {
-    /// Construct an object allowing you to specify how to handle unsubscribes.
-    pub fn new() -> Self {
-        Default::default()
+    #[cfg(feature = "concurrency-limit")]
+    #[tokio::test]
+    async fn sends_wait_for_the_concurrency_limit() {
+        let server = super::dev_server::CaptureServer::start().await.unwrap();
+        let message = Message::new(Email::new("from@test.com"))
+            .add_personalization(Personalization::new(Email::new("to@test.com")));
+        let mut sender = Sender::new(String::from("SG.key"), None);
+        sender.set_host(server.url()).unwrap();
+        sender.set_max_concurrency(1);
+
+        // Hold the only permit, as a send in flight would.
+        let permit = sender.concurrency.clone().unwrap().acquire_owned().await;
+        let waiting = tokio::spawn({
+            let sender = sender.clone();
+            let message = message.clone();
+            async move { sender.send(&message).await.map(|resp| resp.status()) }
+        });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(!waiting.is_finished());
+        assert!(server.captured().is_empty());
+
+        drop(permit);
+        assert_eq!(
+            waiting.await.unwrap().unwrap(),
+            reqwest::StatusCode::ACCEPTED
+        );
+        assert_eq!(server.captured().len(), 1);
     }
 
-    /// The unsubscribe group to associate with this email.
-    pub fn set_group_id(mut self, group_id: u32) -> Self {
-        self.group_id = group_id;
-        self
+    #[cfg(feature = "concurrency-limit")]
+    #[tokio::test]
+    async fn deadlines_bound_the_wait_for_a_permit() {
+        let message = Message::new(Email::new("from@test.com"))
+            .add_personalization(Personalization::new(Email::new("to@test.com")));
+        let mut sender = Sender::new(String::from("SG.key"), None);
+        sender.set_max_concurrency(1);
+        let _permit = sender.concurrency.clone().unwrap().acquire_owned().await;
+
+        let start = Instant::now();
+        assert!(matches!(
+            sender
+                .send_with_deadline(&message, start + Duration::from_millis(50))
+                .await,
+            Err(SendgridError::DeadlineExceeded)
+        ));
+        assert!(start.elapsed() < Duration::from_secs(5));
     }
 
-    /// A set containing the unsubscribe groups that you would like to be displayed on the unsubscribe preferences page.
-    pub fn set_groups_to_display(
-        mut self,
-        groups_to_display: HashSet<u32>,
-    ) -> SendgridResult<Self> {
-        if groups_to_display.len() > 25 {
-            return Err(SendgridError::TooManyItems);
-        }
+    #[test]
+    fn on_behalf_of_header() {
+        let message = Message::new(Email::new("from_email@test.com"))
+            .add_personalization(Personalization::new(Email::new("to_email@test.com")));
+        let mut sender = Sender::new(String::from("SG.key"), None);
+        assert!(!sender
+            .prepare(&message)
+            .unwrap()
+            .headers
+            .contains_key(ON_BEHALF_OF_HEADER));
+
+        sender.set_on_behalf_of("store-eu");
+        let prepared = sender.prepare(&message).unwrap();
+        assert_eq!(prepared.headers[ON_BEHALF_OF_HEADER], "store-eu");
+        let prepared = prepared.set_on_behalf_of("store-us").unwrap();
+        assert_eq!(prepared.headers[ON_BEHALF_OF_HEADER], "store-us");
+    }
 
-        self.groups_to_display = groups_to_display;
-        Ok(self)
+    // Accept one request and return its header lines, answering `202 Accepted`.
+    async fn capture_request_headers(listener: tokio::net::TcpListener) -> Vec<String> {
+        use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut headers = Vec::new();
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).await.unwrap();
+            if line.trim_end().is_empty() {
+                break;
+            }
+            headers.push(line.trim_end().to_ascii_lowercase());
+        }
+        reader
+            .into_inner()
+            .write_all(b"HTTP/1.1 202 Accepted\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        headers
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use crate::v3::message::{MailSettings, SandboxMode};
-    use crate::v3::{
-        ClickTrackingSetting, Email, Message, OpenTrackingSetting, Personalization,
-        SubscriptionTrackingSetting, TrackingSettings, ASM,
-    };
-    use serde::Serialize;
-    use std::collections::HashSet;
+    #[tokio::test]
+    async fn client_default_headers_are_reconciled() {
+        use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION, CONTENT_TYPE};
+
+        let mut defaults = HeaderMap::new();
+        defaults.insert(CONTENT_TYPE, HeaderValue::from_static("text/plain"));
+        defaults.insert(AUTHORIZATION, HeaderValue::from_static("Bearer gateway"));
+        defaults.insert("x-tenant", HeaderValue::from_static("acme"));
+        let client = reqwest::Client::builder()
+            .default_headers(defaults)
+            .build()
+            .unwrap();
+        let message = Message::new(Email::new("from_email@test.com"))
+            .add_personalization(Personalization::new(Email::new("to_email@test.com")));
+
+        for (client_authorization, authorization) in
+            [(false, "bearer sg.key"), (true, "bearer gateway")]
+        {
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let mut sender = Sender::new(String::from("SG.key"), Some(client.clone()));
+            sender
+                .set_host(format!(
+                    "http://{}/v3/mail/send",
+                    listener.local_addr().unwrap()
+                ))
+                .unwrap();
+            sender.set_client_authorization(client_authorization);
+
+            let (headers, resp) =
+                tokio::join!(capture_request_headers(listener), sender.send(&message));
+            assert_eq!(resp.unwrap().status(), 202);
+            let values = |name: &str| -> Vec<_> {
+                headers
+                    .iter()
+                    .filter_map(|h| h.strip_prefix(name)?.strip_prefix(": "))
+                    .collect()
+            };
+            assert_eq!(values("content-type"), ["application/json"]);
+            assert_eq!(values("authorization"), [authorization]);
+            assert_eq!(values("x-tenant"), ["acme"]);
+        }
+    }
 
-    #[derive(Serialize)]
-    struct OuterModel {
-        inners: Vec<InnerModel>,
+    #[tokio::test]
+    async fn batches_keep_their_order() {
+        let server = super::dev_server::CaptureServer::start().await.unwrap();
+        let mut sender = Sender::new(String::from("SG.key"), None);
+        sender.set_host(server.url()).unwrap();
+
+        let message = |subject: &str| {
+            Message::new(Email::new("from@example.com"))
+                .set_subject(subject)
+                .add_personalization(Personalization::new(Email::new("to@example.com")))
+                .set_text("hi")
+        };
+        let messages = [
+            message("first"),
+            Message::new(Email::new("from@example.com")),
+            message("third"),
+        ];
+
+        let results = sender.send_batch(&messages, 2).await;
+        assert_eq!(results.len(), 3);
+        assert!(results[0].is_ok());
+        assert!(matches!(results[1], Err(SendgridError::NoPersonalizations)));
+        assert!(results[2].is_ok());
+        let mut subjects: Vec<_> = server
+            .captured_json()
+            .iter()
+            .map(|m| m["subject"].as_str().unwrap().to_string())
+            .collect();
+        subjects.sort();
+        assert_eq!(subjects, ["first", "third"]);
     }
 
-    #[derive(Serialize)]
-    struct InnerModel {
-        x: String,
-        y: String,
-        z: String,
+    #[tokio::test]
+    async fn batches_keep_their_order_when_sends_finish_out_of_order() {
+        use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+
+        // Answers with the subject as the message id and holds the response to "slow" back, so it
+        // completes after the messages sent behind it.
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let completed = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let log = completed.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let log = log.clone();
+                tokio::spawn(async move {
+                    let mut reader = BufReader::new(stream);
+                    let mut content_length = 0;
+                    loop {
+                        let mut line = String::new();
+                        reader.read_line(&mut line).await.unwrap();
+                        let line = line.trim_end().to_ascii_lowercase();
+                        if line.is_empty() {
+                            break;
+                        }
+                        if let Some(value) = line.strip_prefix("content-length:") {
+                            content_length = value.trim().parse().unwrap();
+                        }
+                    }
+                    let mut body = vec![0; content_length];
+                    reader.read_exact(&mut body).await.unwrap();
+                    let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+                    let subject = body["subject"].as_str().unwrap().to_string();
+
+                    if subject == "slow" {
+                        tokio::time::sleep(Duration::from_millis(200)).await;
+                    }
+                    log.lock().unwrap().push(subject.clone());
+                    let head = format!(
+                        "HTTP/1.1 202 Accepted\r\nX-Message-Id: {}\r\nContent-Length: 0\r\n\
+                         Connection: close\r\n\r\n",
+                        subject
+                    );
+                    reader
+                        .into_inner()
+                        .write_all(head.as_bytes())
+                        .await
+                        .unwrap();
+                });
+            }
+        });
+
+        let mut sender = Sender::new(String::from("SG.key"), None);
+        sender
+            .set_host(format!("http://{}/v3/mail/send", addr))
+            .unwrap();
+        let messages: Vec<_> = ["slow", "second", "third"]
+            .iter()
+            .map(|subject| {
+                Message::new(Email::new("from@example.com"))
+                    .set_subject(subject)
+                    .add_personalization(Personalization::new(Email::new("to@example.com")))
+            })
+            .collect();
+
+        let results = sender.send_batch(&messages, 3).await;
+        let ids: Vec<_> = results
+            .iter()
+            .map(|result| result.as_ref().unwrap().message_id().unwrap())
+            .collect();
+        assert_eq!(ids, ["slow", "second", "third"]);
+        assert_eq!(completed.lock().unwrap().last().unwrap(), "slow");
     }
 
     #[test]
-    fn ip_pool_name() {
-        let json_str = Message::new(Email::new("from_email@test.com"))
-            .add_personalization(Personalization::new(Email::new("to_email@test.com")))
-            .set_ip_pool_name("test_ip_pool")
-            .gen_json();
-        let expected = r#"{"from":{"email":"from_email@test.com"},"subject":"","personalizations":[{"to":[{"email":"to_email@test.com"}]}],"ip_pool_name":"test_ip_pool"}"#;
-        assert_eq!(json_str, expected);
+    fn hosts_are_normalized() {
+        let mut sender = Sender::new(String::from("SG.key"), None);
+        for (host, expected) in [
+            (
+                "http://localhost:3000",
+                "http://localhost:3000/v3/mail/send",
+            ),
+            (
+                "http://localhost:3000/",
+                "http://localhost:3000/v3/mail/send",
+            ),
+            (
+                "https://proxy.example.com/sendgrid/v3/mail/send/",
+                "https://proxy.example.com/sendgrid/v3/mail/send",
+            ),
+        ] {
+            sender.set_host(host).unwrap();
+            assert_eq!(sender.host, expected);
+        }
+
+        for host in [
+            "localhost:3000",
+            "api.sendgrid.com/v3/mail/send",
+            "ftp://example.com",
+        ] {
+            assert!(matches!(
+                sender.set_host(host),
+                Err(SendgridError::InvalidHost { .. })
+            ));
+        }
+        assert_eq!(
+            sender.host,
+            "https://proxy.example.com/sendgrid/v3/mail/send"
+        );
     }
 
     #[test]
-    fn single_category() {
-        let json_str = Message::new(Email::new("from_email@test.com"))
-            .add_personalization(Personalization::new(Email::new("to_email@test.com")))
-            .add_category("test_category")
-            .gen_json();
-        let expected = r#"{"from":{"email":"from_email@test.com"},"subject":"","personalizations":[{"to":[{"email":"to_email@test.com"}]}],"categories":["test_category"]}"#;
-        assert_eq!(json_str, expected);
+    fn personalizations_are_checked() {
+        assert!(matches!(
+            Personalization::default().try_into_valid(),
+            Err(SendgridError::MissingTo(0))
+        ));
+        assert!(matches!(
+            Personalization::default()
+                .add_to(Email::new("to@test.com"))
+                .add_headers([("To", "x")])
+                .try_into_valid(),
+            Err(SendgridError::ReservedHeader(_))
+        ));
+        let p = Personalization::default()
+            .add_to(Email::new("to@test.com"))
+            .try_into_valid()
+            .unwrap();
+
+        let message = Message::new(Email::new("from@test.com"))
+            .add_personalization(p)
+            .add_personalization(Personalization::default());
+        assert!(matches!(
+            message.validate(),
+            Err(SendgridError::MissingTo(1))
+        ));
+        assert_eq!(
+            super::Disposition::default(),
+            super::Disposition::Attachment
+        );
     }
 
-    #[test]
-    fn click_tracking_setting() {
-        let json_str = Message::new(Email::new("from_email@test.com"))
-            .add_personalization(Personalization::new(Email::new("to_email@test.com")))
-            .set_tracking_settings(TrackingSettings {
-                click_tracking: Some(ClickTrackingSetting {
-                    enable: Some(true),
-                    enable_text: None,
-                }),
-                open_tracking: None,
-                subscription_tracking: None,
-            })
-            .gen_json();
-        let expected = r#"{"from":{"email":"from_email@test.com"},"subject":"","personalizations":[{"to":[{"email":"to_email@test.com"}]}],"tracking_settings":{"click_tracking":{"enable":true}}}"#;
-        assert_eq!(json_str, expected);
+    #[tokio::test]
+    async fn sends_with_a_tenant_key() {
+        let server = super::dev_server::CaptureServer::start().await.unwrap();
+        // The capture server rejects requests without a key.
+        let mut sender = Sender::new(String::new(), None);
+        sender.set_host(server.url()).unwrap();
+
+        let message = Message::new(Email::new("from@test.com"))
+            .add_personalization(Personalization::new(Email::new("to@test.com")));
+        assert!(sender.send(&message).await.is_err());
+        let resp = sender.send_with_key(&message, "SG.tenant").await.unwrap();
+        assert_eq!(resp.status(), 202);
+        assert_eq!(server.captured().len(), 1);
     }
 
-    #[test]
-    fn open_tracking_setting() {
-        let json_str = Message::new(Email::new("from_email@test.com"))
-            .add_personalization(Personalization::new(Email::new("to_email@test.com")))
-            .set_tracking_settings(TrackingSettings {
-                click_tracking: None,
-                open_tracking: Some(OpenTrackingSetting {
-                    enable: Some(true),
-                    substitution_tag: None,
-                }),
-                subscription_tracking: None,
-            })
-            .gen_json();
-        let expected = r#"{"from":{"email":"from_email@test.com"},"subject":"","personalizations":[{"to":[{"email":"to_email@test.com"}]}],"tracking_settings":{"open_tracking":{"enable":true}}}"#;
-        assert_eq!(json_str, expected);
+    #[cfg(feature = "send-and-forget")]
+    #[tokio::test]
+    async fn sends_are_forgotten() {
+        let server = super::dev_server::CaptureServer::start().await.unwrap();
+        let mut sender = Sender::new(String::from("SG.key"), None);
+        sender.set_host(server.url()).unwrap();
+
+        let message = Message::new(Email::new("from@test.com"))
+            .add_personalization(Personalization::new(Email::new("to@test.com")));
+        sender.send_and_forget(&message).unwrap().await.unwrap();
+        assert_eq!(server.captured().len(), 1);
+
+        assert!(matches!(
+            sender.send_and_forget(&Message::new(Email::new("from@test.com"))),
+            Err(SendgridError::NoPersonalizations)
+        ));
+    }
+
+    #[cfg(feature = "cancellation")]
+    #[tokio::test]
+    async fn chunked_sends_stop_when_cancelled() {
+        use tokio_util::sync::CancellationToken;
+
+        // Cancels the token once the first chunk has been sent.
+        struct CancelAfterSend(CancellationToken);
+
+        impl crate::interceptor::SendInterceptor for CancelAfterSend {
+            fn after_send(&self, _response: &crate::interceptor::InterceptedResponse<'_>) {
+                self.0.cancel();
+            }
+        }
+
+        let server = super::dev_server::CaptureServer::start().await.unwrap();
+        let token = CancellationToken::new();
+        let mut sender = Sender::new(String::from("SG.key"), None);
+        sender.set_host(server.url()).unwrap();
+        sender.add_interceptor(CancelAfterSend(token.clone()));
+
+        let message = (0..MAX_PERSONALIZATIONS * 3).fold(
+            Message::new(Email::new("from@test.com")),
+            |message, i| {
+                message.add_personalization(Personalization::new(Email::new(format!(
+                    "to{}@test.com",
+                    i
+                ))))
+            },
+        );
+        let results = sender.send_chunked_until_cancelled(&message, &token).await;
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].as_ref().unwrap().status(), 202);
+        assert!(results[1..]
+            .iter()
+            .all(|result| matches!(result, Err(SendgridError::Cancelled))));
+        assert_eq!(server.captured().len(), 1);
+    }
+
+    struct CountingSink(Arc<AtomicUsize>);
+
+    impl crate::v3::archive::ArchiveSink for CountingSink {
+        fn archive(&self, _record: &crate::v3::archive::ArchiveRecord<'_>) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[tokio::test]
+    async fn dry_runs_use_the_sandbox() {
+        let server = super::dev_server::CaptureServer::start().await.unwrap();
+        let mut sender = Sender::new(String::from("SG.key"), None);
+        sender.set_host(server.url()).unwrap();
+        let archived = Arc::new(AtomicUsize::new(0));
+        sender.set_archive_sink(CountingSink(archived.clone()));
+
+        let message = Message::new(Email::new("from@test.com"))
+            .add_personalization(Personalization::new(Email::new("to@test.com")))
+            .set_mail_settings(MailSettings::new().set_footer(Footer::new().set_enable(true)));
+        let dry_run = sender.dry_run(&message).await.unwrap();
+        assert!(dry_run.is_valid());
+        assert_eq!(dry_run.payload, message.to_json().unwrap());
+        let sent = &server.captured_json()[0]["mail_settings"];
+        assert_eq!(sent["sandbox_mode"]["enable"], true);
+        assert_eq!(sent["footer"]["enable"], true);
+        assert_eq!(archived.load(Ordering::Relaxed), 0);
+
+        let mut sender = Sender::new(String::new(), None);
+        sender.set_host(server.url()).unwrap();
+        let dry_run = sender.dry_run(&message).await.unwrap();
+        assert!(matches!(
+            dry_run.outcome,
+            Err(SendgridError::RequestNotSuccessful(e)) if e.status == 401
+        ));
     }
 
     #[test]
-    fn subscription_tracking_setting() {
-        let json_str = Message::new(Email::new("from_email@test.com"))
-            .add_personalization(Personalization::new(Email::new("to_email@test.com")))
-            .set_tracking_settings(TrackingSettings {
-                click_tracking: None,
-                open_tracking: None,
-                subscription_tracking: Some(SubscriptionTrackingSetting { enable: Some(true) }),
-            })
-            .gen_json();
-        let expected = r#"{"from":{"email":"from_email@test.com"},"subject":"","personalizations":[{"to":[{"email":"to_email@test.com"}]}],"tracking_settings":{"subscription_tracking":{"enable":true}}}"#;
-        assert_eq!(json_str, expected);
+    fn sandbox_is_forced() {
+        let mut sender = Sender::new(String::from("SG.key"), None);
+        sender.set_sandbox(true);
+
+        let message = Message::new(Email::new("from@test.com"))
+            .add_personalization(Personalization::new(Email::new("to@test.com")))
+            .set_mail_settings(
+                MailSettings::new()
+                    .set_footer(Footer::new().set_enable(true))
+                    .set_sandbox_mode(SandboxMode::new().set_enable(false)),
+            );
+        let body: serde_json::Value =
+            serde_json::from_str(sender.prepare(&message).unwrap().body()).unwrap();
+        assert_eq!(body["mail_settings"]["sandbox_mode"]["enable"], true);
+        assert_eq!(body["mail_settings"]["footer"]["enable"], true);
+
+        let message = Message::new(Email::new("from@test.com"))
+            .add_personalization(Personalization::new(Email::new("to@test.com")));
+        let body: serde_json::Value =
+            serde_json::from_str(sender.prepare(&message).unwrap().body()).unwrap();
+        assert_eq!(body["mail_settings"]["sandbox_mode"]["enable"], true);
+    }
+
+    #[tokio::test]
+    async fn sends_stop_at_the_deadline() {
+        let message = Message::new(Email::new("from@test.com"))
+            .add_personalization(Personalization::new(Email::new("to@test.com")));
+        let mut sender = Sender::new(String::from("SG.key"), None);
+
+        // The listener accepts connections but never answers.
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        sender
+            .set_host(format!("http://{}/v3/mail/send", addr))
+            .unwrap();
+        let start = Instant::now();
+        let error = sender
+            .send_with_deadline(&message, start + Duration::from_millis(200))
+            .await
+            .unwrap_err();
+        assert!(matches!(error, SendgridError::ReqwestError(e) if e.is_timeout()));
+        assert!(start.elapsed() < Duration::from_secs(5));
+
+        assert!(matches!(
+            sender.send_with_deadline(&message, Instant::now()).await,
+            Err(SendgridError::DeadlineExceeded)
+        ));
     }
 
     #[test]
@@ -719,17 +3896,20 @@ mod tests {
                 String::from("test_category1"),
                 String::from("test_category2"),
             ])
-            .gen_json();
+            .to_json()
+            .unwrap();
         let json_str_multiple_adds = Message::new(Email::new("from_email@test.com"))
             .add_personalization(Personalization::new(Email::new("to_email@test.com")))
             .add_category("test_category1")
             .add_category("test_category2")
-            .gen_json();
+            .to_json()
+            .unwrap();
         let json_str_vec_and_single = Message::new(Email::new("from_email@test.com"))
             .add_personalization(Personalization::new(Email::new("to_email@test.com")))
             .add_category("test_category1")
             .add_categories(&[String::from("test_category2")])
-            .gen_json();
+            .to_json()
+            .unwrap();
 
         let expected = r#"{"from":{"email":"from_email@test.com"},"subject":"","personalizations":[{"to":[{"email":"to_email@test.com"}]}],"categories":["test_category1","test_category2"]}"#;
         assert_eq!(json_str_add_vec, expected);
@@ -752,7 +3932,8 @@ mod tests {
                     .collect(),
                 ),
             )
-            .gen_json();
+            .to_json()
+            .unwrap();
         let expected = r#"{"from":{"email":"from_email@test.com"},"subject":"","personalizations":[{"to":[{"email":"to_email@test.com"}],"dynamic_template_data":{"Denmark":"50","Iceland":"10","Norway":"100"}}]}"#;
         assert_eq!(json_str, expected);
     }
@@ -778,7 +3959,8 @@ mod tests {
                     })
                     .unwrap(),
             )
-            .gen_json();
+            .to_json()
+            .unwrap();
         let expected = r#"{"from":{"email":"from_email@test.com"},"subject":"","personalizations":[{"to":[{"email":"to_email@test.com"}],"dynamic_template_data":{"inners":[{"x":"1","y":"2","z":"3"},{"x":"1","y":"2","z":"3"}]}}]}"#;
         assert_eq!(json_str, expected);
     }
@@ -793,11 +3975,27 @@ mod tests {
                     .set_groups_to_display(HashSet::from([123]))
                     .unwrap(),
             )
-            .gen_json();
+            .to_json()
+            .unwrap();
         let expected = r#"{"from":{"email":"from_email@test.com"},"subject":"","personalizations":[{"to":[{"email":"to_email@test.com"}]}],"asm":{"group_id":123,"groups_to_display":[123]}}"#;
         assert_eq!(json_str, expected);
     }
 
+    #[test]
+    fn reply_thread_headers() {
+        let json_str = Message::new(Email::new("from_email@test.com"))
+            .add_personalization(Personalization::new(Email::new("to_email@test.com")))
+            .set_thread("parent@test.com", &["<root@test.com>", "<parent@test.com>"])
+            .to_json()
+            .unwrap();
+        let value: serde_json::Value = serde_json::from_str(&json_str).unwrap();
+        assert_eq!(value["headers"]["In-Reply-To"], "<parent@test.com>");
+        assert_eq!(
+            value["headers"]["References"],
+            "<root@test.com> <parent@test.com>"
+        );
+    }
+
     #[test]
     fn mail_settings() {
         let json_str = Message::new(Email::new("from_email@test.com"))
@@ -805,7 +4003,8 @@ mod tests {
             .set_mail_settings(
                 MailSettings::new().set_sandbox_mode(SandboxMode::new().set_enable(true)),
             )
-            .gen_json();
+            .to_json()
+            .unwrap();
         let expected = r#"{"from":{"email":"from_email@test.com"},"subject":"","personalizations":[{"to":[{"email":"to_email@test.com"}]}],"mail_settings":{"sandbox_mode":{"enable":true}}}"#;
         assert_eq!(json_str, expected);
     }
@@ -0,0 +1,176 @@
+//! Build attachments from the file fields of multipart uploads.
+//!
+//! [`Attachment::from_multipart_field`] reads a [`MultipartField`] of axum (`axum` feature) or
+//! actix-web (`actix` feature), keeping the name and content type the client sent. The field is
+//! base64 encoded chunk by chunk as it is received, and uploads too large to be sent fail with
+//! [`SendgridError::PayloadTooLarge`] without being read to the end.
+
+use std::fmt::Display;
+use std::pin::pin;
+
+use data_encoding::BASE64;
+use futures::{Stream, StreamExt};
+
+use crate::error::{SendgridError, SendgridResult};
+use crate::v3::{Attachment, MAX_PAYLOAD_SIZE};
+
+/// A file field of a multipart upload.
+pub trait MultipartField: Stream<Item = Result<Self::Chunk, Self::Error>> {
+    /// A chunk of the field's content.
+    type Chunk: AsRef<[u8]>;
+
+    /// The error raised while the field is received.
+    type Error: Display;
+
+    /// The file name the client sent, if any.
+    fn filename(&self) -> Option<&str>;
+
+    /// The content type the client sent, if any.
+    fn mime_type(&self) -> Option<String>;
+}
+
+#[cfg(feature = "axum")]
+impl MultipartField for axum::extract::multipart::Field<'_> {
+    type Chunk = axum::body::Bytes;
+    type Error = axum::extract::multipart::MultipartError;
+
+    fn filename(&self) -> Option<&str> {
+        self.file_name()
+    }
+
+    fn mime_type(&self) -> Option<String> {
+        self.content_type().map(str::to_string)
+    }
+}
+
+#[cfg(feature = "actix")]
+impl MultipartField for actix_multipart::Field {
+    type Chunk = actix_web::web::Bytes;
+    type Error = actix_multipart::MultipartError;
+
+    fn filename(&self) -> Option<&str> {
+        self.content_disposition()
+            .and_then(|disposition| disposition.get_filename())
+    }
+
+    fn mime_type(&self) -> Option<String> {
+        self.content_type()
+            .map(|mime| mime.essence_str().to_string())
+    }
+}
+
+impl Attachment {
+    /// Construct an attachment from a file field of a multipart upload, see the
+    /// [module documentation](self).
+    pub async fn from_multipart_field<F: MultipartField>(field: F) -> SendgridResult<Attachment> {
+        let filename = field.filename().unwrap_or("attachment").to_string();
+        let mime_type = field.mime_type();
+
+        let attachment = Attachment::new()
+            .set_base64_content(encode(field).await?)
+            .set_filename(filename);
+        Ok(match mime_type {
+            Some(mime_type) => attachment.set_mime_type(mime_type),
+            None => attachment,
+        })
+    }
+}
+
+// Base64 encode a stream of chunks, failing as soon as the encoded content would exceed
+// `MAX_PAYLOAD_SIZE`.
+async fn encode<S, C, E>(stream: S) -> SendgridResult<String>
+where
+    S: Stream<Item = Result<C, E>>,
+    C: AsRef<[u8]>,
+    E: Display,
+{
+    let mut stream = pin!(stream);
+    let base64 = BASE64;
+    let mut content = String::new();
+    let mut encoder = base64.new_encoder(&mut content);
+    let mut received = 0;
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(|e| SendgridError::Multipart(e.to_string()))?;
+        received += chunk.as_ref().len();
+        let size = received.div_ceil(3) * 4;
+        if size > MAX_PAYLOAD_SIZE {
+            return Err(SendgridError::PayloadTooLarge {
+                size,
+                limit: MAX_PAYLOAD_SIZE,
+            });
+        }
+        encoder.append(chunk.as_ref());
+    }
+    encoder.finalize();
+    Ok(content)
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::stream;
+
+    use super::*;
+
+    const BODY: &str = "--X\r\n\
+        Content-Disposition: form-data; name=\"file\"; filename=\"report.txt\"\r\n\
+        Content-Type: text/plain\r\n\
+        \r\n\
+        hello\r\n\
+        --X--\r\n";
+
+    #[tokio::test]
+    async fn large_uploads_are_rejected() {
+        let chunk = vec![0; 1024 * 1024];
+        let chunks =
+            stream::repeat(Ok::<_, String>(&chunk[..])).take(MAX_PAYLOAD_SIZE / chunk.len());
+        assert!(matches!(
+            encode(chunks).await,
+            Err(SendgridError::PayloadTooLarge { limit, .. }) if limit == MAX_PAYLOAD_SIZE
+        ));
+
+        let failing = stream::iter([Ok(&b"hi"[..]), Err("connection reset")]);
+        assert!(matches!(
+            encode(failing).await,
+            Err(SendgridError::Multipart(e)) if e == "connection reset"
+        ));
+    }
+
+    #[cfg(feature = "axum")]
+    #[tokio::test]
+    async fn axum_fields_are_attached() {
+        use axum::extract::{FromRequest, Multipart};
+
+        let request = axum::http::Request::builder()
+            .header("content-type", "multipart/form-data; boundary=X")
+            .body(axum::body::Body::from(BODY))
+            .unwrap();
+        let mut multipart = Multipart::from_request(request, &()).await.unwrap();
+        let field = multipart.next_field().await.unwrap().unwrap();
+
+        let attachment = Attachment::from_multipart_field(field).await.unwrap();
+        assert_eq!(attachment.filename, "report.txt");
+        assert_eq!(attachment.mime_type.as_deref(), Some("text/plain"));
+        assert_eq!(attachment.content, BASE64.encode(b"hello"));
+    }
+
+    #[cfg(feature = "actix")]
+    #[tokio::test]
+    async fn actix_fields_are_attached() {
+        use actix_web::http::header::{HeaderMap, HeaderValue, CONTENT_TYPE};
+        use actix_web::web::Bytes;
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static("multipart/form-data; boundary=X"),
+        );
+        let body = stream::once(async { Ok(Bytes::from_static(BODY.as_bytes())) });
+        let mut multipart = actix_multipart::Multipart::new(&headers, body);
+        let field = multipart.next().await.unwrap().unwrap();
+
+        let attachment = Attachment::from_multipart_field(field).await.unwrap();
+        assert_eq!(attachment.filename, "report.txt");
+        assert_eq!(attachment.mime_type.as_deref(), Some("text/plain"));
+        assert_eq!(attachment.content, BASE64.encode(b"hello"));
+    }
+}
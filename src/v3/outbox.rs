@@ -0,0 +1,297 @@
+//! Support for the transactional outbox pattern.
+//!
+//! Applications persist messages with [`OutboxStore::save`] inside the same database transaction
+//! as the rest of their state changes. A [`Relay`] then loads the unsent messages, delivers them
+//! and marks them as sent, so a message is only ever sent when the transaction that produced it
+//! has committed. Failed sends are retried with a [`RetryPolicy`].
+
+use std::collections::HashSet;
+use std::future::Future;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "cancellation")]
+use tokio_util::sync::CancellationToken;
+
+use crate::error::SendgridResult;
+use crate::v3::retry::RetryPolicy;
+use crate::v3::{Message, Sender};
+
+const DEFAULT_BATCH_SIZE: usize = 100;
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// A message stored in an outbox. The `id` is chosen by the application and must be unique
+/// within the store.
+#[derive(Deserialize, Serialize)]
+pub struct OutboxEntry {
+    /// The unique id of this entry.
+    pub id: String,
+
+    /// The message to send.
+    pub message: Message,
+}
+
+/// Persistence hooks implemented by the application on top of its own database. Errors should be
+/// reported with [`crate::SendgridError::Store`].
+pub trait OutboxStore {
+    /// Persist a new entry. This is expected to run inside the caller's transaction.
+    fn save(&self, entry: &OutboxEntry) -> impl Future<Output = SendgridResult<()>> + Send;
+
+    /// Record that the entry with the given id has been delivered to SendGrid.
+    fn mark_sent(&self, id: &str) -> impl Future<Output = SendgridResult<()>> + Send;
+
+    /// Load at most `limit` entries which have not been marked as sent, oldest first.
+    fn load_unsent(
+        &self,
+        limit: usize,
+    ) -> impl Future<Output = SendgridResult<Vec<OutboxEntry>>> + Send;
+}
+
+/// The outcome of a single pass of a [`Relay`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RelayReport {
+    /// The number of entries delivered and marked as sent.
+    pub sent: usize,
+
+    /// The number of entries which could not be delivered and were left in the outbox.
+    pub failed: usize,
+}
+
+/// Delivers the messages saved in an [`OutboxStore`].
+#[must_use]
+pub struct Relay<S> {
+    sender: Sender,
+    store: S,
+    batch_size: usize,
+    retry_policy: RetryPolicy,
+    interval: Duration,
+    // Entries that were delivered but could not be marked as sent. These are never sent twice by
+    // this relay.
+    delivered: HashSet<String>,
+}
+
+impl<S: OutboxStore> Relay<S> {
+    /// Construct a new relay for the given store.
+    ///
+    /// The relay retries with the retry policy of `sender`, or with [`RetryPolicy::default`] if
+    /// it has none. The sender itself then no longer retries, so attempts are not multiplied.
+    pub fn new(mut sender: Sender, store: S) -> Self {
+        let retry_policy = sender.retry_policy.take().unwrap_or_default();
+        Relay {
+            sender,
+            store,
+            batch_size: DEFAULT_BATCH_SIZE,
+            retry_policy,
+            interval: DEFAULT_INTERVAL,
+            delivered: HashSet::new(),
+        }
+    }
+
+    /// Set how many entries are loaded per pass. Defaults to 100.
+    pub fn set_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size;
+        self
+    }
+
+    /// Set how failed sends of an entry are retried within a pass.
+    pub fn set_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Set the pause between passes used by [`Relay::run`]. Defaults to 5 seconds.
+    pub fn set_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Returns a reference to the underlying store.
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// Load one batch of unsent entries, send them and mark them as sent. Entries which fail to
+    /// send are left in the outbox for the next pass. Only errors from the store are returned.
+    pub async fn run_once(&mut self) -> SendgridResult<RelayReport> {
+        let mut report = RelayReport::default();
+
+        for entry in self.store.load_unsent(self.batch_size).await? {
+            if !self.delivered.contains(&entry.id) {
+                if self.send(&entry.message).await.is_err() {
+                    report.failed += 1;
+                    continue;
+                }
+                self.delivered.insert(entry.id.clone());
+            }
+
+            self.store.mark_sent(&entry.id).await?;
+            self.delivered.remove(&entry.id);
+            report.sent += 1;
+        }
+
+        Ok(report)
+    }
+
+    /// Run the relay until the store returns an error, pausing between passes.
+    pub async fn run(mut self) -> SendgridResult<()> {
+        loop {
+            self.run_once().await?;
+            tokio::time::sleep(self.interval).await;
+        }
+    }
+
+    /// Like [`Relay::run`], but stops once `token` is cancelled and returns the totals of the
+    /// passes made. A pass which has started is completed, so no entry is left delivered but not
+    /// marked as sent.
+    #[cfg(feature = "cancellation")]
+    pub async fn run_until_cancelled(
+        mut self,
+        token: &CancellationToken,
+    ) -> SendgridResult<RelayReport> {
+        let mut total = RelayReport::default();
+        while !token.is_cancelled() {
+            let report = self.run_once().await?;
+            total.sent += report.sent;
+            total.failed += report.failed;
+            token
+                .run_until_cancelled(tokio::time::sleep(self.interval))
+                .await;
+        }
+        Ok(total)
+    }
+
+    async fn send(&self, message: &Message) -> SendgridResult<()> {
+        let mut attempt = 1;
+        loop {
+            match self.sender.send(message).await {
+                Ok(_) => return Ok(()),
+                Err(e) => match self.retry_policy.delay(attempt, &e) {
+                    Some(delay) => tokio::time::sleep(delay).await,
+                    None => return Err(e),
+                },
+            }
+            attempt += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use reqwest::StatusCode;
+
+    use super::*;
+    use crate::v3::dev_server::CaptureServer;
+    use crate::v3::{Email, Personalization};
+
+    #[derive(Default)]
+    struct MemoryStore {
+        entries: Mutex<Vec<(String, String, bool)>>,
+    }
+
+    impl OutboxStore for MemoryStore {
+        async fn save(&self, entry: &OutboxEntry) -> SendgridResult<()> {
+            let json = serde_json::to_string(&entry.message)?;
+            self.entries
+                .lock()
+                .unwrap()
+                .push((entry.id.clone(), json, false));
+            Ok(())
+        }
+
+        async fn mark_sent(&self, id: &str) -> SendgridResult<()> {
+            for e in self.entries.lock().unwrap().iter_mut() {
+                if e.0 == id {
+                    e.2 = true;
+                }
+            }
+            Ok(())
+        }
+
+        async fn load_unsent(&self, limit: usize) -> SendgridResult<Vec<OutboxEntry>> {
+            let mut unsent = Vec::new();
+            for (id, json, sent) in self.entries.lock().unwrap().iter().take(limit) {
+                if !sent {
+                    unsent.push(OutboxEntry {
+                        id: id.clone(),
+                        message: serde_json::from_str(json)?,
+                    });
+                }
+            }
+            Ok(unsent)
+        }
+    }
+
+    async fn save(store: &MemoryStore, id: &str) {
+        let message = Message::new(Email::new("from_email@test.com"))
+            .add_personalization(Personalization::new(Email::new("to_email@test.com")));
+        store
+            .save(&OutboxEntry {
+                id: id.to_string(),
+                message,
+            })
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn failed_entries_stay_in_the_outbox() {
+        let store = MemoryStore::default();
+        for id in ["a", "b"] {
+            save(&store, id).await;
+        }
+
+        let server = CaptureServer::start().await.unwrap();
+        server.fail_next(StatusCode::SERVICE_UNAVAILABLE, 6);
+        let mut sender = Sender::new(String::from("key"), None);
+        sender.set_host(server.url()).unwrap();
+        let mut relay = Relay::new(sender, store).set_retry_policy(
+            RetryPolicy::new()
+                .set_initial_delay(Duration::from_millis(10))
+                .set_jitter(false),
+        );
+
+        let start = std::time::Instant::now();
+        let report = relay.run_once().await.unwrap();
+        assert_eq!(report, RelayReport { sent: 0, failed: 2 });
+        // Each entry waits 10 and then 20 milliseconds between its three attempts.
+        assert!(start.elapsed() >= Duration::from_millis(60));
+        assert_eq!(relay.store().load_unsent(10).await.unwrap().len(), 2);
+
+        // Once the server recovers, the next pass delivers them.
+        let report = relay.run_once().await.unwrap();
+        assert_eq!(report, RelayReport { sent: 2, failed: 0 });
+        assert_eq!(server.captured().len(), 2);
+        assert!(relay.store().load_unsent(10).await.unwrap().is_empty());
+
+        #[cfg(feature = "cancellation")]
+        {
+            save(relay.store(), "c").await;
+            server.fail_next(StatusCode::SERVICE_UNAVAILABLE, 3);
+            let token = CancellationToken::new();
+            let cancel = async {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                token.cancel();
+            };
+            let relay = relay.set_interval(Duration::from_secs(3600));
+            let (report, _) = tokio::join!(relay.run_until_cancelled(&token), cancel);
+            assert_eq!(report.unwrap(), RelayReport { sent: 0, failed: 1 });
+        }
+    }
+
+    #[test]
+    fn relays_take_over_the_senders_retry_policy() {
+        let mut sender = Sender::new(String::from("key"), None);
+        sender.set_retry_policy(RetryPolicy::new().set_max_attempts(5));
+        let relay = Relay::new(sender, MemoryStore::default());
+        assert!(relay.sender.retry_policy.is_none());
+        assert_eq!(relay.retry_policy.max_attempts(), 5);
+
+        let relay = Relay::new(
+            Sender::new(String::from("key"), None),
+            MemoryStore::default(),
+        );
+        assert_eq!(relay.retry_policy.max_attempts(), 3);
+    }
+}
@@ -0,0 +1,214 @@
+// Runs the same client behavior tests against the async and the blocking send paths, so that a
+// feature or fix applied to one path and not the other fails the suite. Every test in the
+// `parity_tests!` invocation is generated once per path and talks to a `CaptureServer`.
+
+use std::sync::{Arc, Mutex};
+
+use reqwest::StatusCode;
+use serde_json::Value;
+use tokio::runtime::Runtime;
+
+use crate::error::{SendgridError, SendgridResult};
+use crate::interceptor::{InterceptedResponse, SendInterceptor};
+use crate::v3::archive::{ArchiveOutcome, ArchiveRecord, ArchiveSink};
+use crate::v3::dev_server::CaptureServer;
+use crate::v3::{Email, Message, Personalization, Sender, MAX_PERSONALIZATIONS};
+
+#[derive(Clone, Copy)]
+enum Path {
+    Async,
+    Blocking,
+}
+
+struct Harness {
+    path: Path,
+    // Dropped before the runtime, which it runs on.
+    server: CaptureServer,
+    runtime: Runtime,
+}
+
+impl Harness {
+    fn new(path: Path) -> Harness {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .unwrap();
+        let server = runtime.block_on(CaptureServer::start()).unwrap();
+        Harness {
+            path,
+            server,
+            runtime,
+        }
+    }
+
+    fn sender(&self) -> Sender {
+        let mut sender = Sender::new(String::from("SG.parity"), None);
+        sender.set_host(self.server.url()).unwrap();
+        sender
+    }
+
+    // The blocking client must not be used from within the runtime, so the blocking path runs on
+    // the test thread while the server runs on the runtime's worker.
+    fn send(&self, sender: &Sender, message: &Message) -> SendgridResult<StatusCode> {
+        match self.path {
+            Path::Async => self
+                .runtime
+                .block_on(sender.send(message))
+                .map(|resp| resp.status()),
+            Path::Blocking => sender.blocking_send(message).map(|resp| resp.status()),
+        }
+    }
+
+    fn send_chunked(&self, sender: &Sender, message: &Message) -> Vec<SendgridResult<StatusCode>> {
+        match self.path {
+            Path::Async => self
+                .runtime
+                .block_on(sender.send_chunked(message))
+                .into_iter()
+                .map(|result| result.map(|resp| resp.status()))
+                .collect(),
+            Path::Blocking => sender
+                .blocking_send_chunked(message)
+                .into_iter()
+                .map(|result| result.map(|resp| resp.status()))
+                .collect(),
+        }
+    }
+
+    fn captured(&self) -> Vec<Value> {
+        self.server.captured_json()
+    }
+}
+
+fn message() -> Message {
+    Message::new(Email::new("from@example.com"))
+        .set_subject("parity")
+        .add_personalization(Personalization::new(Email::new("to@example.com")))
+        .set_text("hi")
+}
+
+#[derive(Default)]
+struct RecordingSink(Mutex<Vec<ArchiveOutcome>>);
+
+impl ArchiveSink for Arc<RecordingSink> {
+    fn archive(&self, record: &ArchiveRecord<'_>) {
+        self.0.lock().unwrap().push(record.outcome.clone());
+    }
+}
+
+#[derive(Default)]
+struct RecordingInterceptor(Mutex<Vec<StatusCode>>);
+
+impl SendInterceptor for Arc<RecordingInterceptor> {
+    fn after_send(&self, response: &InterceptedResponse<'_>) {
+        self.0.lock().unwrap().push(response.status);
+    }
+}
+
+macro_rules! parity_tests {
+    ($(fn $name:ident($harness:ident: &Harness) $body:block)*) => {
+        mod async_path {
+            use super::*;
+
+            $(
+                #[test]
+                fn $name() {
+                    let $harness = &Harness::new(Path::Async);
+                    $body
+                }
+            )*
+        }
+
+        mod blocking_path {
+            use super::*;
+
+            $(
+                #[test]
+                fn $name() {
+                    let $harness = &Harness::new(Path::Blocking);
+                    $body
+                }
+            )*
+        }
+    };
+}
+
+parity_tests! {
+    fn sends_the_serialized_message(harness: &Harness) {
+        let status = harness.send(&harness.sender(), &message()).unwrap();
+        assert_eq!(status, StatusCode::ACCEPTED);
+        assert_eq!(harness.captured(), [message().to_json_value().unwrap()]);
+    }
+
+    fn applies_sender_options(harness: &Harness) {
+        let mut sender = harness.sender();
+        sender.set_default_reply_to(Email::new("support@example.com"));
+        harness.send(&sender, &message()).unwrap();
+        assert_eq!(harness.captured()[0]["reply_to"]["email"], "support@example.com");
+    }
+
+    fn rejects_messages_before_sending(harness: &Harness) {
+        let empty = Message::new(Email::new("from@example.com"));
+        assert!(matches!(
+            harness.send(&harness.sender(), &empty),
+            Err(SendgridError::NoPersonalizations)
+        ));
+        assert!(harness.captured().is_empty());
+    }
+
+    fn reports_unsuccessful_status_codes(harness: &Harness) {
+        let mut sender = Sender::new(String::new(), None);
+        sender.set_host(harness.server.url()).unwrap();
+        match harness.send(&sender, &message()) {
+            Err(SendgridError::RequestNotSuccessful(e)) => {
+                assert_eq!(e.status, StatusCode::UNAUTHORIZED);
+                assert!(e.body.contains("authorization grant is invalid"));
+                assert_eq!(e.rate_limit.and_then(|r| r.limit), Some(600));
+            }
+            other => panic!("expected an unsuccessful request, got {:?}", other),
+        }
+    }
+
+    fn classifies_server_errors_as_retryable(harness: &Harness) {
+        harness.server.fail_next(StatusCode::SERVICE_UNAVAILABLE, 1);
+        let error = harness.send(&harness.sender(), &message()).unwrap_err();
+        assert!(error.is_retryable());
+        assert!(harness.server.captured().is_empty());
+    }
+
+    fn archives_sent_payloads(harness: &Harness) {
+        let sink = Arc::new(RecordingSink::default());
+        let mut sender = harness.sender();
+        sender.set_archive_sink(sink.clone());
+        harness.send(&sender, &message()).unwrap();
+        assert_eq!(
+            sink.0.lock().unwrap()[..],
+            [ArchiveOutcome::Accepted { status: 202 }]
+        );
+    }
+
+    fn runs_interceptors(harness: &Harness) {
+        let interceptor = Arc::new(RecordingInterceptor::default());
+        let mut sender = harness.sender();
+        sender.add_interceptor(interceptor.clone());
+        harness.send(&sender, &message()).unwrap();
+        assert_eq!(interceptor.0.lock().unwrap()[..], [StatusCode::ACCEPTED]);
+    }
+
+    fn sends_chunks(harness: &Harness) {
+        let message = (0..MAX_PERSONALIZATIONS + 1).fold(
+            Message::new(Email::new("from@example.com")).set_text("hi"),
+            |m, i| {
+                m.add_personalization(Personalization::new(Email::new(format!(
+                    "to{}@example.com",
+                    i
+                ))))
+            },
+        );
+        let results = harness.send_chunked(&harness.sender(), &message);
+        assert_eq!(results.len(), 2);
+        assert!(results.iter().all(|r| r.is_ok()));
+        assert_eq!(harness.captured().len(), 2);
+    }
+}
@@ -0,0 +1,117 @@
+//! Add preheader text to HTML content.
+//!
+//! Mail clients show the first text of the body next to the subject in the inbox. A preheader is
+//! a hidden snippet at the top of the body which controls this preview. It is followed by
+//! invisible padding so that clients do not fill the rest of the preview with body text such as
+//! "View in browser" links.
+
+use crate::v3::Message;
+
+// The number of preview characters covered by the text and its padding. Clients show at most
+// around 140 characters.
+const PREVIEW_LENGTH: usize = 150;
+
+// A zero width, non collapsing sequence which clients count as preview text but do not display.
+const PADDING: &str = "&#847;&zwnj;&nbsp;";
+
+const START_MARKER: &str = "<!--preheader-->";
+const END_MARKER: &str = "<!--/preheader-->";
+
+const STYLE: &str = "display:none;font-size:1px;color:#ffffff;line-height:1px;max-height:0;\
+                     max-width:0;opacity:0;overflow:hidden;mso-hide:all;";
+
+impl Message {
+    /// Set the preview text shown by mail clients next to the subject. The hidden snippet is
+    /// inserted at the start of the body of every `text/html` content part, replacing one set
+    /// before, so this must be called after the HTML content is set.
+    pub fn set_preheader<S: AsRef<str>>(mut self, text: S) -> Message {
+        let snippet = preheader_snippet(text.as_ref());
+        for content in self.content.iter_mut().flatten() {
+            if content.content_type.eq_ignore_ascii_case("text/html") {
+                content.value = insert_preheader(&content.value, &snippet);
+            }
+        }
+        self
+    }
+}
+
+fn preheader_snippet(text: &str) -> String {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    let padding = PREVIEW_LENGTH.saturating_sub(text.chars().count());
+    format!(
+        r#"{}<div style="{}">{}{}</div>{}"#,
+        START_MARKER,
+        STYLE,
+        escape_html(&text),
+        PADDING.repeat(padding),
+        END_MARKER
+    )
+}
+
+fn insert_preheader(html: &str, snippet: &str) -> String {
+    // Replace a preheader inserted before.
+    if let Some(start) = html.find(START_MARKER) {
+        if let Some(len) = html[start..].find(END_MARKER) {
+            let end = start + len + END_MARKER.len();
+            return format!("{}{}{}", &html[..start], snippet, &html[end..]);
+        }
+    }
+
+    // Insert after the opening body tag, or at the start of a fragment without one.
+    let lower = html.to_ascii_lowercase();
+    let at = lower
+        .find("<body")
+        .and_then(|body| lower[body..].find('>').map(|end| body + end + 1))
+        .unwrap_or(0);
+    format!("{}{}{}", &html[..at], snippet, &html[at..])
+}
+
+pub(crate) fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::v3::Email;
+
+    #[test]
+    fn preheader_is_inserted_after_body() {
+        let message = Message::new(Email::new("from@example.com"))
+            .set_text("Hello")
+            .set_html(r#"<html><BODY class="x"><p>Hello</p></body></html>"#)
+            .set_preheader("Your <order> has shipped")
+            .set_preheader("Your order & more");
+
+        let content = message.content.unwrap();
+        assert_eq!(content[0].value, "Hello");
+        let html = &content[1].value;
+        assert!(
+            html.starts_with(r#"<html><BODY class="x"><!--preheader--><div style="display:none;"#)
+        );
+        assert!(html.contains(">Your order &amp; more&#847;&zwnj;&nbsp;"));
+        assert!(html.ends_with("<!--/preheader--><p>Hello</p></body></html>"));
+        assert_eq!(html.matches(START_MARKER).count(), 1);
+        assert_eq!(html.matches(PADDING).count(), PREVIEW_LENGTH - 17);
+    }
+
+    #[test]
+    fn preheader_starts_fragments() {
+        let snippet = preheader_snippet("Hi");
+        assert_eq!(
+            insert_preheader("<p>Hi</p>", &snippet),
+            format!("{}<p>Hi</p>", snippet)
+        );
+    }
+}
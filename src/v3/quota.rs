@@ -0,0 +1,126 @@
+//! Check the remaining credits of a subuser before a bulk send.
+//!
+//! [`Sender::send_chunked_within_quota`] looks up the credits of a subuser with
+//! [`StatsClient::quota_check`] before sending the chunks of a large message. Depending on the
+//! [`QuotaPolicy`] a campaign which does not fit fails before anything is sent, or only the chunks
+//! which fit are sent.
+
+use reqwest::Response;
+
+use crate::error::{SendgridError, SendgridResult};
+use crate::stats::StatsClient;
+use crate::v3::{Message, Sender};
+
+/// What to do when the remaining credits are not enough for every recipient.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum QuotaPolicy {
+    /// Send nothing and return [`SendgridError::QuotaExceeded`].
+    #[default]
+    FailFast,
+
+    /// Send the chunks which fit in the remaining credits, in order, and return
+    /// [`SendgridError::QuotaExceeded`] for the rest.
+    Throttle,
+}
+
+impl Sender {
+    /// Like [`Sender::send_chunked`], checking the remaining credits of `subuser` first.
+    ///
+    /// An error is returned without sending anything when the credits could not be read, or when
+    /// they are not enough and the policy is [`QuotaPolicy::FailFast`].
+    pub async fn send_chunked_within_quota(
+        &self,
+        mail: &Message,
+        stats: &StatsClient,
+        subuser: &str,
+        policy: QuotaPolicy,
+    ) -> SendgridResult<Vec<SendgridResult<Response>>> {
+        let remaining = stats.quota_check(subuser).await?;
+        let chunks = mail.chunks();
+        let sendable = sendable_chunks(&chunks, remaining, policy)?;
+
+        let mut results = Vec::with_capacity(chunks.len());
+        for chunk in &chunks[..sendable] {
+            results.push(self.send(chunk).await);
+        }
+        for chunk in &chunks[sendable..] {
+            results.push(Err(SendgridError::QuotaExceeded {
+                needed: chunk.recipient_count() as u64,
+                remaining: remaining.unwrap_or_default(),
+            }));
+        }
+        Ok(results)
+    }
+}
+
+// The number of leading chunks which fit in the remaining credits.
+fn sendable_chunks(
+    chunks: &[Message],
+    remaining: Option<u64>,
+    policy: QuotaPolicy,
+) -> SendgridResult<usize> {
+    let Some(remaining) = remaining else {
+        return Ok(chunks.len());
+    };
+
+    let needed: u64 = chunks.iter().map(|c| c.recipient_count() as u64).sum();
+    if needed <= remaining {
+        return Ok(chunks.len());
+    }
+    if policy == QuotaPolicy::FailFast {
+        return Err(SendgridError::QuotaExceeded { needed, remaining });
+    }
+
+    let mut used = 0;
+    Ok(chunks
+        .iter()
+        .take_while(|c| {
+            used += c.recipient_count() as u64;
+            used <= remaining
+        })
+        .count())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::v3::{Email, Personalization};
+
+    fn chunks(sizes: &[usize]) -> Vec<Message> {
+        sizes
+            .iter()
+            .map(|&size| {
+                (0..size).fold(Message::new(Email::new("from@example.com")), |m, i| {
+                    m.add_personalization(Personalization::new(Email::new(format!(
+                        "to{}@example.com",
+                        i
+                    ))))
+                })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn chunks_are_limited_by_the_quota() {
+        let chunks = chunks(&[3, 3, 3]);
+        assert_eq!(
+            sendable_chunks(&chunks, None, QuotaPolicy::FailFast).unwrap(),
+            3
+        );
+        assert_eq!(
+            sendable_chunks(&chunks, Some(9), QuotaPolicy::FailFast).unwrap(),
+            3
+        );
+        assert_eq!(
+            sendable_chunks(&chunks, Some(7), QuotaPolicy::Throttle).unwrap(),
+            2
+        );
+        assert!(matches!(
+            sendable_chunks(&chunks, Some(7), QuotaPolicy::FailFast),
+            Err(SendgridError::QuotaExceeded {
+                needed: 9,
+                remaining: 7
+            })
+        ));
+    }
+}
@@ -0,0 +1,347 @@
+//! Client side rate limiting of mail sends.
+//!
+//! A [`RateLimiter`] takes a token from a token bucket before every send and waits when the
+//! bucket is empty, so the account level SendGrid limits are not exceeded. The buckets live in a
+//! [`RateLimitStore`]: [`MemoryRateLimitStore`] limits a single process, while
+//! `RedisRateLimitStore` (behind the `rate-limit-redis` feature) shares one bucket between every
+//! process using the same Redis key.
+//!
+//! A limiter with an in-memory store can also be set on a sender with
+//! [`Sender::set_rate_limiter`], which limits every send of the sender and its clones.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use reqwest::Response;
+
+use crate::error::{SendgridError, SendgridResult};
+use crate::v3::{Message, Sender};
+
+/// Holds the token buckets of a [`RateLimiter`].
+pub trait RateLimitStore {
+    /// Refill the bucket `key` at `rate` tokens per second up to `capacity` and take one token.
+    /// Returns how long to wait before trying again if the bucket is empty, or
+    /// [`Duration::ZERO`] if a token was taken.
+    fn take(
+        &self,
+        key: &str,
+        capacity: u32,
+        rate: f64,
+    ) -> impl Future<Output = SendgridResult<Duration>> + Send;
+}
+
+/// A [`RateLimitStore`] which keeps its buckets in memory.
+#[derive(Debug, Default)]
+pub struct MemoryRateLimitStore {
+    buckets: Mutex<HashMap<String, (f64, Instant)>>,
+}
+
+impl RateLimitStore for MemoryRateLimitStore {
+    async fn take(&self, key: &str, capacity: u32, rate: f64) -> SendgridResult<Duration> {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+        let (tokens, last) = buckets
+            .entry(key.to_string())
+            .or_insert((f64::from(capacity), now));
+
+        *tokens =
+            (*tokens + now.duration_since(*last).as_secs_f64() * rate).min(f64::from(capacity));
+        *last = now;
+
+        if *tokens >= 1.0 {
+            *tokens -= 1.0;
+            Ok(Duration::ZERO)
+        } else {
+            Ok(Duration::from_secs_f64((1.0 - *tokens) / rate))
+        }
+    }
+}
+
+/// A [`RateLimitStore`] which keeps its buckets in Redis, so that several processes share them.
+#[cfg(feature = "rate-limit-redis")]
+#[derive(Clone)]
+pub struct RedisRateLimitStore {
+    connection: redis::aio::ConnectionManager,
+    prefix: String,
+}
+
+#[cfg(feature = "rate-limit-redis")]
+impl RedisRateLimitStore {
+    // The bucket is refilled and taken from atomically, using the clock of the Redis server so
+    // that every process agrees on the time.
+    const SCRIPT: &'static str = r#"
+        local capacity = tonumber(ARGV[1])
+        local rate = tonumber(ARGV[2]) / 1000
+        local time = redis.call('TIME')
+        local now = tonumber(time[1]) * 1000 + math.floor(tonumber(time[2]) / 1000)
+        local tokens = tonumber(redis.call('HGET', KEYS[1], 'tokens') or capacity)
+        local last = tonumber(redis.call('HGET', KEYS[1], 'last') or now)
+        tokens = math.min(capacity, tokens + math.max(0, now - last) * rate)
+        local wait = 0
+        if tokens >= 1 then
+            tokens = tokens - 1
+        else
+            wait = math.ceil((1 - tokens) / rate)
+        end
+        redis.call('HSET', KEYS[1], 'tokens', tostring(tokens), 'last', now)
+        redis.call('PEXPIRE', KEYS[1], math.ceil(capacity / rate) + 1000)
+        return wait
+    "#;
+
+    /// Construct a store using `connection`. Bucket keys are prefixed with `sendgrid:rate:`.
+    pub fn new(connection: redis::aio::ConnectionManager) -> RedisRateLimitStore {
+        RedisRateLimitStore {
+            connection,
+            prefix: String::from("sendgrid:rate:"),
+        }
+    }
+
+    /// Set the prefix of the Redis keys holding the buckets.
+    pub fn set_prefix<S: Into<String>>(mut self, prefix: S) -> RedisRateLimitStore {
+        self.prefix = prefix.into();
+        self
+    }
+}
+
+#[cfg(feature = "rate-limit-redis")]
+impl RateLimitStore for RedisRateLimitStore {
+    async fn take(&self, key: &str, capacity: u32, rate: f64) -> SendgridResult<Duration> {
+        let mut connection = self.connection.clone();
+        let wait: u64 = redis::Script::new(Self::SCRIPT)
+            .key(format!("{}{}", self.prefix, key))
+            .arg(capacity)
+            .arg(rate)
+            .invoke_async(&mut connection)
+            .await
+            .map_err(|e| crate::error::SendgridError::Store(Box::new(e)))?;
+        Ok(Duration::from_millis(wait))
+    }
+}
+
+/// Limits the rate of sends to a number of requests per second.
+pub struct RateLimiter<S> {
+    store: S,
+    key: String,
+    rate: f64,
+    burst: u32,
+}
+
+impl<S> fmt::Debug for RateLimiter<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RateLimiter")
+            .field("key", &self.key)
+            .field("rate", &self.rate)
+            .field("burst", &self.burst)
+            .finish()
+    }
+}
+
+impl<S: RateLimitStore> RateLimiter<S> {
+    /// Construct a limiter allowing `requests_per_second` sends on average. Limiters with the
+    /// same `key` and store share one bucket.
+    pub fn new<K: Into<String>>(store: S, key: K, requests_per_second: u32) -> Self {
+        let requests_per_second = requests_per_second.max(1);
+        RateLimiter {
+            store,
+            key: key.into(),
+            rate: f64::from(requests_per_second),
+            burst: requests_per_second,
+        }
+    }
+
+    /// Set how many sends may happen at once after the limiter has been idle. Defaults to the
+    /// number of requests per second.
+    pub fn set_burst(mut self, burst: u32) -> Self {
+        self.burst = burst.max(1);
+        self
+    }
+
+    /// Wait until a send is allowed.
+    pub async fn acquire(&self) -> SendgridResult<()> {
+        loop {
+            let wait = self.store.take(&self.key, self.burst, self.rate).await?;
+            if wait.is_zero() {
+                return Ok(());
+            }
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Take a send from the limit without waiting, returning [`SendgridError::RateLimited`] if
+    /// none is left.
+    pub async fn try_acquire(&self) -> SendgridResult<()> {
+        let wait = self.store.take(&self.key, self.burst, self.rate).await?;
+        if wait.is_zero() {
+            Ok(())
+        } else {
+            Err(SendgridError::RateLimited { retry_in: wait })
+        }
+    }
+
+    /// Wait until a send is allowed and send the message.
+    pub async fn send(&self, sender: &Sender, mail: &Message) -> SendgridResult<Response> {
+        self.acquire().await?;
+        sender.send(mail).await
+    }
+}
+
+/// What a sender does when its rate limit is exhausted, see [`Sender::set_rate_limiter`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RateLimitMode {
+    /// Wait until a send is allowed.
+    #[default]
+    Wait,
+
+    /// Fail with [`SendgridError::RateLimited`] without sending.
+    FailFast,
+}
+
+// The limiter of a sender, shared with its clones.
+#[derive(Clone, Debug)]
+pub(crate) struct SenderRateLimit {
+    limiter: Arc<RateLimiter<MemoryRateLimitStore>>,
+    mode: RateLimitMode,
+}
+
+impl SenderRateLimit {
+    pub(crate) fn new(limiter: RateLimiter<MemoryRateLimitStore>, mode: RateLimitMode) -> Self {
+        SenderRateLimit {
+            limiter: Arc::new(limiter),
+            mode,
+        }
+    }
+
+    pub(crate) async fn acquire(&self) -> SendgridResult<()> {
+        match self.mode {
+            RateLimitMode::Wait => self.limiter.acquire().await,
+            RateLimitMode::FailFast => self.limiter.try_acquire().await,
+        }
+    }
+
+    // The in-memory store never suspends, so its future completes on the first poll.
+    #[cfg(feature = "blocking")]
+    pub(crate) fn blocking_acquire(&self, deadline: Option<Instant>) -> SendgridResult<()> {
+        loop {
+            match futures::executor::block_on(self.limiter.try_acquire()) {
+                Err(SendgridError::RateLimited { retry_in })
+                    if self.mode == RateLimitMode::Wait =>
+                {
+                    if deadline.is_some_and(|deadline| Instant::now() + retry_in >= deadline) {
+                        return Err(SendgridError::DeadlineExceeded);
+                    }
+                    std::thread::sleep(retry_in)
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+impl Sender {
+    /// Limit the sends of this sender and all of its clones with `limiter`. When the limit is
+    /// exhausted, sends wait or fail depending on `mode`. Every attempt of a retried send counts
+    /// against the limit. Clones made before this call are not affected.
+    pub fn set_rate_limiter(
+        &mut self,
+        limiter: RateLimiter<MemoryRateLimitStore>,
+        mode: RateLimitMode,
+    ) {
+        self.rate_limit = Some(SenderRateLimit::new(limiter, mode));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::v3::dev_server::CaptureServer;
+    use crate::v3::{Email, Personalization};
+
+    #[tokio::test]
+    async fn bursts_then_waits() {
+        let store = MemoryRateLimitStore::default();
+        for _ in 0..3 {
+            assert_eq!(store.take("key", 3, 10.0).await.unwrap(), Duration::ZERO);
+        }
+        let wait = store.take("key", 3, 10.0).await.unwrap();
+        assert!(wait > Duration::ZERO && wait <= Duration::from_millis(100));
+
+        // Other keys have their own bucket.
+        assert_eq!(store.take("other", 3, 10.0).await.unwrap(), Duration::ZERO);
+
+        let limiter = RateLimiter::new(store, "key", 100).set_burst(1);
+        let start = Instant::now();
+        limiter.acquire().await.unwrap();
+        limiter.acquire().await.unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(10));
+    }
+
+    #[tokio::test]
+    async fn senders_are_limited() {
+        let server = CaptureServer::start().await.unwrap();
+        let message = Message::new(Email::new("from@example.com"))
+            .add_personalization(Personalization::new(Email::new("to@example.com")))
+            .set_text("hi");
+
+        let mut sender = Sender::new(String::from("SG.key"), None);
+        sender.set_host(server.url()).unwrap();
+        let limiter = RateLimiter::new(MemoryRateLimitStore::default(), "sender", 1);
+        sender.set_rate_limiter(limiter, RateLimitMode::FailFast);
+        let clone = sender.clone();
+
+        assert!(sender.send(&message).await.is_ok());
+        match clone.send(&message).await {
+            Err(SendgridError::RateLimited { retry_in }) => {
+                assert!(retry_in > Duration::ZERO && retry_in <= Duration::from_secs(1))
+            }
+            other => panic!("expected the rate limit to be exhausted, got {:?}", other),
+        }
+        assert_eq!(server.captured().len(), 1);
+
+        let limiter = RateLimiter::new(MemoryRateLimitStore::default(), "sender", 20).set_burst(1);
+        sender.set_rate_limiter(limiter, RateLimitMode::Wait);
+        let start = Instant::now();
+        sender.send(&message).await.unwrap();
+        sender.send(&message).await.unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(40));
+        assert_eq!(server.captured().len(), 3);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn waits_end_at_the_deadline() {
+        let server = CaptureServer::start().await.unwrap();
+        let message = Message::new(Email::new("from@example.com"))
+            .add_personalization(Personalization::new(Email::new("to@example.com")))
+            .set_text("hi");
+
+        let mut sender = Sender::new(String::from("SG.key"), None);
+        sender.set_host(server.url()).unwrap();
+        let limiter = RateLimiter::new(MemoryRateLimitStore::default(), "sender", 1).set_burst(1);
+        sender.set_rate_limiter(limiter, RateLimitMode::Wait);
+        sender.send(&message).await.unwrap();
+
+        let start = Instant::now();
+        let deadline = start + Duration::from_millis(50);
+        assert!(matches!(
+            sender.send_with_deadline(&message, deadline).await,
+            Err(SendgridError::DeadlineExceeded)
+        ));
+        assert!(start.elapsed() < Duration::from_millis(500));
+
+        #[cfg(feature = "blocking")]
+        {
+            let blocking = sender.clone();
+            let message = message.clone();
+            let result = tokio::task::spawn_blocking(move || {
+                let deadline = Instant::now() + Duration::from_millis(50);
+                blocking.blocking_send_with_deadline(&message, deadline)
+            })
+            .await
+            .unwrap();
+            assert!(matches!(result, Err(SendgridError::DeadlineExceeded)));
+        }
+        assert_eq!(server.captured().len(), 1);
+    }
+}
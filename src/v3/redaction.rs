@@ -0,0 +1,198 @@
+//! Control how much personal data reaches logs and archives.
+//!
+//! The [`RedactionLevel`] of a [`Sender`](crate::v3::Sender) is applied to the payloads handed to
+//! its [`ArchiveSink`](crate::v3::archive::ArchiveSink) and to the debug output of
+//! [`PreparedSend`](crate::v3::PreparedSend). It can be changed while the application runs, so
+//! production and staging can share code and differ only in configuration.
+
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Arc;
+
+use serde_json::Value;
+
+const REDACTED: &str = "[redacted]";
+
+// The paths of fields whose values are not personal data and are kept at the full level. `[]`
+// stands for the elements of an array.
+const KEPT_FIELDS: [&str; 7] = [
+    "template_id",
+    "batch_id",
+    "ip_pool_name",
+    "categories[]",
+    "content[].type",
+    "attachments[].type",
+    "attachments[].disposition",
+];
+
+/// How much personal data is redacted.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RedactionLevel {
+    /// Redact every value which may contain personal data: addresses, names, subjects, content,
+    /// attachments, headers, substitutions, custom args and template data.
+    Full,
+
+    /// Replace the local part of email addresses, keeping the domain, for example
+    /// `***@example.com`. Everything else is kept.
+    EmailDomainsOnly,
+
+    /// Redact nothing.
+    #[default]
+    None,
+}
+
+impl RedactionLevel {
+    /// Redact an email address.
+    pub fn redact_email(&self, email: &str) -> String {
+        match self {
+            RedactionLevel::Full => String::from(REDACTED),
+            RedactionLevel::EmailDomainsOnly => match email.rsplit_once('@') {
+                Some((_, domain)) => format!("***@{}", domain),
+                None => String::from("***"),
+            },
+            RedactionLevel::None => String::from(email),
+        }
+    }
+
+    /// Redact a mail send payload. Payloads which are not JSON are redacted completely unless the
+    /// level is [`RedactionLevel::None`].
+    pub fn redact_payload(&self, payload: &str) -> String {
+        if *self == RedactionLevel::None {
+            return String::from(payload);
+        }
+        match serde_json::from_str::<Value>(payload) {
+            Ok(mut value) => {
+                self.redact_value("", None, &mut value);
+                value.to_string()
+            }
+            Err(_) => String::from(REDACTED),
+        }
+    }
+
+    // Redact the strings of `value`, the value at `path` of the field `field`. Array elements are
+    // treated as values of the field holding the array.
+    fn redact_value(&self, path: &str, field: Option<&str>, value: &mut Value) {
+        match value {
+            Value::String(s) => match self {
+                RedactionLevel::Full if !KEPT_FIELDS.contains(&path) => {
+                    *s = String::from(REDACTED);
+                }
+                RedactionLevel::EmailDomainsOnly if field == Some("email") => {
+                    *s = self.redact_email(s);
+                }
+                _ => {}
+            },
+            Value::Array(values) => {
+                let path = format!("{}[]", path);
+                for value in values {
+                    self.redact_value(&path, field, value);
+                }
+            }
+            Value::Object(map) => {
+                for (key, value) in map.iter_mut() {
+                    let path = match path {
+                        "" => key.clone(),
+                        path => format!("{}.{}", path, key),
+                    };
+                    self.redact_value(&path, Some(key), value);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn to_u8(self) -> u8 {
+        match self {
+            RedactionLevel::Full => 0,
+            RedactionLevel::EmailDomainsOnly => 1,
+            RedactionLevel::None => 2,
+        }
+    }
+
+    fn from_u8(level: u8) -> RedactionLevel {
+        match level {
+            0 => RedactionLevel::Full,
+            1 => RedactionLevel::EmailDomainsOnly,
+            _ => RedactionLevel::None,
+        }
+    }
+}
+
+// A level shared by a sender and its clones.
+#[derive(Clone, Debug)]
+pub(crate) struct SharedRedactionLevel(Arc<AtomicU8>);
+
+impl Default for SharedRedactionLevel {
+    fn default() -> Self {
+        SharedRedactionLevel(Arc::new(AtomicU8::new(RedactionLevel::default().to_u8())))
+    }
+}
+
+impl SharedRedactionLevel {
+    pub(crate) fn get(&self) -> RedactionLevel {
+        RedactionLevel::from_u8(self.0.load(Ordering::Relaxed))
+    }
+
+    pub(crate) fn set(&self, level: RedactionLevel) {
+        self.0.store(level.to_u8(), Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::v3::{Email, Message, Personalization, Sender};
+
+    #[test]
+    fn payloads_are_redacted() {
+        let payload = Message::new(Email::new("from@example.com").set_name("Shop"))
+            .set_subject("Your order")
+            .set_template_id("d-123")
+            .add_category("orders")
+            .set_text("Hi Ada")
+            .add_personalization(
+                Personalization::new(Email::new("ada@example.org"))
+                    .add_custom_arg("order", "42")
+                    .add_custom_arg("type", "secret"),
+            )
+            .to_json()
+            .unwrap();
+
+        let redacted: Value =
+            serde_json::from_str(&RedactionLevel::Full.redact_payload(&payload)).unwrap();
+        assert_eq!(redacted["from"]["email"], REDACTED);
+        assert_eq!(redacted["from"]["name"], REDACTED);
+        assert_eq!(redacted["subject"], REDACTED);
+        assert_eq!(
+            redacted["personalizations"][0]["custom_args"]["order"],
+            REDACTED
+        );
+        assert_eq!(
+            redacted["personalizations"][0]["custom_args"]["type"],
+            REDACTED
+        );
+        assert_eq!(redacted["content"][0]["type"], "text/plain");
+        assert_eq!(redacted["content"][0]["value"], REDACTED);
+        assert_eq!(redacted["template_id"], "d-123");
+        assert_eq!(redacted["categories"][0], "orders");
+
+        let redacted: Value =
+            serde_json::from_str(&RedactionLevel::EmailDomainsOnly.redact_payload(&payload))
+                .unwrap();
+        assert_eq!(
+            redacted["personalizations"][0]["to"][0]["email"],
+            "***@example.org"
+        );
+        assert_eq!(redacted["from"]["name"], "Shop");
+
+        assert_eq!(RedactionLevel::None.redact_payload(&payload), payload);
+        assert_eq!(RedactionLevel::Full.redact_payload("not json"), REDACTED);
+    }
+
+    #[test]
+    fn clones_share_the_level() {
+        let sender = Sender::new(String::from("SG.key"), None);
+        let clone = sender.clone();
+        sender.set_redaction_level(RedactionLevel::EmailDomainsOnly);
+        assert_eq!(clone.redaction_level(), RedactionLevel::EmailDomainsOnly);
+    }
+}
@@ -0,0 +1,112 @@
+//! Propagate application request ids into messages.
+//!
+//! A request id is written to the message as an email header and as a custom arg on every
+//! personalization. Custom args are echoed back in Event Webhook payloads, so events can be traced
+//! back to the request which sent the message.
+
+use std::fmt;
+use std::sync::Arc;
+
+use crate::v3::Message;
+
+/// The email header carrying the request id.
+pub const REQUEST_ID_HEADER: &str = "X-Request-Id";
+
+/// The custom arg carrying the request id.
+pub const REQUEST_ID_ARG: &str = "request_id";
+
+/// Supplies the id of the current request, for example from a tracing span or task local.
+#[derive(Clone)]
+pub struct RequestIdProvider(Arc<dyn Fn() -> Option<String> + Send + Sync>);
+
+impl RequestIdProvider {
+    /// Construct a provider from a function returning the current request id, if any.
+    pub fn new<F>(provider: F) -> RequestIdProvider
+    where
+        F: Fn() -> Option<String> + Send + Sync + 'static,
+    {
+        RequestIdProvider(Arc::new(provider))
+    }
+
+    pub(crate) fn current(&self) -> Option<String> {
+        (self.0)()
+    }
+}
+
+impl fmt::Debug for RequestIdProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("RequestIdProvider")
+    }
+}
+
+impl Message {
+    /// Set the request id of this message as the `X-Request-Id` header and as the `request_id`
+    /// custom arg of every personalization. Call this after adding the personalizations.
+    pub fn set_request_id(mut self, request_id: &str) -> Message {
+        self.personalizations = self
+            .personalizations
+            .into_iter()
+            .map(|p| p.add_custom_arg(REQUEST_ID_ARG, request_id))
+            .collect();
+        self.add_header(REQUEST_ID_HEADER, request_id)
+    }
+
+    // Header names are case insensitive, so `x-request-id` counts as a request id too.
+    pub(crate) fn has_request_id(&self) -> bool {
+        self.headers.as_ref().is_some_and(|headers| {
+            headers
+                .keys()
+                .any(|name| name.eq_ignore_ascii_case(REQUEST_ID_HEADER))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::v3::custom_args::{ArgType, CustomArgSchema};
+    use crate::v3::{Email, Personalization, Sender};
+
+    #[test]
+    fn request_id_is_injected() {
+        let message = Message::new(Email::new("from@example.com"))
+            .add_personalization(Personalization::new(Email::new("to@example.com")));
+
+        let mut sender = Sender::new(String::from("SG.key"), None);
+        sender.set_request_id_provider(RequestIdProvider::new(|| Some(String::from("req-1"))));
+        let body: serde_json::Value =
+            serde_json::from_str(sender.prepare(&message).unwrap().body()).unwrap();
+        assert_eq!(body["headers"][REQUEST_ID_HEADER], "req-1");
+        assert_eq!(
+            body["personalizations"][0]["custom_args"][REQUEST_ID_ARG],
+            "req-1"
+        );
+
+        // An id set on the message takes precedence.
+        let message = message.set_request_id("req-2");
+        let body: serde_json::Value =
+            serde_json::from_str(sender.prepare(&message).unwrap().body()).unwrap();
+        assert_eq!(body["headers"][REQUEST_ID_HEADER], "req-2");
+
+        // Whatever the case of its name.
+        let message = Message::new(Email::new("from@example.com"))
+            .add_personalization(Personalization::new(Email::new("to@example.com")))
+            .add_header("x-request-id", "req-3");
+        let body: serde_json::Value =
+            serde_json::from_str(sender.prepare(&message).unwrap().body()).unwrap();
+        assert_eq!(body["headers"]["x-request-id"], "req-3");
+        assert!(body["headers"].get(REQUEST_ID_HEADER).is_none());
+    }
+
+    #[test]
+    fn request_id_is_injected_before_validation() {
+        let message = Message::new(Email::new("from@example.com"))
+            .add_personalization(Personalization::new(Email::new("to@example.com")));
+
+        let mut sender = Sender::new(String::from("SG.key"), None);
+        sender
+            .set_custom_arg_schema(CustomArgSchema::new().require(REQUEST_ID_ARG, ArgType::String));
+        sender.set_request_id_provider(RequestIdProvider::new(|| Some(String::from("req-1"))));
+        assert!(sender.prepare(&message).is_ok());
+    }
+}
@@ -0,0 +1,273 @@
+//! Retry failed sends with exponential backoff.
+//!
+//! A [`RetryPolicy`] set with [`Sender::set_retry_policy`](crate::v3::Sender::set_retry_policy)
+//! makes every send of the sender retry failures which are likely to succeed on a later attempt:
+//! throttled requests, server errors and failed connections, see
+//! [`SendgridError::is_retryable`]. The delay doubles after every attempt, is randomized by the
+//! jitter and is replaced by the `Retry-After` delay when the response asks for one. Neither
+//! delay exceeds the maximum delay of the policy.
+//!
+//! ```
+//! use std::time::Duration;
+//!
+//! use sendgrid::v3::retry::RetryPolicy;
+//! use sendgrid::v3::Sender;
+//!
+//! let mut sender = Sender::new(String::from("SG.key"), None);
+//! sender.set_retry_policy(
+//!     RetryPolicy::new()
+//!         .set_max_attempts(5)
+//!         .set_initial_delay(Duration::from_millis(200)),
+//! );
+//! ```
+
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::error::SendgridError;
+
+type Classifier = Arc<dyn Fn(&SendgridError) -> bool + Send + Sync>;
+
+/// How often and how long to wait before retrying a failed send.
+#[must_use]
+#[derive(Clone)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    initial_delay: Duration,
+    max_delay: Duration,
+    jitter: bool,
+    classifier: Option<Classifier>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            initial_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+            jitter: true,
+            classifier: None,
+        }
+    }
+}
+
+impl fmt::Debug for RetryPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RetryPolicy")
+            .field("max_attempts", &self.max_attempts)
+            .field("initial_delay", &self.initial_delay)
+            .field("max_delay", &self.max_delay)
+            .field("jitter", &self.jitter)
+            .field("custom_classifier", &self.classifier.is_some())
+            .finish()
+    }
+}
+
+impl RetryPolicy {
+    /// Construct a policy making up to 3 attempts, starting with a delay of 500 milliseconds.
+    pub fn new() -> RetryPolicy {
+        RetryPolicy::default()
+    }
+
+    /// Set the maximum number of attempts, including the first one. Defaults to 3.
+    pub fn set_max_attempts(mut self, max_attempts: u32) -> RetryPolicy {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    /// Set the delay before the first retry. The delay doubles after every failed attempt.
+    pub fn set_initial_delay(mut self, delay: Duration) -> RetryPolicy {
+        self.initial_delay = delay;
+        self
+    }
+
+    /// Set the longest delay between two attempts. Defaults to 30 seconds. A longer
+    /// `Retry-After` delay is shortened to this delay.
+    pub fn set_max_delay(mut self, delay: Duration) -> RetryPolicy {
+        self.max_delay = delay;
+        self
+    }
+
+    /// Set whether delays are randomized, so that clients failing together do not retry
+    /// together. When enabled, each delay is between half of and the full backoff delay.
+    /// Defaults to true.
+    pub fn set_jitter(mut self, jitter: bool) -> RetryPolicy {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Decide which errors are retried. Defaults to [`SendgridError::is_retryable`].
+    pub fn set_classifier<F>(mut self, classifier: F) -> RetryPolicy
+    where
+        F: Fn(&SendgridError) -> bool + Send + Sync + 'static,
+    {
+        self.classifier = Some(Arc::new(classifier));
+        self
+    }
+
+    /// The maximum number of attempts, including the first one.
+    pub fn max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    /// Whether `error` should be retried.
+    pub fn is_retryable(&self, error: &SendgridError) -> bool {
+        match &self.classifier {
+            Some(classifier) => classifier(error),
+            None => error.is_retryable(),
+        }
+    }
+
+    /// The delay before the attempt following the failed attempt number `attempt`, counting from
+    /// 1, or `None` if `error` is not retried.
+    pub fn delay(&self, attempt: u32, error: &SendgridError) -> Option<Duration> {
+        if attempt >= self.max_attempts || !self.is_retryable(error) {
+            return None;
+        }
+        if let SendgridError::RequestNotSuccessful(e) = error {
+            if let Some(retry_after) = e.retry_after {
+                return Some(retry_after.min(self.max_delay));
+            }
+        }
+
+        let backoff = self
+            .initial_delay
+            .saturating_mul(2u32.saturating_pow(attempt - 1))
+            .min(self.max_delay);
+        if !self.jitter {
+            return Some(backoff);
+        }
+        let fraction = RandomState::new().build_hasher().finish() as f64 / u64::MAX as f64;
+        Some(backoff.mul_f64(0.5 + fraction / 2.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use reqwest::StatusCode;
+
+    use super::*;
+    use crate::error::RequestNotSuccessful;
+    use crate::v3::dev_server::CaptureServer;
+    use crate::v3::{Email, Message, Personalization, Sender};
+
+    fn status(status: StatusCode) -> SendgridError {
+        RequestNotSuccessful::new(status, String::new()).into()
+    }
+
+    #[test]
+    fn delays_back_off_exponentially() {
+        let policy = RetryPolicy::new()
+            .set_max_attempts(5)
+            .set_initial_delay(Duration::from_secs(1))
+            .set_max_delay(Duration::from_secs(3))
+            .set_jitter(false);
+        let error = status(StatusCode::SERVICE_UNAVAILABLE);
+        let delays: Vec<_> = (1..=5)
+            .map(|attempt| policy.delay(attempt, &error))
+            .collect();
+        assert_eq!(
+            delays,
+            [
+                Some(Duration::from_secs(1)),
+                Some(Duration::from_secs(2)),
+                Some(Duration::from_secs(3)),
+                Some(Duration::from_secs(3)),
+                None,
+            ]
+        );
+
+        assert_eq!(policy.delay(1, &status(StatusCode::BAD_REQUEST)), None);
+
+        let throttled = RequestNotSuccessful::new(StatusCode::TOO_MANY_REQUESTS, String::new())
+            .with_retry_after(Some(Duration::from_secs(60)));
+        assert_eq!(
+            policy.delay(1, &throttled.into()),
+            Some(Duration::from_secs(3))
+        );
+        let throttled = RequestNotSuccessful::new(StatusCode::TOO_MANY_REQUESTS, String::new())
+            .with_retry_after(Some(Duration::from_secs(2)));
+        assert_eq!(
+            policy.delay(1, &throttled.into()),
+            Some(Duration::from_secs(2))
+        );
+
+        let jittered = RetryPolicy::new().set_initial_delay(Duration::from_secs(2));
+        let delay = jittered.delay(1, &error).unwrap();
+        assert!(delay >= Duration::from_secs(1) && delay <= Duration::from_secs(2));
+    }
+
+    #[test]
+    fn classification_is_tunable() {
+        let policy = RetryPolicy::new().set_classifier(|error| {
+            matches!(error, SendgridError::RequestNotSuccessful(e) if e.status == StatusCode::BAD_REQUEST)
+        });
+        assert!(policy.is_retryable(&status(StatusCode::BAD_REQUEST)));
+        assert!(!policy.is_retryable(&status(StatusCode::SERVICE_UNAVAILABLE)));
+    }
+
+    #[tokio::test]
+    async fn failed_sends_are_retried() {
+        let server = CaptureServer::start().await.unwrap();
+        server.fail_next(StatusCode::SERVICE_UNAVAILABLE, 2);
+        let classified = Arc::new(AtomicU32::new(0));
+        let counter = classified.clone();
+        let mut sender = Sender::new(String::from("SG.key"), None);
+        sender.set_host(server.url()).unwrap();
+        sender.set_retry_policy(
+            RetryPolicy::new()
+                .set_max_attempts(3)
+                .set_initial_delay(Duration::from_millis(1))
+                .set_classifier(move |error| {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                    error.is_retryable()
+                }),
+        );
+
+        let message = Message::new(Email::new("from@example.com"))
+            .add_personalization(Personalization::new(Email::new("to@example.com")))
+            .set_text("hi");
+        assert_eq!(sender.send(&message).await.unwrap().status(), 202);
+        assert_eq!(classified.load(Ordering::SeqCst), 2);
+        assert_eq!(server.captured().len(), 1);
+
+        server.fail_next(StatusCode::SERVICE_UNAVAILABLE, 3);
+        assert!(sender.send(&message).await.unwrap_err().is_retryable());
+        // Every failed attempt but the last one is classified.
+        assert_eq!(classified.load(Ordering::SeqCst), 4);
+        assert_eq!(server.captured().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn retries_stop_at_the_deadline() {
+        let server = CaptureServer::start().await.unwrap();
+        server.fail_next(StatusCode::SERVICE_UNAVAILABLE, 1);
+        let mut sender = Sender::new(String::from("SG.key"), None);
+        sender.set_host(server.url()).unwrap();
+        sender.set_retry_policy(
+            RetryPolicy::new()
+                .set_max_attempts(5)
+                .set_initial_delay(Duration::from_secs(60))
+                .set_jitter(false),
+        );
+
+        let message = Message::new(Email::new("from@example.com"))
+            .add_personalization(Personalization::new(Email::new("to@example.com")))
+            .set_text("hi");
+        let start = std::time::Instant::now();
+        let deadline = start + Duration::from_secs(5);
+        let error = sender
+            .send_with_deadline(&message, deadline)
+            .await
+            .unwrap_err();
+        // The first backoff would end after the deadline, so the server error is returned.
+        assert!(error.is_retryable());
+        assert!(server.captured().is_empty());
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,244 @@
+//! Provides a sender which rotates between several API keys.
+
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use reqwest::{Client, Response, StatusCode};
+
+use crate::error::{SendgridError, SendgridResult};
+use crate::tls;
+use crate::v3::{Message, Sender};
+
+const DEFAULT_MAX_AUTH_FAILURES: u32 = 3;
+
+struct Slot {
+    sender: Sender,
+    weight: u32,
+    auth_failures: AtomicU32,
+    disabled: AtomicBool,
+}
+
+struct Inner {
+    slots: Vec<Slot>,
+    next: AtomicUsize,
+    max_auth_failures: u32,
+}
+
+/// Spreads sends across several API keys, for example one per subuser, to shard quota or
+/// reputation. Keys are picked in weighted round-robin order. A key that is rejected with a 401 or
+/// 403 status too many times in a row is taken out of the rotation and the message is retried
+/// with the next key.
+///
+/// Clones share the rotation and health state.
+#[derive(Clone)]
+pub struct RotatingSender {
+    inner: Arc<Inner>,
+}
+
+/// Used to configure a [`RotatingSender`].
+#[must_use]
+pub struct RotatingSenderBuilder {
+    client: Client,
+    host: Option<String>,
+    keys: Vec<(String, u32)>,
+    max_auth_failures: u32,
+}
+
+impl RotatingSenderBuilder {
+    /// Add an API key with a weight of 1.
+    pub fn add_key<S: Into<String>>(self, api_key: S) -> Self {
+        self.add_weighted_key(api_key, 1)
+    }
+
+    /// Add an API key which is picked `weight` times per rotation. A weight of 0 is treated as 1.
+    pub fn add_weighted_key<S: Into<String>>(mut self, api_key: S, weight: u32) -> Self {
+        self.keys.push((api_key.into(), weight.max(1)));
+        self
+    }
+
+    /// Sets the host to use for the API for every key.
+    pub fn set_host<S: Into<String>>(mut self, host: S) -> Self {
+        self.host = Some(host.into());
+        self
+    }
+
+    /// Set how many consecutive 401 or 403 responses remove a key from the rotation. Defaults
+    /// to 3.
+    pub fn set_max_auth_failures(mut self, max_auth_failures: u32) -> Self {
+        self.max_auth_failures = max_auth_failures.max(1);
+        self
+    }
+
+    /// Build the sender. All keys share one connection pool. Fails if the host is invalid, see
+    /// [`Sender::set_host`].
+    pub fn build(self) -> SendgridResult<RotatingSender> {
+        let mut slots = Vec::new();
+        for (key, weight) in self.keys {
+            let mut sender = Sender::new(key, Some(self.client.clone()));
+            if let Some(host) = &self.host {
+                sender.set_host(host.clone())?;
+            }
+            slots.push(Slot {
+                sender,
+                weight,
+                auth_failures: AtomicU32::new(0),
+                disabled: AtomicBool::new(false),
+            });
+        }
+
+        Ok(RotatingSender {
+            inner: Arc::new(Inner {
+                slots,
+                next: AtomicUsize::new(0),
+                max_auth_failures: self.max_auth_failures,
+            }),
+        })
+    }
+}
+
+impl RotatingSender {
+    /// Start configuring a rotating sender. The `client` parameter is optional and `None` uses
+    /// the default.
+    pub fn builder(client: Option<Client>) -> RotatingSenderBuilder {
+        RotatingSenderBuilder {
+            client: client.unwrap_or_else(tls::default_client),
+            host: None,
+            keys: Vec::new(),
+            max_auth_failures: DEFAULT_MAX_AUTH_FAILURES,
+        }
+    }
+
+    /// The number of keys still in the rotation.
+    pub fn healthy_keys(&self) -> usize {
+        self.inner
+            .slots
+            .iter()
+            .filter(|s| !s.disabled.load(Ordering::Relaxed))
+            .count()
+    }
+
+    /// Send a V3 message with the next key in the rotation and return the HTTP response or an
+    /// error. Authentication failures are retried with the other keys, trying each key at most
+    /// once.
+    pub async fn send(&self, mail: &Message) -> SendgridResult<Response> {
+        let mut last_error = SendgridError::NoHealthyKeys;
+        let mut tried = Vec::new();
+        while let Some(index) = self.pick(&tried) {
+            tried.push(index);
+            match self.inner.slots[index].sender.send(mail).await {
+                Ok(resp) => {
+                    self.record_success(index);
+                    return Ok(resp);
+                }
+                Err(SendgridError::RequestNotSuccessful(e)) if is_auth_failure(e.status) => {
+                    self.record_auth_failure(index);
+                    last_error = e.into();
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        Err(last_error)
+    }
+
+    // Pick the next healthy slot which is not in `tried` in weighted round-robin order.
+    fn pick(&self, tried: &[usize]) -> Option<usize> {
+        let healthy =
+            || {
+                self.inner.slots.iter().enumerate().filter(|(index, s)| {
+                    !s.disabled.load(Ordering::Relaxed) && !tried.contains(index)
+                })
+            };
+        let total: usize = healthy().map(|(_, s)| s.weight as usize).sum();
+        if total == 0 {
+            return None;
+        }
+
+        let mut position = self.inner.next.fetch_add(1, Ordering::Relaxed) % total;
+        for (index, slot) in healthy() {
+            if position < slot.weight as usize {
+                return Some(index);
+            }
+            position -= slot.weight as usize;
+        }
+        None
+    }
+
+    fn record_success(&self, index: usize) {
+        self.inner.slots[index]
+            .auth_failures
+            .store(0, Ordering::Relaxed);
+    }
+
+    fn record_auth_failure(&self, index: usize) {
+        let slot = &self.inner.slots[index];
+        if slot.auth_failures.fetch_add(1, Ordering::Relaxed) + 1 >= self.inner.max_auth_failures {
+            slot.disabled.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+fn is_auth_failure(status: StatusCode) -> bool {
+    status == StatusCode::UNAUTHORIZED || status == StatusCode::FORBIDDEN
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::v3::dev_server::CaptureServer;
+    use crate::v3::{Email, Personalization};
+
+    #[test]
+    fn weighted_round_robin() {
+        let sender = RotatingSender::builder(None)
+            .add_weighted_key("a", 2)
+            .add_key("b")
+            .build()
+            .unwrap();
+        let picks: Vec<usize> = (0..6).map(|_| sender.pick(&[]).unwrap()).collect();
+        assert_eq!(picks, vec![0, 0, 1, 0, 0, 1]);
+        assert_eq!(sender.pick(&[0]), Some(1));
+        assert_eq!(sender.pick(&[0, 1]), None);
+    }
+
+    #[tokio::test]
+    async fn rejected_keys_are_not_retried() {
+        let server = CaptureServer::start().await.unwrap();
+        // The capture server rejects the empty key with a 401.
+        let sender = RotatingSender::builder(None)
+            .add_weighted_key("", 2)
+            .add_key("b")
+            .set_host(server.url())
+            .build()
+            .unwrap();
+        let message = Message::new(Email::new("from@example.com"))
+            .add_personalization(Personalization::new(Email::new("to@example.com")));
+
+        assert!(sender.send(&message).await.is_ok());
+        assert_eq!(server.captured().len(), 1);
+        assert_eq!(sender.healthy_keys(), 2);
+    }
+
+    #[test]
+    fn keys_are_removed_after_auth_failures() {
+        let sender = RotatingSender::builder(None)
+            .add_key("a")
+            .add_key("b")
+            .set_max_auth_failures(2)
+            .build()
+            .unwrap();
+
+        sender.record_auth_failure(0);
+        sender.record_success(0);
+        sender.record_auth_failure(0);
+        assert_eq!(sender.healthy_keys(), 2);
+
+        sender.record_auth_failure(0);
+        assert_eq!(sender.healthy_keys(), 1);
+        assert!((0..4).all(|_| sender.pick(&[]) == Some(1)));
+
+        sender.record_auth_failure(1);
+        sender.record_auth_failure(1);
+        assert_eq!(sender.pick(&[]), None);
+    }
+}
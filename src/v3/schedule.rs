@@ -0,0 +1,93 @@
+//! Compute `send_at` timestamps from a recipient's local time.
+//!
+//! SendGrid schedules messages with a Unix timestamp and accepts timestamps at most 72 hours in
+//! the future. These helpers turn "9am in the recipient's time zone" into such a timestamp.
+
+use chrono::{DateTime, Duration, LocalResult, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+
+use crate::error::{SendgridError, SendgridResult};
+use crate::v3::send_at::send_at_timestamp;
+use crate::v3::Personalization;
+
+pub use crate::v3::send_at::MAX_SCHEDULE_HOURS;
+
+/// Convert a local date and time in `tz` into a `send_at` timestamp, checking that it is neither
+/// in the past nor more than 72 hours after `now`. Ambiguous local times, which occur when clocks
+/// are turned back, resolve to the earlier instant. Local times skipped when clocks are turned
+/// forward are moved forward by an hour.
+pub fn local_send_at(tz: Tz, local: NaiveDateTime, now: DateTime<Utc>) -> SendgridResult<u64> {
+    let at = match tz.from_local_datetime(&local) {
+        LocalResult::Single(at) | LocalResult::Ambiguous(at, _) => at,
+        LocalResult::None => tz
+            .from_local_datetime(&(local + Duration::hours(1)))
+            .earliest()
+            .ok_or_else(|| {
+                SendgridError::InvalidSendAt(format!("{} does not exist in {}", local, tz))
+            })?,
+    }
+    .with_timezone(&Utc);
+
+    send_at_timestamp(at, now)
+}
+
+/// The `send_at` timestamp of the next occurrence of `time` in `tz` after `now`.
+pub fn next_local_send_at(tz: Tz, time: NaiveTime, now: DateTime<Utc>) -> SendgridResult<u64> {
+    let today = now.with_timezone(&tz).date_naive();
+    let candidate = today.and_time(time);
+    match local_send_at(tz, candidate, now) {
+        Err(SendgridError::InvalidSendAt(_)) => {
+            local_send_at(tz, candidate + Duration::days(1), now)
+        }
+        result => result,
+    }
+}
+
+impl Personalization {
+    /// Schedule this personalization for the next time the recipient's clock in `tz` reads
+    /// `time`, for example 9am local time.
+    pub fn set_local_send_at(self, tz: Tz, time: NaiveTime) -> SendgridResult<Personalization> {
+        let send_at = next_local_send_at(tz, time, Utc::now())?;
+        Ok(self.set_send_at(send_at))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc)
+    }
+
+    #[test]
+    fn next_nine_am_local() {
+        let nine = NaiveTime::from_hms_opt(9, 0, 0).unwrap();
+        let now = utc("2024-03-01T12:00:00Z");
+
+        // Already past 9am in Berlin, so the next day is used.
+        let berlin = next_local_send_at(chrono_tz::Europe::Berlin, nine, now).unwrap();
+        assert_eq!(berlin, utc("2024-03-02T08:00:00Z").timestamp() as u64);
+
+        // Still early morning in Los Angeles.
+        let la = next_local_send_at(chrono_tz::America::Los_Angeles, nine, now).unwrap();
+        assert_eq!(la, utc("2024-03-01T17:00:00Z").timestamp() as u64);
+    }
+
+    #[test]
+    fn schedule_window_is_validated() {
+        let now = utc("2024-03-01T12:00:00Z");
+        let local = |d, h| {
+            NaiveDate::from_ymd_opt(2024, 3, d)
+                .unwrap()
+                .and_hms_opt(h, 0, 0)
+                .unwrap()
+        };
+
+        assert!(local_send_at(chrono_tz::UTC, local(1, 11), now).is_err());
+        assert!(local_send_at(chrono_tz::UTC, local(4, 12), now).is_ok());
+        assert!(local_send_at(chrono_tz::UTC, local(4, 13), now).is_err());
+    }
+}
@@ -0,0 +1,68 @@
+//! Schedule personalizations with `chrono` date times.
+//!
+//! SendGrid expects `send_at` as a Unix timestamp at most 72 hours in the future. These helpers
+//! convert a `DateTime<Utc>` and check the scheduling window before the message is sent.
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::error::{SendgridError, SendgridResult};
+use crate::v3::Personalization;
+
+/// The furthest into the future SendGrid allows a message to be scheduled.
+pub const MAX_SCHEDULE_HOURS: i64 = 72;
+
+/// Convert `at` into a `send_at` timestamp, checking that it is neither in the past nor more than
+/// 72 hours after `now`.
+pub fn send_at_timestamp(at: DateTime<Utc>, now: DateTime<Utc>) -> SendgridResult<u64> {
+    if at < now {
+        return Err(SendgridError::InvalidSendAt(format!(
+            "{} is in the past",
+            at
+        )));
+    }
+    if at - now > Duration::hours(MAX_SCHEDULE_HOURS) {
+        return Err(SendgridError::InvalidSendAt(format!(
+            "{} is more than {} hours in the future",
+            at, MAX_SCHEDULE_HOURS
+        )));
+    }
+
+    Ok(at.timestamp() as u64)
+}
+
+impl Personalization {
+    /// Schedule this personalization for `at`, which must be within the next 72 hours.
+    pub fn set_send_at_datetime(self, at: DateTime<Utc>) -> SendgridResult<Personalization> {
+        let send_at = send_at_timestamp(at, Utc::now())?;
+        Ok(self.set_send_at(send_at))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::v3::Email;
+
+    #[test]
+    fn send_at_window() {
+        let now = DateTime::parse_from_rfc3339("2024-05-01T12:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        assert_eq!(
+            send_at_timestamp(now + Duration::hours(1), now).unwrap(),
+            1_714_568_400
+        );
+        assert!(send_at_timestamp(now - Duration::seconds(1), now).is_err());
+        assert!(send_at_timestamp(now + Duration::hours(73), now).is_err());
+
+        let p = Personalization::new(Email::new("to@example.com"));
+        assert!(p
+            .clone()
+            .set_send_at_datetime(Utc::now() + Duration::hours(2))
+            .is_ok());
+        assert!(matches!(
+            p.set_send_at_datetime(Utc::now() + Duration::days(4)),
+            Err(SendgridError::InvalidSendAt(_))
+        ));
+    }
+}
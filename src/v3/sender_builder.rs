@@ -0,0 +1,201 @@
+use std::time::Duration;
+
+use reqwest::{Client, Proxy};
+
+use crate::error::SendgridResult;
+use crate::region::Region;
+use crate::tls;
+#[cfg(feature = "blocking")]
+use crate::v3::blocking_client::BlockingClient;
+use crate::v3::{Sender, USER_AGENT};
+
+/// Configures the connection of a [`Sender`].
+///
+/// ```
+/// # fn main() -> sendgrid::SendgridResult<()> {
+/// use std::time::Duration;
+///
+/// use sendgrid::v3::SenderBuilder;
+///
+/// let sender = SenderBuilder::new("SG.key")
+///     .set_timeout(Duration::from_secs(10))
+///     .set_connect_timeout(Duration::from_secs(2))
+///     .set_user_agent_suffix("billing/1.4")
+///     .build()?;
+/// # Ok(())
+/// # }
+/// ```
+#[must_use]
+#[derive(Clone, Debug)]
+pub struct SenderBuilder {
+    api_key: String,
+    host: Option<String>,
+    timeout: Option<Duration>,
+    connect_timeout: Option<Duration>,
+    proxy: Option<Proxy>,
+    user_agent_suffix: Option<String>,
+    client: Option<Client>,
+    #[cfg(feature = "blocking")]
+    blocking_client: Option<reqwest::blocking::Client>,
+}
+
+impl SenderBuilder {
+    /// Construct a builder for a sender using `api_key`.
+    pub fn new<S: Into<String>>(api_key: S) -> SenderBuilder {
+        SenderBuilder {
+            api_key: api_key.into(),
+            host: None,
+            timeout: None,
+            connect_timeout: None,
+            proxy: None,
+            user_agent_suffix: None,
+            client: None,
+            #[cfg(feature = "blocking")]
+            blocking_client: None,
+        }
+    }
+
+    /// Set the mail send URL, see [`Sender::set_host`].
+    pub fn set_host<S: Into<String>>(mut self, host: S) -> SenderBuilder {
+        self.host = Some(host.into());
+        self
+    }
+
+    /// Send through the endpoint of `region`. A host set afterwards replaces it.
+    pub fn set_region(mut self, region: Region) -> SenderBuilder {
+        self.host = Some(region.mail_send_url());
+        self
+    }
+
+    /// Set the time allowed for a whole request, from connecting until the response body is read.
+    pub fn set_timeout(mut self, timeout: Duration) -> SenderBuilder {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Set the time allowed for connecting to the host.
+    pub fn set_connect_timeout(mut self, timeout: Duration) -> SenderBuilder {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Send requests through `proxy`.
+    pub fn set_proxy(mut self, proxy: Proxy) -> SenderBuilder {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    /// Append `suffix` to the `User-Agent` header, for example to identify the sending service.
+    pub fn set_user_agent_suffix<S: Into<String>>(mut self, suffix: S) -> SenderBuilder {
+        self.user_agent_suffix = Some(suffix.into());
+        self
+    }
+
+    /// Send with a client built elsewhere. The timeouts and proxy of this builder do not apply
+    /// to it.
+    pub fn set_client(mut self, client: Client) -> SenderBuilder {
+        self.client = Some(client);
+        self
+    }
+
+    /// Send with a blocking client built elsewhere. The timeouts and proxy of this builder do not
+    /// apply to it.
+    #[cfg(feature = "blocking")]
+    pub fn set_blocking_client(mut self, client: reqwest::blocking::Client) -> SenderBuilder {
+        self.blocking_client = Some(client);
+        self
+    }
+
+    /// Build the sender.
+    pub fn build(self) -> SendgridResult<Sender> {
+        let client = match self.client {
+            Some(client) => client,
+            None => {
+                let mut builder = tls::client_builder();
+                if let Some(timeout) = self.timeout {
+                    builder = builder.timeout(timeout);
+                }
+                if let Some(timeout) = self.connect_timeout {
+                    builder = builder.connect_timeout(timeout);
+                }
+                if let Some(proxy) = self.proxy.clone() {
+                    builder = builder.proxy(proxy);
+                }
+                builder.build()?
+            }
+        };
+
+        #[cfg(feature = "blocking")]
+        let blocking_client = match self.blocking_client {
+            Some(client) => BlockingClient::ready(client),
+            None => {
+                let (timeout, connect_timeout, proxy) =
+                    (self.timeout, self.connect_timeout, self.proxy);
+                BlockingClient::lazy(move || {
+                    let mut builder = tls::blocking_client_builder();
+                    if let Some(timeout) = timeout {
+                        builder = builder.timeout(timeout);
+                    }
+                    if let Some(timeout) = connect_timeout {
+                        builder = builder.connect_timeout(timeout);
+                    }
+                    if let Some(proxy) = proxy.clone() {
+                        builder = builder.proxy(proxy);
+                    }
+                    builder.build()
+                })
+            }
+        };
+
+        let mut sender = Sender::with_clients(
+            self.api_key,
+            client,
+            #[cfg(feature = "blocking")]
+            blocking_client,
+        );
+        if let Some(host) = self.host {
+            sender.set_host(host)?;
+        }
+        if let Some(suffix) = self.user_agent_suffix {
+            sender.user_agent = format!("{} {}", USER_AGENT, suffix);
+        }
+        // Reject a suffix which can not be sent now rather than on the first send.
+        sender.get_headers()?;
+        Ok(sender)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::SendgridError;
+
+    #[test]
+    fn senders_are_configured() {
+        let sender = SenderBuilder::new("SG.key")
+            .set_host("http://localhost:3000/v3/mail/send")
+            .set_timeout(Duration::from_secs(5))
+            .set_user_agent_suffix("billing/1.4")
+            .build()
+            .unwrap();
+        assert_eq!(sender.host, "http://localhost:3000/v3/mail/send");
+
+        assert_eq!(
+            sender.get_headers().unwrap()[reqwest::header::USER_AGENT],
+            "sendgrid-rs billing/1.4"
+        );
+
+        let sender = SenderBuilder::new("SG.key")
+            .set_region(Region::Eu)
+            .build()
+            .unwrap();
+        assert_eq!(sender.host, "https://api.eu.sendgrid.com/v3/mail/send");
+
+        assert!(matches!(
+            SenderBuilder::new("SG.key")
+                .set_user_agent_suffix("bad\nvalue")
+                .build(),
+            Err(SendgridError::InvalidHeader(_))
+        ));
+    }
+}
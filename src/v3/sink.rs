@@ -0,0 +1,154 @@
+//! Provides a [`Sink`] based interface for pushing messages through a [`Sender`].
+
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures::channel::mpsc;
+use futures::{Sink, Stream, StreamExt};
+use reqwest::Response;
+
+use crate::error::{SendgridError, SendgridResult};
+use crate::v3::{Message, Sender};
+
+/// The sending half returned by [`Sender::into_sink`]. Messages pushed into this sink are sent by
+/// the paired results stream. When the buffer is full the sink applies back-pressure until the
+/// results stream makes progress.
+#[derive(Clone, Debug)]
+pub struct MessageSink {
+    inner: mpsc::Sender<Message>,
+}
+
+impl Sink<Message> for MessageSink {
+    type Error = SendgridError;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.inner)
+            .poll_ready(cx)
+            .map_err(|_| SendgridError::SinkClosed)
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, item: Message) -> Result<(), Self::Error> {
+        Pin::new(&mut self.inner)
+            .start_send(item)
+            .map_err(|_| SendgridError::SinkClosed)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.inner)
+            .poll_flush(cx)
+            .map_err(|_| SendgridError::SinkClosed)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.inner)
+            .poll_close(cx)
+            .map_err(|_| SendgridError::SinkClosed)
+    }
+}
+
+impl Sender {
+    /// Turn this sender into a [`Sink`] of messages and a stream of send results.
+    ///
+    /// The sink buffers `buffer` messages, plus one for each clone of the sink, before exerting
+    /// back-pressure, and the results
+    /// stream keeps at most `concurrency` requests in flight. Messages are only sent while the
+    /// results stream is being polled, so it must be driven (for example by spawning it onto a
+    /// runtime) for the sink to make progress. Results are yielded in completion order and the
+    /// stream ends once every clone of the sink has been closed or dropped.
+    ///
+    /// ```rust,ignore
+    /// use futures::{SinkExt, StreamExt};
+    ///
+    /// let (mut sink, results) = sender.into_sink(64, 8);
+    /// tokio::spawn(results.for_each(|res| async move { println!("{:?}", res) }));
+    /// sink.send(message).await?;
+    /// ```
+    pub fn into_sink(
+        self,
+        buffer: usize,
+        concurrency: usize,
+    ) -> (
+        MessageSink,
+        impl Stream<Item = SendgridResult<Response>> + Send + 'static,
+    ) {
+        let (tx, rx) = mpsc::channel(buffer);
+        let results = rx
+            .map(move |message: Message| {
+                let sender = self.clone();
+                async move { sender.send(&message).await }
+            })
+            .buffer_unordered(concurrency.max(1));
+
+        (MessageSink { inner: tx }, results)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use futures::{SinkExt, StreamExt};
+
+    use crate::v3::dev_server::CaptureServer;
+    use crate::v3::{Email, Message, Personalization, Sender};
+
+    fn message() -> Message {
+        Message::new(Email::new("from_email@test.com"))
+            .add_personalization(Personalization::new(Email::new("to_email@test.com")))
+    }
+
+    #[tokio::test]
+    async fn sink_yields_one_result_per_message() {
+        let server = CaptureServer::start().await.unwrap();
+        let mut sender = Sender::new(String::from("SG.key"), None);
+        sender.set_host(server.url()).unwrap();
+        let (mut sink, results) = sender.into_sink(2, 2);
+
+        let producer = async move {
+            for _ in 0..3 {
+                sink.send(message()).await.unwrap();
+            }
+            sink.close().await.unwrap();
+        };
+        let (_, results) = tokio::join!(producer, results.collect::<Vec<_>>());
+
+        assert_eq!(results.len(), 3);
+        assert!(results.iter().all(|r| r.as_ref().unwrap().status() == 202));
+        assert_eq!(server.captured().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn full_buffers_apply_back_pressure() {
+        let server = CaptureServer::start().await.unwrap();
+        server.pause();
+        let mut sender = Sender::new(String::from("SG.key"), None);
+        sender.set_host(server.url()).unwrap();
+        let (mut sink, results) = sender.into_sink(1, 2);
+        let results = tokio::spawn(results.collect::<Vec<_>>());
+
+        // Two messages are sent while two more wait in the channel, which holds `buffer` messages
+        // plus one for each clone of the sink.
+        for _ in 0..4 {
+            sink.feed(message()).await.unwrap();
+        }
+        let start = Instant::now();
+        while server.in_flight() < 2 {
+            assert!(start.elapsed() < Duration::from_secs(5));
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert!(
+            tokio::time::timeout(Duration::from_millis(100), sink.feed(message()))
+                .await
+                .is_err()
+        );
+        assert_eq!(server.in_flight(), 2);
+
+        server.resume();
+        sink.send(message()).await.unwrap();
+        sink.close().await.unwrap();
+        let results = results.await.unwrap();
+        assert_eq!(results.len(), 5);
+        assert!(results.iter().all(Result::is_ok));
+        assert_eq!(server.captured().len(), 5);
+    }
+}
@@ -0,0 +1,135 @@
+//! Normalize subjects taken from user input.
+//!
+//! Subjects containing line breaks are rejected by the API or mangled by mail clients, and very
+//! long subjects are cut off unpredictably. [`SubjectNormalization`] trims and collapses
+//! whitespace and truncates subjects at a fixed length. Apply it when building a message with
+//! [`Message::normalize_subject`] or to every message a sender sends with
+//! [`Sender::set_subject_normalization`](crate::v3::Sender::set_subject_normalization).
+
+use crate::v3::Message;
+
+/// How subjects are normalized.
+#[must_use]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SubjectNormalization {
+    max_length: Option<usize>,
+    ellipsis: String,
+}
+
+impl Default for SubjectNormalization {
+    fn default() -> Self {
+        SubjectNormalization {
+            max_length: None,
+            ellipsis: String::from("…"),
+        }
+    }
+}
+
+impl SubjectNormalization {
+    /// Construct a normalization which trims the subject and replaces line breaks and runs of
+    /// whitespace with a single space, without truncating.
+    pub fn new() -> SubjectNormalization {
+        SubjectNormalization::default()
+    }
+
+    /// Truncate subjects longer than `max_length` characters, including the ellipsis. When
+    /// `max_length` is shorter than the ellipsis, subjects are truncated without it.
+    pub fn set_max_length(mut self, max_length: usize) -> SubjectNormalization {
+        self.max_length = Some(max_length);
+        self
+    }
+
+    /// Set the text appended to truncated subjects. Defaults to `…`.
+    pub fn set_ellipsis<S: Into<String>>(mut self, ellipsis: S) -> SubjectNormalization {
+        self.ellipsis = ellipsis.into();
+        self
+    }
+
+    /// Normalize a subject.
+    pub fn apply(&self, subject: &str) -> String {
+        let mut normalized = subject.split_whitespace().collect::<Vec<_>>().join(" ");
+
+        if let Some(max_length) = self.max_length {
+            if normalized.chars().count() > max_length {
+                let ellipsis = self.ellipsis.chars().count();
+                if max_length < ellipsis {
+                    normalized = normalized.chars().take(max_length).collect::<String>();
+                } else {
+                    normalized = normalized.chars().take(max_length - ellipsis).collect();
+                    normalized.truncate(normalized.trim_end().len());
+                    normalized.push_str(&self.ellipsis);
+                }
+            }
+        }
+
+        normalized
+    }
+
+    pub(crate) fn is_normalized(&self, message: &Message) -> bool {
+        self.apply(&message.subject) == message.subject
+            && message.personalizations.iter().all(|p| {
+                p.subject
+                    .as_ref()
+                    .is_none_or(|subject| self.apply(subject) == *subject)
+            })
+    }
+}
+
+impl Message {
+    /// Normalize the subject of the message and of every personalization.
+    pub fn normalize_subject(mut self, normalization: &SubjectNormalization) -> Message {
+        self.subject = normalization.apply(&self.subject);
+        for p in &mut self.personalizations {
+            if let Some(subject) = &mut p.subject {
+                *subject = normalization.apply(subject);
+            }
+        }
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::v3::{Email, Personalization, Sender};
+
+    #[test]
+    fn subjects_are_normalized() {
+        let normalization = SubjectNormalization::new();
+        assert_eq!(
+            normalization.apply("  Your order\r\n  has   shipped \n"),
+            "Your order has shipped"
+        );
+
+        let normalization = normalization.set_max_length(12);
+        assert_eq!(normalization.apply("Short"), "Short");
+        assert_eq!(normalization.apply("Your order has shipped"), "Your order…");
+        assert_eq!(
+            normalization.set_ellipsis("...").apply("Grüße aus München"),
+            "Grüße aus..."
+        );
+        assert_eq!(
+            SubjectNormalization::new()
+                .set_max_length(2)
+                .set_ellipsis("...")
+                .apply("Your order"),
+            "Yo"
+        );
+    }
+
+    #[test]
+    fn sender_normalizes_subjects() {
+        let message = Message::new(Email::new("from@example.com"))
+            .set_subject("Hello\nthere")
+            .add_personalization(
+                Personalization::new(Email::new("to@example.com")).set_subject(" Hi\n"),
+            );
+
+        let mut sender = Sender::new(String::from("SG.key"), None);
+        sender.set_subject_normalization(SubjectNormalization::new());
+        let body: serde_json::Value =
+            serde_json::from_str(sender.prepare(&message).unwrap().body()).unwrap();
+        assert_eq!(body["subject"], "Hello there");
+        assert_eq!(body["personalizations"][0]["subject"], "Hi");
+    }
+}
@@ -0,0 +1,229 @@
+//! Append UTM parameters to the links of HTML content.
+//!
+//! Teams which disable click tracking can not use the ganalytics setting, because SendGrid only
+//! tags the links it rewrites. [`UtmTagging`] adds the campaign parameters to every absolute link
+//! in `text/html` content instead. Apply it when building a message with [`Message::tag_links`]
+//! or to every message a sender sends with
+//! [`Sender::set_utm_tagging`](crate::v3::Sender::set_utm_tagging).
+//!
+//! Links which already carry a `utm_source` parameter are left alone, as are links matching an
+//! exclusion pattern, for example unsubscribe pages.
+
+use url::form_urlencoded::byte_serialize;
+
+use crate::v3::Message;
+
+/// The UTM parameters added to links.
+#[must_use]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UtmTagging {
+    params: Vec<(&'static str, String)>,
+    exclusions: Vec<String>,
+}
+
+impl UtmTagging {
+    /// Construct a tagging which adds `utm_source`, `utm_medium` and `utm_campaign`.
+    pub fn new<S, M, C>(source: S, medium: M, campaign: C) -> UtmTagging
+    where
+        S: Into<String>,
+        M: Into<String>,
+        C: Into<String>,
+    {
+        UtmTagging {
+            params: vec![
+                ("utm_source", source.into()),
+                ("utm_medium", medium.into()),
+                ("utm_campaign", campaign.into()),
+            ],
+            exclusions: Vec::new(),
+        }
+    }
+
+    /// Also add `utm_term`.
+    pub fn set_term<S: Into<String>>(self, term: S) -> UtmTagging {
+        self.set_param("utm_term", term.into())
+    }
+
+    /// Also add `utm_content`.
+    pub fn set_content<S: Into<String>>(self, content: S) -> UtmTagging {
+        self.set_param("utm_content", content.into())
+    }
+
+    /// Leave links matching `pattern` untouched. A `*` in the pattern matches any text, so
+    /// `https://example.com/unsubscribe*` excludes every unsubscribe link of the site.
+    pub fn add_exclusion<S: Into<String>>(mut self, pattern: S) -> UtmTagging {
+        self.exclusions.push(pattern.into());
+        self
+    }
+
+    fn set_param(mut self, name: &'static str, value: String) -> UtmTagging {
+        self.params.retain(|(n, _)| *n != name);
+        self.params.push((name, value));
+        self
+    }
+
+    /// Tag the `href` attributes of an HTML document.
+    pub fn apply(&self, html: &str) -> String {
+        // Lowercasing ASCII keeps byte offsets, so positions found in `lower` index `html`.
+        let lower = html.to_ascii_lowercase();
+        let mut tagged = String::with_capacity(html.len());
+        let mut copied = 0;
+        let mut search = 0;
+
+        while let Some(found) = lower[search..].find("href") {
+            let start = search + found + "href".len();
+            search = start;
+            let Some((quote, value_start)) = attribute_value(&lower, start) else {
+                continue;
+            };
+            let Some(len) = html[value_start..].find(quote) else {
+                break;
+            };
+            let value_end = value_start + len;
+            search = value_end;
+
+            if let Some(link) = self.tag(&html[value_start..value_end]) {
+                tagged.push_str(&html[copied..value_start]);
+                tagged.push_str(&link);
+                copied = value_end;
+            }
+        }
+
+        tagged.push_str(&html[copied..]);
+        tagged
+    }
+
+    // Tag a single link, returning `None` when it is left as is.
+    fn tag(&self, link: &str) -> Option<String> {
+        let lower = link.to_ascii_lowercase();
+        if !(lower.starts_with("http://") || lower.starts_with("https://"))
+            || lower.contains("utm_source=")
+            || self.exclusions.iter().any(|p| glob_matches(p, link))
+        {
+            return None;
+        }
+
+        let (base, fragment) = match link.find('#') {
+            Some(i) => link.split_at(i),
+            None => (link, ""),
+        };
+        let mut tagged = String::from(base);
+        let mut separator = match base.find('?') {
+            Some(i) if i + 1 == base.len() || base.ends_with('&') => "",
+            Some(_) => "&",
+            None => "?",
+        };
+        for (name, value) in &self.params {
+            tagged.push_str(separator);
+            tagged.push_str(name);
+            tagged.push('=');
+            tagged.extend(byte_serialize(value.as_bytes()));
+            separator = "&";
+        }
+        tagged.push_str(fragment);
+        Some(tagged)
+    }
+}
+
+// Find the quoted value of an attribute whose name ends at `start`.
+fn attribute_value(lower: &str, start: usize) -> Option<(char, usize)> {
+    let rest = lower[start..].trim_start();
+    let rest = rest.strip_prefix('=')?.trim_start();
+    let quote = rest.chars().next().filter(|c| matches!(c, '"' | '\''))?;
+    Some((quote, lower.len() - rest.len() + 1))
+}
+
+// Match `text` against a pattern in which `*` matches any run of characters.
+fn glob_matches(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = text.strip_prefix(first) else {
+        return false;
+    };
+
+    let parts: Vec<_> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(i) => rest = &rest[i + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+impl Message {
+    /// Add the UTM parameters of `tagging` to the links of every `text/html` content part.
+    pub fn tag_links(mut self, tagging: &UtmTagging) -> Message {
+        for content in self.content.iter_mut().flatten() {
+            if content.content_type.eq_ignore_ascii_case("text/html") {
+                content.value = tagging.apply(&content.value);
+            }
+        }
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::v3::{Email, Personalization, Sender};
+
+    #[test]
+    fn links_are_tagged() {
+        let tagging = UtmTagging::new("newsletter", "email", "spring sale")
+            .add_exclusion("https://example.com/unsubscribe*");
+        let html = concat!(
+            r#"<a href="https://example.com/shop">Shop</a> "#,
+            r#"<a HREF = 'https://example.com/item?id=1#top'>Item</a> "#,
+            r#"<a href="https://example.com/unsubscribe?u=1">Unsubscribe</a> "#,
+            r#"<a href="mailto:help@example.com">Help</a> "#,
+            r#"<a href="https://example.com/?utm_source=x">Tagged</a>"#,
+        );
+        let expected = concat!(
+            r#"<a href="https://example.com/shop?utm_source=newsletter&utm_medium=email&utm_campaign=spring+sale">Shop</a> "#,
+            r#"<a HREF = 'https://example.com/item?id=1&utm_source=newsletter&utm_medium=email&utm_campaign=spring+sale#top'>Item</a> "#,
+            r#"<a href="https://example.com/unsubscribe?u=1">Unsubscribe</a> "#,
+            r#"<a href="mailto:help@example.com">Help</a> "#,
+            r#"<a href="https://example.com/?utm_source=x">Tagged</a>"#,
+        );
+        assert_eq!(tagging.apply(html), expected);
+    }
+
+    #[test]
+    fn exclusions_match_wildcards() {
+        assert!(glob_matches(
+            "https://*.example.com/*",
+            "https://a.example.com/x"
+        ));
+        assert!(glob_matches("https://example.com/", "https://example.com/"));
+        assert!(!glob_matches(
+            "https://example.com/",
+            "https://example.com/x"
+        ));
+        assert!(!glob_matches(
+            "*/unsubscribe",
+            "https://example.com/unsubscribe/x"
+        ));
+    }
+
+    #[test]
+    fn sender_tags_html_content() {
+        let message = Message::new(Email::new("from@example.com"))
+            .add_personalization(Personalization::new(Email::new("to@example.com")))
+            .set_text("https://example.com")
+            .set_html(r#"<a href="https://example.com">Visit</a>"#);
+
+        let mut sender = Sender::new(String::from("SG.key"), None);
+        sender.set_utm_tagging(UtmTagging::new("app", "email", "welcome").set_content("cta"));
+        let body: serde_json::Value =
+            serde_json::from_str(sender.prepare(&message).unwrap().body()).unwrap();
+        assert_eq!(body["content"][0]["value"], "https://example.com");
+        assert_eq!(
+            body["content"][1]["value"],
+            r#"<a href="https://example.com?utm_source=app&utm_medium=email&utm_campaign=welcome&utm_content=cta">Visit</a>"#
+        );
+    }
+}
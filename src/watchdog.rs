@@ -0,0 +1,263 @@
+//! A background watchdog for the bounce and spam report rates of an account.
+//!
+//! [`Watchdog`] periodically reads the global stats of the last few days and calls back when the
+//! bounce rate or the spam report rate is above its threshold, so an application can pause
+//! sending before its sender reputation suffers.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use tokio::task::JoinHandle;
+#[cfg(feature = "cancellation")]
+use tokio_util::sync::CancellationToken;
+
+use crate::error::{SendgridError, SendgridResult};
+use crate::stats::{Metrics, StatsClient};
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// The rates of a check which exceeded a threshold.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Alert {
+    /// The metrics of the checked window added up.
+    pub metrics: Metrics,
+
+    /// The bounce rate of the window, between 0 and 1.
+    pub bounce_rate: f64,
+
+    /// The spam report rate of the window, between 0 and 1.
+    pub spam_report_rate: f64,
+
+    /// Whether the bounce rate is above its threshold.
+    pub bounce_rate_exceeded: bool,
+
+    /// Whether the spam report rate is above its threshold.
+    pub spam_report_rate_exceeded: bool,
+}
+
+/// Checks the bounce and spam report rates of the account against thresholds.
+#[must_use]
+#[derive(Clone, Debug)]
+pub struct Watchdog {
+    stats: StatsClient,
+    interval: Duration,
+    window_days: u64,
+    min_requests: u64,
+    max_bounce_rate: f64,
+    max_spam_report_rate: f64,
+}
+
+impl Watchdog {
+    /// Construct a watchdog checking every 15 minutes whether more than 5% of the emails of the
+    /// last day bounced or more than 0.1% were reported as spam.
+    pub fn new(stats: StatsClient) -> Watchdog {
+        Watchdog {
+            stats,
+            interval: Duration::from_secs(15 * 60),
+            window_days: 1,
+            min_requests: 100,
+            max_bounce_rate: 0.05,
+            max_spam_report_rate: 0.001,
+        }
+    }
+
+    /// Set the time between checks. Intervals shorter than a second are treated as a second, so
+    /// that a zero interval does not query the stats API in a busy loop.
+    pub fn set_interval(mut self, interval: Duration) -> Watchdog {
+        self.interval = interval.max(Duration::from_secs(1));
+        self
+    }
+
+    /// Set the number of days, including today, whose stats are checked.
+    pub fn set_window_days(mut self, window_days: u64) -> Watchdog {
+        self.window_days = window_days.max(1);
+        self
+    }
+
+    /// Set the number of requests the window needs before the rates are checked, so that a few
+    /// bounces on a quiet day do not raise an alert. Defaults to 100.
+    pub fn set_min_requests(mut self, min_requests: u64) -> Watchdog {
+        self.min_requests = min_requests;
+        self
+    }
+
+    /// Set the highest acceptable bounce rate, between 0 and 1.
+    pub fn set_max_bounce_rate(mut self, max_bounce_rate: f64) -> Watchdog {
+        self.max_bounce_rate = max_bounce_rate;
+        self
+    }
+
+    /// Set the highest acceptable spam report rate, between 0 and 1.
+    pub fn set_max_spam_report_rate(mut self, max_spam_report_rate: f64) -> Watchdog {
+        self.max_spam_report_rate = max_spam_report_rate;
+        self
+    }
+
+    /// Check the rates once and return an alert if a threshold is exceeded.
+    pub async fn check(&self) -> SendgridResult<Option<Alert>> {
+        let today = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs()
+            / SECONDS_PER_DAY;
+        let start_date = format_date(today.saturating_sub(self.window_days - 1));
+
+        let metrics = self
+            .stats
+            .global_stats(&start_date, None)
+            .await?
+            .iter()
+            .fold(Metrics::default(), |total, period| total + period.metrics());
+        Ok(self.evaluate(metrics))
+    }
+
+    /// Check the rates every interval on the current tokio runtime, calling `on_alert` when a
+    /// threshold is exceeded and `on_error` when the stats could not be read. The watchdog stops
+    /// when the returned handle is aborted.
+    pub fn spawn<A, E>(self, on_alert: A, on_error: E) -> JoinHandle<()>
+    where
+        A: FnMut(Alert) + Send + 'static,
+        E: FnMut(SendgridError) + Send + 'static,
+    {
+        tokio::spawn(self.watch(on_alert, on_error))
+    }
+
+    /// Like [`Watchdog::spawn`], but the watchdog also stops when `token` is cancelled, abandoning
+    /// a check in progress.
+    #[cfg(feature = "cancellation")]
+    pub fn spawn_until_cancelled<A, E>(
+        self,
+        token: CancellationToken,
+        on_alert: A,
+        on_error: E,
+    ) -> JoinHandle<()>
+    where
+        A: FnMut(Alert) + Send + 'static,
+        E: FnMut(SendgridError) + Send + 'static,
+    {
+        tokio::spawn(async move {
+            token
+                .run_until_cancelled(self.watch(on_alert, on_error))
+                .await;
+        })
+    }
+
+    async fn watch<A, E>(self, mut on_alert: A, mut on_error: E)
+    where
+        A: FnMut(Alert),
+        E: FnMut(SendgridError),
+    {
+        let mut interval = tokio::time::interval(self.interval);
+        loop {
+            interval.tick().await;
+            match self.check().await {
+                Ok(Some(alert)) => on_alert(alert),
+                Ok(None) => {}
+                Err(e) => on_error(e),
+            }
+        }
+    }
+
+    fn evaluate(&self, metrics: Metrics) -> Option<Alert> {
+        if metrics.requests < self.min_requests {
+            return None;
+        }
+
+        let bounce_rate = metrics.bounce_rate();
+        let spam_report_rate = metrics.spam_report_rate();
+        let alert = Alert {
+            metrics,
+            bounce_rate,
+            spam_report_rate,
+            bounce_rate_exceeded: bounce_rate > self.max_bounce_rate,
+            spam_report_rate_exceeded: spam_report_rate > self.max_spam_report_rate,
+        };
+        (alert.bounce_rate_exceeded || alert.spam_report_rate_exceeded).then_some(alert)
+    }
+}
+
+// Format a day counted from the Unix epoch as `YYYY-MM-DD`.
+fn format_date(days: u64) -> String {
+    // Howard Hinnant's civil_from_days.
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dates_are_formatted() {
+        assert_eq!(format_date(0), "1970-01-01");
+        assert_eq!(format_date(19_783), "2024-03-01");
+        assert_eq!(format_date(19_782), "2024-02-29");
+    }
+
+    #[test]
+    fn intervals_are_at_least_a_second() {
+        let watchdog = Watchdog::new(StatsClient::new(String::from("SG.key"), None));
+        assert_eq!(
+            watchdog.clone().set_interval(Duration::ZERO).interval,
+            Duration::from_secs(1)
+        );
+        assert_eq!(
+            watchdog.set_interval(Duration::from_secs(60)).interval,
+            Duration::from_secs(60)
+        );
+    }
+
+    #[test]
+    fn thresholds_raise_alerts() {
+        let watchdog = Watchdog::new(StatsClient::new(String::from("SG.key"), None));
+        let mut metrics = Metrics {
+            requests: 1000,
+            delivered: 1000,
+            bounces: 10,
+            ..Default::default()
+        };
+        assert_eq!(watchdog.evaluate(metrics), None);
+
+        metrics.bounces = 60;
+        let alert = watchdog.evaluate(metrics).unwrap();
+        assert!(alert.bounce_rate_exceeded);
+        assert!(!alert.spam_report_rate_exceeded);
+
+        // Too few requests to judge.
+        metrics.requests = 50;
+        assert_eq!(watchdog.evaluate(metrics), None);
+    }
+
+    #[cfg(feature = "cancellation")]
+    #[tokio::test]
+    async fn watchdogs_stop_when_cancelled() {
+        // The capture server only implements mail sends, so every check fails.
+        let server = crate::v3::dev_server::CaptureServer::start().await.unwrap();
+        let mut stats = StatsClient::new(String::from("SG.key"), None);
+        stats.set_host(format!("http://{}", server.addr()));
+        let token = CancellationToken::new();
+        let (errors_tx, mut errors) = tokio::sync::mpsc::unbounded_channel();
+
+        let handle = Watchdog::new(stats).spawn_until_cancelled(
+            token.clone(),
+            |_| {},
+            move |e| {
+                let _ = errors_tx.send(e);
+            },
+        );
+        // The first check runs right away.
+        assert!(errors.recv().await.is_some());
+        token.cancel();
+        tokio::time::timeout(Duration::from_secs(5), handle)
+            .await
+            .unwrap()
+            .unwrap();
+    }
+}
@@ -0,0 +1,243 @@
+//! OAuth support for the SendGrid Event Webhook.
+//!
+//! When OAuth is enabled for the Event Webhook, SendGrid requests an access token from your token
+//! URL with the client credentials grant and sends it as a bearer token with every event POST.
+//! [`WebhookOAuth`] implements both halves on your side: it issues short lived, HMAC signed tokens
+//! to SendGrid and verifies them on the webhook endpoint without storing any state.
+//! [`OAuthSettings`] is the matching payload for the webhook settings API.
+//!
+//! See the [webhook security docs](https://www.twilio.com/docs/sendgrid/for-developers/tracking-events/getting-started-event-webhook-security-features)
+//! for details.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use data_encoding::BASE64URL_NOPAD;
+use hmac::{Hmac, Mac};
+use reqwest::{Client, Method, Response};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use crate::api::ApiClient;
+use crate::error::{SendgridError, SendgridResult};
+
+type HmacSha256 = Hmac<Sha256>;
+
+const EVENT_WEBHOOK_SETTINGS_PATH: &str = "/v3/user/webhooks/event/settings";
+
+const DEFAULT_TOKEN_LIFETIME: Duration = Duration::from_secs(60 * 60);
+
+/// The OAuth fields of the Event Webhook settings.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct OAuthSettings {
+    /// The client id SendGrid uses to request tokens.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub oauth_client_id: Option<String>,
+
+    /// The client secret SendGrid uses to request tokens. This is never returned by the API.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub oauth_client_secret: Option<String>,
+
+    /// The URL SendGrid requests tokens from.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub oauth_token_url: Option<String>,
+}
+
+impl OAuthSettings {
+    /// Construct the settings for the given client credentials and token URL.
+    pub fn new<S: Into<String>>(client_id: S, client_secret: S, token_url: S) -> OAuthSettings {
+        OAuthSettings {
+            oauth_client_id: Some(client_id.into()),
+            oauth_client_secret: Some(client_secret.into()),
+            oauth_token_url: Some(token_url.into()),
+        }
+    }
+}
+
+/// Update the OAuth settings of the Event Webhook. The `client` parameter is optional and `None`
+/// uses the default.
+pub async fn update_oauth_settings(
+    api_key: &str,
+    settings: &OAuthSettings,
+    client: Option<Client>,
+) -> SendgridResult<Response> {
+    let api = ApiClient::new(api_key.to_string(), client);
+    api.execute(
+        api.request(Method::PATCH, EVENT_WEBHOOK_SETTINGS_PATH)?
+            .json(settings),
+    )
+    .await
+}
+
+/// The body returned from the token URL.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct AccessToken {
+    /// The bearer token SendGrid will send with events.
+    pub access_token: String,
+
+    /// Always `Bearer`.
+    pub token_type: String,
+
+    /// The lifetime of the token in seconds.
+    pub expires_in: u64,
+}
+
+/// Issues and verifies the bearer tokens used by the Event Webhook.
+#[must_use]
+#[derive(Clone)]
+pub struct WebhookOAuth {
+    client_id: String,
+    client_secret: String,
+    signing_key: Vec<u8>,
+    lifetime: Duration,
+}
+
+impl WebhookOAuth {
+    /// Construct a new issuer. The client credentials must match the ones configured in
+    /// [`OAuthSettings`] and `signing_key` is a secret used to sign the issued tokens.
+    pub fn new<S: Into<String>, K: Into<Vec<u8>>>(
+        client_id: S,
+        client_secret: S,
+        signing_key: K,
+    ) -> WebhookOAuth {
+        WebhookOAuth {
+            client_id: client_id.into(),
+            client_secret: client_secret.into(),
+            signing_key: signing_key.into(),
+            lifetime: DEFAULT_TOKEN_LIFETIME,
+        }
+    }
+
+    /// Set the lifetime of issued tokens. Defaults to one hour.
+    pub fn set_token_lifetime(mut self, lifetime: Duration) -> WebhookOAuth {
+        self.lifetime = lifetime;
+        self
+    }
+
+    /// Handle a client credentials request to the token URL, returning a new token when the
+    /// credentials match.
+    pub fn issue_token(&self, client_id: &str, client_secret: &str) -> SendgridResult<AccessToken> {
+        self.issue_token_at(client_id, client_secret, SystemTime::now())
+    }
+
+    /// Verify the `Authorization` header of an Event Webhook request.
+    pub fn verify(&self, authorization: &str) -> SendgridResult<()> {
+        self.verify_at(authorization, SystemTime::now())
+    }
+
+    fn issue_token_at(
+        &self,
+        client_id: &str,
+        client_secret: &str,
+        now: SystemTime,
+    ) -> SendgridResult<AccessToken> {
+        // Compare both values fully so the response time does not reveal which one was wrong.
+        let id_ok = constant_time_eq(client_id.as_bytes(), self.client_id.as_bytes());
+        let secret_ok = constant_time_eq(client_secret.as_bytes(), self.client_secret.as_bytes());
+        if !(id_ok & secret_ok) {
+            return Err(SendgridError::InvalidWebhookToken);
+        }
+
+        let expires = unix_seconds(now) + self.lifetime.as_secs();
+        let payload = BASE64URL_NOPAD.encode(format!("{}.{}", self.client_id, expires).as_bytes());
+        let signature = BASE64URL_NOPAD.encode(&self.sign(payload.as_bytes()));
+
+        Ok(AccessToken {
+            access_token: format!("{}.{}", payload, signature),
+            token_type: String::from("Bearer"),
+            expires_in: self.lifetime.as_secs(),
+        })
+    }
+
+    fn verify_at(&self, authorization: &str, now: SystemTime) -> SendgridResult<()> {
+        let token = authorization
+            .trim()
+            .strip_prefix("Bearer ")
+            .ok_or(SendgridError::InvalidWebhookToken)?;
+        let (payload, signature) = token
+            .split_once('.')
+            .ok_or(SendgridError::InvalidWebhookToken)?;
+        let signature = BASE64URL_NOPAD
+            .decode(signature.as_bytes())
+            .map_err(|_| SendgridError::InvalidWebhookToken)?;
+
+        let mut mac = self.mac();
+        mac.update(payload.as_bytes());
+        mac.verify_slice(&signature)
+            .map_err(|_| SendgridError::InvalidWebhookToken)?;
+
+        let payload = BASE64URL_NOPAD
+            .decode(payload.as_bytes())
+            .map_err(|_| SendgridError::InvalidWebhookToken)?;
+        let payload = String::from_utf8(payload).map_err(|_| SendgridError::InvalidWebhookToken)?;
+        let expires: u64 = payload
+            .rsplit_once('.')
+            .and_then(|(_, expires)| expires.parse().ok())
+            .ok_or(SendgridError::InvalidWebhookToken)?;
+        if unix_seconds(now) >= expires {
+            return Err(SendgridError::InvalidWebhookToken);
+        }
+
+        Ok(())
+    }
+
+    fn mac(&self) -> HmacSha256 {
+        // HMAC accepts keys of any length so this can not fail.
+        HmacSha256::new_from_slice(&self.signing_key).unwrap()
+    }
+
+    fn sign(&self, data: &[u8]) -> Vec<u8> {
+        let mut mac = self.mac();
+        mac.update(data);
+        mac.finalize().into_bytes().to_vec()
+    }
+}
+
+fn unix_seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn issued_tokens_verify_until_expiry() {
+        let oauth = WebhookOAuth::new("client", "secret", "signing key")
+            .set_token_lifetime(Duration::from_secs(60));
+        let now = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+
+        let token = oauth.issue_token_at("client", "secret", now).unwrap();
+        assert_eq!(token.token_type, "Bearer");
+        assert_eq!(token.expires_in, 60);
+
+        let header = format!("Bearer {}", token.access_token);
+        assert!(oauth.verify_at(&header, now).is_ok());
+        assert!(oauth
+            .verify_at(&header, now + Duration::from_secs(60))
+            .is_err());
+        assert!(oauth.verify_at(&token.access_token, now).is_err());
+        assert!(WebhookOAuth::new("client", "secret", "other key")
+            .verify_at(&header, now)
+            .is_err());
+    }
+
+    #[test]
+    fn wrong_credentials_are_rejected() {
+        let oauth = WebhookOAuth::new("client", "secret", "signing key");
+        assert!(oauth.issue_token("client", "wrong").is_err());
+        assert!(oauth.issue_token("other", "secret").is_err());
+    }
+
+    #[test]
+    fn oauth_settings_json() {
+        let settings = OAuthSettings::new("client", "secret", "https://example.com/token");
+        let expected = r#"{"oauth_client_id":"client","oauth_client_secret":"secret","oauth_token_url":"https://example.com/token"}"#;
+        assert_eq!(serde_json::to_string(&settings).unwrap(), expected);
+    }
+}